@@ -9,10 +9,10 @@ fn bench_math(c: &mut Criterion) {
         r = sin(x)
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn(f64) -> f64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(f64,), f64>("bench_sin").unwrap();
 
-    c.bench_function("jit_sin", |b| b.iter(|| func(black_box(2.0))));
+    c.bench_function("jit_sin", |b| b.iter(|| func.call((black_box(2.0),))));
 }
 
 fn bench_native_sin(c: &mut Criterion) {
@@ -27,10 +27,10 @@ fn bench_array_sum(c: &mut Criterion) {
         r = toy_sum_array(arr)
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn() -> f64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(), f64>("bench_sum").unwrap();
 
-    c.bench_function("jit_sum_array", |b| b.iter(|| black_box(func())));
+    c.bench_function("jit_sum_array", |b| b.iter(|| black_box(func.call(()))));
 }
 
 fn bench_dynamic_array(c: &mut Criterion) {
@@ -47,10 +47,10 @@ fn bench_dynamic_array(c: &mut Criterion) {
         drop(arr)
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn() -> i64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(), i64>("bench_dynamic_array").unwrap();
 
-    c.bench_function("jit_dynamic_array", |b| b.iter(|| black_box(func())));
+    c.bench_function("jit_dynamic_array", |b| b.iter(|| black_box(func.call(()))));
 }
 
 fn bench_native_dynamic_array(c: &mut Criterion) {
@@ -1,390 +1,303 @@
 use anyhow::{Context, Result, anyhow};
-use cranelift_jit_demo::cli::Cli;
-use cranelift_jit_demo::jit;
+use cranelift_jit_demo::cli::{
+    BuildArgs, CheckArgs, Cli, Command, CompileArgs, FmtArgs, MessageFormat, ReplArgs, RunArgs,
+    TestArgs,
+};
+use cranelift_jit_demo::formatter;
+use cranelift_jit_demo::frontend::Type;
+use cranelift_jit_demo::jit::JIT;
+use cranelift_jit_demo::pipeline::{self, Emit};
+use cranelift_jit_demo::repl::{self, Repl};
+use cranelift_jit_demo::value::Value;
 use std::fs;
-use std::mem;
+use std::io::{self, BufRead, Write};
 use std::path::Path;
-
-fn main() -> Result<()> {
-    let cli = Cli::parse_args();  //调用Self::parse()，底层库是clap，RUST主流的命令行参数解析库，根据 struct 的字段和 #[arg] 属性：获得脚本路径、是否运行测试等信息。
-    if cli.test {       //当传了--test参数时，运行所有集成测试，验证JIT编译器的正确性。
-        println!("Running integration tests...");
-        run_all_tests().context("Integration tests failed")?;
-        println!("All tests passed!");
-    } else if let Some(file_path) = cli.file { //当传了脚本路径参数时，运行指定的toy脚本。
-        run_script(&file_path).with_context(|| format!("Failed to run script: {:?}", file_path))?;
-    } else {
-        use clap::CommandFactory;
-        Cli::command().print_help()?;
-        println!();
+use std::process::ExitCode;
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse_args(); //调用Self::parse()，底层库是clap，RUST主流的命令行参数解析库，根据 struct 的字段和 #[arg] 属性：获得子命令及其参数。
+    match (cli.command, cli.file) {
+        (Some(Command::Run(args)), _) => run_script(&args),
+        (Some(Command::Check(args)), _) => check_files(&args),
+        (Some(Command::Build(args)), _) => build_file(&args),
+        (Some(Command::Fmt(args)), _) => format_files(&args),
+        (Some(Command::Test(args)), _) => test_files(&args),
+        (Some(Command::Repl(args)), _) => repl(&args),
+        // 旧式调用 `toy FILE`，等价于 `toy run FILE`
+        (None, Some(file_path)) => run_script(&RunArgs::for_file(file_path)),
+        (None, None) => {
+            use clap::CommandFactory;
+            Cli::command().print_help()?;
+            println!();
+            Ok(ExitCode::SUCCESS)
+        }
     }
-
-    Ok(())
 }
 
-fn run_script(path: &Path) -> Result<()> {
-    // 1. Verify file existence and extension
+// ═══════════════════════════════════════════════════════════
+// 共用的编译流水线
+// ═══════════════════════════════════════════════════════════
+
+/// 检查文件存在且扩展名为 .toy，读出源码
+fn read_source(path: &Path) -> Result<String> {
     if !path.exists() {
         return Err(anyhow!("File not found: {:?}", path));
     }
     if path.extension().and_then(|s| s.to_str()) != Some("toy") {
         return Err(anyhow!("File must have .toy extension: {:?}", path));
     }
-
-    // 2. Read source code
-    let source =
-        fs::read_to_string(path).with_context(|| format!("Failed to read file: {:?}", path))?;
-
-    // 3. JIT Compile
-    let mut jit = jit::JIT::default();
-    let code_ptr = jit     //重点核心是这里的compile
-        .compile(&source)     
-        .map_err(|e| anyhow!("Compilation error: {}", e))?;
-
-    // 4. Execute (assuming no arguments for now, or main)
-    // The current toy language JIT.compile returns the entry point of the function defined in the script.
-    unsafe {
-        let func = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        let result = func();
-        println!("Return value: {}", result);
-    }
-
-    Ok(())
-}
-
-fn run_all_tests() -> Result<()> {
-    let mut jit = jit::JIT::default();
-
-    println!("foo(1, 0) = {}", run_foo(&mut jit).map_err(|e| anyhow!(e))?);
-    println!(
-        "recursive_fib(10) = {}",
-        run_recursive_fib(&mut jit, 10).map_err(|e| anyhow!(e))?
-    );
-    println!(
-        "iterative_fib(10) = {}",
-        run_iterative_fib(&mut jit, 10).map_err(|e| anyhow!(e))?
-    );
-    println!(
-        "float_add(1.5, 2.5) = {}",
-        run_float_add(&mut jit, 1.5, 2.5).map_err(|e| anyhow!(e))?
-    );
-    println!(
-        "mixed_add(10, 2.5) = {}",
-        run_mixed_add(&mut jit, 10, 2.5, 2.5).map_err(|e| anyhow!(e))?
-    );
-
-    run_hello(&mut jit).map_err(|e| anyhow!(e))?;
-
-    println!(
-        "mul_div(10.0, 5.0) = {}",
-        run_mul_div(&mut jit, 10.0, 5.0).map_err(|e| anyhow!(e))?
-    );
-
-    run_custom_string(&mut jit, "Customize String Test Success!").map_err(|e| anyhow!(e))?;
-
-    println!("--- Running String Literal Test ---");
-    run_string_test(&mut jit).map_err(|e| anyhow!(e))?;
-
-    println!("--- Running I128 Test ---");
-    run_i128_test(&mut jit).map_err(|e| anyhow!(e))?;
-
-    println!("--- Running Complex Test ---");
-    run_complex_test(&mut jit).map_err(|e| anyhow!(e))?;
-
-    println!("--- Running Array Test ---");
-    run_array_test(&mut jit).map_err(|e| anyhow!(e))?;
-
-    println!("--- Running Dynamic Array Test ---");
-    run_dynamic_array_test(&mut jit).map_err(|e| anyhow!(e))?;
-
-    #[cfg(feature = "mkl")]
-    {
-        println!("--- Running MKL DGEMM Test ---");
-        run_mkl_test(&mut jit).map_err(|e| anyhow!(e))?;
-    }
-
-    Ok(())
+    fs::read_to_string(path).with_context(|| format!("Failed to read file: {:?}", path))
 }
 
-// --- Test helper functions (migrated from original toy.rs) ---
-
-#[cfg(feature = "mkl")]
-fn run_mkl_test(jit: &mut jit::JIT) -> Result<(), String> {
-    let code = r#"
-    fn test_mkl(c: [f64; 4]) -> (r: i64) {
-        a = [1.0, 2.0, 3.0, 4.0]
-        b = [5.0, 6.0, 7.0, 8.0]
-        toy_mkl_dgemm(2, 2, 2, 1.0, a, 0.0, b, c)
-        r = 0
-    }
-    "#;
-
-    unsafe {
-        let code_ptr = jit.compile(code)?;
-        let func: extern "C" fn(*mut f64) -> i64 = mem::transmute(code_ptr);
-
-        let mut c = [0.0f64; 4];
-        func(c.as_mut_ptr());
-
-        println!("MKL DGEMM Result Matrix C: {:?}", c);
-        if c[0] == 19.0 && c[1] == 22.0 && c[2] == 43.0 && c[3] == 50.0 {
-            println!("MKL DGEMM Test Passed!");
-            Ok(())
-        } else {
-            Err(format!(
-                "MKL DGEMM Test Failed: expected [19, 22, 43, 50], got {:?}",
-                c
-            ))
-        }
-    }
-}
-
-fn run_i128_test(jit: &mut jit::JIT) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(I128_TEST_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        Ok(code_fn())
-    }
+/// 按编译选项把整个文件 JIT 编译为一个模块，出错时返回渲染好的诊断。
+/// 同时返回文件中定义的函数名（按名字排序）
+fn compile_file(path: &Path, source: &str, compile: &CompileArgs) -> Result<(JIT, Vec<String>)> {
+    let mut jit = JIT::new(compile.options());
+    let functions = jit.compile_program(source).map_err(|e| {
+        anyhow!(
+            "Compilation failed:\n{}",
+            e.render(&path.display().to_string(), source)
+        )
+    })?;
+    let mut names: Vec<String> = functions.into_keys().collect();
+    names.sort();
+    Ok((jit, names))
 }
 
-const I128_TEST_CODE: &str = r#"
-    fn i128_test() -> (r: i64) {
-        x = 100 as i128
-        r = x as i64
-    }
-"#;
-
-fn run_foo(jit: &mut jit::JIT) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(FOO_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn(i64, i64) -> i64>(code_ptr);
-        Ok(code_fn(1, 0))
+/// 产物的文件扩展名
+fn extension(kind: Emit) -> &'static str {
+    match kind {
+        Emit::Clif => "clif",
+        Emit::Asm => "s",
     }
 }
 
-fn run_recursive_fib(jit: &mut jit::JIT, input: i64) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(RECURSIVE_FIB_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn(i64) -> i64>(code_ptr);
-        Ok(code_fn(input))
+/// run / test 时把 --emit 请求的产物打印到标准输出
+fn print_artifacts(jit: &JIT) {
+    for artifact in jit.artifacts() {
+        println!(
+            ";; {} ({})\n{}",
+            artifact.function,
+            extension(artifact.kind),
+            artifact.text.trim_end()
+        );
     }
 }
 
-fn run_iterative_fib(jit: &mut jit::JIT, input: i64) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(ITERATIVE_FIB_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn(i64) -> i64>(code_ptr);
-        Ok(code_fn(input))
-    }
+fn is_integer(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::I128
+    )
 }
 
-fn run_float_add(jit: &mut jit::JIT, a: f64, b: f64) -> Result<f64, String> {
-    unsafe {
-        let code_ptr = jit.compile(FLOAT_ADD_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn(f64, f64) -> f64>(code_ptr);
-        Ok(code_fn(a, b))
+fn exit_status(ok: bool) -> ExitCode {
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
 }
 
-fn run_mixed_add(jit: &mut jit::JIT, a: i32, b: f64, c: f64) -> Result<f64, String> {
-    unsafe {
-        let code_ptr = jit.compile(MIXED_ADD_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn(i32, f64, f64) -> f64>(code_ptr);
-        Ok(code_fn(a, b, c))
-    }
-}
+// ═══════════════════════════════════════════════════════════
+// 子命令
+// ═══════════════════════════════════════════════════════════
+
+/// toy run FILE [--entry NAME] [--exit-code] -- ARGS...
+fn run_script(args: &RunArgs) -> Result<ExitCode> {
+    let path = args.file.as_path();
+    let run = || -> Result<ExitCode> {
+        let source = read_source(path)?;
+        let (mut jit, _) = compile_file(path, &source, &args.compile)?;
+        print_artifacts(&jit);
+
+        let sig = jit
+            .signature(&args.entry)
+            .ok_or_else(|| anyhow!("No `{}` function defined in {:?}", args.entry, path))?
+            .clone();
+
+        // 按入口函数声明的参数类型解析命令行参数
+        if args.args.len() != sig.params.len() {
+            return Err(anyhow!(
+                "`{}` has signature {}, but {} argument(s) were given",
+                args.entry,
+                sig,
+                args.args.len()
+            ));
+        }
+        let values = sig
+            .params
+            .iter()
+            .zip(&args.args)
+            .map(|(ty, text)| Value::parse(ty, text))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?;
+
+        if args.exit_code && !is_integer(&sig.ret) {
+            return Err(anyhow!(
+                "--exit-code needs an integer return value, but `{}` returns {}",
+                args.entry,
+                sig.ret
+            ));
+        }
 
-fn run_hello(jit: &mut jit::JIT) -> Result<i64, String> {
-    jit.create_data("hello_string", "hello world!\0".as_bytes().to_vec())?;
-    unsafe {
-        let code_ptr = jit.compile(HELLO_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        Ok(code_fn())
-    }
-}
+        // 调用入口函数，按声明的返回类型打印结果
+        let result = jit.call(&args.entry, &values)?;
+        println!("Return value: {}", result);
 
-fn run_mul_div(jit: &mut jit::JIT, a: f64, b: f64) -> Result<f64, String> {
-    unsafe {
-        let code_ptr = jit.compile(MUL_DIV_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn(f64, f64) -> f64>(code_ptr);
-        Ok(code_fn(a, b))
-    }
+        // --exit-code：返回的整数作为进程退出码（低 8 位）
+        Ok(match result.as_i64() {
+            Some(code) if args.exit_code => ExitCode::from(code as u8),
+            _ => ExitCode::SUCCESS,
+        })
+    };
+    run().with_context(|| format!("Failed to run script: {:?}", path))
 }
 
-fn run_custom_string(jit: &mut jit::JIT, msg: &str) -> Result<i64, String> {
-    let mut msg_bytes = msg.as_bytes().to_vec();
-    msg_bytes.push(0); // Null terminator
-    jit.create_data("custom_msg", msg_bytes)?;
-    unsafe {
-        let code_ptr = jit.compile(CUSTOM_STRING_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        Ok(code_fn())
+/// toy check FILE... [--message-format json] [--strict] —— 只做前端检查，不生成代码。
+/// 任一文件有错误时退出码为 1
+fn check_files(args: &CheckArgs) -> Result<ExitCode> {
+    let mut ok = true;
+    for path in &args.files {
+        let source = read_source(path)?;
+        let origin = path.display().to_string();
+        let errors = pipeline::check_program_with(&source, &args.compile.options());
+        ok &= errors.is_empty();
+        match args.message_format {
+            MessageFormat::Human if errors.is_empty() => println!("{}: ok", origin),
+            MessageFormat::Human => {
+                for e in &errors {
+                    eprintln!("{}\n", e.render(&origin, &source));
+                }
+            }
+            MessageFormat::Json => {
+                for line in errors.iter().flat_map(|e| e.to_json(&origin, &source)) {
+                    println!("{}", line);
+                }
+            }
+        }
     }
+    Ok(exit_status(ok))
 }
 
-fn run_string_test(jit: &mut jit::JIT) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(STRING_TEST_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        Ok(code_fn())
+/// toy build FILE [-o DIR]（别名 emit）—— 编译但不运行，把 IR / 汇编文本写入 DIR/<文件名>.<扩展名>。
+/// JIT 只在内存中生成机器码，不输出目标文件
+fn build_file(args: &BuildArgs) -> Result<ExitCode> {
+    let path = args.file.as_path();
+    let mut compile = args.compile.clone();
+    if compile.emit.is_empty() {
+        compile.emit = vec![Emit::Clif, Emit::Asm];
     }
-}
 
-fn run_complex_test(jit: &mut jit::JIT) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(COMPLEX_TEST_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        let result = code_fn();
-        println!("Complex Test Status: {}", result);
-        if result == 1 {
-            Ok(result)
-        } else {
-            Err(format!("Complex test failed"))
-        }
+    let source = read_source(path)?;
+    let (jit, _) = compile_file(path, &source, &compile)?;
+
+    fs::create_dir_all(&args.out_dir)
+        .with_context(|| format!("Failed to create directory: {:?}", args.out_dir))?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    for kind in &compile.emit {
+        let text = jit
+            .artifacts()
+            .iter()
+            .filter(|a| a.kind == *kind)
+            .map(|a| format!(";; {}\n{}\n", a.function, a.text.trim_end()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let out = args.out_dir.join(format!("{}.{}", stem, extension(*kind)));
+        fs::write(&out, text).with_context(|| format!("Failed to write file: {:?}", out))?;
+        println!("wrote {}", out.display());
     }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn run_array_test(jit: &mut jit::JIT) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(ARRAY_TEST_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        let result = code_fn();
-        println!("Array test result: {}", result);
-        if result == 30 {
-            Ok(result)
-        } else {
-            Err(format!("Array test failed: expected 30, got {}", result))
+/// toy fmt [--check] FILE...
+fn format_files(args: &FmtArgs) -> Result<ExitCode> {
+    let mut ok = true;
+    for path in &args.files {
+        let source = read_source(path)?;
+        let formatted = formatter::format_source(&source)
+            .map_err(|e| anyhow!("{}", e.render(&path.display().to_string(), &source)))?;
+        if formatted == source {
+            continue;
         }
-    }
-}
-
-fn run_dynamic_array_test(jit: &mut jit::JIT) -> Result<i64, String> {
-    unsafe {
-        let code_ptr = jit.compile(DYNAMIC_ARRAY_TEST_CODE)?;
-        let code_fn = mem::transmute::<_, extern "C" fn() -> i64>(code_ptr);
-        let result = code_fn();
-        println!("Dynamic array test result: {}", result);
-        if result == 40 {
-            Ok(result)
+        if args.check {
+            ok = false;
+            println!("{} is not formatted", path.display());
         } else {
-            Err(format!(
-                "Dynamic array test failed: expected 40, got {}",
-                result
-            ))
+            fs::write(path, formatted)
+                .with_context(|| format!("Failed to write file: {:?}", path))?;
         }
     }
+    Ok(exit_status(ok))
 }
 
-const FOO_CODE: &str = r#"
-    fn foo(a: i64, b: i64) -> (c: i64) {
-        c = if a {
-            if b {
-                30
-            } else {
-                40
-            }
-        } else {
-            50
-        }
-        c = c + 2
-    }
-"#;
-
-const RECURSIVE_FIB_CODE: &str = r#"
-    fn recursive_fib(n: i64) -> (r: i64) {
-        r = if n == 0 {
-                    0
+/// toy test FILE... —— 调用每个无参数的 `test_*` 函数，返回 0 视为通过
+fn test_files(args: &TestArgs) -> Result<ExitCode> {
+    let (mut passed, mut failed) = (0, 0);
+    for path in &args.files {
+        let source = read_source(path)?;
+        let (mut jit, names) = compile_file(path, &source, &args.compile)?;
+        print_artifacts(&jit);
+
+        for name in names.iter().filter(|n| n.starts_with("test_")) {
+            let sig = jit.signature(name).cloned().expect("compiled function");
+            let outcome = if !sig.params.is_empty() || !is_integer(&sig.ret) {
+                Err(format!("expected () -> integer, found {}", sig))
             } else {
-                if n == 1 {
-                    1
-                } else {
-                    recursive_fib(n - 1) + recursive_fib(n - 2)
+                match jit.call(name, &[])? {
+                    result if result.as_i64() == Some(0) => Ok(()),
+                    result => Err(format!("returned {}", result)),
+                }
+            };
+            match outcome {
+                Ok(()) => {
+                    passed += 1;
+                    println!("test {}::{} ... ok", path.display(), name);
+                }
+                Err(reason) => {
+                    failed += 1;
+                    println!("test {}::{} ... FAILED ({})", path.display(), name, reason);
                 }
-            }
-    }
-"#;
-
-const ITERATIVE_FIB_CODE: &str = r#"
-    fn iterative_fib(n: i64) -> (r: i64) {
-        if n == 0 {
-            r = 0
-        } else {
-            n = n - 1
-            a = 0
-            r = 1
-            while n != 0 {
-                t = r
-                r = r + a
-                a = t
-                n = n - 1
             }
         }
     }
-"#;
-
-const FLOAT_ADD_CODE: &str = r#"
-    fn float_add(a: f64, b: f64) -> (c: f64) {
-        c = a + b
-    }
-"#;
-
-const MIXED_ADD_CODE: &str = r#"
-    fn mixed_add(a: i32, b: f64, c: f64) -> (r: f64) {
-        r = (a as f64) + b + c
-    }
-"#;
-
-const HELLO_CODE: &str = r#"
-fn hello() -> (r: i64) {
-    puts(&hello_string)
-}
-"#;
 
-const MUL_DIV_CODE: &str = r#"
-fn mul_div(a: f64, b: f64) -> (c: f64) {
-    c = a * b / 2.0
-}
-"#;
-
-const CUSTOM_STRING_CODE: &str = r#"
-fn custom_string_func() -> (r: i64) {
-    puts(&custom_msg)
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        passed,
+        failed
+    );
+    Ok(exit_status(failed == 0))
 }
-"#;
 
-const STRING_TEST_CODE: &str = r#"
-fn string_test() -> (r: i64) {
-    s = "Hello from JIT String Literal!\nWith Newline\tAnd Tab"
-    puts(s)
-    fmt = "Printf Test: %s %d\n"
-    world = "World"
-    num = 123
-    printf(fmt, world, num)
-    r = 0
-}
-"#;
+/// toy repl —— 逐行读入，括号配平后整段交给 Repl 求值
+fn repl(args: &ReplArgs) -> Result<ExitCode> {
+    let mut session = Repl::new(args.compile.options());
+    let mut input = String::new();
+    println!("toy REPL, :help for commands, :quit or Ctrl-D to exit");
+    loop {
+        print!("{}", if input.is_empty() { "toy> " } else { "...> " });
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            println!();
+            break;
+        }
+        input.push_str(&line);
+        if !repl::is_complete(&input) {
+            continue;
+        }
 
-const COMPLEX_TEST_CODE: &str = r#"
-    fn complex_test() -> (status: i64) {
-        c1 = 1.5 + 2.5i
-        c2 = 0.5 + 0.5i
-        c = c1 + c2
-        status = 1
+        let entry = std::mem::take(&mut input);
+        if matches!(entry.trim(), ":quit" | ":q") {
+            break;
+        }
+        match session.eval(&entry) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(e) => eprintln!("{}", e),
+        }
     }
-"#;
-
-const ARRAY_TEST_CODE: &str = r#"
-fn array_test() -> (r: i64) {
-    arr = [10, 20, 30] 
-    x = arr[2]
-    r = x
-}
-"#;
-
-const DYNAMIC_ARRAY_TEST_CODE: &str = r#"
-fn dynamic_array_test() -> (r: i64) {
-    arr = array [10, 20, 30]
-    array_push(arr, 40)
-    r = arr[3]
+    Ok(ExitCode::SUCCESS)
 }
-"#;
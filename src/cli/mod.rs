@@ -1,21 +1,161 @@
-use clap::Parser;
+use crate::pipeline::{CompileOptions, Emit, OptLevel};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(name = "toy")]
 #[command(version, about = "A toy language JIT compiler and runner", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
-    /// The .toy script file to run
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The .toy script file to run (same as `toy run FILE`)
     #[arg(value_name = "FILE")]
     pub file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compile a .toy script and call its entry function
+    Run(RunArgs),
+    /// Parse, type check and ownership check without generating code
+    Check(CheckArgs),
+    /// Compile without running and write the Cranelift IR and disassembly to files
+    #[command(alias = "emit")]
+    Build(BuildArgs),
+    /// Reformat .toy sources in place
+    Fmt(FmtArgs),
+    /// Run every `test_*` function in the given scripts
+    Test(TestArgs),
+    /// Start an interactive session
+    Repl(ReplArgs),
+}
+
+/// 所有会生成代码的子命令共用的编译选项
+#[derive(Args, Debug, Clone, Default)]
+pub struct CompileArgs {
+    /// Cranelift optimization level
+    #[arg(long, value_enum, default_value_t = OptLevel::None)]
+    pub opt_level: OptLevel,
+
+    /// Extra artifacts to print or write (comma separated)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub emit: Vec<Emit>,
+
+    /// Require every local variable to be declared with `let`
+    #[arg(long)]
+    pub strict: bool,
+}
+
+impl CompileArgs {
+    pub fn options(&self) -> CompileOptions {
+        CompileOptions {
+            opt_level: self.opt_level,
+            emit: self.emit.clone(),
+            strict: self.strict,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// The .toy script file to run
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Function to call
+    #[arg(long, value_name = "NAME", default_value = "main")]
+    pub entry: String,
+
+    /// Exit with the integer returned by the entry function
+    #[arg(long)]
+    pub exit_code: bool,
+
+    #[command(flatten)]
+    pub compile: CompileArgs,
+
+    /// Arguments for the entry function, parsed by its declared parameter types
+    #[arg(last = true, value_name = "ARGS")]
+    pub args: Vec<String>,
+}
 
-    /// Run integration tests
-    #[arg(short, long)]
-    pub test: bool,
+impl RunArgs {
+    /// 旧式调用 `toy FILE`：无参数调用 main
+    pub fn for_file(file: PathBuf) -> Self {
+        RunArgs {
+            file,
+            entry: "main".to_string(),
+            exit_code: false,
+            compile: CompileArgs::default(),
+            args: Vec::new(),
+        }
+    }
+}
+
+/// `toy check` 的诊断输出格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// rustc-style reports on stderr
+    #[default]
+    Human,
+    /// One JSON object per diagnostic on stdout
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// The .toy files to check
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// How to print diagnostics
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+
+    #[command(flatten)]
+    pub compile: CompileArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct BuildArgs {
+    /// The .toy file to compile
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Directory for the emitted artifacts
+    #[arg(short, long, value_name = "DIR", default_value = "target/toy")]
+    pub out_dir: PathBuf,
+
+    #[command(flatten)]
+    pub compile: CompileArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    /// The .toy files to format
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Report files that are not formatted instead of rewriting them
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct TestArgs {
+    /// The .toy files containing `test_*` functions
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub compile: CompileArgs,
+}
 
-    /// Set verbose level
-    #[arg(short, long, action = clap::ArgAction::Count)]
-    pub verbose: u8,
+#[derive(Args, Debug)]
+pub struct ReplArgs {
+    #[command(flatten)]
+    pub compile: CompileArgs,
 }
 
 impl Cli {
@@ -0,0 +1,368 @@
+//! 编译诊断 —— 把错误定位到源码并按 rustc 的格式渲染
+//!
+//! 解析错误、类型错误、所有权错误都转换为 `Diagnostic`，再由 `render`
+//! 输出带行号、源码片段和下划线的报告：
+//!
+//! ```text
+//! error: array 'a' used after being dropped
+//!  --> demo.toy:5:9
+//!   |
+//! 4 |     drop(a)
+//!   |     ------- array 'a' dropped here
+//! 5 |     r = a[0]
+//!   |         ^^^^ used here
+//! ```
+//!
+//! 主标注用 `^` 标出出错位置，次要标注用 `-` 标出相关位置，
+//! 附加说明显示为 `= note: ...`。
+//!
+//! 编辑器和 CI 使用 `to_json`，每条诊断输出为一行 JSON（见 `toy check --message-format json`）。
+
+use crate::frontend::Span;
+use peg::error::ParseError;
+use peg::str::LineCol;
+
+/// 源码片段中 tab 按多少列显示
+const TAB_WIDTH: usize = 4;
+
+/// 诊断的严重程度
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// 附着在一段源码上的标注
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// 一条可渲染的编译错误
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// 稳定的错误代码，形如 `ownership::leaked_array`，供工具按类别匹配
+    pub code: Option<&'static str>,
+    /// 主消息，显示在 `error:` 之后
+    pub message: String,
+    /// 主标注：出错的位置
+    pub primary: Label,
+    /// 次要标注：与错误相关的其他位置（例如数组在哪里被 drop）
+    pub secondary: Vec<Label>,
+    /// 附加说明
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span, label: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            primary: Label {
+                span,
+                message: label.into(),
+            },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: label.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// 把 peg 的解析错误转换为诊断，指向第一个无法解析的字符
+    pub fn from_parse_error(err: &ParseError<LineCol>, source: &str) -> Self {
+        let offset = err.location.offset;
+        let (found, len) = match source.get(offset..).and_then(|rest| rest.chars().next()) {
+            Some(c) => (format!("{:?}", c), c.len_utf8()),
+            None => ("end of input".to_string(), 0),
+        };
+        Diagnostic::error(
+            format!("expected {}, found {}", err.expected, found),
+            Span::new(offset, offset + len),
+            format!("expected {}", err.expected),
+        )
+        .with_code("parse::syntax_error")
+    }
+
+    /// 渲染为 rustc 风格的多行文本。`origin` 是源码的名字（通常是文件路径）
+    pub fn render(&self, origin: &str, source: &str) -> String {
+        let mut labels: Vec<(&Label, bool)> = std::iter::once((&self.primary, true))
+            .chain(self.secondary.iter().map(|l| (l, false)))
+            .collect();
+        labels.sort_by_key(|(l, _)| (l.span.start, l.span.end));
+
+        let max_line = labels
+            .iter()
+            .map(|(l, _)| line_col(source, l.span.start).0)
+            .max()
+            .unwrap_or(1);
+        let width = max_line.to_string().len();
+        let pad = " ".repeat(width);
+
+        let (line, col) = line_col(source, self.primary.span.start);
+        let mut out = format!("{}: {}\n", self.severity.as_str(), self.message);
+        out += &format!("{}--> {}:{}:{}\n", pad, origin, line, col);
+        out += &format!("{} |\n", pad);
+
+        let mut last_line = None;
+        for (label, is_primary) in labels {
+            let (line, _) = line_col(source, label.span.start);
+            if last_line != Some(line) {
+                if let Some(prev) = last_line
+                    && line > prev + 1
+                {
+                    out += "...\n";
+                }
+                out += &format!(
+                    "{:>width$} | {}\n",
+                    line,
+                    expand_tabs(line_text(source, line)),
+                    width = width
+                );
+                last_line = Some(line);
+            }
+            let (start, len) = underline(source, label.span);
+            let mark = if is_primary { "^" } else { "-" };
+            let underline_line = format!(
+                "{}{} {}",
+                " ".repeat(start),
+                mark.repeat(len),
+                label.message
+            );
+            out += &format!("{} | {}\n", pad, underline_line.trim_end());
+        }
+
+        for note in &self.notes {
+            out += &format!("{} = note: {}\n", pad, note);
+        }
+        out
+    }
+
+    /// 输出为一行 JSON：
+    ///
+    /// ```text
+    /// {"file":"demo.toy","severity":"error","code":"ownership::use_after_drop",
+    ///  "message":"...","span":{"start":41,"end":42,"line":3,"column":9,"end_line":3,"end_column":10},
+    ///  "label":"used here","related":[{"span":{...},"message":"..."}],"notes":[]}
+    /// ```
+    ///
+    /// start / end 是字节偏移；line / column 从 1 开始，列号按字符计
+    pub fn to_json(&self, origin: &str, source: &str) -> String {
+        let related = self
+            .secondary
+            .iter()
+            .map(|l| {
+                format!(
+                    "{{\"span\":{},\"message\":{}}}",
+                    span_json(source, l.span),
+                    json_string(&l.message)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let notes = self
+            .notes
+            .iter()
+            .map(|n| json_string(n))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"file\":{},\"severity\":\"{}\",\"code\":{},\"message\":{},\"span\":{},\"label\":{},\"related\":[{}],\"notes\":[{}]}}",
+            json_string(origin),
+            self.severity.as_str(),
+            self.code.map_or("null".to_string(), json_string),
+            json_string(&self.message),
+            span_json(source, self.primary.span),
+            json_string(&self.primary.message),
+            related,
+            notes
+        )
+    }
+}
+
+fn span_json(source: &str, span: Span) -> String {
+    let (line, column) = line_col(source, span.start);
+    let (end_line, end_column) = line_col(source, span.end);
+    format!(
+        "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+        span.start, span.end, line, column, end_line, end_column
+    )
+}
+
+/// 转义为带引号的 JSON 字符串
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 字节偏移 → (行号, 列号)，都从 1 开始，列号按字符计
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = floor_char_boundary(source, offset);
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let col = source[line_start..offset].chars().count() + 1;
+    (line, col)
+}
+
+/// 第 `line` 行（从 1 开始）的内容，不含换行符
+fn line_text(source: &str, line: usize) -> &str {
+    source
+        .split('\n')
+        .nth(line - 1)
+        .unwrap_or("")
+        .trim_end_matches('\r')
+}
+
+/// 计算 span 在所在行中的下划线位置：(起始显示列, 长度)。
+/// 跨行的 span 只标到行尾；空 span 标一个字符宽。
+fn underline(source: &str, span: Span) -> (usize, usize) {
+    let start = floor_char_boundary(source, span.start);
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let end = floor_char_boundary(source, span.end.clamp(start, line_end));
+
+    let col = display_width(&source[line_start..start]);
+    let len = display_width(source[start..end].trim_end());
+    (col, len.max(1))
+}
+
+fn display_width(s: &str) -> usize {
+    s.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+fn expand_tabs(s: &str) -> String {
+    s.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn floor_char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_primary_and_secondary() {
+        let source = "fn f() -> (r: i64) {\n    drop(a)\n    r = a[0]\n}\n";
+        let diag = Diagnostic::error(
+            "array 'a' used after being dropped",
+            Span::new(41, 42),
+            "used here",
+        )
+        .with_secondary(Span::new(25, 32), "array 'a' dropped here");
+        assert_eq!(
+            diag.render("demo.toy", source),
+            "error: array 'a' used after being dropped\n \
+             --> demo.toy:3:9\n  \
+             |\n\
+             2 |     drop(a)\n  \
+             |     ------- array 'a' dropped here\n\
+             3 |     r = a[0]\n  \
+             |         ^ used here\n"
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        let source = "fn f() -> (r: i64) {\n    drop(a)\n    r = a[0]\n}\n";
+        let diag = Diagnostic::error(
+            "array 'a' used after being dropped",
+            Span::new(41, 42),
+            "used here",
+        )
+        .with_code("ownership::use_after_drop")
+        .with_secondary(Span::new(25, 32), "dropped \"here\"")
+        .with_note("tab\there");
+        assert_eq!(
+            diag.to_json("dir\\demo.toy", source),
+            r#"{"file":"dir\\demo.toy","severity":"error","code":"ownership::use_after_drop","#
+                .to_string()
+                + r#""message":"array 'a' used after being dropped","#
+                + r#""span":{"start":41,"end":42,"line":3,"column":9,"end_line":3,"end_column":10},"#
+                + r#""label":"used here","#
+                + r#""related":[{"span":{"start":25,"end":32,"line":2,"column":5,"end_line":2,"end_column":12},"message":"dropped \"here\""}],"#
+                + r#""notes":["tab\there"]}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_error_points_at_unexpected_input() {
+        let source = "fn f() -> (r: i64) {\n    r = 1 +\n}\n";
+        let err = crate::frontend::parser::function(source).unwrap_err();
+        let rendered = Diagnostic::from_parse_error(&err, source).render("f.toy", source);
+        assert!(rendered.contains("--> f.toy:2:12"), "got:\n{}", rendered);
+        assert!(rendered.contains("found '\\n'"), "got:\n{}", rendered);
+        assert!(
+            rendered.contains("expected expression"),
+            "got:\n{}",
+            rendered
+        );
+
+        // 两个表达式之间缺少运算符或语句分隔符
+        let source = "fn f(a: i64) -> (r: i64) {\n    r = a a\n}\n";
+        let err = crate::frontend::parser::function(source).unwrap_err();
+        let rendered = Diagnostic::from_parse_error(&err, source).render("f.toy", source);
+        assert!(rendered.contains("--> f.toy:2:11"), "got:\n{}", rendered);
+        assert!(rendered.contains("newline"), "got:\n{}", rendered);
+        assert!(rendered.contains("operator"), "got:\n{}", rendered);
+    }
+
+    #[test]
+    fn test_line_col_counts_chars() {
+        let source = "a\n\"é\" b";
+        assert_eq!(line_col(source, 0), (1, 1));
+        assert_eq!(line_col(source, 2), (2, 1));
+        assert_eq!(line_col(source, 7), (2, 5));
+    }
+}
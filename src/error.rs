@@ -0,0 +1,193 @@
+//! 编译错误 —— `JIT` 对外返回的结构化错误类型
+//!
+//! 编译流水线的每个阶段都有对应的变体，嵌入方可以按阶段区分处理，
+//! 也可以直接用 `render` 得到 rustc 风格的诊断文本：
+//!
+//! | 阶段 | 变体 |
+//! |------|------|
+//! | 解析 | `Parse` |
+//! | 类型检查 | `Type` |
+//! | 所有权检查 | `Ownership` |
+//! | IR 翻译 / Cranelift 校验 | `Codegen` |
+//! | 符号声明 / 链接 | `Linking` |
+//!
+//! 编译之后通过名字取函数（`JIT::get_func`）或调用函数（`JIT::call`）失败时返回 `CallError`。
+
+use crate::diagnostic::{self, Diagnostic};
+use crate::frontend::Span;
+use crate::ownership::OwnershipError;
+use crate::type_checker::TypeError;
+use peg::error::ParseError;
+use peg::str::LineCol;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub enum CompileError {
+    /// 源码无法解析
+    Parse(ParseError<LineCol>),
+    /// 类型检查失败（同一函数内的所有类型错误）
+    Type(Vec<TypeError>),
+    /// 所有权检查失败（同一函数内的所有所有权错误）
+    Ownership(Vec<OwnershipError>),
+    /// 翻译为 Cranelift IR 或 IR 校验失败。
+    /// span 指向出错的表达式（如果能定位到）
+    Codegen {
+        function: String,
+        message: String,
+        span: Option<Span>,
+    },
+    /// 符号声明、重复定义或 finalize 失败
+    Linking(String),
+}
+
+impl CompileError {
+    /// 转换为带源码位置的诊断；无法定位到源码的错误返回空列表
+    pub fn diagnostics(&self, source: &str) -> Vec<Diagnostic> {
+        match self {
+            CompileError::Parse(e) => vec![Diagnostic::from_parse_error(e, source)],
+            CompileError::Type(errors) => errors.iter().map(|e| e.to_diagnostic()).collect(),
+            CompileError::Ownership(errors) => errors.iter().map(|e| e.to_diagnostic()).collect(),
+            CompileError::Codegen {
+                function,
+                message,
+                span: Some(span),
+            } => vec![
+                Diagnostic::error(
+                    message.clone(),
+                    *span,
+                    format!("while compiling '{}'", function),
+                )
+                .with_code("codegen::error"),
+            ],
+            CompileError::Codegen { span: None, .. } | CompileError::Linking(_) => Vec::new(),
+        }
+    }
+
+    /// 按 rustc 风格渲染，多条诊断之间空一行。`origin` 是源码的名字（通常是文件路径）
+    pub fn render(&self, origin: &str, source: &str) -> String {
+        let diagnostics = self.diagnostics(source);
+        if diagnostics.is_empty() {
+            return format!("error: {}", self);
+        }
+        diagnostics
+            .iter()
+            .map(|d| d.render(origin, source))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_string()
+    }
+
+    /// 每条诊断输出为一行 JSON（格式见 `Diagnostic::to_json`）。
+    /// 无法定位到源码的错误也输出一行，`span` 为 null
+    pub fn to_json(&self, origin: &str, source: &str) -> Vec<String> {
+        let diagnostics = self.diagnostics(source);
+        if !diagnostics.is_empty() {
+            return diagnostics
+                .iter()
+                .map(|d| d.to_json(origin, source))
+                .collect();
+        }
+        let (code, message) = match self {
+            CompileError::Linking(message) => ("linking::error", message.clone()),
+            _ => ("codegen::error", self.to_string()),
+        };
+        vec![format!(
+            "{{\"file\":{},\"severity\":\"error\",\"code\":\"{}\",\"message\":{},\"span\":null,\"label\":null,\"related\":[],\"notes\":[]}}",
+            diagnostic::json_string(origin),
+            code,
+            diagnostic::json_string(&message)
+        )]
+    }
+
+    /// 给尚未定位的 Codegen 错误补上表达式位置（已有位置的保持不变）
+    pub(crate) fn or_span(self, span: Span) -> Self {
+        match self {
+            CompileError::Codegen {
+                function,
+                message,
+                span: None,
+            } => CompileError::Codegen {
+                function,
+                message,
+                span: Some(span),
+            },
+            other => other,
+        }
+    }
+}
+
+fn write_all<T: fmt::Display>(f: &mut fmt::Formatter<'_>, errors: &[T]) -> fmt::Result {
+    for (i, e) in errors.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{}", e)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Parse(e) => write!(f, "parse error: {}", e),
+            CompileError::Type(errors) => write_all(f, errors),
+            CompileError::Ownership(errors) => write_all(f, errors),
+            CompileError::Codegen {
+                function, message, ..
+            } => write!(f, "codegen error in '{}': {}", function, message),
+            CompileError::Linking(message) => write!(f, "linking error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompileError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// 按名字获取或调用已编译的 toy 函数时的错误
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallError {
+    /// 没有编译过该名字的 toy 函数
+    UnknownFunction(String),
+    /// 请求的 Rust 签名与 toy 函数的签名不一致
+    SignatureMismatch {
+        function: String,
+        expected: String,
+        found: String,
+    },
+    /// 该类型的参数或返回值无法通过 `JIT::call` 动态传递
+    UnsupportedType { function: String, ty: String },
+    /// 生成调用跳板失败
+    Linking(String),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::UnknownFunction(name) => write!(f, "no compiled function named '{}'", name),
+            CallError::SignatureMismatch {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function '{}' has signature {}, but was requested as {}",
+                function, expected, found
+            ),
+            CallError::UnsupportedType { function, ty } => write!(
+                f,
+                "function '{}' uses type {}, which cannot be passed through JIT::call",
+                function, ty
+            ),
+            CallError::Linking(message) => write!(f, "linking error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
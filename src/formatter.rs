@@ -0,0 +1,828 @@
+//! 源码格式化 —— `toy fmt` 把 AST 重新打印为统一风格的 toy 源码
+//!
+//! 风格约定：
+//! - 语句块缩进 4 个空格，顶层定义之间空一行；相邻的常量与静态变量之间只保留原有的空行
+//! - 结构体每个字段、枚举每个变体占一行，最后一项后也写逗号
+//! - match 每个分支占一行，`=>` 之后是单个表达式时写在同一行并以逗号结尾
+//! - 二元运算符两侧各一个空格，逗号后一个空格
+//! - 只在优先级需要时保留括号，例如 `(a + b) * c`、`a - (b - c)`
+//! - 语句之间原有的空行保留为一个空行
+//! - 注释原样保留；`;` 分隔的多条语句拆成多行
+//!
+//! 格式化结果重新解析后与原 AST 结构相同（见下方测试）。
+
+use crate::error::CompileError;
+use crate::frontend::{
+    EnumDef, Expr, ExprKind, Function, GlobalDef, Item, MatchArm, Program, Span, StructDef, Type,
+    comment_spans, parser,
+};
+use std::cell::Cell;
+
+const INDENT: &str = "    ";
+
+/// 解析并格式化整个 .toy 文件
+pub fn format_source(input: &str) -> Result<String, CompileError> {
+    let program = parser::program(input).map_err(CompileError::Parse)?;
+    Ok(format_program(&program, input))
+}
+
+/// 把整个程序打印为源码。`source` 是解析出这些定义的源码，
+/// 用于按 span 找回语句之间的空行和注释（手工构造的 AST 传空串即可）
+pub fn format_program(program: &Program, source: &str) -> String {
+    if program.items.is_empty() {
+        // 只有注释的文件
+        let mut out = String::new();
+        Source::new(source).write_comments(&mut out, usize::MAX, 0, None);
+        return out;
+    }
+    let texts = format_items(&program.items, source);
+    let mut out = String::new();
+    for (i, text) in texts.iter().enumerate() {
+        let separate = match (
+            i.checked_sub(1).map(|j| &program.items[j]),
+            &program.items[i],
+        ) {
+            (None, _) => false,
+            (Some(Item::Global(prev)), Item::Global(def)) => {
+                blank_line_after(source, prev.span.end, def.span.start)
+            }
+            _ => true,
+        };
+        if separate {
+            out.push('\n');
+        }
+        out += text;
+    }
+    out
+}
+
+/// `end` 所在行（含行尾注释）之后是否紧跟着空行，`start` 之前的部分才算
+fn blank_line_after(source: &str, end: usize, start: usize) -> bool {
+    source
+        .get(end..start)
+        .and_then(|gap| gap.split_once('\n'))
+        .is_some_and(|(_, rest)| rest.trim_start_matches([' ', '\t', '\r']).starts_with('\n'))
+}
+
+/// 与 `format_program` 相同，但每个顶层定义单独返回一段文本。
+/// 定义之前的注释归入该定义，最后一个定义之后的注释归入最后一个定义
+pub fn format_items(items: &[Item], source: &str) -> Vec<String> {
+    let source = Source::new(source);
+    let mut texts: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            Item::Struct(def) => format_struct(def, &source),
+            Item::Enum(def) => format_enum(def, &source),
+            Item::Global(def) => format_global(def, &source),
+            Item::Function(f) => format_function(f, &source),
+        })
+        .collect();
+    if let Some(last) = texts.last_mut() {
+        source.write_comments(last, usize::MAX, 0, Some(source.cursor.get()));
+    }
+    texts
+}
+
+fn format_struct(def: &StructDef, source: &Source) -> String {
+    let fields = def
+        .fields
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty));
+    format_declaration(&format!("struct {}", def.name), fields, def.span, source)
+}
+
+fn format_enum(def: &EnumDef, source: &Source) -> String {
+    let variants = def.variants.iter().map(|(name, payload)| {
+        if payload.is_empty() {
+            name.clone()
+        } else {
+            let tys: Vec<String> = payload.iter().map(|ty| ty.to_string()).collect();
+            format!("{}({})", name, tys.join(", "))
+        }
+    });
+    format_declaration(&format!("enum {}", def.name), variants, def.span, source)
+}
+
+/// 常量与静态变量：`const N: i64 = 64` 占一行，之前的注释各占一行，行尾注释留在行尾
+fn format_global(def: &GlobalDef, source: &Source) -> String {
+    let mut out = String::new();
+    if let Some(end) = source.write_comments(&mut out, def.span.start, 0, None)
+        && source.has_blank_line(end, def.span.start)
+    {
+        out.push('\n');
+    }
+    out += &format!(
+        "{} {}: {} = {}",
+        def.kind.keyword(),
+        def.name,
+        def.ty,
+        format_expr(&def.value, 0, source)
+    );
+    let mut end = def.span.end;
+    if let Some(comment) = source.trailing_comment(end) {
+        out.push(' ');
+        out.push_str(source.take(comment));
+        end = comment.end;
+    }
+    out.push('\n');
+    source.cursor.set(end);
+    out
+}
+
+/// 结构体与枚举声明：`head {` 之后每项一行。
+/// 声明中间的注释（字段和变体没有 span）统一写在 `}` 之前
+fn format_declaration(
+    head: &str,
+    members: impl Iterator<Item = String>,
+    span: Span,
+    source: &Source,
+) -> String {
+    let start = source.skip_trivia(source.cursor.get());
+    let open = source.text[start..].find('{').map_or(start, |n| start + n);
+    let mut out = String::new();
+    if let Some(end) = source.write_comments(&mut out, open, 0, None)
+        && source.has_blank_line(end, start)
+    {
+        out.push('\n');
+    }
+    out += &format!("{} {{\n", head);
+    for member in members {
+        out += &format!("{}{},\n", INDENT, member);
+    }
+    source.write_comments(&mut out, span.end, 1, None);
+    out.push_str("}\n");
+    source.cursor.set(span.end);
+    out
+}
+
+fn format_function((name, params, the_return, stmts): &Function, source: &Source) -> String {
+    // 函数之前和函数头中的注释写在函数头之前
+    let start = source.skip_trivia(source.cursor.get());
+    let open = source.text[start..].find('{').map_or(start, |n| start + n);
+    let mut out = String::new();
+    if let Some(end) = source.write_comments(&mut out, open, 0, None)
+        && source.has_blank_line(end, start)
+    {
+        out.push('\n');
+    }
+
+    let params = params
+        .iter()
+        .map(|(n, ty)| format!("{}: {}", n, ty))
+        .collect::<Vec<_>>()
+        .join(", ");
+    out += &format!(
+        "fn {}({}) -> ({}: {}) {{\n",
+        name, params, the_return.0, the_return.1
+    );
+    write_stmts(&mut out, stmts, 1, source);
+    // 空函数体中的注释
+    let close = source.skip_trivia(stmts.last().map_or(open + 1, |s| s.span.end));
+    source.write_comments(&mut out, close, 1, None);
+    out.push_str("}\n");
+    source.cursor.set(close + 1);
+    out
+}
+
+fn write_stmts(out: &mut String, stmts: &[Expr], depth: usize, source: &Source) {
+    let mut prev_end = None;
+    for stmt in stmts {
+        prev_end = source.write_comments(out, stmt.span.start, depth, prev_end);
+        if prev_end.is_some_and(|end| source.has_blank_line(end, stmt.span.start)) {
+            out.push('\n');
+        }
+        out.push_str(&INDENT.repeat(depth));
+        out.push_str(&format_expr(stmt, depth, source));
+        prev_end = Some(stmt.span.end);
+        if let Some(comment) = source.trailing_comment(stmt.span.end) {
+            out.push(' ');
+            out.push_str(source.take(comment));
+            prev_end = Some(comment.end);
+        }
+        out.push('\n');
+    }
+    // 最后一条语句之后、`}` 之前的注释
+    if let Some(end) = prev_end {
+        source.write_comments(out, source.skip_trivia(end), depth, prev_end);
+    }
+}
+
+/// `{ ... }` 语句块：左括号跟在前面的内容后，右括号按 depth 缩进
+fn format_block(stmts: &[Expr], depth: usize, source: &Source) -> String {
+    let mut out = String::from("{\n");
+    write_stmts(&mut out, stmts, depth + 1, source);
+    out.push_str(&INDENT.repeat(depth));
+    out.push('}');
+    out
+}
+
+/// `match x { ... }`：每个分支一行，分支之前的注释各占一行。
+/// 只有一个非语句表达式的分支写成 `pattern => value,`，其余写成块
+fn format_match(scrutinee: &Expr, arms: &[MatchArm], depth: usize, source: &Source) -> String {
+    let mut out = format!("match {} {{\n", format_operand(scrutinee, 1, depth, source));
+    let mut prev_end = None;
+    for arm in arms {
+        prev_end = source.write_comments(&mut out, arm.span.start, depth + 1, prev_end);
+        if prev_end.is_some_and(|end| source.has_blank_line(end, arm.span.start)) {
+            out.push('\n');
+        }
+        out.push_str(&INDENT.repeat(depth + 1));
+        match arm.body.as_slice() {
+            [value] if precedence(&value.kind) > 0 => {
+                out += &format!(
+                    "{} => {},",
+                    arm.pattern,
+                    format_expr(value, depth + 1, source)
+                );
+                prev_end = Some(value.span.end);
+            }
+            body => {
+                out += &format!(
+                    "{} => {}",
+                    arm.pattern,
+                    format_block(body, depth + 1, source)
+                );
+                // 分支在块的 `}` 处结束
+                let open = source
+                    .text
+                    .get(arm.span.end..)
+                    .and_then(|rest| rest.find('{'));
+                let inner = match body.last() {
+                    Some(stmt) => Some(stmt.span.end),
+                    None => open.map(|n| arm.span.end + n + 1),
+                };
+                prev_end = inner.map(|end| source.skip_trivia(end) + 1).or(prev_end);
+            }
+        }
+        if let Some(end) = prev_end
+            && let Some(comment) = source.trailing_comment(end)
+        {
+            out.push(' ');
+            out.push_str(source.take(comment));
+            prev_end = Some(comment.end);
+        }
+        out.push('\n');
+    }
+    if let Some(end) = prev_end {
+        source.write_comments(&mut out, source.skip_trivia(end), depth + 1, prev_end);
+    }
+    out.push_str(&INDENT.repeat(depth));
+    out.push('}');
+    out
+}
+
+/// 数字字面量保留源码中的写法（`0xFF`、`1_000`、`1e-9`）；没有对应源码时
+/// 打印 parser 规范化后的十进制文本，类型不是默认的 i64 / f64 时加上后缀
+fn format_literal(expr: &Expr, text: &str, ty: &Type, source: &Source) -> String {
+    if let Some(original) = source.original(expr.span)
+        && original.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return original.to_string();
+    }
+    let default = if text.contains(['.', 'e', 'E']) {
+        Type::F64
+    } else {
+        Type::I64
+    };
+    if *ty == Type::Bool || *ty == default {
+        text.to_string()
+    } else {
+        format!("{}{}", text, ty)
+    }
+}
+
+/// 被格式化的源码。AST 不保留注释，按源码位置把注释放回输出：
+/// 注释按出现顺序输出，语句之后同一行的注释留在行尾，其余注释各占一行，
+/// 写在它之后的第一条语句（或块的 `}`）之前。表达式内部的注释因此会移到下一行，但不会丢失
+struct Source<'a> {
+    text: &'a str,
+    comments: Vec<Span>,
+    /// 下一条还没有输出的注释
+    next_comment: Cell<usize>,
+    /// 上一个已格式化的函数在源码中的结束位置
+    cursor: Cell<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(text: &'a str) -> Self {
+        Source {
+            text,
+            comments: comment_spans(text),
+            next_comment: Cell::new(0),
+            cursor: Cell::new(0),
+        }
+    }
+
+    /// 表达式在源码中的原文，用于保留字面量的写法。手工构造的 AST 没有原文；
+    /// 括号中的表达式的 span 包含括号，由调用方按开头字符判断
+    fn original(&self, span: Span) -> Option<&'a str> {
+        self.text
+            .get(span.start..span.end)
+            .filter(|text| !text.is_empty())
+    }
+
+    /// 两个位置之间的源码里是否有空行
+    fn has_blank_line(&self, end: usize, start: usize) -> bool {
+        self.text
+            .get(end..start)
+            .is_some_and(|gap| gap.matches('\n').count() >= 2)
+    }
+
+    /// 从 `pos` 起跳过空白和注释，返回下一个记号的位置
+    fn skip_trivia(&self, mut pos: usize) -> usize {
+        while let Some(&b) = self.text.as_bytes().get(pos) {
+            if b.is_ascii_whitespace() {
+                pos += 1;
+            } else if let Some(c) = self.comments.iter().find(|c| c.start == pos) {
+                pos = c.end;
+            } else {
+                break;
+            }
+        }
+        pos
+    }
+
+    /// 下一条还没有输出、且开始于 `pos` 之前的注释
+    fn pending_before(&self, pos: usize) -> Option<Span> {
+        self.comments
+            .get(self.next_comment.get())
+            .filter(|c| c.start < pos)
+            .copied()
+    }
+
+    /// 紧跟在 `end` 之后、与它在同一行且中间没有其他语句的注释
+    /// （中间可以有 `;` 或 match 分支后的 `,`）
+    fn trailing_comment(&self, end: usize) -> Option<Span> {
+        let c = *self.comments.get(self.next_comment.get())?;
+        let gap = self.text.get(end..c.start)?;
+        gap.chars()
+            .all(|ch| matches!(ch, ' ' | '\t' | '\r' | ';' | ','))
+            .then_some(c)
+    }
+
+    /// 标记注释已输出，返回它的原文
+    fn take(&self, comment: Span) -> &'a str {
+        self.next_comment.set(self.next_comment.get() + 1);
+        &self.text[comment.start..comment.end]
+    }
+
+    /// 把开始于 `pos` 之前、还没有输出的注释各占一行写出。`prev_end` 是同一层
+    /// 上一项的结束位置，用于保留空行；返回最后写出的一项的结束位置
+    fn write_comments(
+        &self,
+        out: &mut String,
+        pos: usize,
+        depth: usize,
+        mut prev_end: Option<usize>,
+    ) -> Option<usize> {
+        while let Some(comment) = self.pending_before(pos) {
+            if prev_end.is_some_and(|end| self.has_blank_line(end, comment.start)) {
+                out.push('\n');
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(self.take(comment));
+            out.push('\n');
+            prev_end = Some(comment.end);
+        }
+        prev_end
+    }
+}
+
+/// 运算符优先级，与 frontend 中 precedence! 的分层一致（数字越大结合越紧）
+fn precedence(kind: &ExprKind) -> u8 {
+    match kind {
+        ExprKind::Or(..) => 1,
+        ExprKind::And(..) => 2,
+        ExprKind::Eq(..)
+        | ExprKind::Ne(..)
+        | ExprKind::Lt(..)
+        | ExprKind::Le(..)
+        | ExprKind::Gt(..)
+        | ExprKind::Ge(..) => 3,
+        ExprKind::BitOr(..) => 4,
+        ExprKind::BitXor(..) => 5,
+        ExprKind::BitAnd(..) => 6,
+        ExprKind::Shl(..) | ExprKind::Shr(..) => 7,
+        ExprKind::Add(..) | ExprKind::Sub(..) => 8,
+        ExprKind::Mul(..) | ExprKind::Div(..) | ExprKind::Rem(..) => 9,
+        ExprKind::Cast(..) => 10,
+        ExprKind::Not(_) | ExprKind::Neg(_) => 11,
+        // 语句类表达式不在 precedence! 内，作为操作数时必须加括号
+        ExprKind::Assign(..)
+        | ExprKind::Let(..)
+        | ExprKind::CompoundAssign(..)
+        | ExprKind::IndexAssign(..)
+        | ExprKind::FieldAssign(..)
+        | ExprKind::IfElse(..)
+        | ExprKind::Match(..)
+        | ExprKind::WhileLoop(..)
+        | ExprKind::ForRange(..)
+        | ExprKind::ForEach(..)
+        | ExprKind::Block(_)
+        | ExprKind::Drop(_)
+        | ExprKind::Return(_)
+        | ExprKind::Break
+        | ExprKind::Continue => 0,
+        _ => 12,
+    }
+}
+
+/// 打印作为操作数的子表达式：优先级不低于 `min` 时不加括号
+fn format_operand(expr: &Expr, min: u8, depth: usize, source: &Source) -> String {
+    let text = format_expr(expr, depth, source);
+    if precedence(&expr.kind) >= min {
+        text
+    } else {
+        format!("({})", text)
+    }
+}
+
+/// 左结合的二元运算：右操作数与自身同级时需要括号
+fn format_binary(op: &str, a: &Expr, b: &Expr, level: u8, depth: usize, source: &Source) -> String {
+    format!(
+        "{} {} {}",
+        format_operand(a, level, depth, source),
+        op,
+        format_operand(b, level + 1, depth, source)
+    )
+}
+
+fn format_list(exprs: &[Expr], depth: usize, source: &Source) -> String {
+    exprs
+        .iter()
+        .map(|e| format_expr(e, depth, source))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_expr(expr: &Expr, depth: usize, source: &Source) -> String {
+    let kind = &expr.kind;
+    let level = precedence(kind);
+    let list = |exprs: &[Expr]| format_list(exprs, depth, source);
+    let sub = |e: &Expr| format_expr(e, depth, source);
+    let operand = |e: &Expr, min: u8| format_operand(e, min, depth, source);
+    let binary = |op: &str, a: &Expr, b: &Expr| format_binary(op, a, b, level, depth, source);
+    let block = |stmts: &[Expr]| format_block(stmts, depth, source);
+    match kind {
+        ExprKind::Literal(text, ty) => format_literal(expr, text, ty, source),
+        // 字符串保留源码中的写法（转义方式、原始字符串）
+        ExprKind::StringLiteral(s) => match source.original(expr.span) {
+            Some(original) if original.starts_with(['"', 'r']) => original.to_string(),
+            _ => format!("\"{}\"", escape(s)),
+        },
+        ExprKind::ComplexLiteral(re, im, _) if *re == 0.0 && !im.is_sign_negative() => {
+            format!("{:?}i", im)
+        }
+        ExprKind::ComplexLiteral(re, im, _) if im.is_sign_negative() => {
+            format!("{:?} - {:?}i", re, -im)
+        }
+        ExprKind::ComplexLiteral(re, im, _) => format!("{:?} + {:?}i", re, im),
+        ExprKind::ArrayLiteral(elems, _) => format!("[{}]", list(elems)),
+        ExprKind::DynamicArrayLiteral(elems, _) => format!("array [{}]", list(elems)),
+        ExprKind::Identifier(name) => name.clone(),
+        ExprKind::GlobalDataAddr(name) => format!("&{}", name),
+        ExprKind::Assign(name, value) => format!("{} = {}", name, sub(value)),
+        ExprKind::Let(name, mutable, ty, value) => format!(
+            "let {}{}{} = {}",
+            if *mutable { "mut " } else { "" },
+            name,
+            ty.as_ref().map(|t| format!(": {}", t)).unwrap_or_default(),
+            sub(value)
+        ),
+        ExprKind::CompoundAssign(name, op, value) => {
+            format!("{} {} {}", name, op.symbol(), sub(value))
+        }
+        ExprKind::IndexAssign(base, idx, op, value) => format!(
+            "{}[{}] {} {}",
+            operand(base, 12),
+            sub(idx),
+            op.map_or("=", |op| op.symbol()),
+            sub(value)
+        ),
+        ExprKind::FieldAssign(base, field, op, value) => format!(
+            "{}.{} {} {}",
+            operand(base, 12),
+            field,
+            op.map_or("=", |op| op.symbol()),
+            sub(value)
+        ),
+        ExprKind::StructLiteral(name, fields) => format!(
+            "{} {{ {} }}",
+            name,
+            fields
+                .iter()
+                .map(|(f, e)| format!("{}: {}", f, sub(e)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ExprKind::EnumLiteral(name, variant, args) if args.is_empty() => {
+            format!("{}::{}", name, variant)
+        }
+        ExprKind::EnumLiteral(name, variant, args) => {
+            format!("{}::{}({})", name, variant, list(args))
+        }
+        ExprKind::Eq(a, b) => binary("==", a, b),
+        ExprKind::Ne(a, b) => binary("!=", a, b),
+        ExprKind::Lt(a, b) => binary("<", a, b),
+        ExprKind::Le(a, b) => binary("<=", a, b),
+        ExprKind::Gt(a, b) => binary(">", a, b),
+        ExprKind::Ge(a, b) => binary(">=", a, b),
+        ExprKind::And(a, b) => binary("&&", a, b),
+        ExprKind::Or(a, b) => binary("||", a, b),
+        ExprKind::Not(e) => format!("!{}", operand(e, level)),
+        ExprKind::Neg(e) => format!("-{}", operand(e, level)),
+        ExprKind::Add(a, b) => binary("+", a, b),
+        ExprKind::Sub(a, b) => binary("-", a, b),
+        ExprKind::Mul(a, b) => binary("*", a, b),
+        ExprKind::Div(a, b) => binary("/", a, b),
+        ExprKind::Rem(a, b) => binary("%", a, b),
+        ExprKind::BitAnd(a, b) => binary("&", a, b),
+        ExprKind::BitOr(a, b) => binary("|", a, b),
+        ExprKind::BitXor(a, b) => binary("^", a, b),
+        ExprKind::Shl(a, b) => binary("<<", a, b),
+        ExprKind::Shr(a, b) => binary(">>", a, b),
+        ExprKind::Cast(e, ty) => format!("{} as {}", operand(e, level), ty),
+        ExprKind::Index(a, idx) => format!("{}[{}]", operand(a, level), sub(idx)),
+        ExprKind::Field(a, field) => format!("{}.{}", operand(a, level), field),
+        ExprKind::Call(name, args) => format!("{}({})", name, list(args)),
+        ExprKind::Drop(name) => format!("drop({})", name),
+        ExprKind::IfElse(cond, then_body, else_body) => {
+            let head = format!("if {} {}", sub(cond), block(then_body));
+            match else_body.as_slice() {
+                [] => head,
+                [
+                    chained @ Expr {
+                        kind: ExprKind::IfElse(..),
+                        ..
+                    },
+                ] => format!("{} else {}", head, sub(chained)),
+                _ => format!("{} else {}", head, block(else_body)),
+            }
+        }
+        ExprKind::Match(scrutinee, arms) => format_match(scrutinee, arms, depth, source),
+        ExprKind::WhileLoop(cond, body) => format!("while {} {}", sub(cond), block(body)),
+        ExprKind::ForRange(var, start, end, inclusive, step, body) => format!(
+            "for {} in {}..{}{}{} {}",
+            var,
+            operand(start, 1),
+            if *inclusive { "=" } else { "" },
+            operand(end, 1),
+            step.as_ref()
+                .map(|s| format!(" step {}", operand(s, 1)))
+                .unwrap_or_default(),
+            block(body)
+        ),
+        ExprKind::ForEach(var, iterable, body) => {
+            format!("for {} in {} {}", var, operand(iterable, 1), block(body))
+        }
+        ExprKind::Block(body) => block(body),
+        ExprKind::Return(value) => format!("return {}", sub(value)),
+        ExprKind::Break => "break".to_string(),
+        ExprKind::Continue => "continue".to_string(),
+    }
+}
+
+/// 与 frontend 的 escape_sequence() 对应
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(code: &str) -> String {
+        let formatted = format_source(code).unwrap();
+        assert_eq!(
+            parser::program(&formatted).unwrap(),
+            parser::program(code).unwrap(),
+            "formatting changed the AST:\n{}",
+            formatted
+        );
+        // 幂等：再格式化一次结果不变
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        formatted
+    }
+
+    #[test]
+    fn test_format_normalizes_layout() {
+        let code =
+            "fn f(a:i64,b:  [f64; 3]) -> (r:i64) {\n  x = (a+1)*  2\n\n      r=x - (a - 1)\n}\n";
+        assert_eq!(
+            roundtrip(code),
+            "fn f(a: i64, b: [f64; 3]) -> (r: i64) {\n    x = (a + 1) * 2\n\n    r = x - (a - 1)\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_nested_blocks() {
+        let code = r#"
+fn f(n: i64) -> (r: i64) {
+r = if n == 0 {
+0
+} else {
+1
+}
+while n > 0 {
+{
+s = "a\tb\n"
+drop(arr)
+}
+if n == 5 {
+continue
+} else {
+break
+}
+n = n - 1
+}
+for i in 0..n+1 {
+s = i
+}
+for i in n..=0 step 0-2 {
+for x   in  [1, 2] {
+s = x
+}
+}
+c = 1.5 + 2.0i
+
+
+arr = array [1, 2]
+x = arr[0] as f64
+ok = !(n>0&&n<9)||!done as i64==1
+m = -n%3+(n&6|n^1<<2)>>1 - - n
+arr[n-1]+=x*2
+arr[0]=arr[1]
+m/=2
+}
+
+fn g() -> (r: i64) {
+r = f(1)
+if r > 1 {
+r = 1
+}   else   {
+if r > 0 {
+r = 2
+}
+}
+return   r+1
+}
+"#;
+        let formatted = roundtrip(code);
+        assert!(
+            formatted.contains("    r = if n == 0 {\n        0\n    } else {\n        1\n    }\n")
+        );
+        assert!(formatted.contains("        {\n            s = \"a\\tb\\n\"\n"));
+        assert!(formatted.contains("}\n\nfn g() -> (r: i64) {\n"));
+        assert!(formatted.contains("    return r + 1\n"));
+        // 只含一个 if 的 else 块写成 else if，空的 else 省略
+        assert!(formatted.contains(
+            "    if r > 1 {\n        r = 1\n    } else if r > 0 {\n        r = 2\n    }\n"
+        ));
+        assert!(formatted.contains("    for i in 0..n + 1 {\n"));
+        assert!(formatted.contains("    for i in n..=0 step 0 - 2 {\n        for x in [1, 2] {\n"));
+        assert!(formatted.contains("    ok = !(n > 0 && n < 9) || !done as i64 == 1\n"));
+        assert!(formatted.contains("    m = -n % 3 + (n & 6 | n ^ 1 << 2) >> 1 - -n\n"));
+        assert!(formatted.contains("    arr[n - 1] += x * 2\n    arr[0] = arr[1]\n    m /= 2\n"));
+        // 原有的空行保留为一个
+        assert!(formatted.contains("    c = 1.5 + 2.0i\n\n    arr = array [1, 2]\n"));
+    }
+
+    #[test]
+    fn test_format_numeric_literals() {
+        // 复数字面量按数值重新打印
+        let code = "fn f() -> (r: i64) {\n    a = 0xFF_FF+0b1010i8*1_000\n    b = 2.5e-3f32+1e9\n    c = 1.0-2.0i\n    d = 1.5E+2 + 0.5i\n    r = -128i8 as i64\n}\n";
+        assert_eq!(
+            roundtrip(code),
+            "fn f() -> (r: i64) {\n    a = 0xFF_FF + 0b1010i8 * 1_000\n    b = 2.5e-3f32 + 1e9\n    c = 1.0 - 2.0i\n    d = 150.0 + 0.5i\n    r = -128i8 as i64\n}\n"
+        );
+
+        // 没有源码时打印规范化的文本，非默认类型加后缀
+        let stmts = parser::program(code).unwrap().split().2.remove(0).3;
+        let function = ("f".to_string(), vec![], ("r".to_string(), Type::I64), stmts);
+        let program = Program {
+            items: vec![Item::Function(function)],
+        };
+        let formatted = format_program(&program, "");
+        assert!(
+            formatted.contains("    a = 65535 + 10i8 * 1000\n"),
+            "{}",
+            formatted
+        );
+        assert!(
+            formatted.contains("    b = 2.5e-3f32 + 1e9\n"),
+            "{}",
+            formatted
+        );
+        assert_eq!(
+            parser::program(&formatted).unwrap(),
+            parser::program(code).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_string_literals() {
+        // 字符串保留原来的写法
+        let code = "fn f() -> (r: i64) {\n    a = \"héllo\\t\\u{1F600}\\x41\\0\"\n    b = r\"C:\\dir\"\n    c = r#\"say \"hi\" // x\"#   // y\n    r = str_len(a)\n}\n";
+        let formatted = roundtrip(code);
+        assert!(
+            formatted.contains("    a = \"héllo\\t\\u{1F600}\\x41\\0\"\n"),
+            "{}",
+            formatted
+        );
+        assert!(
+            formatted.contains("    b = r\"C:\\dir\"\n"),
+            "{}",
+            formatted
+        );
+        assert!(
+            formatted.contains("    c = r#\"say \"hi\" // x\"# // y\n"),
+            "{}",
+            formatted
+        );
+
+        // 没有源码时重新转义
+        let stmts = parser::program(code).unwrap().split().2.remove(0).3;
+        let function = ("f".to_string(), vec![], ("r".to_string(), Type::I64), stmts);
+        let program = Program {
+            items: vec![Item::Function(function)],
+        };
+        let formatted = format_program(&program, "");
+        assert!(
+            formatted.contains("    a = \"héllo\\t😀A\\0\"\n"),
+            "{}",
+            formatted
+        );
+        assert!(
+            formatted.contains("    c = \"say \\\"hi\\\" // x\"\n"),
+            "{}",
+            formatted
+        );
+        assert_eq!(
+            parser::program(&formatted).unwrap(),
+            parser::program(code).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let code = "// header\r\n\r\n/* doc */\r\nfn f(x: i64) -> (r: i64) {\r\n  // leading\r\n  r = x; r = r * 2 // trailing\r\n\r\n  if r > 0 { r = 1 } /* after if */\r\n  // before close\r\n}\r\nfn g() -> (r: i64) {\r\n  /* empty */\r\n}\r\n// tail";
+        assert_eq!(
+            roundtrip(code),
+            "// header\n\n/* doc */\nfn f(x: i64) -> (r: i64) {\n    // leading\n    r = x\n    r = r * 2 // trailing\n\n    if r > 0 {\n        r = 1\n    } /* after if */\n    // before close\n}\n\nfn g() -> (r: i64) {\n    /* empty */\n}\n// tail\n"
+        );
+        // 字符串中的 // 不是注释
+        assert_eq!(
+            roundtrip("fn f() -> (r: i64) {\n    s = \"a // b\" // c\n}\n"),
+            "fn f() -> (r: i64) {\n    s = \"a // b\" // c\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_structs() {
+        let code = "// point\nstruct Point {x:f64,y:f64}\nstruct Line {\n  a: Point, // start\n  b: Point\n}\nfn f(l: Line) -> (p: Point) {\n  p = Point{x:l.a.x+1.0,y:l.b.y}\n  l.a.x+=p.x\n  pts = [p, p]\n  pts[0].y = (l.a).x\n}\n";
+        assert_eq!(
+            roundtrip(code),
+            "// point\nstruct Point {\n    x: f64,\n    y: f64,\n}\n\nstruct Line {\n    a: Point,\n    b: Point,\n    // start\n}\n\nfn f(l: Line) -> (p: Point) {\n    p = Point { x: l.a.x + 1.0, y: l.b.y }\n    l.a.x += p.x\n    pts = [p, p]\n    pts[0].y = l.a.x\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_enums_and_match() {
+        let code = "enum Shape {Circle(f64),Rect(f64,f64),\n  Empty}\nfn f(s: Shape, n: i64) -> (r: f64) {\n  r = match s {\n    // round\n    Shape::Circle(x)=>x*2.0, // trailing\n    Shape::Rect(w,_) => { w }\n\n    _=>{r = 1.0\n    r}\n  }\n  match n { 0|1=>print_i64(n), 2..=9 => {} , m=>print_i64(-m)}\n  s = Shape::Empty\n}\n";
+        assert_eq!(
+            roundtrip(code),
+            "enum Shape {\n    Circle(f64),\n    Rect(f64, f64),\n    Empty,\n}\n\nfn f(s: Shape, n: i64) -> (r: f64) {\n    r = match s {\n        // round\n        Shape::Circle(x) => x * 2.0, // trailing\n        Shape::Rect(w, _) => w,\n\n        _ => {\n            r = 1.0\n            r\n        }\n    }\n    match n {\n        0 | 1 => print_i64(n),\n        2..=9 => {\n        }\n        m => print_i64(-m),\n    }\n    s = Shape::Empty\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_let() {
+        let code = "fn f() -> (r: i64) {\n  let x=1\n  let   mut y:i32 = x as i32\n  {let x = 2.5\n  y+=1}\n  r = x\n}\n";
+        assert_eq!(
+            roundtrip(code),
+            "fn f() -> (r: i64) {\n    let x = 1\n    let mut y: i32 = x as i32\n    {\n        let x = 2.5\n        y += 1\n    }\n    r = x\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_globals() {
+        let code = "// 尺寸\nconst  N:i64=8*8\nstatic mut count :i64 = -1 // 计数\n\nstatic  ON: bool=true\nfn f() -> (r: i64) {\n  count+=1\n  r = N\n}\n";
+        assert_eq!(
+            roundtrip(code),
+            "// 尺寸\nconst N: i64 = 8 * 8\nstatic mut count: i64 = -1 // 计数\n\nstatic ON: bool = true\n\nfn f() -> (r: i64) {\n    count += 1\n    r = N\n}\n"
+        );
+    }
+}
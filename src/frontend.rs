@@ -1,6 +1,46 @@
-/// AST 节点 — 表达式
+/// 源码中的字节区间 `[start, end)`，由 parser 在构造 AST 节点时记录
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// AST 节点 — 表达式（语句也是表达式），附带它在源码中的位置
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+/// 比较 AST 时只看结构，不看位置
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+/// 由编译器合成、没有对应源码的节点（例如测试中手工构造的 AST），位置为空区间
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Expr::new(kind, Span::default())
+    }
+}
+
+/// 表达式的种类
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
+pub enum ExprKind {
     Literal(String, Type),                   // 字面量 "42", "3.14" + 类型
     StringLiteral(String),                   // "hello" 字符串
     ComplexLiteral(f64, f64, Type),          // 1.5 + 2.5i (实部, 虚部, 类型)
@@ -14,22 +54,313 @@ pub enum Expr {
     Le(Box<Expr>, Box<Expr>),                // <=
     Gt(Box<Expr>, Box<Expr>),                // >
     Ge(Box<Expr>, Box<Expr>),                // >=
+    And(Box<Expr>, Box<Expr>),               // && 短路与
+    Or(Box<Expr>, Box<Expr>),                // || 短路或
+    Not(Box<Expr>),                          // ! 逻辑非
     Add(Box<Expr>, Box<Expr>),               // +
     Sub(Box<Expr>, Box<Expr>),               // -
     Mul(Box<Expr>, Box<Expr>),               // *
     Div(Box<Expr>, Box<Expr>),               // /
+    Rem(Box<Expr>, Box<Expr>),               // % 取余（浮点为 fmod）
+    BitAnd(Box<Expr>, Box<Expr>),            // &
+    BitOr(Box<Expr>, Box<Expr>),             // |
+    BitXor(Box<Expr>, Box<Expr>),            // ^
+    Shl(Box<Expr>, Box<Expr>),               // <<
+    Shr(Box<Expr>, Box<Expr>),               // >> 算术右移
+    Neg(Box<Expr>),                          // -x 取负
     IfElse(Box<Expr>, Vec<Expr>, Vec<Expr>), // if-else
     WhileLoop(Box<Expr>, Vec<Expr>),         // while 循环
-    Call(String, Vec<Expr>),                 // 函数调用
-    Index(Box<Expr>, Box<Expr>),             // arr[idx] 索引
-    GlobalDataAddr(String),                  // &name 全局数据地址
-    Cast(Box<Expr>, Type),                   // expr as Type
-    Drop(String),                            // drop(var) 显式释放
-    Block(Vec<Expr>),                        // 块作用域 { stmts }
+    // let mut x: T = expr (变量名, 是否可变, 类型标注, 初值)
+    Let(String, bool, Option<Type>, Box<Expr>),
+    // x += expr (变量名, 复合运算符, 右值)
+    CompoundAssign(String, AssignOp, Box<Expr>),
+    // arr[i] = expr / arr[i] += expr (数组, 索引, 复合运算符, 右值)
+    IndexAssign(Box<Expr>, Box<Expr>, Option<AssignOp>, Box<Expr>),
+    // p.x = expr / p.x += expr (结构体, 字段名, 复合运算符, 右值)
+    FieldAssign(Box<Expr>, String, Option<AssignOp>, Box<Expr>),
+    // for i in a..b / a..=b step s { } (变量, 起点, 终点, 是否包含终点, 步长, 循环体)
+    ForRange(
+        String,
+        Box<Expr>,
+        Box<Expr>,
+        bool,
+        Option<Box<Expr>>,
+        Vec<Expr>,
+    ),
+    ForEach(String, Box<Expr>, Vec<Expr>), // for x in arr { } 遍历 [T; N] / array<T>
+    Call(String, Vec<Expr>),               // 函数调用
+    Index(Box<Expr>, Box<Expr>),           // arr[idx] 索引
+    Field(Box<Expr>, String),              // p.x 字段访问
+    StructLiteral(String, Vec<(String, Expr)>), // Point { x: 1.0, y: 2.0 } 结构体字面量
+    EnumLiteral(String, String, Vec<Expr>), // Shape::Rect(1.0, 2.0) 枚举值 (枚举名, 变体名, 载荷)
+    Match(Box<Expr>, Vec<MatchArm>),       // match x { pattern => value, ... }
+    GlobalDataAddr(String),                // &name 全局数据地址
+    Cast(Box<Expr>, Type),                 // expr as Type
+    Drop(String),                          // drop(var) / drop(p.items) 显式释放
+    Block(Vec<Expr>),                      // 块作用域 { stmts }
+    Return(Box<Expr>),                     // return expr 提前返回
+    Break,                                 // break 跳出最内层循环
+    Continue,                              // continue 进入最内层循环的下一次迭代
+}
+
+/// 复合赋值运算符：`x op= e` 与 `x = x op e` 语义相同，
+/// 但索引赋值 `arr[i] op= e` 中的索引只求值一次
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignOp {
+    Add, // +=
+    Sub, // -=
+    Mul, // *=
+    Div, // /=
+}
+
+impl AssignOp {
+    /// 源码中的写法，例如 "+="
+    pub fn symbol(self) -> &'static str {
+        match self {
+            AssignOp::Add => "+=",
+            AssignOp::Sub => "-=",
+            AssignOp::Mul => "*=",
+            AssignOp::Div => "/=",
+        }
+    }
+
+    /// 构造对应的二元表达式 `lhs op rhs`
+    pub fn apply(self, lhs: Expr, rhs: Expr) -> ExprKind {
+        let (l, r) = (Box::new(lhs), Box::new(rhs));
+        match self {
+            AssignOp::Add => ExprKind::Add(l, r),
+            AssignOp::Sub => ExprKind::Sub(l, r),
+            AssignOp::Mul => ExprKind::Mul(l, r),
+            AssignOp::Div => ExprKind::Div(l, r),
+        }
+    }
+}
+
+/// `match` 的一个分支 `pattern => value` 或 `pattern => { stmts }`，
+/// 分支的值是最后一条语句的值（与 if 相同）
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Vec<Expr>,
+    /// 模式在源码中的位置
+    pub span: Span,
+}
+
+/// 与 Expr 相同，比较时不看位置
+impl PartialEq for MatchArm {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.body == other.body
+    }
+}
+
+/// `match` 分支的模式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,                              // _
+    Binding(String),                       // x：匹配任意值并绑定到变量 x
+    Int(i64),                              // 42 / -1 / 0xFF
+    Range(i64, i64, bool),                 // 1..10 / 1..=9 (起点, 终点, 是否包含终点)
+    Variant(String, String, Vec<Pattern>), // Shape::Rect(w, _)，载荷只能是绑定或 _
+    Or(Vec<Pattern>),                      // 1 | 2 | 5..=9，各分支不能绑定变量
+}
+
+impl Pattern {
+    /// 模式能否匹配枚举变体 `variant` 的值（载荷模式只能是绑定或 `_`，总能匹配）
+    pub fn matches_variant(&self, variant: &str) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Variant(_, v, _) => v == variant,
+            Pattern::Or(alternatives) => alternatives.iter().any(|p| p.matches_variant(variant)),
+            Pattern::Int(_) | Pattern::Range(..) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(name) => write!(f, "{}", name),
+            Pattern::Int(n) => write!(f, "{}", n),
+            Pattern::Range(a, b, inclusive) => {
+                write!(f, "{}..{}{}", a, if *inclusive { "=" } else { "" }, b)
+            }
+            Pattern::Variant(name, variant, fields) if fields.is_empty() => {
+                write!(f, "{}::{}", name, variant)
+            }
+            Pattern::Variant(name, variant, fields) => {
+                let fields: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}::{}({})", name, variant, fields.join(", "))
+            }
+            Pattern::Or(alternatives) => {
+                let alternatives: Vec<String> =
+                    alternatives.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", alternatives.join(" | "))
+            }
+        }
+    }
+}
+
+/// 解析出的函数定义：(函数名, 参数列表, (返回变量名, 返回类型), 函数体语句)
+pub type Function = (String, Vec<(String, Type)>, (String, Type), Vec<Expr>);
+
+/// 结构体声明 `struct Point { x: f64, y: f64 }`，字段按声明顺序排列，
+/// 内存布局与同样字段顺序的 C 结构体相同（见 `TypeChecker::struct_layout`）
+#[derive(Debug, Clone)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<(String, Type)>,
+    /// 整个声明在源码中的位置，字段类型有误时指向这里
+    pub span: Span,
+}
+
+/// 与 Expr 相同，比较时不看位置
+impl PartialEq for StructDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.fields == other.fields
+    }
+}
+
+/// 枚举声明 `enum Shape { Circle(f64), Rect(f64, f64), Empty }`：
+/// 每个变体带零个或多个载荷字段，内存布局与 `#[repr(C)]` 的 Rust 枚举相同
+/// （见 `TypeChecker::enum_layout`）
+#[derive(Debug, Clone)]
+pub struct EnumDef {
+    pub name: String,
+    pub variants: Vec<(String, Vec<Type>)>,
+    pub span: Span,
+}
+
+impl EnumDef {
+    /// 变体的序号（即标签值）与载荷类型
+    pub fn variant(&self, name: &str) -> Option<(usize, &[Type])> {
+        self.variants
+            .iter()
+            .position(|(v, _)| v == name)
+            .map(|i| (i, self.variants[i].1.as_slice()))
+    }
+}
+
+/// 与 Expr 相同，比较时不看位置
+impl PartialEq for EnumDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.variants == other.variants
+    }
+}
+
+/// 模块级的常量或静态变量：`const N: i64 = 64`、`static mut counter: i64 = 0`。
+/// 初值必须是常量表达式，对模块中的全部函数可见
+#[derive(Debug, Clone)]
+pub struct GlobalDef {
+    pub kind: GlobalKind,
+    pub name: String,
+    pub ty: Type,
+    pub value: Expr,
+    /// 整个声明在源码中的位置
+    pub span: Span,
+}
+
+/// 与 Expr 相同，比较时不看位置
+impl PartialEq for GlobalDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.name == other.name
+            && self.ty == other.ty
+            && self.value == other.value
+    }
+}
+
+/// 模块级声明的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalKind {
+    /// `const`：编译期常量，使用处由常量折叠替换为字面量
+    Const,
+    /// `static`：只读的全局数据
+    Static,
+    /// `static mut`：可读写的全局数据
+    StaticMut,
+}
+
+impl GlobalKind {
+    /// 源码中的写法
+    pub fn keyword(self) -> &'static str {
+        match self {
+            GlobalKind::Const => "const",
+            GlobalKind::Static => "static",
+            GlobalKind::StaticMut => "static mut",
+        }
+    }
+}
+
+/// 源文件中的一个顶层定义
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Struct(StructDef),
+    Enum(EnumDef),
+    Global(GlobalDef),
+    Function(Function),
+}
+
+/// 解析出的整个 .toy 文件：按源码顺序排列的顶层定义
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+impl Program {
+    /// 全部结构体声明
+    pub fn structs(&self) -> impl Iterator<Item = &StructDef> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Struct(def) => Some(def),
+            _ => None,
+        })
+    }
+
+    /// 全部枚举声明
+    pub fn enums(&self) -> impl Iterator<Item = &EnumDef> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Enum(def) => Some(def),
+            _ => None,
+        })
+    }
+
+    /// 全部常量与静态变量
+    pub fn globals(&self) -> impl Iterator<Item = &GlobalDef> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Global(def) => Some(def),
+            _ => None,
+        })
+    }
+
+    /// 全部函数定义
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Function(f) => Some(f),
+            _ => None,
+        })
+    }
+
+    /// 拆分为 (结构体声明, 枚举声明, 函数定义, 常量与静态变量)
+    pub fn split(self) -> (Vec<StructDef>, Vec<EnumDef>, Vec<Function>, Vec<GlobalDef>) {
+        let mut structs = Vec::new();
+        let mut enums = Vec::new();
+        let mut functions = Vec::new();
+        let mut globals = Vec::new();
+        for item in self.items {
+            match item {
+                Item::Struct(def) => structs.push(def),
+                Item::Enum(def) => enums.push(def),
+                Item::Global(def) => globals.push(def),
+                Item::Function(f) => functions.push(f),
+            }
+        }
+        (structs, enums, functions, globals)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
+    Bool,
     I8,
     I16,
     I32,
@@ -42,32 +373,145 @@ pub enum Type {
     String,
     Complex64,
     Complex128,
-    Array(Box<Type>, usize), // Fixed size array for now
+    Array(Box<Type>, usize),          // Fixed size array for now
+    ConstLenArray(Box<Type>, String), // [T; N]，长度是常量名，登记常量后换成 Array
     DynamicArray(Box<Type>),
+    Struct(String), // 按名字引用的结构体或枚举，字段与布局由 struct / enum 声明决定
+}
+
+/// 按源码中的写法打印类型，用于错误信息
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::I128 => write!(f, "i128"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::String => write!(f, "string"),
+            Type::Complex64 => write!(f, "complex64"),
+            Type::Complex128 => write!(f, "complex128"),
+            Type::Array(elem, len) => write!(f, "[{}; {}]", elem, len),
+            Type::ConstLenArray(elem, len) => write!(f, "[{}; {}]", elem, len),
+            Type::DynamicArray(elem) => write!(f, "array<{}>", elem),
+            Type::Struct(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// 源码中全部注释（`// ...` 与 `/* ... */`）的位置，按出现顺序排列。
+/// 与语法中的 comment() 规则一致；字符串字面量里的 `//` 不算注释。
+/// 解析出的 AST 不保留注释，`toy fmt` 据此把它们放回输出
+pub fn comment_spans(source: &str) -> Vec<Span> {
+    let bytes = source.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(end) = string_literal_end(source, i) {
+            i = end;
+            continue;
+        }
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                let end = source[i..].find('\n').map_or(source.len(), |n| i + n);
+                // CRLF 文件中行尾的 \r 不算注释内容
+                let text = source[i..end].trim_end_matches('\r');
+                spans.push(Span::new(i, i + text.len()));
+                i = end;
+            }
+            (b'/', Some(b'*')) => {
+                let end = source[i + 2..]
+                    .find("*/")
+                    .map_or(source.len(), |n| i + 2 + n + 2);
+                spans.push(Span::new(i, end));
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    spans
+}
+
+/// `pos` 处如果是字符串字面量（`"..."`、`r"..."`、`r#"..."#`）的开头，返回它的结束位置，
+/// 没有结束引号时返回源码末尾。供不经过 parser 的扫描（注释定位、REPL 括号配平）跳过字符串
+pub fn string_literal_end(source: &str, pos: usize) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let raw_end = |body: usize, close: &str| {
+        source[body..]
+            .find(close)
+            .map_or(source.len(), |n| body + n + close.len())
+    };
+    // 标识符末尾的 r（例如 `for"`）不是原始字符串的前缀
+    let after_word = pos > 0 && (bytes[pos - 1].is_ascii_alphanumeric() || bytes[pos - 1] == b'_');
+    match &bytes[pos..] {
+        [b'"', ..] => {
+            let mut i = pos + 1;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'"' => return Some(i + 1),
+                    _ => i += 1,
+                }
+            }
+            Some(bytes.len())
+        }
+        _ if after_word => None,
+        [b'r', b'#', b'"', ..] => Some(raw_end(pos + 3, "\"#")),
+        [b'r', b'"', ..] => Some(raw_end(pos + 2, "\"")),
+        _ => None,
+    }
+}
+
+/// 把 `0x` / `0o` / `0b` 字面量的数位转换为十进制文本。
+/// 连 u128 都放不下的数保留原样，由类型检查报告超出范围
+fn radix_literal(prefix: &str, digits: &str, radix: u32) -> String {
+    let digits = digits.replace('_', "");
+    match u128::from_str_radix(&digits, radix) {
+        Ok(n) => n.to_string(),
+        Err(_) => format!("{}{}", prefix, digits),
+    }
+}
+
+/// 模式中是否绑定了变量（`|` 的各个分支不能绑定变量）
+fn binds_variables(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Binding(_) => true,
+        Pattern::Variant(_, _, fields) => fields.iter().any(binds_variables),
+        Pattern::Or(alternatives) => alternatives.iter().any(binds_variables),
+        _ => false,
+    }
+}
+
+/// 解析语法上已经确认合法的浮点数位（可含 `_` 和指数）
+fn parse_float(digits: &str) -> f64 {
+    digits.replace('_', "").parse().unwrap()
 }
 
 peg::parser!(pub grammar parser() for str {    //peg 是 Parsing Expression Grammars 的 Rust 实现的第三方 crate
-    use super::{Expr, Type};
+    use super::{AssignOp, EnumDef, Expr, ExprKind, Function, GlobalDef, GlobalKind, Item, MatchArm, Pattern, Program, Span, StructDef, Type, binds_variables, parse_float, radix_literal};
     //use — 把路径里的项引入到当前作用域
     //super — 模块路径里的"上一级"，
     //即在从父模块开始找 Expr（表达式枚举）和 Type（类型枚举），这样我们就可以在语法规则里直接使用它们了
     /// rule是peg宏的关键字，表示定义一个语法规则
     /// function()规则用于解析函数定义，返回一个元组，包含函数名、参数列表、返回值类型和函数体语句列表
     /// // c = a + b 的表示：
-            //Expr::Assign(
+            //ExprKind::Assign(
             //"c".to_string(),                    // 赋值的目标变量名
             //Box::new(                           // 赋值的内容（右值）
-                //Expr::Add(                      // 加法表达式 a + b
-                //    Box::new(Expr::Identifier("a".to_string())), // 左操作数 a
-                //    Box::new(Expr::Identifier("b".to_string()))  // 右操作数 b
+                //ExprKind::Add(                      // 加法表达式 a + b
+                //    Box::new(ExprKind::Identifier("a".to_string())), // 左操作数 a
+                //    Box::new(ExprKind::Identifier("b".to_string()))  // 右操作数 b
                     //)
                 //)
             //)
-    pub rule function() -> (String, Vec<(String, Type)>, (String, Type), Vec<Expr>)
+    pub rule function() -> Function
         //允许在函数定义的最开始出现任意数量（ * ）的空格、制表符或换行符；要求 接下来必须紧跟字符串 fn；
         // _ ：这是一个在别处定义的规则（通常代表任意空白字符），表示允许 fn 和名字之间有空格调用
         //identifier() 规则去解析一个标识符（比如 add ），把解析出来的结果（一个字符串）赋值给变量 name
-        = [' ' | '\t' | '\n']* "fn" _ name:identifier() _
+        = __ "fn" _ name:identifier() _
         //"(" ... ")" ：要求必须有一对圆括号包裹。
         // params:(...) ：把括号里解析出来的内容赋值给 params 变量。
         // (...) ** ","：这是一个 PEG 的特殊语法，意思是 “被逗号分隔的列表”
@@ -82,108 +526,325 @@ peg::parser!(pub grammar parser() for str {    //peg 是 Parsing Expression Gram
         // 部逻辑和参数列表完全一样：解析 名字: 类型 （例如 r: i64 ），并打包成 (String, Type)
         //注意：这里没有 ** "," ，说明你的语言目前只支持 单个返回值
         "(" ret:(_ i:identifier() _ ":" _ t:type_name() _ {(i, t)}) ")" _
-        "{" _ "\n"
+        "{"
         //- 调用 statements() 规则。这个规则会解析花括号里的一系列语句（比如 a = 1; b = 2; ）。
         //- 结果存入 stmts 变量（类型是 Vec<Expr> ）。
         //- 最后返回一个元组 (name, params, ret, stmts) ，包含函数名、参数列表、返回值类型和语句列表。
         stmts:statements()
-        _ "}" _ "\n" _
+        _ "}" __
         { (name, params, ret, stmts) }
 
+    /// program() 规则用于解析整个 .toy 文件：任意数量的结构体、枚举声明、常量、静态变量和函数定义，
+    /// 按源码顺序排列。类型与函数之间可以互相前向引用
+    pub rule program() -> Program
+        = items:item()* __ { Program { items } }
+
+    rule item() -> Item
+        = s:struct_def() { Item::Struct(s) }
+        / e:enum_def() { Item::Enum(e) }
+        / g:global_def() { Item::Global(g) }
+        / f:function() { Item::Function(f) }
+
+    /// `struct Name { field: Type, ... }`：至少一个字段，字段之间用逗号分隔（可以换行），
+    /// 最后一个字段后的逗号可以省略
+    pub rule struct_def() -> StructDef
+        = __ start:position!() "struct" word_end() _ name:identifier() _ "{"
+        fields:(__ f:identifier() _ ":" _ t:type_name() _ { (f, t) }) ++ ","
+        __ ","? __ "}" end:position!() __
+        { StructDef { name, fields, span: Span::new(start, end) } }
+
+    /// `enum Name { A, B(T), C(T, U) }`：至少一个变体，规则与结构体字段相同
+    pub rule enum_def() -> EnumDef
+        = __ start:position!() "enum" word_end() _ name:identifier() _ "{"
+        variants:(__ v:identifier() _ payload:("(" _ t:((_ t:type_name() _ { t }) ++ ",") _ ")" { t })? _ { (v, payload.unwrap_or_default()) }) ++ ","
+        __ ","? __ "}" end:position!() __
+        { EnumDef { name, variants, span: Span::new(start, end) } }
+
+    /// `const N: T = 值`、`static NAME: T = 值` 或 `static mut NAME: T = 值`，类型标注不能省略，
+    /// 与语句一样以换行或 `;` 结束
+    pub rule global_def() -> GlobalDef
+        = __ start:position!() kind:global_kind() _ name:identifier() _ ":" _ ty:type_name() _
+        "=" !"=" _ value:expression() end:position!() _ end_of_statement() __
+        { GlobalDef { kind, name, ty, value, span: Span::new(start, end) } }
+
+    rule global_kind() -> GlobalKind
+        = "const" word_end() { GlobalKind::Const }
+        / "static" word_end() _ "mut" word_end() { GlobalKind::StaticMut }
+        / "static" word_end() { GlobalKind::Static }
+
     //一个“语句块”是由 0个或多个 （ * ）“单条语句”组成的序列
     //statement()*会不断调用 statement() 规则，直到无法匹配为止
     //匹配到的所有结果会自动收集成一个 Vec （向量/列表）
     //s.into_iter().flatten().collect()把"嵌套的 Vec"拍平成一个单层的 Vec<Expr> 返回，因为statement()规则返回的是 Option<Expr>，所以会有一些 None 需要过滤掉，最终得到一个只包含 Some(Expr) 的 Vec<Expr>。
-    rule statements() -> Vec<Expr>
+    //pub：REPL 直接解析不在函数体内的语句
+    pub rule statements() -> Vec<Expr>
         = s:(statement()*) { s.into_iter().flatten().collect() }
     //statement() ：单条语句的定义
     //e:expression() _ ：调用更底层的 expression() 规则来解析实际的逻辑（比如 a + b 或 c = 1 ）
         //前后允许有空白字符 _
         //结果存入变量 e
-    //"\n"明确规定：每一条语句后面 必须 跟一个换行符
+    //end_of_statement()：语句以换行或 ; 结束（见下）
     //{ e } ：解析成功后，把表达式 e 返回
+    //第二个分支是空语句：空行、只有注释的行、多余的 ;
     rule statement() -> Option<Expr>
-        = _ e:expression() _ "\n" { Some(e) }
-        / _ "\n" { None }
+        = _ e:expression() _ end_of_statement() { Some(e) }
+        / _ quiet!{"\n" / ";"} { None }
+
+    /// 语句以换行或 `;` 结束；块中最后一条语句后紧跟 `}`、或已到输入末尾时可以省略
+    rule end_of_statement()
+        = quiet!{"\n" / ";" / &"}" / ![_]}
+        / expected!("newline")
+        / expected!("\";\"")
 
     //expression() ：表达式的定义
     //if_else() / while_loop() / assignment() / binary_op() ：
         //- 分别调用对应的规则来解析不同类型的表达式（比如 if 语句、while 循环、赋值语句、二元操作符）
         //- 每个规则都有自己的语法和优先级，确保解析顺序正确，用/表示匹配优先级（这里先匹配if-else）
+    //start / end 用 position!() 记录表达式在源码中的字节区间，报错时据此定位到行列
+    //binary_op() 的各层节点由 precedence! 的区间捕获规则自己记录位置
     rule expression() -> Expr
+        = start:position!() kind:statement_kind() end:position!() { Expr::new(kind, Span::new(start, end)) }
+        / binary_op()           //表示二元操作符，例如 a + b 或 a * b
+
+    rule statement_kind() -> ExprKind
         = if_else()
+        / match_expr()
         / while_loop()
+        / for_loop()
         / block_stmt()
-        / "drop" _ "(" _ i:identifier() _ ")" { Expr::Drop(i) }
+        / quiet!{"drop"} _ "(" _ p:$(identifier() ("." identifier())*) _ ")" { ExprKind::Drop(p.to_owned()) }
+        / quiet!{"return" word_end()} _ e:expression() { ExprKind::Return(Box::new(e)) }
+        / quiet!{"break" word_end()} { ExprKind::Break }
+        / quiet!{"continue" word_end()} { ExprKind::Continue }
+        / let_decl()
         / assignment()          //表示赋值语句，例如 a = 1
-        / binary_op()           //表示二元操作符，例如 a + b 或 a * b
 
-    rule if_else() -> Expr
-        = "if" _ e:expression() _ "{" _ "\n"
-        then_body:statements() _ "}" _ "else" _ "{" _ "\n"
+    /// if 语句：else 可省略（等价于空的 else 分支），`else if` 脱糖为只含一个 IfElse 的 else 分支
+    rule if_else() -> ExprKind
+        = quiet!{"if"} _ e:expression() _ "{"
+        then_body:statements() _ "}"
+        else_body:else_branch()?
+        { ExprKind::IfElse(Box::new(e), then_body, else_body.unwrap_or_default()) }
+
+    rule else_branch() -> Vec<Expr>
+        = _ "else" _ start:position!() kind:if_else() end:position!()
+        { vec![Expr::new(kind, Span::new(start, end))] }
+        / _ "else" _ "{"
         else_body:statements() _ "}"
-        { Expr::IfElse(Box::new(e), then_body, else_body) }
+        { else_body }
+
+    /// `match x { pattern => value, ... }`：分支之间用逗号或换行分隔，块形式的分支 `=> { ... }`
+    /// 之后的逗号可以省略。被匹配的值与 for 的区间一样用 binary_op()，`{` 不会被当成结构体字面量
+    rule match_expr() -> ExprKind
+        = quiet!{"match" word_end()} _ e:binary_op() _ "{"
+        arms:match_arm()* __ "}"
+        { ExprKind::Match(Box::new(e), arms) }
+
+    rule match_arm() -> MatchArm
+        = __ start:position!() pattern:pattern() end:position!() _ "=>" _ body:arm_body() _ ","?
+        { MatchArm { pattern, body, span: Span::new(start, end) } }
 
-    rule while_loop() -> Expr
-        = "while" _ e:expression() _ "{" _ "\n"
+    rule arm_body() -> Vec<Expr>
+        = "{" body:statements() _ "}" { body }
+        / e:expression() { vec![e] }
+
+    /// 模式：`_`、变量绑定、整数、整数区间、枚举变体，或用 `|` 连接的多个不绑定变量的模式
+    rule pattern() -> Pattern
+        = alternatives:(single_pattern() ++ (_ "|" _)) {?
+            match alternatives.len() {
+                1 => Ok(alternatives.into_iter().next().unwrap()),
+                _ if alternatives.iter().any(binds_variables) => Err("pattern without bindings"),
+                _ => Ok(Pattern::Or(alternatives)),
+            }
+        }
+
+    rule single_pattern() -> Pattern
+        = "_" word_end() { Pattern::Wildcard }
+        / e:identifier() _ "::" _ v:identifier()
+        fields:(_ "(" _ f:((_ p:field_pattern() _ { p }) ++ ",") _ ")" { f })?
+        { Pattern::Variant(e, v, fields.unwrap_or_default()) }
+        / a:pattern_int() _ ".." inclusive:"="? _ b:pattern_int() { Pattern::Range(a, b, inclusive.is_some()) }
+        / n:pattern_int() { Pattern::Int(n) }
+        / !raw_string_start() i:identifier() { Pattern::Binding(i) }
+
+    rule field_pattern() -> Pattern
+        = "_" word_end() { Pattern::Wildcard }
+        / i:identifier() { Pattern::Binding(i) }
+
+    /// 模式中的整数：可带负号，写法与整数字面量相同（类型后缀被忽略，范围由类型检查按被匹配值的类型判断）
+    rule pattern_int() -> i64
+        = neg:"-"? _ n:number() {?
+            match n {
+                ExprKind::Literal(text, ty) if !matches!(ty, Type::F32 | Type::F64) => {
+                    let text = if neg.is_some() { format!("-{}", text) } else { text };
+                    text.parse().map_err(|_| "integer that fits in i64")
+                }
+                _ => Err("integer"),
+            }
+        }
+
+    rule while_loop() -> ExprKind
+        = quiet!{"while"} _ e:expression() _ "{"
         loop_body:statements() _ "}"
-        { Expr::WhileLoop(Box::new(e), loop_body) }
+        { ExprKind::WhileLoop(Box::new(e), loop_body) }
+
+    /// for 循环：区间与起点、终点、步长都只求值一次；`step` 为负时倒序遍历
+    rule for_loop() -> ExprKind
+        = quiet!{"for" word_end()} _ i:identifier() _ "in" word_end() _ a:binary_op() _ ".." inclusive:"="? _ b:binary_op()
+        step:(_ "step" _ s:binary_op() { s })? _ "{"
+        loop_body:statements() _ "}"
+        { ExprKind::ForRange(i, Box::new(a), Box::new(b), inclusive.is_some(), step.map(Box::new), loop_body) }
+        / quiet!{"for" word_end()} _ i:identifier() _ "in" word_end() _ e:binary_op() _ "{"
+        loop_body:statements() _ "}"
+        { ExprKind::ForEach(i, Box::new(e), loop_body) }
 
     /// 块作用域：{ stmts }
     /// PEG 有序选择天然消除歧义——if/while 以关键字开头，不会匹配独立的 {
-    rule block_stmt() -> Expr
-        = "{" _ "\n"
-        body:statements() _ "}" _
-        { Expr::Block(body) }
+    rule block_stmt() -> ExprKind
+        = quiet!{"{"}
+        body:statements() _ "}"
+        { ExprKind::Block(body) }
+
+    /// `let x = e`、`let mut x = e`、`let x: T = e`：声明一个块作用域的变量（见 resolver 模块），
+    /// 有类型标注时变量取标注的类型，初值必须能隐式转换为它
+    rule let_decl() -> ExprKind
+        = quiet!{"let" word_end()} _ m:("mut" word_end() _)? i:identifier() _
+        t:(":" _ t:type_name() _ { t })? "=" !"=" _ e:expression()
+        { ExprKind::Let(i, m.is_some(), t, Box::new(e)) }
 
     ///变量赋值语法，identifier()明确规定左边 必须是一个标识符。匹配到的变量名（字符串）存入变量 i
     /// e:expression()匹配赋值号右边的部分（右值），右边可以是 任意表达式 （数字、运算、函数调用、甚至另一个赋值）
-    rule assignment() -> Expr
-        = i:identifier() _ "=" _ e:expression() {Expr::Assign(i, Box::new(e))}
+    /// `arr[i] = e` 写入数组元素，`p.x = e` 写入结构体字段，二者可以嵌套（`pts[i].x`、`p.items[0]`）；
+    /// `+= -= *= /=` 为复合赋值。`"=" !"="` 避免把 `a == b` 的前半截当成赋值
+    rule assignment() -> ExprKind
+        = t:quiet!{place()} _ op:assign_op() _ e:expression() {
+            match t.kind {
+                ExprKind::Index(base, idx) => ExprKind::IndexAssign(base, idx, op, Box::new(e)),
+                ExprKind::Field(base, f) => ExprKind::FieldAssign(base, f, op, Box::new(e)),
+                _ => unreachable!("place() only returns indexes and fields"),
+            }
+        }
+        / i:quiet!{identifier()} _ op:compound_op() _ e:expression() {ExprKind::CompoundAssign(i, op, Box::new(e))}
+        / i:quiet!{identifier()} _ quiet!{"=" !"="} _ e:expression() {ExprKind::Assign(i, Box::new(e))}
+
+    /// 赋值目标：变量之后跟至少一个 `[索引]` 或 `.字段`
+    rule place() -> Expr
+        = p:place_path() {? match p.kind {
+            ExprKind::Index(..) | ExprKind::Field(..) => Ok(p),
+            _ => Err("index or field"),
+        } }
+
+    rule place_path() -> Expr = precedence!{
+        start:position!() node:@ end:position!() { Expr::new(node, Span::new(start, end)) }
+        --
+        a:@ _ "[" _ idx:expression() _ "]" { ExprKind::Index(Box::new(a), Box::new(idx)) }
+        a:@ "." !"." _ f:identifier() { ExprKind::Field(Box::new(a), f) }
+        i:identifier() { ExprKind::Identifier(i) }
+    }
+
+    rule assign_op() -> Option<AssignOp>
+        = op:compound_op() { Some(op) }
+        / quiet!{"=" !"="} { None }
+        / expected!("\"=\"")
+
+    rule compound_op() -> AssignOp
+        = quiet!{
+            "+=" { AssignOp::Add }
+            / "-=" { AssignOp::Sub }
+            / "*=" { AssignOp::Mul }
+            / "/=" { AssignOp::Div }
+        }
 
     ///二元操作符语法，precedence!{} ：定义操作符的优先级。
     ///- 每个操作符都有一个优先级，数字越大优先级越高。
-    ///- 每个操作符的定义格式是 a:@ _ "操作符" _ b:(@) { 表达式 }
-    ///    - a:@ ：左边的操作数，用 @ 表示“捕获”（匹配到的内容会被暂存起来）
+    ///- 每个操作符的定义格式是 a:(@) _ "操作符" _ b:@ { 表达式 }
+    ///    - a:(@) ：左边的操作数，用 @ 表示“捕获”（匹配到的内容会被暂存起来）；
+    ///      括号表示左边可以是同一层的运算，所以同级运算左结合：`a - b - c` 是 `(a - b) - c`
     ///    - _ "操作符" _ ：中间的操作符，这里是 + 或 -
-    ///    - b:(@) ：右边的操作数，也用 @ 表示“捕获”
+    ///    - b:@ ：右边的操作数，只能是更高一层的运算
     ///    - { 表达式 } ：匹配成功后，执行的 Rust 代码，这里是创建一个 Add 或 Sub 表达式
+    ///- 第一层 start:position!() node:@ end:position!() 是区间捕获规则：
+    ///  下面每一层构造出的 ExprKind 都会被包装成带源码位置的 Expr
     rule binary_op() -> Expr = precedence!{
-        a:@ _ "==" _ b:(@) { Expr::Eq(Box::new(a), Box::new(b)) }
-        a:@ _ "!=" _ b:(@) { Expr::Ne(Box::new(a), Box::new(b)) }
-        a:@ _ "<"  _ b:(@) { Expr::Lt(Box::new(a), Box::new(b)) }
-        a:@ _ "<=" _ b:(@) { Expr::Le(Box::new(a), Box::new(b)) }
-        a:@ _ ">"  _ b:(@) { Expr::Gt(Box::new(a), Box::new(b)) }
-        a:@ _ ">=" _ b:(@) { Expr::Ge(Box::new(a), Box::new(b)) }
+        start:position!() node:@ end:position!() { Expr::new(node, Span::new(start, end)) }
+        --
+        a:(@) _ quiet!{"||"} _ b:@ { ExprKind::Or(Box::new(a), Box::new(b)) }
+        --
+        a:(@) _ quiet!{"&&"} _ b:@ { ExprKind::And(Box::new(a), Box::new(b)) }
+        --
+        a:(@) _ quiet!{"=="} _ b:@ { ExprKind::Eq(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"!="} _ b:@ { ExprKind::Ne(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"<"}  _ b:@ { ExprKind::Lt(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"<="} _ b:@ { ExprKind::Le(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{">"}  _ b:@ { ExprKind::Gt(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{">="} _ b:@ { ExprKind::Ge(Box::new(a), Box::new(b)) }
         --
-        a:@ _ "+" _ b:(@) { Expr::Add(Box::new(a), Box::new(b)) }
-        a:@ _ "-" _ b:(@) { Expr::Sub(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"|" !"|"} _ b:@ { ExprKind::BitOr(Box::new(a), Box::new(b)) }
         --
-        a:@ _ "*" _ b:(@) { Expr::Mul(Box::new(a), Box::new(b)) }
-        a:@ _ "/" _ b:(@) { Expr::Div(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"^"} _ b:@ { ExprKind::BitXor(Box::new(a), Box::new(b)) }
         --
-        a:@ _ "as" _ t:type_name() { Expr::Cast(Box::new(a), t) }
+        a:(@) _ quiet!{"&" !"&"} _ b:@ { ExprKind::BitAnd(Box::new(a), Box::new(b)) }
         --
-        a:@ _ "[" _ idx:expression() _ "]" { Expr::Index(Box::new(a), Box::new(idx)) }
-        i:identifier() _ "(" args:((_ e:expression() _ {e}) ** ",") ")" { Expr::Call(i, args) }
-        i:identifier() { Expr::Identifier(i) }
-        l:literal() { l }
-        "(" _ e:expression() _ ")" { e }
+        a:(@) _ quiet!{"<<"} _ b:@ { ExprKind::Shl(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{">>"} _ b:@ { ExprKind::Shr(Box::new(a), Box::new(b)) }
+        --
+        a:(@) _ quiet!{"+"} _ b:@ { ExprKind::Add(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"-"} _ b:@ { ExprKind::Sub(Box::new(a), Box::new(b)) }
+        --
+        a:(@) _ quiet!{"*"} _ b:@ { ExprKind::Mul(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"/"} _ b:@ { ExprKind::Div(Box::new(a), Box::new(b)) }
+        a:(@) _ quiet!{"%"} _ b:@ { ExprKind::Rem(Box::new(a), Box::new(b)) }
+        --
+        a:@ _ quiet!{"as"} _ t:type_name() { ExprKind::Cast(Box::new(a), t) }
+        --
+        quiet!{"!"} _ e:@ { ExprKind::Not(Box::new(e)) }
+        quiet!{"-"} _ e:@ { ExprKind::Neg(Box::new(e)) }
+        --
+        a:@ _ quiet!{"["} _ idx:expression() _ "]" { ExprKind::Index(Box::new(a), Box::new(idx)) }
+        a:@ quiet!{"." !"."} _ f:identifier() { ExprKind::Field(Box::new(a), f) }
+        a:@ _ k:missing_operator() { k }
+        k:atom() { k }
     }
+
+    /// 操作数：枚举值、函数调用、结构体字面量、变量、字面量或括号表达式
+    rule atom() -> ExprKind
+        = !raw_string_start() e:quiet!{identifier()} _ "::" _ v:identifier()
+        args:(_ "(" args:((_ e:expression() _ {e}) ** ",") ")" { args })?
+        { ExprKind::EnumLiteral(e, v, args.unwrap_or_default()) }
+        / !raw_string_start() i:quiet!{identifier()} _ quiet!{"("} args:((_ e:expression() _ {e}) ** ",") ")" { ExprKind::Call(i, args) }
+        / !raw_string_start() n:quiet!{identifier()} _ &struct_literal_start() "{"
+        fields:(__ f:identifier() _ ":" _ e:expression() _ { (f, e) }) ++ ","
+        __ ","? __ "}" { ExprKind::StructLiteral(n, fields) }
+        / !raw_string_start() i:quiet!{identifier()} { ExprKind::Identifier(i) }
+        / l:literal() { l }
+        / quiet!{"("} _ e:expression() _ ")" { e.kind }
+        / expected!("expression")
+
+    /// `Name {` 之后是 `字段:` 才是结构体字面量，否则 `if x { ... }` 中的 `x {` 会被误认
+    rule struct_literal_start() = quiet!{"{" __ identifier() _ ":"}
+
+    /// 操作数之后既不是运算符也不是语句结尾时，报 "expected operator"（总是失败）
+    rule missing_operator() -> ExprKind
+        = expected!("operator")
     //a:@ _ "[" _ idx:expression() _ "]"匹配 arr[0]、darr[i+1] 这种下标访问
     //这里调用的是完整的顶层 expression()，不是 binary_op()，所以索引里可以塞 if/while/赋值等任意表达式，比如 arr[if i > 0 { i } else { 0 }]
     //i:identifier() _ "(" args:((_ e:expression() _ {e}) ** ",") ")" 函数调用，匹配 foo(a, b, c)、puts("hello") 这种调用
     //** "," 允许0 个参数
+    //"|" !"|" 和 "&" !"&" 用负向预查，避免把 || / && 拆成两个按位运算符
+    //一元的 - 和 ! 在 as 之上：-x as f64 是 (-x) as f64；-3 解析为 Neg(3)，由常量折叠还原为字面量
+    //运算符和各种操作数的起始记号都包在 quiet!{} 里，出错时不逐个列出，
+    //而是由 atom() / missing_operator() 中的 expected! 汇总成 "expected expression" / "expected operator"
 
     ///解析过程 ( a + b * c ) ：
     ///- 解析器首先尝试匹配最外层的低优先级规则（加法层）。
     ///- 它识别出 + 号。 + 号左边的 a 会“向下”递归去匹配更高优先级的规则，最终匹配到一个标识符 a 。
     ///- + 号右边的 b * c ，由于 * 定义在更下层的规则中（Level 3），所以解析器会优先将 b 和 c 按照乘法规则组合在一起。
-    ///- 最终结果就是： Expr::Add(a, Expr::Mul(b, c))
+    ///- 最终结果就是： ExprKind::Add(a, ExprKind::Mul(b, c))
 
 
     rule type_name() -> Type
-        = "i8" { Type::I8 }
+        = "bool" { Type::Bool }
+        / "i8" { Type::I8 }
         / "i16" { Type::I16 }
         / "i32" { Type::I32 }
         / "i64" { Type::I64 }
@@ -197,6 +858,8 @@ peg::parser!(pub grammar parser() for str {    //peg 是 Parsing Expression Gram
         / "[" _ t:type_name() _ ";" _ len:$(['0'..='9']+) _ "]" {
             Type::Array(Box::new(t), len.parse().unwrap())
         }
+        / "[" _ t:type_name() _ ";" _ n:identifier() _ "]" { Type::ConstLenArray(Box::new(t), n) }
+        / n:identifier() { Type::Struct(n) }
     //array<T> 和 [T; N] 是 Toy 里仅有的两种"带参数类型"语法，分别构造 Type::DynamicArray(Box<Type>) 和 Type::Array(Box<Type>, usize)。t:type_name() 的递归让它们能任意嵌套，$(...) 让 len 拿到原始数字字符串供后续解析。语法直接照搬 Rust，只在 type_name() 内部生效，不会和数组字面量 [1, 2, 3] 冲突，因为分隔符（; vs ,）和元素语法（type_name vs expression）不同。
 
     //$ 符号 ：这是 PEG 的操作符，意思是“捕获匹配到的原始字符串”。如果不加 $ ，匹配成功了但你拿不到具体的文本内容
@@ -209,7 +872,7 @@ peg::parser!(pub grammar parser() for str {    //peg 是 Parsing Expression Gram
         / expected!("identifier")
 //
 //用 { n.to_owned() } 把 &str 转成 String（函数签名要求返回 String）
-//keyword()：尝试匹配任意一个关键字（fn / if / else / while / as / array / i8..i128 / f32 / f64 / string / complex64 / complex128）
+//keyword()：尝试匹配任意一个关键字（fn / if / else / while / const / static / as / true / false / array / bool / i8..i128 / f32 / f64 / string / complex64 / complex128）
 // 负向字符类：!['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
 // 要求当前位置的字符不是字母/数字/下划线（也就是"非标识符字符"）
 // 然后后面的['a'..='z' | 'A'..='Z' | '_'] 要求当前位置的字符必须是字母或下划线（也就是"标识符开头字符"）
@@ -217,55 +880,142 @@ peg::parser!(pub grammar parser() for str {    //peg 是 Parsing Expression Gram
 //通过两层否定的负向预查 精确判断"当前位置是'关键字 + 非标识符字符'还是'真标识符'"
 
     rule keyword()
-        = "fn" / "if" / "else" / "while" / "as" / "array" / "i8" / "i16" / "i32" / "i64" / "i128" / "f32" / "f64" / "string" / "complex64" / "complex128"
+        = "fn" / "if" / "else" / "while" / "for" / "in" / "return" / "break" / "continue" / "let" / "mut" / "as" / "struct" / "enum" / "const" / "static" / "match" / "true" / "false" / "array" / "bool" / "i8" / "i16" / "i32" / "i64" / "i128" / "f32" / "f64" / "string" / "complex64" / "complex128"
 
-    rule literal() -> Expr
-        = s:string_literal() { Expr::StringLiteral(s) }
+    /// 关键字之后不能紧跟标识符字符，避免把 `breaker`、`returned` 之类的变量名拆开
+    rule word_end()
+        = !['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
+
+    rule literal() -> ExprKind
+        = s:string_literal() { ExprKind::StringLiteral(s) }
         / c:complex_literal() { c }
         / a:dynamic_array_literal() { a }
         / a:array_literal() { a }
-        / n:$(['0'..='9']+ "." ['0'..='9']+) { Expr::Literal(n.to_owned(), Type::F64) }
-        / n:$(['0'..='9']+) { Expr::Literal(n.to_owned(), Type::I64) }
-        / "&" i:identifier() { Expr::GlobalDataAddr(i) }
+        / b:quiet!{$("true" / "false")} word_end() { ExprKind::Literal(b.to_owned(), Type::Bool) }
+        / n:number() { n }
+        / quiet!{"&"} i:identifier() { ExprKind::GlobalDataAddr(i) }
 
     // 负责解析 Toy 语法里所有源代码里直接写出来的常量值
-    //顺序为：字符串字面量、复数字面量、动态数组字面量、固定数组字面量、浮点数字面量、整数字面量、全局数据地址
+    //顺序为：字符串字面量、复数字面量、动态数组字面量、固定数组字面量、布尔字面量、浮点数字面量、整数字面量、全局数据地址
+
+    /// 数字字面量：整数可写成十进制、`0x` 十六进制、`0o` 八进制或 `0b` 二进制，浮点数可带指数 `1e-9`，
+    /// 数位之间可以用 `_` 分隔。类型后缀指定字面量的类型：`10i32`、`2.5f32`、`1i128`、`1f64`，
+    /// 没有后缀时整数为 i64、浮点数为 f64。字面量文本统一转换为十进制，是否超出类型范围由类型检查报告
+    rule number() -> ExprKind
+        = n:quiet!{$(float_digits())} t:float_suffix()? number_end() {
+            ExprKind::Literal(n.replace('_', ""), t.unwrap_or(Type::F64))
+        }
+        / n:quiet!{$(decimal())} t:float_suffix() number_end() {
+            ExprKind::Literal(n.replace('_', ""), t)
+        }
+        / quiet!{"0x"} n:radix_digits(16) t:int_suffix()? number_end() {
+            ExprKind::Literal(radix_literal("0x", n, 16), t.unwrap_or(Type::I64))
+        }
+        / quiet!{"0o"} n:radix_digits(8) t:int_suffix()? number_end() {
+            ExprKind::Literal(radix_literal("0o", n, 8), t.unwrap_or(Type::I64))
+        }
+        / quiet!{"0b"} n:radix_digits(2) t:int_suffix()? number_end() {
+            ExprKind::Literal(radix_literal("0b", n, 2), t.unwrap_or(Type::I64))
+        }
+        / n:quiet!{$(decimal())} t:int_suffix()? number_end() {
+            ExprKind::Literal(n.replace('_', ""), t.unwrap_or(Type::I64))
+        }
+
+    /// 数字之后紧跟字母或数字说明后缀不合法，例如 `10u8`、`0b102`
+    rule number_end()
+        = !['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
+        / expected!("type suffix")
+
+    /// `0x` / `0o` / `0b` 之后的数位，首位之后可以有 `_`
+    rule radix_digits(radix: u32) -> &'input str
+        = quiet!{$([c if c.is_digit(radix)] [c if c.is_digit(radix) || c == '_']*)}
+        / expected!(match radix { 16 => "hexadecimal digit", 8 => "octal digit", _ => "binary digit" })
+
+    /// 十进制数位，首位之后可以有 `_`
+    rule decimal() = ['0'..='9'] ['0'..='9' | '_']*
+
+    /// 浮点数：`1.5`、`1.5e3`、`1e-9`（`1.` 不是浮点数，否则会和区间 `1..5` 冲突）
+    rule float_digits() = decimal() ("." decimal() exponent()? / exponent())
+
+    rule exponent() = ['e' | 'E'] ['+' | '-']? decimal()
+
+    rule int_suffix() -> Type
+        = quiet!{
+            "i8" { Type::I8 }
+            / "i16" { Type::I16 }
+            / "i32" { Type::I32 }
+            / "i64" { Type::I64 }
+            / "i128" { Type::I128 }
+        }
+
+    rule float_suffix() -> Type
+        = quiet!{"f32" { Type::F32 } / "f64" { Type::F64 }}
     //array 关键字是区分固定数组还是动态数组的，因为它们的语法不同（array [1, 2, 3] vs [1, 2, 3]），所以放在不同的规则里解析
-    rule array_literal() -> Expr
-        = "[" _ elems:((_ e:expression() _ {e}) ** ",") _ "]" {
-            Expr::ArrayLiteral(elems, Type::I64) // Placeholder type, inferred in JIT
+    rule array_literal() -> ExprKind
+        = quiet!{"["} _ elems:((_ e:expression() _ {e}) ** ",") _ "]" {
+            ExprKind::ArrayLiteral(elems, Type::I64) // Placeholder type, inferred in JIT
         }
 
-    rule dynamic_array_literal() -> Expr
-        = "array" _ "[" _ elems:((_ e:expression() _ {e}) ** ",") _ "]" {
-            Expr::DynamicArrayLiteral(elems, Type::I64) // Placeholder type, inferred in JIT
+    rule dynamic_array_literal() -> ExprKind
+        = quiet!{"array"} _ "[" _ elems:((_ e:expression() _ {e}) ** ",") _ "]" {
+            ExprKind::DynamicArrayLiteral(elems, Type::I64) // Placeholder type, inferred in JIT
         }
 
+    /// `"..."`（UTF-8，支持转义）或原始字符串 `r"..."` / `r#"..."#`（内容原样保留，
+    /// 后者可以包含 `"`）
     rule string_literal() -> String
-        = "\"" s:double_quoted_character()* "\"" { s.into_iter().collect() }
+        = quiet!{"\""} s:double_quoted_character()* "\"" { s.into_iter().collect() }
+        / quiet!{"r#\""} s:$((!"\"#" [_])*) "\"#" { s.to_owned() }
+        / quiet!{"r\""} s:$((!"\"" [_])*) "\"" { s.to_owned() }
+
+    /// `r"` / `r#"` 开头的是原始字符串，不是名为 r 的变量
+    rule raw_string_start() = "r" "#"? "\""
 
     rule double_quoted_character() -> char
-        = !("\"" / "\\") c:any_char() { c }
+        = !("\"" / "\\") c:[_] { c }
         / "\\" esc:escape_sequence() { esc }
 
+    /// 转义序列，与 Rust 相同：`\xNN` 只能表示 ASCII（00-7F），`\u{...}` 为 1-6 位十六进制的 Unicode 码点
     rule escape_sequence() -> char
-        = "\"" { '"' }
-        / "\\" { '\\' }
-        / "n" { '\n' }
-        / "t" { '\t' }
-        / "r" { '\r' }
-
-    rule any_char() -> char
-        = c:['\x00'..='\x7f'] { c } // ASCII only for simplicity, or use utf8
+        = quiet!{
+            "\"" { '"' }
+            / "\\" { '\\' }
+            / "'" { '\'' }
+            / "n" { '\n' }
+            / "t" { '\t' }
+            / "r" { '\r' }
+            / "0" { '\0' }
+            / "x" h:$(['0'..='7'] ['0'..='9' | 'a'..='f' | 'A'..='F']) {
+                char::from(u8::from_str_radix(h, 16).unwrap())
+            }
+            / "u{" h:$(['0'..='9' | 'a'..='f' | 'A'..='F']*<1,6>) "}" {?
+                char::from_u32(u32::from_str_radix(h, 16).unwrap()).ok_or("")
+            }
+        }
+        / expected!("escape sequence (\\n \\t \\r \\0 \\\\ \\\" \\' \\xNN \\u{NNNN})")
 
+    rule complex_literal() -> ExprKind = quiet!{complex_literal_()}
 
-    rule complex_literal() -> Expr
-        = r:$(['0'..='9']+ "." ['0'..='9']+) _ "+" _ i:$(['0'..='9']+ "." ['0'..='9']+) "i" {
-            Expr::ComplexLiteral(r.parse().unwrap(), i.parse().unwrap(), Type::Complex128)
+    /// `1.5 + 2.5i`、`1.0 - 2.0i`、`2.5i`，实部和虚部都是浮点数，可带指数
+    rule complex_literal_() -> ExprKind
+        = r:$(float_digits()) _ sign:$("+" / "-") _ i:$(float_digits()) "i" word_end() {
+            let im: f64 = parse_float(i);
+            let im = if sign == "-" { -im } else { im };
+            ExprKind::ComplexLiteral(parse_float(r), im, Type::Complex128)
         }
-        / i:$(['0'..='9']+ "." ['0'..='9']+) "i" {
-            Expr::ComplexLiteral(0.0, i.parse().unwrap(), Type::Complex128)
+        / i:$(float_digits()) "i" word_end() {
+            ExprKind::ComplexLiteral(0.0, parse_float(i), Type::Complex128)
         }
 
-    rule _() =  quiet!{[' ' | '\t']*}
+    /// 行内空白：空格、制表符、Windows 换行中的 \r 和注释，不含换行（换行是语句分隔符）。
+    /// 块注释可以跨行，其中的换行不结束语句
+    rule _() =  quiet!{([' ' | '\t' | '\r'] / comment())*}
+
+    /// 任意空白，含换行：函数之间、文件首尾
+    rule __() = quiet!{([' ' | '\t' | '\r' | '\n'] / comment())*}
+
+    /// `// 行注释`（不含行尾换行）与 `/* 块注释 */`（不嵌套）
+    rule comment()
+        = "//" (!"\n" [_])*
+        / "/*" (!"*/" [_])* "*/"
 });
@@ -0,0 +1,194 @@
+//! 类型化的函数句柄 —— 不用 `transmute` 就能安全地调用 JIT 编译出的 toy 函数
+//!
+//! ```ignore
+//! jit.compile_program(source)?;
+//! let add = jit.get_func::<(f64, f64), f64>("add")?;
+//! assert_eq!(add.call((1.0, 2.0)), 3.0);
+//! ```
+//!
+//! `get_func` 在取句柄时把请求的 Rust 签名与 toy 函数登记的签名逐项比对，
+//! 不一致时返回 `CallError::SignatureMismatch`，因此 `Func::call` 本身不需要 `unsafe`。
+//! 句柄借用了 `JIT`，在句柄存活期间 JIT 模块（以及机器码所在的内存）不会被释放。
+//!
+//! | toy 类型 | Rust 参数类型 | Rust 返回类型 |
+//! |----------|---------------|---------------|
+//! | bool, i8 … i128, f32, f64 | 同名基本类型 | 同名基本类型 |
+//! | `[T; N]` | `&mut [T; N]` | — |
+//! | string | `&ToyString` | — |
+
+use crate::frontend::Type;
+use crate::jit::JIT;
+use crate::runtime::string::ToyString;
+use std::ffi::c_char;
+use std::marker::PhantomData;
+use std::mem;
+
+/// 可以作为 toy 函数参数传入的 Rust 类型
+pub trait ToyArg {
+    /// 按 C ABI 实际传递的类型
+    type Abi: Copy;
+    /// 是否与 toy 形参类型一致
+    fn matches(ty: &Type) -> bool;
+    /// 签名不匹配时显示的 Rust 类型名
+    fn describe() -> String;
+    fn into_abi(self) -> Self::Abi;
+}
+
+/// 可以作为 toy 函数返回值接收的 Rust 类型
+pub trait ToyRet {
+    type Abi: Copy;
+    fn matches(ty: &Type) -> bool;
+    fn describe() -> String;
+    fn from_abi(abi: Self::Abi) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($rust:ty => $toy:ident),* $(,)?) => {$(
+        impl ToyArg for $rust {
+            type Abi = $rust;
+            fn matches(ty: &Type) -> bool {
+                *ty == Type::$toy
+            }
+            fn describe() -> String {
+                stringify!($rust).to_string()
+            }
+            fn into_abi(self) -> $rust {
+                self
+            }
+        }
+
+        impl ToyRet for $rust {
+            type Abi = $rust;
+            fn matches(ty: &Type) -> bool {
+                *ty == Type::$toy
+            }
+            fn describe() -> String {
+                stringify!($rust).to_string()
+            }
+            fn from_abi(abi: $rust) -> $rust {
+                abi
+            }
+        }
+    )*};
+}
+
+impl_scalar! {
+    bool => Bool,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    i128 => I128,
+    f32 => F32,
+    f64 => F64,
+}
+
+/// 定长数组按指针传递，长度必须与 toy 声明的 `[T; N]` 完全一致。
+/// toy 函数可以写数组形参的元素（`a[0] = v`），所以只接受 `&mut`
+impl<T: ToyArg<Abi = T>, const N: usize> ToyArg for &mut [T; N] {
+    type Abi = *mut T;
+    fn matches(ty: &Type) -> bool {
+        matches!(ty, Type::Array(elem, len) if *len == N && T::matches(elem))
+    }
+    fn describe() -> String {
+        format!("&mut [{}; {}]", T::describe(), N)
+    }
+    fn into_abi(self) -> *mut T {
+        self.as_mut_ptr()
+    }
+}
+
+/// toy 字符串按指向内容的指针传递，内容之前带长度头（见 `runtime::string`）
+impl ToyArg for &ToyString {
+    type Abi = *const c_char;
+    fn matches(ty: &Type) -> bool {
+        *ty == Type::String
+    }
+    fn describe() -> String {
+        "&ToyString".to_string()
+    }
+    fn into_abi(self) -> *const c_char {
+        self.as_ptr()
+    }
+}
+
+/// 参数列表：由 `ToyArg` 组成的元组（最多 8 个参数）
+pub trait ToyArgs {
+    fn matches(params: &[Type]) -> bool;
+    fn describe() -> String;
+    /// # Safety
+    /// `code` 必须指向一个参数与返回类型都与 `Self`/`R` 匹配的函数
+    unsafe fn call<R: ToyRet>(self, code: *const u8) -> R;
+}
+
+macro_rules! impl_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: ToyArg),*> ToyArgs for ($($arg,)*) {
+            fn matches(params: &[Type]) -> bool {
+                let expected: &[fn(&Type) -> bool] = &[$($arg::matches),*];
+                params.len() == expected.len()
+                    && params.iter().zip(expected).all(|(ty, matches)| matches(ty))
+            }
+
+            fn describe() -> String {
+                let names: Vec<String> = vec![$($arg::describe()),*];
+                format!("({})", names.join(", "))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn call<R: ToyRet>(self, code: *const u8) -> R {
+                let ($($arg,)*) = self;
+                let f = unsafe {
+                    mem::transmute::<*const u8, extern "C" fn($($arg::Abi),*) -> R::Abi>(code)
+                };
+                R::from_abi(f($($arg.into_abi()),*))
+            }
+        }
+    };
+}
+
+impl_args!();
+impl_args!(A);
+impl_args!(A, B);
+impl_args!(A, B, C);
+impl_args!(A, B, C, D);
+impl_args!(A, B, C, D, E);
+impl_args!(A, B, C, D, E, F);
+impl_args!(A, B, C, D, E, F, G);
+impl_args!(A, B, C, D, E, F, G, H);
+
+/// 已校验签名的 toy 函数句柄，由 `JIT::get_func` 创建
+pub struct Func<'jit, Args, Ret> {
+    code: *const u8,
+    _jit: PhantomData<&'jit JIT>,
+    _signature: PhantomData<fn(Args) -> Ret>,
+}
+
+impl<Args, Ret> Clone for Func<'_, Args, Ret> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Args, Ret> Copy for Func<'_, Args, Ret> {}
+
+impl<'jit, Args: ToyArgs, Ret: ToyRet> Func<'jit, Args, Ret> {
+    /// 调用方必须保证 `code` 是 `jit` 中与 `Args`/`Ret` 签名一致的函数
+    pub(crate) unsafe fn new(_jit: &'jit JIT, code: *const u8) -> Self {
+        Func {
+            code,
+            _jit: PhantomData,
+            _signature: PhantomData,
+        }
+    }
+
+    pub fn call(&self, args: Args) -> Ret {
+        // SAFETY: 签名已在 JIT::get_func 中校验；'jit 保证机器码仍然有效
+        unsafe { args.call(self.code) }
+    }
+
+    /// 机器码地址
+    pub fn as_ptr(&self) -> *const u8 {
+        self.code
+    }
+}
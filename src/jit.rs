@@ -1,15 +1,28 @@
-use crate::frontend::{Expr, Type as FrontendType, parser};
-use crate::optimizer;
+use crate::error::{CallError, CompileError};
+use crate::frontend::{
+    AssignOp, Expr, ExprKind, Function, GlobalKind, MatchArm, Pattern, Program, Span,
+    Type as FrontendType, parser,
+};
+use crate::func::{Func, ToyArgs, ToyRet};
 use crate::ownership;
-use crate::runtime;
+use crate::pipeline::{self, Artifact, CompileOptions, Emit};
+use crate::runtime::{self, string};
 use crate::type_checker::{self, TypeChecker};
-use cranelift::codegen::ir::BlockArg;
+use crate::value::{self, CallFrame, MarshalError};
+use cranelift::codegen::entity::SecondaryMap;
 use cranelift::codegen::ir::InstBuilder;
+use cranelift::codegen::ir::entities::AnyEntity;
+use cranelift::codegen::ir::{BlockArg, ExternalName, FuncRef, Inst};
 use cranelift::codegen::ir::{StackSlotData, StackSlotKind};
+use cranelift::codegen::isa::TargetFrontendConfig;
+use cranelift::codegen::write::{FuncWriter, PlainWriter, decorate_function};
+use cranelift::frontend::Switch;
 use cranelift::prelude::*;
 use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::{DataDescription, Linkage, Module};
+use cranelift_module::{DataDescription, FuncId, Linkage, Module};
 use std::collections::HashMap;
+use std::fmt;
+use std::mem;
 use std::slice;
 
 /// 基础 JIT 类。
@@ -32,13 +45,39 @@ pub struct JIT {
 
     /// 类型检查器和函数签名注册表
     type_checker: TypeChecker,
+
+    /// 已编译完成的 toy 函数：函数名 → 机器码指针，供 `get_func` 查找
+    compiled: HashMap<String, *const u8>,
+
+    /// `call` 使用的调用跳板：函数名 → 跳板机器码指针
+    trampolines: HashMap<String, *const u8>,
+
+    /// 优化级别与需要输出的产物
+    options: CompileOptions,
+
+    /// 按 `options.emit` 收集的编译产物
+    artifacts: Vec<Artifact>,
 }
 
 impl Default for JIT {
     fn default() -> Self {
+        Self::new(CompileOptions::default())
+    }
+}
+
+impl JIT {
+    /// 按给定的优化级别与产物选项创建 JIT
+    pub fn new(options: CompileOptions) -> Self {
         let mut flag_builder = settings::builder();
         flag_builder.set("use_colocated_libcalls", "false").unwrap();
         flag_builder.set("is_pic", "false").unwrap();
+        flag_builder
+            .set("opt_level", options.opt_level.as_setting())
+            .unwrap();
+        // i128 / complex128 参数和返回值在 x86-64 上需要这个扩展（按两个寄存器传递）
+        flag_builder
+            .set("enable_llvm_abi_extensions", "true")
+            .unwrap();
         let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
             panic!("host machine is not supported: {}", msg);
         });
@@ -57,86 +96,441 @@ impl Default for JIT {
             data_description: DataDescription::new(),
             module,
             type_checker: TypeChecker::new(),
+            compiled: HashMap::new(),
+            trampolines: HashMap::new(),
+            options,
+            artifacts: Vec::new(),
         }
     }
-}
 
-impl JIT {
     /// Compile a string in the toy language into machine code.
-    pub fn compile(&mut self, input: &str) -> Result<*const u8, String> {
+    ///
+    /// 出错时返回 `CompileError`，可用 `CompileError::render` 渲染为带源码位置的诊断。
+    pub fn compile(&mut self, input: &str) -> Result<*const u8, CompileError> {
         // 首先，解析字符串，生成AST节点
+        let function = parser::function(input).map_err(CompileError::Parse)?;
         let (name, params, the_return, stmts) =
-            parser::function(input).map_err(|e| e.to_string())?;
+            pipeline::resolve_signature(&self.type_checker, function)?;
 
-        // 应用常量折叠优化
-        let stmts = optimizer::fold_constants_in_stmts(stmts);
+        // 先声明函数，使递归调用能查到它的真实签名
+        let id = self.declare_function(&name, &params, &the_return)?;
+        self.define_function(id, name.clone(), params, the_return, stmts)?;
 
-        // ═══════════════════════════════════════════════════════════
-        // 阶段 1: 编译期所有权检查 (内存回收机制 — 编译期静态分析)
-        // ═══════════════════════════════════════════════════════════
-        //
-        // 所有权检查器遍历 AST，追踪每个 DynamicArray 的状态。
-        // 成功时输出 ScopeAnalysis — 每个作用域应释放哪些数组的"清单"。
-        // 失败时返回错误（LeakedArray / DoubleDrop / UseAfterDrop 等）。
-        //
-        // ScopeAnalysis 随后传递给 FunctionTranslator，实现统一的
-        // "编译期检查 → JIT 运行时释放"数据流。
-        let scope_analysis = {
-            let mut checker = ownership::OwnershipChecker::new();
-            let (analysis, errors) = checker.analyze_function(&params, &stmts, &the_return.0);
-            if !errors.is_empty() {
-                let error_msgs: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
-                return Err(format!("ownership errors:\n{}", error_msgs.join("\n")));
-            }
-            analysis
+        // 最终ize定义的函数
+        self.finalize()?;
+
+        // 现在可以检索指向机器码的指针
+        let code = self.module.get_finalized_function(id);
+        self.compiled.insert(name, code);
+
+        Ok(code)
+    }
+
+    /// 将包含多个函数的 toy 源码编译为同一个模块。
+    ///
+    /// 所有函数先统一声明、再逐个翻译，因此函数之间可以前向引用和互相递归。
+    /// 返回 函数名 → 机器码指针 的映射表。
+    pub fn compile_program(
+        &mut self,
+        input: &str,
+    ) -> Result<HashMap<String, *const u8>, CompileError> {
+        let program = parser::program(input).map_err(CompileError::Parse)?;
+        self.compile_module(program)
+    }
+
+    /// 与 `compile_program` 相同，但输入是已经解析好的程序：
+    /// 先登记并检查全部常量、静态变量、结构体与枚举，为静态变量定义数据对象，再编译其中的函数
+    pub fn compile_module(
+        &mut self,
+        program: Program,
+    ) -> Result<HashMap<String, *const u8>, CompileError> {
+        let (structs, enums, functions, globals) = program.split();
+        let mut errors = pipeline::register_globals(&mut self.type_checker, &globals);
+        errors.extend(pipeline::register_types(
+            &mut self.type_checker,
+            &structs,
+            &enums,
+        ));
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
+        }
+        for def in &globals {
+            if def.kind != GlobalKind::Const {
+                self.define_static(&def.name)?;
+            }
+        }
+        self.compile_functions(functions)
+    }
+
+    /// 只检查已解析的程序而不编译，JIT 的状态保持不变。程序中可以使用已编译的函数、类型与全局变量；
+    /// 与 `compile_module` 一样只返回第一个错误
+    pub fn check_module(&self, program: Program) -> Result<(), CompileError> {
+        let errors =
+            pipeline::check_module(self.type_checker.clone(), program, self.options.strict);
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// 与 `compile_program` 相同，但输入是已经解析好的函数定义
+    /// （例如 REPL 把一条表达式包装成的匿名函数）
+    pub fn compile_functions(
+        &mut self,
+        functions: Vec<Function>,
+    ) -> Result<HashMap<String, *const u8>, CompileError> {
+        let functions = functions
+            .into_iter()
+            .map(|f| pipeline::resolve_signature(&self.type_checker, f))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // 阶段 0: 声明全部函数 (Linkage::Export)，翻译阶段的调用可直接引用
+        let mut ids = Vec::with_capacity(functions.len());
+        for (name, params, the_return, _) in &functions {
+            if ids.iter().any(|(n, _)| n == name) {
+                return Err(CompileError::Linking(format!(
+                    "duplicate definition of function '{}'",
+                    name
+                )));
+            }
+            let id = self.declare_function(name, params, the_return)?;
+            ids.push((name.clone(), id));
+        }
+
+        for ((name, params, the_return, stmts), (_, id)) in functions.into_iter().zip(&ids) {
+            self.define_function(*id, name, params, the_return, stmts)?;
+        }
+
+        // 整个模块只 finalize 一次
+        self.finalize()?;
+
+        let code: HashMap<_, _> = ids
+            .into_iter()
+            .map(|(name, id)| (name, self.module.get_finalized_function(id)))
+            .collect();
+        self.compiled
+            .extend(code.iter().map(|(name, ptr)| (name.clone(), *ptr)));
+        Ok(code)
+    }
+
+    /// 按名字取得一个已编译 toy 函数的类型化句柄。
+    ///
+    /// `Args` 是参数元组、`Ret` 是返回类型，例如 `get_func::<(f64, f64), f64>("add")`。
+    /// 二者必须与 toy 函数的签名逐项一致，否则返回 `CallError::SignatureMismatch`。
+    pub fn get_func<Args: ToyArgs, Ret: ToyRet>(
+        &self,
+        name: &str,
+    ) -> Result<Func<'_, Args, Ret>, CallError> {
+        let (code, sig) = self
+            .compiled
+            .get(name)
+            .zip(self.type_checker.resolve_func(name))
+            .ok_or_else(|| CallError::UnknownFunction(name.to_string()))?;
+
+        if !Args::matches(&sig.params) || !Ret::matches(&sig.ret) {
+            return Err(CallError::SignatureMismatch {
+                function: name.to_string(),
+                expected: sig.to_string(),
+                found: format!("{} -> {}", Args::describe(), Ret::describe()),
+            });
+        }
+        // SAFETY: 上面已确认 Args/Ret 与 toy 签名一致
+        Ok(unsafe { Func::new(self, *code) })
+    }
+
+    /// 已编译 toy 函数登记的签名（未编译过该名字时返回 None）
+    pub fn signature(&self, name: &str) -> Option<&type_checker::FunctionSignature> {
+        self.compiled
+            .get(name)
+            .and(self.type_checker.resolve_func(name))
+    }
+
+    /// 登记了全部已编译函数签名与内置函数的类型检查器
+    pub fn type_checker(&self) -> &TypeChecker {
+        &self.type_checker
+    }
+
+    /// 按名字动态调用一个 toy 函数或外部函数，实参与返回值都是 `value::Value`。
+    ///
+    /// 实参按函数登记的签名检查并编组（外部函数的定长数组展开为 (ptr, len)），
+    /// 不一致时返回 `CallError::SignatureMismatch`。适用于运行时才知道签名的宿主工具；
+    /// 签名在编译期已知时优先使用 `get_func`。
+    pub fn call(&mut self, name: &str, args: &[value::Value]) -> Result<value::Value, CallError> {
+        let sig = self
+            .type_checker
+            .resolve_func(name)
+            .filter(|s| s.is_external || self.compiled.contains_key(name))
+            .cloned()
+            .ok_or_else(|| CallError::UnknownFunction(name.to_string()))?;
+
+        let marshal_error = |e| match e {
+            MarshalError::Mismatch => CallError::SignatureMismatch {
+                function: name.to_string(),
+                expected: sig.to_string(),
+                found: format!(
+                    "({})",
+                    args.iter()
+                        .map(|a| a.type_name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            MarshalError::Unsupported(ty) => CallError::UnsupportedType {
+                function: name.to_string(),
+                ty: ty.to_string(),
+            },
         };
 
-        // ═══════════════════════════════════════════════════════════
-        // 阶段 2: AST → Cranelift IR 翻译 (含运行时 auto-drop)
-        // ═══════════════════════════════════════════════════════════
-        self.translate(name.clone(), params, the_return, stmts, scope_analysis)?;
+        let mut frame = CallFrame::new(&sig, args).map_err(marshal_error)?;
+        let code = self
+            .trampoline(name, &frame.abi_params, to_cranelift_type(&sig.ret))
+            .map_err(|e| CallError::Linking(e.to_string()))?;
+
+        // SAFETY: 跳板按 frame.abi_params 读取参数槽，返回值写入 16 字节的 ret
+        let trampoline =
+            unsafe { mem::transmute::<*const u8, extern "C" fn(*const u128, *mut u128)>(code) };
+        let mut ret = 0u128;
+        trampoline(frame.slots.as_ptr(), &mut ret);
+        unsafe { frame.read_return(&sig.ret, ret) }.map_err(marshal_error)
+    }
 
-        // 最后，声明函数并定义它
-        // 导出函数，使外部代码可以调用它
+    /// 生成（或从缓存取出）`name` 的调用跳板：`fn(args: *const u128, ret: *mut u128)`。
+    /// 跳板从每个 16 字节参数槽中按 ABI 类型读出实参，调用目标函数，再把返回值写入 ret。
+    fn trampoline(
+        &mut self,
+        name: &str,
+        abi_params: &[types::Type],
+        abi_ret: types::Type,
+    ) -> Result<*const u8, CompileError> {
+        if let Some(code) = self.trampolines.get(name) {
+            return Ok(*code);
+        }
+
+        // 与 translate_call 一样按名字导入：toy 函数和注册的外部函数都能解析到
+        let mut callee_sig = self.module.make_signature();
+        for ty in abi_params {
+            callee_sig.params.push(AbiParam::new(*ty));
+        }
+        callee_sig.returns.push(AbiParam::new(abi_ret));
+        let callee = self
+            .module
+            .declare_function(name, Linkage::Import, &callee_sig)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
+
+        let ptr = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(ptr));
+        sig.params.push(AbiParam::new(ptr));
         let id = self
             .module
-            .declare_function(&name, Linkage::Export, &self.ctx.func.signature)
-            .map_err(|e| e.to_string())?;
+            .declare_anonymous_function(&sig)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
+
+        self.ctx.func.signature = sig;
+        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        builder.seal_block(entry_block);
 
+        let args_ptr = builder.block_params(entry_block)[0];
+        let ret_ptr = builder.block_params(entry_block)[1];
+        let args: Vec<Value> = abi_params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                let offset = (i * value::SLOT_SIZE) as i32;
+                builder
+                    .ins()
+                    .load(*ty, MemFlags::trusted(), args_ptr, offset)
+            })
+            .collect();
+        let local_callee = self.module.declare_func_in_func(callee, builder.func);
+        let call = builder.ins().call(local_callee, &args);
+        let result = builder.inst_results(call)[0];
+        builder.ins().store(MemFlags::trusted(), result, ret_ptr, 0);
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        let result =
+            self.module
+                .define_function(id, &mut self.ctx)
+                .map_err(|e| CompileError::Codegen {
+                    function: name.to_string(),
+                    message: e.to_string(),
+                    span: None,
+                });
+        self.module.clear_context(&mut self.ctx);
+        result?;
+        self.finalize()?;
+
+        let code = self.module.get_finalized_function(id);
+        self.trampolines.insert(name.to_string(), code);
+        Ok(code)
+    }
+
+    /// 按 toy 函数头在模块中声明一个导出函数
+    fn declare_function(
+        &mut self,
+        name: &str,
+        params: &[(String, FrontendType)],
+        the_return: &(String, FrontendType),
+    ) -> Result<FuncId, CompileError> {
+        let mut sig = self.module.make_signature();
+        push_abi_signature(&mut sig, params.iter().map(|(_, ty)| ty), &the_return.1);
+
+        let id = self
+            .module
+            .declare_function(name, Linkage::Export, &sig)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
+
+        // 登记到类型检查器，供其他函数调用时做参数/返回类型检查
+        self.type_checker.register_function(
+            name,
+            params.iter().map(|(_, ty)| ty.clone()).collect(),
+            the_return.1.clone(),
+        );
+        Ok(id)
+    }
+
+    /// 对单个已声明的函数执行 类型检查 → 常量折叠 → 所有权检查 → 翻译 → 定义。
+    fn define_function(
+        &mut self,
+        id: FuncId,
+        name: String,
+        params: Vec<(String, FrontendType)>,
+        the_return: (String, FrontendType),
+        stmts: Vec<Expr>,
+    ) -> Result<(), CompileError> {
+        // ═══════════════════════════════════════════════════════════
+        // 阶段 1: 名字解析 → 类型检查 → 常量折叠 → 编译期所有权检查 (见 pipeline::check_function)
+        // ═══════════════════════════════════════════════════════════
+        let (stmts, scope_analysis) = pipeline::check_function(
+            &self.type_checker,
+            &params,
+            &the_return,
+            stmts,
+            self.options.strict,
+        )?;
+
+        // ═══════════════════════════════════════════════════════════
+        // 阶段 2: AST → Cranelift IR 翻译 (含运行时 auto-drop)
+        // ═══════════════════════════════════════════════════════════
         // 定义函数，将Cranelift IR转换为机器码
-        self.module
-            .define_function(id, &mut self.ctx)
-            .map_err(|e| e.to_string())?;
+        self.ctx.set_disasm(self.options.emit.contains(&Emit::Asm));
+        let result = self
+            .translate(name.clone(), params, the_return, stmts, scope_analysis)
+            .and_then(|()| {
+                // 这里的失败来自 Cranelift 的 IR 校验或代码生成
+                self.module
+                    .define_function(id, &mut self.ctx)
+                    .map_err(|e| CompileError::Codegen {
+                        function: name.clone(),
+                        message: e.to_string(),
+                        span: None,
+                    })
+            });
+        if result.is_ok() {
+            self.collect_artifacts(&name);
+        }
 
-        // 编译完成后，清除上下文状态
+        // 编译完成后（无论成功与否），清除上下文状态。
+        // 翻译中途失败时 FunctionBuilder 没有 finalize，构建器上下文里还残留着
+        // 半成品的 SSA 状态，需要重置，JIT 才能继续编译后面的代码
         self.module.clear_context(&mut self.ctx);
+        if result.is_err() {
+            self.builder_context = FunctionBuilderContext::new();
+        }
 
-        // 最终ize定义的函数
-        self.module.finalize_definitions().unwrap();
+        result
+    }
 
-        // 现在可以检索指向机器码的指针
-        let code = self.module.get_finalized_function(id);
+    /// 按 `options.emit` 记录刚定义完的函数的产物（define_function 之后、clear_context 之前调用）
+    fn collect_artifacts(&mut self, name: &str) {
+        for kind in self.options.emit.clone() {
+            let text = match kind {
+                // define_function 已在 ctx.func 上原地完成优化
+                Emit::Clif => {
+                    let mut text = String::new();
+                    let mut writer = SymbolWriter {
+                        module: &self.module,
+                    };
+                    decorate_function(&mut writer, &mut text, &self.ctx.func)
+                        .ok()
+                        .map(|()| text)
+                }
+                Emit::Asm => self.ctx.compiled_code().and_then(|c| c.vcode.clone()),
+            };
+            if let Some(text) = text {
+                self.artifacts.push(Artifact {
+                    function: name.to_string(),
+                    kind,
+                    text,
+                });
+            }
+        }
+    }
 
-        Ok(code)
+    /// 到目前为止收集到的编译产物（按编译顺序）
+    pub fn artifacts(&self) -> &[Artifact] {
+        &self.artifacts
+    }
+
+    /// 完成模块中所有已定义函数/数据的重定位，之后才能取得机器码指针
+    fn finalize(&mut self) -> Result<(), CompileError> {
+        self.module
+            .finalize_definitions()
+            .map_err(|e| CompileError::Linking(e.to_string()))
     }
 
     /// 创建一个零初始化的数据段
-    pub fn create_data(&mut self, name: &str, contents: Vec<u8>) -> Result<&[u8], String> {
+    pub fn create_data(&mut self, name: &str, contents: Vec<u8>) -> Result<&[u8], CompileError> {
         self.data_description.define(contents.into_boxed_slice());
         let id = self
             .module
             .declare_data(name, Linkage::Export, true, false)
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
 
         self.module
             .define_data(id, &self.data_description)
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
         self.data_description.clear();
-        self.module.finalize_definitions().unwrap();
+        self.finalize()?;
         let buffer = self.module.get_finalized_data(id);
         Ok(unsafe { slice::from_raw_parts(buffer.0, buffer.1) })
     }
 
+    /// 为已登记的静态变量 `name` 定义数据对象，内容是初值按 Cranelift 表示编码的字节。
+    /// `static mut` 放在可写的数据段，函数通过 `symbol_value` 取得地址后读写它
+    fn define_static(&mut self, name: &str) -> Result<(), CompileError> {
+        let def = &self.type_checker.globals[name];
+        // register_globals 已把初值折叠成字面量，检查失败的声明不会走到这里
+        let ExprKind::Literal(text, _) = &def.value.kind else {
+            return Err(CompileError::Linking(format!(
+                "initializer of '{}' is not a constant expression",
+                name
+            )));
+        };
+        let value = value::Value::parse(&def.ty, text).map_err(CompileError::Linking)?;
+        let size = to_cranelift_type(&def.ty).bytes() as usize;
+        let writable = def.kind == GlobalKind::StaticMut;
+        let mut contents = vec![0u8; size];
+        value.encode_scalar(&mut contents);
+
+        self.data_description.define(contents.into_boxed_slice());
+        self.data_description.set_align(size as u64);
+        let id = self
+            .module
+            .declare_data(name, Linkage::Export, writable, false)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
+        let result = self
+            .module
+            .define_data(id, &self.data_description)
+            .map_err(|e| CompileError::Linking(e.to_string()));
+        self.data_description.clear();
+        result
+    }
+
     // 将toy语言的AST节点转换为Cranelift IR
     /// 参数:
     /// - name: 函数名
@@ -150,22 +544,13 @@ impl JIT {
         the_return: (String, FrontendType),
         stmts: Vec<Expr>,
         scope_analysis: ownership::ScopeAnalysis,
-    ) -> Result<(), String> {
-        // 将参数类型添加到函数签名中
-        for (_, ty) in &params {
-            self.ctx
-                .func
-                .signature
-                .params
-                .push(AbiParam::new(to_cranelift_type(ty)));
-        }
-
-        // 将返回类型添加到函数签名中
-        self.ctx
-            .func
-            .signature
-            .returns
-            .push(AbiParam::new(to_cranelift_type(&the_return.1)));
+    ) -> Result<(), CompileError> {
+        // 将参数类型与返回类型添加到函数签名中（返回结构体时多一个隐藏的首参数）
+        push_abi_signature(
+            &mut self.ctx.func.signature,
+            params.iter().map(|(_, ty)| ty),
+            &the_return.1,
+        );
 
         // 创建函数构建器并设置入口块
         let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
@@ -180,7 +565,15 @@ impl JIT {
         // 使用的都是 Cranelift IR 的库函数，builder.func.signature 是 Cranelift IR 中的函数签名对象，builder.create_block() 创建一个新的基本块，builder.append_block_params_for_function_params() 将函数参数绑定到基本块参数，builder.switch_to_block() 切换当前构建器上下文到指定的基本块，builder.seal_block() 标记基本块为封闭状态（不再接受新的前驱块）。
 
         // 声明所有变量（参数、返回变量及隐式变量）
-        let variables = declare_variables(&mut builder, &params, &stmts, entry_block, &the_return);
+        let variables = declare_variables(
+            &mut builder,
+            self.module.target_config(),
+            &self.type_checker,
+            &params,
+            &stmts,
+            entry_block,
+            &the_return,
+        );
 
         // 创建表达式翻译器（消费 ownership checker 输出的 ScopeAnalysis）
         let mut trans = FunctionTranslator {
@@ -188,25 +581,27 @@ impl JIT {
             variables,
             module: &mut self.module,
             current_func_name: name,
-            current_func_ret_type: to_cranelift_type(&the_return.1),
+            return_name: the_return.0.clone(),
             string_counter: 0,
             type_checker: &self.type_checker,
             // 内存回收系统(新增）
             scope_analysis,    // 标注了每个作用域层有哪些数组要释放
             scope_depth: 0,    // 当前作用域深度，初始为 0（函数体顶层）
+            scope_spans: Vec::new(),
             explicitly_dropped: Vec::new(),  // 记录已通过 drop() 显式释放的变量，避免 auto-drop 重复释放
+            loops: Vec::new(),
         };
 
         // 逐条翻译函数体语句
         for expr in stmts {
-            trans.translate_expr(expr);
+            trans.translate_expr(expr)?;
         }
 
         // 读取返回变量的值并生成 return 指令，即变量可以被 def_var 反复重新绑定，每次赋新值。use_var 永远读最新的那次绑定。所以不管函数体里 r 被改了多少次，这里拿到的就是最终值
-        let (return_variable, _return_ty) = trans
-            .variables
-            .get(&the_return.0)
-            .expect("return variable not defined");
+        let (return_variable, _return_ty) =
+            trans.variables.get(&the_return.0).ok_or_else(|| {
+                trans.error(format!("return variable '{}' not defined", the_return.0))
+            })?;
         let return_value = trans.builder.use_var(*return_variable);
 
         // ═══════════════════════════════════════════════════════════
@@ -221,7 +616,7 @@ impl JIT {
         //
         // 块作用域和循环作用域的释放由 translate_expr 中的 Block/WhileLoop
         // 分支在翻译时即时处理（Phase 3），不在此处集中处理。
-        trans.emit_scope_drop(0, Some(*return_variable));
+        trans.emit_scope_drop(0, Some(*return_variable))?;
         //                             ↑ cranelift-frontend::FunctionBuilder
 
         trans.builder.ins().return_(&[return_value]);
@@ -232,8 +627,67 @@ impl JIT {
     }
 }
 
-fn to_cranelift_type(t: &FrontendType) -> types::Type {
+/// 输出 CLIF 文本时在外部函数的声明后注明符号名，例如 `fn2 = u0:3 sig2 ; array_drop`，
+/// 否则调用只显示为 `call fn2(v5)`，看不出调用的是哪个运行时函数
+struct SymbolWriter<'a> {
+    module: &'a JITModule,
+}
+
+impl FuncWriter for SymbolWriter<'_> {
+    fn write_block_header(
+        &mut self,
+        w: &mut dyn fmt::Write,
+        func: &codegen::ir::Function,
+        block: Block,
+        indent: usize,
+    ) -> fmt::Result {
+        PlainWriter.write_block_header(w, func, block, indent)
+    }
+
+    fn write_instruction(
+        &mut self,
+        w: &mut dyn fmt::Write,
+        func: &codegen::ir::Function,
+        aliases: &SecondaryMap<Value, Vec<Value>>,
+        inst: Inst,
+        indent: usize,
+    ) -> fmt::Result {
+        PlainWriter.write_instruction(w, func, aliases, inst, indent)
+    }
+
+    fn write_entity_definition(
+        &mut self,
+        w: &mut dyn fmt::Write,
+        func: &codegen::ir::Function,
+        entity: AnyEntity,
+        value: &dyn fmt::Display,
+        maybe_fact: Option<&codegen::ir::pcc::Fact>,
+    ) -> fmt::Result {
+        // 模块把函数声明为命名空间 0、序号为 FuncId 的外部名字
+        let symbol = match entity {
+            AnyEntity::FuncRef(func_ref) => match &func.dfg.ext_funcs[func_ref].name {
+                ExternalName::User(name) => {
+                    let name = &func.params.user_named_funcs()[*name];
+                    (name.namespace == 0).then(|| {
+                        let id = FuncId::from_u32(name.index);
+                        let decl = self.module.declarations().get_function_decl(id);
+                        decl.linkage_name(id).into_owned()
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        match symbol {
+            Some(symbol) => writeln!(w, "    {} = {} ; {}", entity, value, symbol),
+            None => self.super_entity_definition(w, func, entity, value, maybe_fact),
+        }
+    }
+}
+
+pub(crate) fn to_cranelift_type(t: &FrontendType) -> types::Type {
     match t {
+        FrontendType::Bool => types::I8, // 0 / 1
         FrontendType::I8 => types::I8,
         FrontendType::I16 => types::I16,
         FrontendType::I32 => types::I32,
@@ -246,13 +700,75 @@ fn to_cranelift_type(t: &FrontendType) -> types::Type {
         FrontendType::Complex128 => types::I128,     // 打包的 2xf64
         FrontendType::Array(_, _) => types::I64,     // 指针
         FrontendType::DynamicArray(_) => types::I64, // 指向 DynamicArray 结构体的指针
+        FrontendType::Struct(_) => types::I64,       // 指向结构体存储的指针
+        // 长度是常量名的数组在编译前已换成 Array，与它相同
+        FrontendType::ConstLenArray(..) => types::I64,
     }
 }
 
+/// 按 toy 函数头填写 Cranelift 签名。结构体参数按指针传递；
+/// 返回结构体的函数多一个隐藏的首参数 (sret)：调用者分配的返回值存储地址，
+/// 函数把结果写入其中并返回这个地址
+fn push_abi_signature<'t>(
+    sig: &mut Signature,
+    params: impl IntoIterator<Item = &'t FrontendType>,
+    ret: &FrontendType,
+) {
+    if matches!(ret, FrontendType::Struct(_)) {
+        sig.params.push(AbiParam::new(types::I64));
+    }
+    for ty in params {
+        sig.params.push(AbiParam::new(to_cranelift_type(ty)));
+    }
+    sig.returns.push(AbiParam::new(to_cranelift_type(ret)));
+}
+
+/// 在栈上为结构体（或定长数组）分配一块清零的存储，返回其地址。
+/// 清零保证从未赋值的动态数组字段是空指针，作用域退出时的 auto-drop 对它是 no-op
+fn alloc_zeroed(
+    builder: &mut FunctionBuilder,
+    config: TargetFrontendConfig,
+    type_checker: &TypeChecker,
+    ty: &FrontendType,
+) -> Value {
+    let size = type_checker.size_of(ty);
+    let align = type_checker.align_of(ty);
+    let slot = builder.create_sized_stack_slot(StackSlotData::new(
+        StackSlotKind::ExplicitSlot,
+        size,
+        align.trailing_zeros() as u8,
+    ));
+    let addr = builder.ins().stack_addr(types::I64, slot, 0);
+    builder.emit_small_memset(config, addr, 0, size as u64, align as u8, MemFlags::new());
+    addr
+}
+
+/// 把 `p.a.items` 这样的字段路径拆成 (基表达式 `p.a`, 最后一级字段名 `items`)
+fn split_field_path(path: &str) -> Option<(Expr, String)> {
+    let mut parts = path.split('.');
+    let mut base = Expr::from(ExprKind::Identifier(parts.next()?.to_string()));
+    let mut field = parts.next()?.to_string();
+    for part in parts {
+        base = Expr::from(ExprKind::Field(Box::new(base), field));
+        field = part.to_string();
+    }
+    Some((base, field))
+}
+
 fn is_complex(t: &FrontendType) -> bool {
     matches!(t, FrontendType::Complex64 | FrontendType::Complex128)
 }
 
+/// 值是否按值内嵌在所在的内存中（没有单独的指针）：结构体总是内嵌，
+/// 定长数组只在结构体字段中内嵌，作为数组元素时存的是指针
+fn is_inline(t: &FrontendType, in_struct: bool) -> bool {
+    match t {
+        FrontendType::Struct(_) => true,
+        FrontendType::Array(..) => in_struct,
+        _ => false,
+    }
+}
+
 enum BinOp {
     Add,
     Sub,
@@ -260,6 +776,18 @@ enum BinOp {
     Div,
 }
 
+/// 正在翻译的循环：break / continue 的跳转目标
+struct LoopContext {
+    /// while 为头块（重新求值条件），for 为步进块
+    continue_block: Block,
+    exit_block: Block,
+    /// 循环体的作用域深度，跳出前释放从当前深度到这一层的数组
+    scope_depth: usize,
+    /// `for x in array [...]` 遍历的临时 DynamicArray 及其 drop 函数，
+    /// 在出口块释放；循环内提前 return 时由 emit_return_drops 释放
+    temporary: Option<(Value, &'static str)>,
+}
+
 /// 函数翻译器 — 消费 ScopeAnalysis, 在翻译过程中插入 auto-drop 指令。
 ///
 /// # 内存回收中的角色
@@ -270,6 +798,7 @@ enum BinOp {
 /// - **函数返回前**: 遍历 scope_vars[0], 释放顶层未 drop/return 的数组
 /// - **Block 退出时**: scope_depth++, 翻译块内语句, emit_scope_drop(depth)
 /// - **While 每次迭代**: 循环体翻译后, emit_scope_drop(loop_depth)
+/// - **break / continue**: 跳转前对当前深度到循环体深度逐层 emit_scope_drop
 ///
 /// `explicitly_dropped` 列表确保已通过 `drop(arr)` 手动释放的变量
 /// 不会被 auto-drop 再次释放 (防止 double-free)。
@@ -278,24 +807,52 @@ struct FunctionTranslator<'a> {
     variables: HashMap<String, (Variable, FrontendType)>, // 变量映射表
     module: &'a mut JITModule,                            // JIT模块引用
     current_func_name: String,                            // 当前函数名
-    current_func_ret_type: types::Type,                   // 当前函数返回类型
+    return_name: String,                                  // 返回变量名
     string_counter: usize,
     type_checker: &'a TypeChecker,
     /// 作用域分析结果（由 ownership checker 预计算）。
     /// JIT 直接查询此结构，不再独立追踪作用域。
-    /// 键 = 作用域深度, 值 = 该深度内定义的 DynamicArray 变量名列表。
+    /// 顶层按深度 0 查 scope_vars，嵌套作用域按 Block / 循环的位置查 scopes。
     scope_analysis: ownership::ScopeAnalysis,
     /// 当前作用域深度。0 = 函数体顶层，每进入一层 Block/While body +1。
     /// emit_scope_drop(depth) 使用此值确定释放哪个作用域的数组。
     scope_depth: usize,
+    /// 由外到内嵌套的 Block / 循环表达式的位置，`scope_spans[d - 1]` 创建了深度 d 的作用域
+    scope_spans: Vec<Span>,
     /// 已通过 drop() 语句显式释放的 Cranelift Variable 集合。
     /// translate_drop() 在遇到 `drop(arr)` 时填充。
     /// emit_scope_drop() 遍历时会跳过此集合中的变量, 并在处理后清理条目,
     /// 防止跨迭代/跨作用域残留。
     explicitly_dropped: Vec<Variable>,
+    /// 由外到内嵌套的 while 循环
+    loops: Vec<LoopContext>,
 }
 
 impl<'a> FunctionTranslator<'a> {
+    /// 构造一个属于当前函数的 Codegen 错误；位置由 translate_expr 补上
+    fn error(&self, message: impl Into<String>) -> CompileError {
+        CompileError::Codegen {
+            function: self.current_func_name.clone(),
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// 在模块中声明一个外部（Import）函数并返回当前函数内可调用的句柄
+    fn import_function(&mut self, name: &str, sig: &Signature) -> Result<FuncRef, CompileError> {
+        let callee = self
+            .module
+            .declare_function(name, Linkage::Import, sig)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
+        Ok(self.module.declare_func_in_func(callee, self.builder.func))
+    }
+
+    /// 在当前变量表下推断表达式类型（用户函数调用按其声明的返回类型）
+    fn infer_type(&self, expr: &Expr) -> FrontendType {
+        self.type_checker
+            .infer_type(expr, &|n| self.variables.get(n).map(|(_, t)| t.clone()))
+    }
+
     /// 根据元素类型返回对应的 drop 函数名
     fn drop_func_for(elem_ty: &FrontendType) -> &'static str {
         match elem_ty {
@@ -314,7 +871,7 @@ impl<'a> FunctionTranslator<'a> {
     /// 在 Cranelift IR 中生成对 Rust 运行时释放函数的调用。
     ///
     /// 生成的 IR 等价于 C: `int64_t array_drop(int64_t arr_ptr);`
-    fn emit_drop_call(&mut self, drop_func_name: &str, val: Value) {
+    fn emit_drop_call(&mut self, drop_func_name: &str, val: Value) -> Result<(), CompileError> {
         // ① 构造 C 函数签名: fn(i64) -> i64
         //    cranelift-module: make_signature()
         //    cranelift-codegen: AbiParam, types
@@ -326,22 +883,17 @@ impl<'a> FunctionTranslator<'a> {
         //    cranelift-module: declare_function()
         //    运行时通过 register_builtins 将 "array_drop" 映射到
         //    Rust 函数 dynamic_array_drop_i64 的地址
-        let drop_callee = self
-            .module
-            .declare_function(drop_func_name, Linkage::Import, &drop_sig)
-            .unwrap();
-
+        //
         // ③ 将模块级符号解析为当前 IR 函数内部可引用的局部句柄
         //    cranelift-jit: declare_func_in_func()
         //    类比: 全局符号 → 当前编译单元的导入表条目
-        let drop_local = self
-            .module
-            .declare_func_in_func(drop_callee, self.builder.func);
+        let drop_local = self.import_function(drop_func_name, &drop_sig)?;
 
         // ④ 发射 call 指令: call @array_drop_xxx(val)
         //    cranelift-frontend: InsertBuilder (通过 self.builder.ins())
         //    最终由 finalize_definitions 将 call 目标链接到 Rust 运行时地址
         self.builder.ins().call(drop_local, &[val]);
+        Ok(())
     }
 
     /// 对指定作用域深度中所有未显式 drop 的 DynamicArray 发射 drop 调用。
@@ -363,6 +915,7 @@ impl<'a> FunctionTranslator<'a> {
     /// (translate_drop 已发射 call array_drop), 并在处理后清理条目, 避免:
     /// 1. 同一作用域内重复 drop
     /// 2. 循环下一次迭代时误跳过同名变量
+    ///
     /// fn demo() -> (r: i64) {
     //a = array [1, 2]        // scope_vars[0] = ["a", "b"]
     //b = array [3, 4]
@@ -372,49 +925,144 @@ impl<'a> FunctionTranslator<'a> {
     //drop(a)
     //r = 0
 //}
-    fn emit_scope_drop(&mut self, depth: usize, return_variable: Option<Variable>) {
-        if let Some(vars) = self.scope_analysis.scope_vars.get(&depth) {
-            let vars = vars.clone();
-            for name in &vars {
-                if let Some((var, ty)) = self.variables.get(name) {
-                    if let Some(ret_var) = return_variable
-                        && *var == ret_var    //跳过返回变量
+    fn emit_scope_drop(
+        &mut self,
+        depth: usize,
+        return_variable: Option<Variable>,
+    ) -> Result<(), CompileError> {
+        let vars = self.scope_vars_at(depth);
+        for name in &vars {
+            // 结构体中的动态数组字段（如 p.items）：释放后字段置空，重复释放是 no-op
+            if name.contains('.') {
+                self.drop_array_field(name)?;
+                continue;
+            }
+            if let Some((var, ty)) = self.variables.get(name) {
+                if let Some(ret_var) = return_variable
+                    && *var == ret_var    //跳过返回变量
+                {
+                    continue;
+                }
+                if self.explicitly_dropped.contains(var) {     //跳过已显式 drop 的变量
+                    self.explicitly_dropped.retain(|v| v != var);
+                    continue;
+                }
+                if let FrontendType::DynamicArray(elem_ty) = ty {  //不是 DynamicArray 不放
+                    let var = *var;
+                    let drop_func = Self::drop_func_for(elem_ty);
+                    let val = self.builder.use_var(var);
+                    self.emit_drop_call(drop_func, val)?;
+                    // 释放后置空：循环中提前 return 时，上一轮迭代留下的指针不会被再次释放
+                    let null = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+                    self.builder.def_var(var, null);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 提前 return 时，从当前作用域到函数顶层逐层释放 DynamicArray。
+    /// `keep` 中的变量（返回变量、被返回的数组）所有权转交给调用者，不释放
+    fn emit_return_drops(&mut self, keep: &[Variable]) -> Result<(), CompileError> {
+        self.emit_jump_drops(0, keep)?;
+        let temporaries: Vec<_> = self.loops.iter().filter_map(|l| l.temporary).collect();
+        for (val, drop_func) in temporaries {
+            self.emit_drop_call(drop_func, val)?;
+        }
+        Ok(())
+    }
+
+    /// return / break / continue 跳出作用域前，从当前作用域到 `to_depth` 逐层释放 DynamicArray。
+    ///
+    /// 不查 explicitly_dropped：它按翻译顺序记录，不区分控制流路径，
+    /// `if c { drop(a) } else { return 5 }` 中 else 分支翻译时 `a` 已在列表里，
+    /// 但这条路径上 `a` 并没有被释放。translate_drop 释放后把变量置空，
+    /// 运行时的释放函数对空指针是 no-op，所以这里一律释放
+    fn emit_jump_drops(&mut self, to_depth: usize, keep: &[Variable]) -> Result<(), CompileError> {
+        for depth in (to_depth..=self.scope_depth).rev() {
+            for name in &self.scope_vars_at(depth) {
+                if let Some((root, _)) = name.split_once('.') {
+                    // 被返回的结构体连同它的数组字段一起交给调用者
+                    if !self
+                        .variables
+                        .get(root)
+                        .is_some_and(|(var, _)| keep.contains(var))
                     {
-                        continue;
-                    }
-                    if self.explicitly_dropped.contains(var) {     //跳过已显式 drop 的变量
-                        self.explicitly_dropped.retain(|v| v != var);
-                        continue;
-                    }
-                    if let FrontendType::DynamicArray(elem_ty) = ty {  //不是 DynamicArray 不放
-                        let drop_func = Self::drop_func_for(elem_ty);
-                        let val = self.builder.use_var(*var);
-                        self.emit_drop_call(drop_func, val);
+                        self.drop_array_field(name)?;
                     }
+                    continue;
+                }
+                if let Some((var, FrontendType::DynamicArray(elem_ty))) = self.variables.get(name)
+                    && !keep.contains(var)
+                {
+                    let var = *var;
+                    let drop_func = Self::drop_func_for(elem_ty);
+                    let val = self.builder.use_var(var);
+                    self.emit_drop_call(drop_func, val)?;
+                    // 置空：continue 之后的下一次迭代若在重新赋值前 return，不会再次释放
+                    let null = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+                    self.builder.def_var(var, null);
                 }
             }
         }
+        Ok(())
     }
 
-    fn translate_expr(&mut self, expr: Expr) -> Value {
+    /// 深度 `depth` 的作用域中定义的 DynamicArray 变量名
+    fn scope_vars_at(&self, depth: usize) -> Vec<String> {
+        let vars = match depth {
+            0 => self.scope_analysis.scope_vars.get(&0),
+            _ => self.scope_analysis.scopes.get(&self.scope_spans[depth - 1]),
+        };
+        vars.cloned().unwrap_or_default()
+    }
+
+    /// 进入 `span` 处的 Block / 循环创建的作用域
+    fn enter_scope(&mut self, span: Span) {
+        self.scope_depth += 1;
+        self.scope_spans.push(span);
+    }
+
+    fn leave_scope(&mut self) {
+        self.scope_depth -= 1;
+        self.scope_spans.pop();
+    }
+
+    /// 翻译一个表达式；失败时把表达式的位置附加到错误上
+    fn translate_expr(&mut self, expr: Expr) -> Result<Value, CompileError> {
+        let span = expr.span;
+        self.translate_expr_kind(expr.kind, span)
+            .map_err(|e| e.or_span(span))
+    }
+
+    fn translate_expr_kind(&mut self, kind: ExprKind, span: Span) -> Result<Value, CompileError> {
         //梯度下降翻译
-        match expr {
-            Expr::Literal(val, ty) => {
+        let value = match kind {
+            ExprKind::Literal(val, ty) => {
                 //// 翻译字面量
                 let cl_ty: types::Type = to_cranelift_type(&ty);
                 match ty {
-                    FrontendType::F32 => self.builder.ins().f32const(
-                        val.parse::<f32>()
-                            .unwrap_or_else(|_| panic!("Invalid f32 literal: {}", val)),
-                    ),
-                    FrontendType::F64 => self.builder.ins().f64const(
-                        val.parse::<f64>()
-                            .unwrap_or_else(|_| panic!("Invalid f64 literal: {}", val)),
-                    ),
+                    FrontendType::Bool => {
+                        InstBuilder::iconst(self.builder.ins(), cl_ty, (val == "true") as i64)
+                    }
+                    FrontendType::F32 => {
+                        let f = val
+                            .parse::<f32>()
+                            .map_err(|_| self.error(format!("invalid f32 literal: {}", val)))?;
+                        self.builder.ins().f32const(f)
+                    }
+                    FrontendType::F64 => {
+                        let f = val
+                            .parse::<f64>()
+                            .map_err(|_| self.error(format!("invalid f64 literal: {}", val)))?;
+                        self.builder.ins().f64const(f)
+                    }
                     _ => {
+                        // i128 的最小值写作 Neg(2^127)，数值部分按 u128 解析后回绕，取负后正好还原
                         let int_val = val
                             .parse::<i128>()
-                            .unwrap_or_else(|_| panic!("Invalid integer literal: {}", val));
+                            .or_else(|_| val.parse::<u128>().map(|n| n as i128))
+                            .map_err(|_| self.error(format!("invalid integer literal: {}", val)))?;
                         if cl_ty == types::I128 {
                             // 将 i128 分解为两个 i64: 低位和高位
                             // i128 在内存中是小端存储 (低字节在前)
@@ -445,12 +1093,10 @@ impl<'a> FunctionTranslator<'a> {
                 }
             }
 
-            Expr::Add(lhs, rhs) => {
-                let ty = type_checker::infer_type(&lhs, &|n| {
-                    self.variables.get(n).map(|(_, t)| t.clone())
-                });
+            ExprKind::Add(lhs, rhs) => {
+                let ty = self.infer_type(&lhs);
                 if is_complex(&ty) {
-                    self.translate_complex_binop(*lhs, *rhs, BinOp::Add)
+                    self.translate_complex_binop(*lhs, *rhs, BinOp::Add)?
                 } else {
                     self.translate_binary_op(*lhs, *rhs, |b, l, r| {
                         let ty = b.func.dfg.value_type(l);
@@ -459,15 +1105,13 @@ impl<'a> FunctionTranslator<'a> {
                         } else {
                             b.ins().iadd(l, r)
                         }
-                    })
+                    })?
                 }
             }
-            Expr::Sub(lhs, rhs) => {
-                let ty = type_checker::infer_type(&lhs, &|n| {
-                    self.variables.get(n).map(|(_, t)| t.clone())
-                });
+            ExprKind::Sub(lhs, rhs) => {
+                let ty = self.infer_type(&lhs);
                 if is_complex(&ty) {
-                    self.translate_complex_binop(*lhs, *rhs, BinOp::Sub)
+                    self.translate_complex_binop(*lhs, *rhs, BinOp::Sub)?
                 } else {
                     self.translate_binary_op(*lhs, *rhs, |b, l, r| {
                         let ty = b.func.dfg.value_type(l);
@@ -476,15 +1120,13 @@ impl<'a> FunctionTranslator<'a> {
                         } else {
                             b.ins().isub(l, r)
                         }
-                    })
+                    })?
                 }
             }
-            Expr::Mul(lhs, rhs) => {
-                let ty = type_checker::infer_type(&lhs, &|n| {
-                    self.variables.get(n).map(|(_, t)| t.clone())
-                });
+            ExprKind::Mul(lhs, rhs) => {
+                let ty = self.infer_type(&lhs);
                 if is_complex(&ty) {
-                    self.translate_complex_binop(*lhs, *rhs, BinOp::Mul)
+                    self.translate_complex_binop(*lhs, *rhs, BinOp::Mul)?
                 } else {
                     self.translate_binary_op(*lhs, *rhs, |b, l, r| {
                         let ty = b.func.dfg.value_type(l);
@@ -493,105 +1135,200 @@ impl<'a> FunctionTranslator<'a> {
                         } else {
                             b.ins().imul(l, r)
                         }
-                    })
+                    })?
                 }
             }
-            Expr::Div(lhs, rhs) => {
-                let ty = type_checker::infer_type(&lhs, &|n| {
-                    self.variables.get(n).map(|(_, t)| t.clone())
-                });
+            ExprKind::Div(lhs, rhs) => {
+                let ty = self.infer_type(&lhs);
                 if is_complex(&ty) {
-                    self.translate_complex_binop(*lhs, *rhs, BinOp::Div)
+                    self.translate_complex_binop(*lhs, *rhs, BinOp::Div)?
                 } else {
-                    self.translate_binary_op(*lhs, *rhs, |b, l, r| {
-                        let ty = b.func.dfg.value_type(l);
-                        if ty.is_float() {
-                            b.ins().fdiv(l, r)
-                        } else {
-                            b.ins().sdiv(l, r)
-                        }
-                    })
+                    let l_val = self.translate_expr(*lhs)?;
+                    let r_val = self.translate_expr(*rhs)?;
+                    let (l, r) = self.promote_operands(l_val, r_val)?;
+                    if self.builder.func.dfg.value_type(l).is_float() {
+                        self.builder.ins().fdiv(l, r)
+                    } else {
+                        self.translate_int_div(l, r, false)?
+                    }
+                }
+            }
+            ExprKind::Rem(lhs, rhs) => {
+                let l_val = self.translate_expr(*lhs)?;
+                let r_val = self.translate_expr(*rhs)?;
+                let (l, r) = self.promote_operands(l_val, r_val)?;
+                if self.builder.func.dfg.value_type(l).is_float() {
+                    self.translate_fmod(l, r)?
+                } else {
+                    // 与 Rust 相同，余数与被除数同号；除数为 0 时 trap
+                    self.translate_int_div(l, r, true)?
+                }
+            }
+            ExprKind::BitAnd(lhs, rhs) => {
+                self.translate_binary_op(*lhs, *rhs, |b, l, r| b.ins().band(l, r))?
+            }
+            ExprKind::BitOr(lhs, rhs) => {
+                self.translate_binary_op(*lhs, *rhs, |b, l, r| b.ins().bor(l, r))?
+            }
+            ExprKind::BitXor(lhs, rhs) => {
+                self.translate_binary_op(*lhs, *rhs, |b, l, r| b.ins().bxor(l, r))?
+            }
+            // 移位量不做类型提升：Cranelift 允许它与左侧位宽不同，并按左侧位宽取模
+            ExprKind::Shl(lhs, rhs) => {
+                let l = self.translate_expr(*lhs)?;
+                let r = self.translate_expr(*rhs)?;
+                self.builder.ins().ishl(l, r)
+            }
+            ExprKind::Shr(lhs, rhs) => {
+                // toy 的整数都是有符号的，>> 是算术右移
+                let l = self.translate_expr(*lhs)?;
+                let r = self.translate_expr(*rhs)?;
+                self.builder.ins().sshr(l, r)
+            }
+            ExprKind::Neg(expr) => {
+                let val = self.translate_expr(*expr)?;
+                if self.builder.func.dfg.value_type(val).is_float() {
+                    self.builder.ins().fneg(val)
+                } else {
+                    self.builder.ins().ineg(val)
                 }
             }
 
-            Expr::Eq(lhs, rhs) => self.translate_cmp(*lhs, *rhs, IntCC::Equal, FloatCC::Equal),
-            Expr::Ne(lhs, rhs) => {
-                self.translate_cmp(*lhs, *rhs, IntCC::NotEqual, FloatCC::NotEqual)
+            ExprKind::Eq(lhs, rhs) => {
+                self.translate_cmp(*lhs, *rhs, IntCC::Equal, FloatCC::Equal)?
             }
-            Expr::Lt(lhs, rhs) => {
-                self.translate_cmp(*lhs, *rhs, IntCC::SignedLessThan, FloatCC::LessThan)
+            ExprKind::Ne(lhs, rhs) => {
+                self.translate_cmp(*lhs, *rhs, IntCC::NotEqual, FloatCC::NotEqual)?
             }
-            Expr::Le(lhs, rhs) => self.translate_cmp(
+            ExprKind::Lt(lhs, rhs) => {
+                self.translate_cmp(*lhs, *rhs, IntCC::SignedLessThan, FloatCC::LessThan)?
+            }
+            ExprKind::Le(lhs, rhs) => self.translate_cmp(
                 *lhs,
                 *rhs,
                 IntCC::SignedLessThanOrEqual,
                 FloatCC::LessThanOrEqual,
-            ),
-            Expr::Gt(lhs, rhs) => {
-                self.translate_cmp(*lhs, *rhs, IntCC::SignedGreaterThan, FloatCC::GreaterThan)
+            )?,
+            ExprKind::Gt(lhs, rhs) => {
+                self.translate_cmp(*lhs, *rhs, IntCC::SignedGreaterThan, FloatCC::GreaterThan)?
             }
-            Expr::Ge(lhs, rhs) => self.translate_cmp(
+            ExprKind::Ge(lhs, rhs) => self.translate_cmp(
                 *lhs,
                 *rhs,
                 IntCC::SignedGreaterThanOrEqual,
                 FloatCC::GreaterThanOrEqual,
-            ),
-
-            Expr::Call(name, args) => self.translate_call(name, args),
-            Expr::GlobalDataAddr(name) => self.translate_global_data_addr(name),
-            Expr::StringLiteral(s) => self.translate_string_literal(s),
-            Expr::ComplexLiteral(re, im, ty) => self.translate_complex_literal(re, im, ty),
-            Expr::ArrayLiteral(elems, ty) => self.translate_array_literal(elems, ty),
-            Expr::DynamicArrayLiteral(elems, ty) => self.translate_dynamic_array_literal(elems, ty),
-            Expr::Index(base, idx) => self.translate_index(*base, *idx),
-            Expr::Identifier(name) => {
-                let (variable, _) = self.variables.get(&name).expect("variable not defined");
-                self.builder.use_var(*variable)
-            }
-            Expr::Assign(name, expr) => self.translate_assign(name, *expr),
-            Expr::IfElse(condition, then_body, else_body) => {
-                self.translate_if_else(*condition, then_body, else_body)
-            }
-            Expr::WhileLoop(condition, loop_body) => {
-                self.scope_depth += 1;
+            )?,
+            ExprKind::And(lhs, rhs) => self.translate_logical(*lhs, *rhs, true)?,
+            ExprKind::Or(lhs, rhs) => self.translate_logical(*lhs, *rhs, false)?,
+            ExprKind::Not(expr) => {
+                let val = self.translate_expr(*expr)?;
+                self.builder.ins().icmp_imm(IntCC::Equal, val, 0)
+            }
+
+            ExprKind::Call(name, args) => self.translate_call(name, args)?,
+            ExprKind::GlobalDataAddr(name) => self.translate_global_data_addr(name)?,
+            ExprKind::StringLiteral(s) => self.translate_string_literal(s)?,
+            ExprKind::ComplexLiteral(re, im, ty) => self.translate_complex_literal(re, im, ty)?,
+            ExprKind::ArrayLiteral(elems, ty) => self.translate_array_literal(elems, ty)?,
+            ExprKind::DynamicArrayLiteral(elems, ty) => {
+                self.translate_dynamic_array_literal(elems, ty)?
+            }
+            ExprKind::Index(base, idx) => self.translate_index(*base, *idx)?,
+            ExprKind::Identifier(name) => match self.variables.get(&name) {
+                Some((variable, _)) => self.builder.use_var(*variable),
+                // 不是局部变量时读取同名的静态变量
+                None => self.translate_static_load(&name)?,
+            },
+            ExprKind::Assign(name, expr) | ExprKind::Let(name, _, _, expr) => {
+                self.translate_assign(name, *expr)?
+            }
+            // x op= e 的目标是变量，按 x = x op e 翻译即可
+            ExprKind::CompoundAssign(name, op, value) => {
+                let target = Expr::from(ExprKind::Identifier(name.clone()));
+                let rhs = Expr::from(op.apply(target, *value));
+                self.translate_assign(name, rhs)?
+            }
+            ExprKind::IndexAssign(base, idx, op, value) => {
+                self.translate_index_assign(*base, *idx, op, *value)?
+            }
+            ExprKind::StructLiteral(name, fields) => self.translate_struct_literal(name, fields)?,
+            ExprKind::EnumLiteral(name, variant, args) => {
+                self.translate_enum_literal(name, &variant, args)?
+            }
+            ExprKind::Match(scrutinee, arms) => self.translate_match(*scrutinee, arms)?,
+            ExprKind::Field(base, field) => {
+                let (addr, ty) = self.translate_field_addr(*base, &field)?;
+                self.load_value(addr, &ty, true)
+            }
+            ExprKind::FieldAssign(base, field, op, value) => {
+                self.translate_field_assign(*base, field, op, *value)?
+            }
+            ExprKind::IfElse(condition, then_body, else_body) => {
+                self.translate_if_else(*condition, then_body, else_body)?
+            }
+            ExprKind::WhileLoop(condition, loop_body) => {
+                self.enter_scope(span);
                 let result = self.translate_while_loop(*condition, loop_body, self.scope_depth);
-                self.scope_depth -= 1;
-                result
+                self.leave_scope();
+                result?
             }
-            Expr::Cast(expr, target_ty) => {
-                let val = self.translate_expr(*expr);
-                self.translate_cast(val, to_cranelift_type(&target_ty))
+            ExprKind::ForRange(var, start, end, inclusive, step, loop_body) => {
+                self.enter_scope(span);
+                let range = (*start, *end, inclusive, step.map(|s| *s));
+                let result = self.translate_for_range(&var, range, loop_body, self.scope_depth);
+                self.leave_scope();
+                result?
             }
-            Expr::Drop(name) => self.translate_drop(&name),
-            Expr::Block(body) => {
-                self.scope_depth += 1;
+            ExprKind::ForEach(var, iterable, loop_body) => {
+                self.enter_scope(span);
+                let result = self.translate_for_each(&var, *iterable, loop_body, self.scope_depth);
+                self.leave_scope();
+                result?
+            }
+            ExprKind::Cast(expr, target_ty) => {
+                let from_ty = self.infer_type(&expr);
+                let val = self.translate_expr(*expr)?;
+                if target_ty == FrontendType::Bool && from_ty != FrontendType::Bool {
+                    // 整数 → bool：非零即 true（bool → 整数由 translate_cast 扩展 0 / 1）
+                    self.builder.ins().icmp_imm(IntCC::NotEqual, val, 0)
+                } else {
+                    self.translate_cast(val, to_cranelift_type(&target_ty))?
+                }
+            }
+            ExprKind::Drop(name) => self.translate_drop(&name)?,
+            ExprKind::Block(body) => {
+                self.enter_scope(span);
                 for stmt in body {
-                    self.translate_expr(stmt);
+                    self.translate_expr(stmt)?;
                 }
-                self.emit_scope_drop(self.scope_depth, None);
-                self.scope_depth -= 1;
+                self.emit_scope_drop(self.scope_depth, None)?;
+                self.leave_scope();
                 InstBuilder::iconst(self.builder.ins(), types::I64, 0)
             }
-        }
+            ExprKind::Return(expr) => self.translate_return(*expr)?,
+            ExprKind::Break => self.translate_loop_jump(true)?,
+            ExprKind::Continue => self.translate_loop_jump(false)?,
+        };
+        Ok(value)
     }
 
-    fn translate_binary_op<F>(&mut self, lhs: Expr, rhs: Expr, op: F) -> Value
+    fn translate_binary_op<F>(&mut self, lhs: Expr, rhs: Expr, op: F) -> Result<Value, CompileError>
     where
         F: Fn(&mut FunctionBuilder, Value, Value) -> Value,
     {
-        let l_val = self.translate_expr(lhs); // 先把左边的表达式翻译完，拿到结果线头
-        let r_val = self.translate_expr(rhs); // 再把右边的表达式翻译完，拿到结果线头
-        let (l_promoted, r_promoted) = self.promote_operands(l_val, r_val);
+        let l_val = self.translate_expr(lhs)?; // 先把左边的表达式翻译完，拿到结果线头
+        let r_val = self.translate_expr(rhs)?; // 再把右边的表达式翻译完，拿到结果线头
+        let (l_promoted, r_promoted) = self.promote_operands(l_val, r_val)?;
         //如果左边是 i32，右边是 i64，要把左边“拉长”成 i64
-        op(&mut self.builder, l_promoted, r_promoted) // 生成真正的加法指令
+        Ok(op(&mut self.builder, l_promoted, r_promoted)) // 生成真正的加法指令
     }
 
-    fn promote_operands(&mut self, lhs: Value, rhs: Value) -> (Value, Value) {
+    fn promote_operands(&mut self, lhs: Value, rhs: Value) -> Result<(Value, Value), CompileError> {
         let l_ty = self.builder.func.dfg.value_type(lhs);
         let r_ty = self.builder.func.dfg.value_type(rhs);
 
         if l_ty == r_ty {
-            return (lhs, rhs);
+            return Ok((lhs, rhs));
         }
 
         // 隐式提升：int -> 更宽的 int，float -> 更宽的 float。
@@ -600,78 +1337,198 @@ impl<'a> FunctionTranslator<'a> {
         if l_ty.is_int() && r_ty.is_int() {
             if l_ty.bits() < r_ty.bits() {
                 let l_new_s = self.builder.ins().sextend(r_ty, lhs);
-                return (l_new_s, rhs);
+                return Ok((l_new_s, rhs));
             } else {
                 let r_new_s = self.builder.ins().sextend(l_ty, rhs);
-                return (lhs, r_new_s);
+                return Ok((lhs, r_new_s));
             }
         }
 
         if l_ty.is_float() && r_ty.is_float() {
             if l_ty.bits() < r_ty.bits() {
                 let l_new = self.builder.ins().fpromote(r_ty, lhs);
-                return (l_new, rhs);
+                return Ok((l_new, rhs));
             } else {
                 let r_new = self.builder.ins().fpromote(l_ty, rhs);
-                return (lhs, r_new);
+                return Ok((lhs, r_new));
             }
         }
 
-        panic!("Incompatible types in operation: {:?} vs {:?}", l_ty, r_ty);
+        Err(self.error(format!(
+            "incompatible types in operation: {} vs {}",
+            l_ty, r_ty
+        )))
     }
 
     /// 翻译类型转换
-    fn translate_cast(&mut self, val: Value, target_ty: types::Type) -> Value {
+    fn translate_cast(
+        &mut self,
+        val: Value,
+        target_ty: types::Type,
+    ) -> Result<Value, CompileError> {
         let src_ty = self.builder.func.dfg.value_type(val);
         if src_ty == target_ty {
-            return val;
+            return Ok(val);
         }
 
         if src_ty.is_int() && target_ty.is_int() {
             if src_ty.bits() < target_ty.bits() {
-                return self.builder.ins().sextend(target_ty, val);
+                return Ok(self.builder.ins().sextend(target_ty, val));
             } else {
-                return self.builder.ins().ireduce(target_ty, val);
+                return Ok(self.builder.ins().ireduce(target_ty, val));
             }
         } //整数转整数 (Int -> Int)
 
         if src_ty.is_float() && target_ty.is_float() {
             //浮点转浮点 (Float -> Float)
             if src_ty.bits() < target_ty.bits() {
-                return self.builder.ins().fpromote(target_ty, val);
+                return Ok(self.builder.ins().fpromote(target_ty, val));
             } else {
-                return self.builder.ins().fdemote(target_ty, val);
+                return Ok(self.builder.ins().fdemote(target_ty, val));
             }
         }
 
         if src_ty.is_int() && target_ty.is_float() {
             //整数转浮点 (Int -> Float)
-            return self.builder.ins().fcvt_from_sint(target_ty, val);
+            return Ok(self.builder.ins().fcvt_from_sint(target_ty, val));
         }
 
         if src_ty.is_float() && target_ty.is_int() {
-            return self.builder.ins().fcvt_to_sint(target_ty, val);
+            return Ok(self.builder.ins().fcvt_to_sint(target_ty, val));
         }
 
-        panic!("Unsupported cast from {:?} to {:?}", src_ty, target_ty);
+        Err(self.error(format!("unsupported cast from {} to {}", src_ty, target_ty)))
     }
 
     ///比较操作
-    fn translate_cmp(&mut self, lhs: Expr, rhs: Expr, int_cc: IntCC, float_cc: FloatCC) -> Value {
-        let l_val = self.translate_expr(lhs);
-        let r_val = self.translate_expr(rhs);
-        let (l, r) = self.promote_operands(l_val, r_val);
+    fn translate_cmp(
+        &mut self,
+        lhs: Expr,
+        rhs: Expr,
+        int_cc: IntCC,
+        float_cc: FloatCC,
+    ) -> Result<Value, CompileError> {
+        let l_val = self.translate_expr(lhs)?;
+        let r_val = self.translate_expr(rhs)?;
+        let (l, r) = self.promote_operands(l_val, r_val)?;
         let ty = self.builder.func.dfg.value_type(l);
 
-        let bool_res = if ty.is_float() {
-            self.builder.ins().fcmp(float_cc, l, r)
+        // icmp / fcmp 的结果就是 i8 的 0 / 1，正好是 bool 的表示
+        if ty.is_float() {
+            Ok(self.builder.ins().fcmp(float_cc, l, r))
         } else {
-            self.builder.ins().icmp(int_cc, l, r)
+            Ok(self.builder.ins().icmp(int_cc, l, r))
+        }
+    }
+
+    /// 浮点 `%`：Cranelift 没有浮点取余指令，调用运行时的 toy_fmod（f32 先提升为 f64）
+    fn translate_fmod(&mut self, l: Value, r: Value) -> Result<Value, CompileError> {
+        let ty = self.builder.func.dfg.value_type(l);
+        let (l, r) = if ty == types::F32 {
+            (
+                self.builder.ins().fpromote(types::F64, l),
+                self.builder.ins().fpromote(types::F64, r),
+            )
+        } else {
+            (l, r)
         };
-        // 如果缺少 bint，则使用 select 代替
-        let one = InstBuilder::iconst(self.builder.ins(), types::I64, 1);
-        let zero = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
-        self.builder.ins().select(bool_res, one, zero)
+
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(types::F64));
+        sig.params.push(AbiParam::new(types::F64));
+        sig.returns.push(AbiParam::new(types::F64));
+        let callee = self.import_function("toy_fmod", &sig)?;
+        let call = self.builder.ins().call(callee, &[l, r]);
+        let result = self.builder.inst_results(call)[0];
+
+        if ty == types::F32 {
+            Ok(self.builder.ins().fdemote(types::F32, result))
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// 整数 `/`（`rem` 为 false）与 `%`。x64 后端不支持 i128 的 sdiv / srem，
+    /// i128 改为调用运行时的 toy_i128_div / toy_i128_rem，调用前照样检查除数为 0
+    /// 和 `MIN / -1`，trap 行为与其它整数类型一致
+    fn translate_int_div(&mut self, l: Value, r: Value, rem: bool) -> Result<Value, CompileError> {
+        let ty = self.builder.func.dfg.value_type(l);
+        if ty != types::I128 {
+            return Ok(if rem {
+                self.builder.ins().srem(l, r)
+            } else {
+                self.builder.ins().sdiv(l, r)
+            });
+        }
+
+        let zero = self.int_const(ty, 0);
+        let div_by_zero = self.builder.ins().icmp(IntCC::Equal, r, zero);
+        self.builder
+            .ins()
+            .trapnz(div_by_zero, TrapCode::unwrap_user(1));
+        if !rem {
+            // sdiv 在 i128::MIN / -1 时溢出，srem 此时结果为 0
+            let lo = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+            let hi = InstBuilder::iconst(self.builder.ins(), types::I64, i64::MIN);
+            let min = self.builder.ins().iconcat(lo, hi);
+            let minus_one = self.int_const(ty, -1);
+            let is_min = self.builder.ins().icmp(IntCC::Equal, l, min);
+            let is_minus_one = self.builder.ins().icmp(IntCC::Equal, r, minus_one);
+            let overflow = self.builder.ins().band(is_min, is_minus_one);
+            self.builder
+                .ins()
+                .trapnz(overflow, TrapCode::unwrap_user(1));
+        }
+
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(types::I128));
+        sig.params.push(AbiParam::new(types::I128));
+        sig.returns.push(AbiParam::new(types::I128));
+        let name = if rem { "toy_i128_rem" } else { "toy_i128_div" };
+        let callee = self.import_function(name, &sig)?;
+        let call = self.builder.ins().call(callee, &[l, r]);
+        Ok(self.builder.inst_results(call)[0])
+    }
+
+    /// 短路 `&&` / `||`：只有左操作数不能决定结果时才求值右操作数。
+    ///
+    /// ```text
+    /// a && b:  brif a, rhs, merge(a)      a || b:  brif a, merge(a), rhs
+    ///          rhs: jump merge(b)                  rhs: jump merge(b)
+    /// ```
+    fn translate_logical(
+        &mut self,
+        lhs: Expr,
+        rhs: Expr,
+        is_and: bool,
+    ) -> Result<Value, CompileError> {
+        let l_val = self.translate_expr(lhs)?;
+
+        let rhs_block = self.builder.create_block();
+        let merge_block = self.builder.create_block();
+        self.builder.append_block_param(merge_block, types::I8);
+
+        let short_circuit = [BlockArg::Value(l_val)];
+        if is_and {
+            self.builder
+                .ins()
+                .brif(l_val, rhs_block, &[], merge_block, &short_circuit);
+        } else {
+            self.builder
+                .ins()
+                .brif(l_val, merge_block, &short_circuit, rhs_block, &[]);
+        }
+
+        self.builder.switch_to_block(rhs_block);
+        self.builder.seal_block(rhs_block);
+        let r_val = self.translate_expr(rhs)?;
+        self.builder
+            .ins()
+            .jump(merge_block, &[BlockArg::Value(r_val)]);
+
+        self.builder.switch_to_block(merge_block);
+        self.builder.seal_block(merge_block);
+        Ok(self.builder.block_params(merge_block)[0])
     }
     /// 翻译变量赋值语句。
     ///
@@ -689,23 +1546,33 @@ impl<'a> FunctionTranslator<'a> {
     /// FIXME: 重新赋值时旧数组的指针被覆盖，旧数组泄漏。
     /// 所有权检查器已通过覆盖检测捕获此场景（报 LeakedArray），
     /// JIT 层暂无运行时覆盖前 auto-drop。
-    fn translate_assign(&mut self, name: String, expr: Expr) -> Value {
-        let new_value = self.translate_expr(expr);
+    fn translate_assign(&mut self, name: String, expr: Expr) -> Result<Value, CompileError> {
+        let new_value = self.translate_expr(expr)?;
+        if !self.variables.contains_key(&name) && self.type_checker.globals.contains_key(&name) {
+            return self.translate_static_store(&name, new_value);
+        }
         let (variable, ty) = {
-            let (v, t) = self.variables.get(&name).unwrap_or_else(|| {
-                panic!(
-                    "Variable '{}' not found - compiler bug in variable declaration",
+            let (v, t) = self.variables.get(&name).ok_or_else(|| {
+                self.error(format!(
+                    "variable '{}' was not declared before assignment",
                     name
-                )
-            });
+                ))
+            })?;
             (*v, t.clone())
         };
 
+        // 结构体变量的值是它的存储地址，赋值即把右值整体复制进去
+        if matches!(ty, FrontendType::Struct(_)) {
+            let dest = self.builder.use_var(variable);
+            self.emit_copy(dest, new_value, &ty);
+            return Ok(dest);
+        }
+
         let target_ty = to_cranelift_type(&ty);
         let val_ty = self.builder.func.dfg.value_type(new_value);
 
         let final_value = if val_ty != target_ty {
-            self.translate_cast(new_value, target_ty)
+            self.translate_cast(new_value, target_ty)?
         } else {
             new_value
         };
@@ -714,7 +1581,7 @@ impl<'a> FunctionTranslator<'a> {
         // 注：DynamicArray 作用域追踪现已由 scope_analysis 统一管理，
         // 不再在此处手动登记 dynamic_arrays。
 
-        final_value
+        Ok(final_value)
     }
 
     /// 翻译 `drop(arr)` 语句——显式释放 DynamicArray。
@@ -722,161 +1589,705 @@ impl<'a> FunctionTranslator<'a> {
     /// 1. 查找变量，标记为 explicitly_dropped（避免 scope exit auto-drop 重复释放）
     /// 2. 使用 emit_drop_call + drop_func_for 发射 drop 调用
     /// 3. 返回 0
-    fn translate_drop(&mut self, name: &str) -> Value {
+    ///
+    /// `drop(p.items)` 释放结构体中的数组字段，释放后字段置空，
+    /// 作用域退出时的 auto-drop 对它是 no-op
+    fn translate_drop(&mut self, name: &str) -> Result<Value, CompileError> {
+        if name.contains('.') {
+            if !self.drop_array_field(name)? {
+                return Err(self.error(format!(
+                    "drop() can only be called on a dynamic array, '{}' is not one",
+                    name
+                )));
+            }
+            return Ok(InstBuilder::iconst(self.builder.ins(), types::I64, 0));
+        }
         let (var, arr_ty) = self
             .variables
             .get(name)
-            .unwrap_or_else(|| panic!("Variable '{}' not found for drop()", name));
+            .ok_or_else(|| self.error(format!("variable '{}' not found for drop()", name)))?;
+        let var = *var;
+
+        let drop_func_name = match arr_ty {
+            FrontendType::DynamicArray(inner) => Self::drop_func_for(inner),
+            other => {
+                return Err(self.error(format!(
+                    "drop() can only be called on a dynamic array, '{}' has type {}",
+                    name, other
+                )));
+            }
+        };
+
+        self.explicitly_dropped.push(var);
+        let val = self.builder.use_var(var);
+        self.emit_drop_call(drop_func_name, val)?;
+        // 释放后置空：explicitly_dropped 只按翻译顺序记录，不区分控制流路径，
+        // 其他路径上（如 break 之后的循环出口）再次释放时只是对空指针的 no-op
+        let null = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+        self.builder.def_var(var, null);
+
+        Ok(InstBuilder::iconst(self.builder.ins(), types::I64, 0))
+    }
 
-        self.explicitly_dropped.push(*var);
+    /// 释放字段路径 `path`（如 `p.items`）指向的动态数组并把字段置空。
+    /// 路径不是动态数组字段时什么也不做，返回 false
+    fn drop_array_field(&mut self, path: &str) -> Result<bool, CompileError> {
+        let Some((base, field)) = split_field_path(path) else {
+            return Ok(false);
+        };
+        let base_ty = self.infer_type(&base);
+        let Some(FrontendType::DynamicArray(elem_ty)) =
+            self.type_checker.field_type(&base_ty, &field)
+        else {
+            return Ok(false);
+        };
+        let (addr, _) = self.translate_field_addr(base, &field)?;
+        let val = self
+            .builder
+            .ins()
+            .load(types::I64, MemFlags::new(), addr, 0);
+        self.emit_drop_call(Self::drop_func_for(&elem_ty), val)?;
+        let null = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+        self.builder.ins().store(MemFlags::new(), null, addr, 0);
+        Ok(true)
+    }
 
-        let elem_ty = match arr_ty {
-            FrontendType::DynamicArray(inner) => inner.as_ref(),
-            _ => panic!("drop() can only be called on DynamicArray"),
+    /// `return expr`：按返回类型转换返回值，释放所有外层作用域的 DynamicArray 后发射 return
+    fn translate_return(&mut self, expr: Expr) -> Result<Value, CompileError> {
+        // 直接返回的数组变量所有权转交给调用者
+        let returned = match &expr.kind {
+            ExprKind::Identifier(name) => self.variables.get(name).map(|(var, _)| *var),
+            _ => None,
+        };
+        let value = self.translate_expr(expr)?;
+
+        let (return_variable, return_ty) = self
+            .variables
+            .get(&self.return_name)
+            .map(|(var, ty)| (*var, ty.clone()))
+            .ok_or_else(|| {
+                self.error(format!(
+                    "return variable '{}' not defined",
+                    self.return_name
+                ))
+            })?;
+        let value = if matches!(return_ty, FrontendType::Struct(_)) {
+            // 结构体写入调用者提供的 sret 存储，返回该地址
+            let dest = self.builder.use_var(return_variable);
+            self.emit_copy(dest, value, &return_ty);
+            dest
+        } else {
+            let return_ty = to_cranelift_type(&return_ty);
+            if self.builder.func.dfg.value_type(value) != return_ty {
+                self.translate_cast(value, return_ty)?
+            } else {
+                value
+            }
         };
 
-        let drop_func_name = Self::drop_func_for(elem_ty);
-        let val = self.builder.use_var(*var);
-        self.emit_drop_call(drop_func_name, val);
+        let keep: Vec<Variable> = std::iter::once(return_variable).chain(returned).collect();
+        self.emit_return_drops(&keep)?;
+        self.builder.ins().return_(&[value]);
+
+        Ok(self.switch_to_unreachable_block())
+    }
+
+    /// `break`（`to_exit`）/ `continue`：先释放从当前作用域到循环体作用域的 DynamicArray，
+    /// 再跳到最内层循环的出口块 / 头块
+    fn translate_loop_jump(&mut self, to_exit: bool) -> Result<Value, CompileError> {
+        let keyword = if to_exit { "break" } else { "continue" };
+        let (target, loop_depth) = match self.loops.last() {
+            Some(l) if to_exit => (l.exit_block, l.scope_depth),
+            Some(l) => (l.continue_block, l.scope_depth),
+            None => return Err(self.error(format!("'{}' outside of a loop", keyword))),
+        };
+
+        self.emit_jump_drops(loop_depth, &[])?;
+        self.builder.ins().jump(target, &[]);
+
+        Ok(self.switch_to_unreachable_block())
+    }
+
+    /// return / break / continue 之后的语句不可达，翻译到一个没有前驱的新块中
+    fn switch_to_unreachable_block(&mut self) -> Value {
+        let unreachable_block = self.builder.create_block();
+        self.builder.switch_to_block(unreachable_block);
+        self.builder.seal_block(unreachable_block);
+        InstBuilder::iconst(self.builder.ins(), types::I64, 0)
+    }
+
+    /// if-else 语句
+    fn translate_if_else(
+        &mut self,
+        condition: Expr,
+        then_body: Vec<Expr>,
+        else_body: Vec<Expr>,
+    ) -> Result<Value, CompileError> {
+        // 合并后的类型取第一个产生值的分支，以 return / break / continue 结束的分支不参与
+        let value_body = if type_checker::diverges(&then_body) {
+            &else_body
+        } else {
+            &then_body
+        };
+        let result_ty = match value_body.last() {
+            Some(last) => to_cranelift_type(&self.infer_type(last)),
+            None => types::I64,
+        };
+        let condition_value = self.translate_expr(condition)?;
+
+        let then_block = self.builder.create_block();
+        let else_block = self.builder.create_block();
+        let merge_block = self.builder.create_block();
+        self.builder.append_block_param(merge_block, result_ty);
+
+        self.builder
+            .ins()
+            .brif(condition_value, then_block, &[], else_block, &[]);
+
+        // 以 return / break / continue 结束的分支里的 drop() 只属于那条路径，
+        // 分支结束后恢复 explicitly_dropped，否则作用域退出时会跳过这些数组
+        let dropped_before = self.explicitly_dropped.clone();
+
+        self.builder.switch_to_block(then_block);
+        self.builder.seal_block(then_block);
+        let mut then_return = InstBuilder::iconst(self.builder.ins(), types::I64, 0); // 默认值
+        for expr in then_body {
+            then_return = self.translate_expr(expr)?;
+        }
+        if self.builder.is_unreachable() {
+            self.explicitly_dropped = dropped_before.clone();
+        }
+        let then_return = self.translate_cast(then_return, result_ty)?;
+        self.builder
+            .ins()
+            .jump(merge_block, &[BlockArg::Value(then_return)]);
+
+        self.builder.switch_to_block(else_block);
+        self.builder.seal_block(else_block);
+        let mut else_return = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+        for expr in else_body {
+            else_return = self.translate_expr(expr)?;
+        }
+        if self.builder.is_unreachable() {
+            self.explicitly_dropped = dropped_before;
+        }
+
+        // 显式转换各分支的结果以匹配合并后的类型（不可达分支中的占位值同样转换）
+        let else_return = self.translate_cast(else_return, result_ty)?;
+        self.builder
+            .ins()
+            .jump(merge_block, &[BlockArg::Value(else_return)]);
+
+        self.builder.switch_to_block(merge_block);
+        self.builder.seal_block(merge_block);
+
+        Ok(self.builder.block_params(merge_block)[0])
+    }
+
+    /// match 表达式。枚举按标签分派：每个变体跳到第一个能匹配它的分支，
+    /// 由 `Switch` 生成 br_table（标签是从 0 开始的连续整数）；整数按分支顺序
+    /// 逐个比较（compare chain）。类型检查保证分支覆盖所有值，没有分支匹配的路径
+    /// 不可达，以 trap 结束。分支与 if 的分支相同，值是最后一条语句的值
+    fn translate_match(
+        &mut self,
+        scrutinee: Expr,
+        arms: Vec<MatchArm>,
+    ) -> Result<Value, CompileError> {
+        let ty = self.infer_type(&scrutinee);
+        // 与 if 相同，合并后的类型取第一个产生值的分支
+        let value_arm = arms.iter().find(|arm| !type_checker::diverges(&arm.body));
+        let result_ty = match value_arm.or(arms.first()).and_then(|arm| arm.body.last()) {
+            Some(last) => to_cranelift_type(&self.infer_type(last)),
+            None => types::I64,
+        };
+        let value = self.translate_expr(scrutinee)?;
+
+        let arm_blocks: Vec<Block> = arms.iter().map(|_| self.builder.create_block()).collect();
+        let no_match = self.builder.create_block();
+        // 被跳转到的分支；前面的分支已经覆盖全部值时，后面的分支不会生成代码
+        let mut reachable = vec![false; arms.len()];
+        if let FrontendType::Struct(name) = &ty {
+            let def = self
+                .type_checker
+                .enums
+                .get(name)
+                .ok_or_else(|| self.error(format!("undefined type '{}'", name)))?;
+            let mut switch = Switch::new();
+            for (tag, (variant, _)) in def.variants.iter().enumerate() {
+                if let Some(i) = arms
+                    .iter()
+                    .position(|arm| arm.pattern.matches_variant(variant))
+                {
+                    switch.set_entry(tag as u128, arm_blocks[i]);
+                    reachable[i] = true;
+                }
+            }
+            let tag = self
+                .builder
+                .ins()
+                .load(types::I32, MemFlags::new(), value, 0);
+            switch.emit(&mut self.builder, tag, no_match);
+        } else {
+            let mut exhausted = false;
+            for (i, arm) in arms.iter().enumerate() {
+                reachable[i] = true;
+                let Some(matched) = self.translate_pattern_test(value, &ty, &arm.pattern) else {
+                    self.builder.ins().jump(arm_blocks[i], &[]);
+                    exhausted = true;
+                    break;
+                };
+                let next = self.builder.create_block();
+                self.builder
+                    .ins()
+                    .brif(matched, arm_blocks[i], &[], next, &[]);
+                self.builder.switch_to_block(next);
+                self.builder.seal_block(next);
+            }
+            if !exhausted {
+                self.builder.ins().jump(no_match, &[]);
+            }
+        }
+        self.builder.seal_block(no_match);
+
+        let merge_block = self.builder.create_block();
+        self.builder.append_block_param(merge_block, result_ty);
+        // 与 if 相同：以 return / break / continue 结束的分支里的 drop() 只属于那条路径
+        let dropped_before = self.explicitly_dropped.clone();
+        for ((arm, block), reachable) in arms.into_iter().zip(arm_blocks).zip(reachable) {
+            self.builder.seal_block(block);
+            if !reachable {
+                continue;
+            }
+            self.builder.switch_to_block(block);
+            self.bind_pattern(&arm.pattern, value, &ty)?;
+            let mut arm_value = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
+            for stmt in arm.body {
+                arm_value = self.translate_expr(stmt)?;
+            }
+            if self.builder.is_unreachable() {
+                self.explicitly_dropped = dropped_before.clone();
+            }
+            if self.builder.func.dfg.value_type(arm_value) != result_ty {
+                arm_value = self.translate_cast(arm_value, result_ty)?;
+            }
+            self.builder
+                .ins()
+                .jump(merge_block, &[BlockArg::Value(arm_value)]);
+        }
+
+        self.builder.switch_to_block(no_match);
+        self.builder.ins().trap(TrapCode::unwrap_user(1));
+        self.builder.switch_to_block(merge_block);
+        self.builder.seal_block(merge_block);
+        Ok(self.builder.block_params(merge_block)[0])
+    }
+
+    /// 整数值 `value` 是否匹配模式，返回 bool 值；总能匹配的模式（`_`、绑定）返回 None
+    fn translate_pattern_test(
+        &mut self,
+        value: Value,
+        ty: &FrontendType,
+        pattern: &Pattern,
+    ) -> Option<Value> {
+        let cl_ty = to_cranelift_type(ty);
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) | Pattern::Variant(..) => None,
+            Pattern::Int(n) => {
+                let n = self.int_const(cl_ty, *n);
+                Some(self.builder.ins().icmp(IntCC::Equal, value, n))
+            }
+            Pattern::Range(start, end, inclusive) => {
+                let (start, end) = (self.int_const(cl_ty, *start), self.int_const(cl_ty, *end));
+                let above = self
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedGreaterThanOrEqual, value, start);
+                let cc = if *inclusive {
+                    IntCC::SignedLessThanOrEqual
+                } else {
+                    IntCC::SignedLessThan
+                };
+                let below = self.builder.ins().icmp(cc, value, end);
+                Some(self.builder.ins().band(above, below))
+            }
+            Pattern::Or(alternatives) => {
+                let mut matched = None;
+                for alternative in alternatives {
+                    let test = self.translate_pattern_test(value, ty, alternative)?;
+                    matched = Some(match matched {
+                        Some(m) => self.builder.ins().bor(m, test),
+                        None => test,
+                    });
+                }
+                matched
+            }
+        }
+    }
+
+    /// `ty` 类型（整数）的常量 `n`。窄于 64 位的类型截断为无符号形式，i128 由 i64 符号扩展
+    fn int_const(&mut self, ty: types::Type, n: i64) -> Value {
+        match ty.bits() {
+            128 => {
+                let n = InstBuilder::iconst(self.builder.ins(), types::I64, n);
+                self.builder.ins().sextend(types::I128, n)
+            }
+            64 => InstBuilder::iconst(self.builder.ins(), ty, n),
+            bits => InstBuilder::iconst(self.builder.ins(), ty, n & ((1 << bits) - 1)),
+        }
+    }
+
+    /// 把已匹配的值中被模式绑定的部分写入对应变量：`x` 绑定整个值，
+    /// `E::V(a, _)` 绑定载荷字段。结构体和枚举按值复制
+    fn bind_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: Value,
+        ty: &FrontendType,
+    ) -> Result<(), CompileError> {
+        match pattern {
+            Pattern::Binding(name) => self.bind_variable(name, value),
+            Pattern::Variant(name, variant, fields) => {
+                let (index, payload) = self
+                    .type_checker
+                    .enums
+                    .get(name)
+                    .and_then(|def| def.variant(variant))
+                    .map(|(index, payload)| (index, payload.to_vec()))
+                    .ok_or_else(|| self.error(format!("no variant '{}' in '{}'", variant, ty)))?;
+                let layout = self
+                    .type_checker
+                    .enum_layout(name)
+                    .ok_or_else(|| self.error(format!("undefined type '{}'", name)))?;
+                for ((field, field_ty), offset) in
+                    fields.iter().zip(&payload).zip(&layout.payloads[index])
+                {
+                    if let Pattern::Binding(binding) = field {
+                        let addr = self.builder.ins().iadd_imm(value, *offset as i64);
+                        let val = self.load_value(addr, field_ty, true);
+                        self.bind_variable(binding, val)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 
-        InstBuilder::iconst(self.builder.ins(), types::I64, 0)
+    /// 模式绑定的变量在入口块已经声明（结构体与枚举变量已有自己的存储）
+    fn bind_variable(&mut self, name: &str, value: Value) -> Result<(), CompileError> {
+        let (variable, ty) = self
+            .variables
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(format!("variable '{}' was not declared", name)))?;
+        if matches!(ty, FrontendType::Struct(_)) {
+            let dest = self.builder.use_var(variable);
+            self.emit_copy(dest, value, &ty);
+        } else {
+            self.builder.def_var(variable, value);
+        }
+        Ok(())
     }
 
-    /// if-else 语句
-    fn translate_if_else(
+    /// while 循环语句
+    fn translate_while_loop(
         &mut self,
         condition: Expr,
-        then_body: Vec<Expr>,
-        else_body: Vec<Expr>,
-    ) -> Value {
-        let condition_value = self.translate_expr(condition);
+        loop_body: Vec<Expr>,
+        loop_scope_depth: usize,
+    ) -> Result<Value, CompileError> {
+        let header_block = self.builder.create_block();
+        let body_block = self.builder.create_block();
+        let exit_block = self.builder.create_block();
 
-        let then_block = self.builder.create_block();
-        let else_block = self.builder.create_block();
-        let merge_block = self.builder.create_block();
+        self.builder.ins().jump(header_block, &[]);
+        self.builder.switch_to_block(header_block);
 
+        let condition_value = self.translate_expr(condition)?;
         self.builder
             .ins()
-            .brif(condition_value, then_block, &[], else_block, &[]);
+            .brif(condition_value, body_block, &[], exit_block, &[]);
 
-        self.builder.switch_to_block(then_block);
-        self.builder.seal_block(then_block);
-        let mut then_return = InstBuilder::iconst(self.builder.ins(), types::I64, 0); // 默认值
-        for expr in then_body {
-            then_return = self.translate_expr(expr);
-        }
-        let then_ty = self.builder.func.dfg.value_type(then_return);
-        self.builder.append_block_param(merge_block, then_ty);
+        self.builder.switch_to_block(body_block);
+        self.builder.seal_block(body_block);
+
+        self.translate_loop_body(loop_body, header_block, exit_block, loop_scope_depth, None)?;
+
+        self.builder.switch_to_block(exit_block);
+        self.builder.seal_block(header_block);
+        self.builder.seal_block(exit_block);
+
+        Ok(InstBuilder::iconst(self.builder.ins(), types::I64, 0))
+    }
+
+    /// `for i in start..end step s`：起点、终点、步长只求值一次。
+    /// 计数器是独立的 Cranelift 变量，循环体修改 i 不影响迭代次数；
+    /// 步长为负时倒序，条件变为 `i > end`（包含终点时 `i >= end`）
+    fn translate_for_range(
+        &mut self,
+        var: &str,
+        (start, end, inclusive, step): (Expr, Expr, bool, Option<Expr>),
+        loop_body: Vec<Expr>,
+        loop_scope_depth: usize,
+    ) -> Result<Value, CompileError> {
+        let (loop_var, ty) = self
+            .variables
+            .get(var)
+            .map(|(v, ty)| (*v, to_cranelift_type(ty)))
+            .ok_or_else(|| self.error(format!("loop variable '{}' not defined", var)))?;
+
+        let start_val = self.translate_expr(start)?;
+        let start_val = self.translate_cast(start_val, ty)?;
+        let end_val = self.translate_expr(end)?;
+        let end_val = self.translate_cast(end_val, ty)?;
+        let step_val = match step {
+            Some(step) => {
+                let val = self.translate_expr(step)?;
+                self.translate_cast(val, ty)?
+            }
+            None => self.int_const(ty, 1),
+        };
+        // 字面量 0 已由类型检查拒绝，其余步长为 0 时 trap，否则循环不会结束。
+        // 这里与下面都只用 i128 也支持的 icmp，不用 trapz / icmp_imm / sadd_overflow
+        let zero = self.int_const(ty, 0);
+        let zero_step = self.builder.ins().icmp(IntCC::Equal, step_val, zero);
         self.builder
             .ins()
-            .jump(merge_block, &[BlockArg::Value(then_return)]);
+            .trapnz(zero_step, TrapCode::unwrap_user(1));
 
-        self.builder.switch_to_block(else_block);
-        self.builder.seal_block(else_block);
-        let mut else_return = InstBuilder::iconst(self.builder.ins(), types::I64, 0);
-        for expr in else_body {
-            else_return = self.translate_expr(expr);
-        }
+        let counter = self.builder.declare_var(ty);
+        self.builder.def_var(counter, start_val);
+
+        let header_block = self.builder.create_block();
+        let body_block = self.builder.create_block();
+        let step_block = self.builder.create_block();
+        let exit_block = self.builder.create_block();
 
-        // 显式转换 else 结果以匹配 then 结果类型（简单统一）
-        let else_return_cast = if then_ty != self.builder.func.dfg.value_type(else_return) {
-            // 为简单起见，我们直接使用 else_return，希望一切顺利或依赖验证错误。
-            // 在此处实现正确的转换需要访问 self.translate_cast，这需要 &mut self。
-            // 我们可以调用它！
-            self.translate_cast(else_return, then_ty)
+        self.builder.ins().jump(header_block, &[]);
+        self.builder.switch_to_block(header_block);
+        let i = self.builder.use_var(counter);
+        let (up, down) = if inclusive {
+            (
+                IntCC::SignedLessThanOrEqual,
+                IntCC::SignedGreaterThanOrEqual,
+            )
         } else {
-            else_return
+            (IntCC::SignedLessThan, IntCC::SignedGreaterThan)
         };
+        let ascending = self
+            .builder
+            .ins()
+            .icmp(IntCC::SignedGreaterThanOrEqual, step_val, zero);
+        let below = self.builder.ins().icmp(up, i, end_val);
+        let above = self.builder.ins().icmp(down, i, end_val);
+        let in_range = self.builder.ins().select(ascending, below, above);
         self.builder
             .ins()
-            .jump(merge_block, &[BlockArg::Value(else_return_cast)]);
+            .brif(in_range, body_block, &[], exit_block, &[]);
 
-        self.builder.switch_to_block(merge_block);
-        self.builder.seal_block(merge_block);
+        self.builder.switch_to_block(body_block);
+        self.builder.seal_block(body_block);
+        let i = self.builder.use_var(counter);
+        self.builder.def_var(loop_var, i);
+        self.translate_loop_body(loop_body, step_block, exit_block, loop_scope_depth, None)?;
+
+        self.builder.switch_to_block(step_block);
+        self.builder.seal_block(step_block);
+        // 越过类型的取值范围时结束：`for i in 120i8..=127i8` 的 127 + 1 会回绕成 -128。
+        // 相加溢出当且仅当结果相对 i 移动的方向与步长的符号相反
+        let i = self.builder.use_var(counter);
+        let next = self.builder.ins().iadd(i, step_val);
+        let moved_down = self.builder.ins().icmp(IntCC::SignedLessThan, next, i);
+        let descending = self
+            .builder
+            .ins()
+            .icmp(IntCC::SignedLessThan, step_val, zero);
+        let overflow = self.builder.ins().bxor(moved_down, descending);
+        self.builder.def_var(counter, next);
+        self.builder
+            .ins()
+            .brif(overflow, exit_block, &[], header_block, &[]);
+
+        self.builder.switch_to_block(exit_block);
+        self.builder.seal_block(header_block);
+        self.builder.seal_block(exit_block);
 
-        self.builder.block_params(merge_block)[0]
+        Ok(InstBuilder::iconst(self.builder.ins(), types::I64, 0))
     }
 
-    /// while 循环语句
-    fn translate_while_loop(
+    /// `for x in arr`：按下标遍历 [T; N] / array<T>。
+    /// 定长数组的长度在编译期已知，下标总在范围内，因此不做 translate_index 的边界检查；
+    /// 动态数组每次迭代重新读取长度。遍历的若是临时动态数组（字面量或调用结果），循环结束后释放；
+    /// `for x in b.items` 与 `for x in rows[i]` 只是借用，数组仍归原来的位置所有
+    fn translate_for_each(
         &mut self,
-        condition: Expr,
+        var: &str,
+        iterable: Expr,
         loop_body: Vec<Expr>,
         loop_scope_depth: usize,
-    ) -> Value {
+    ) -> Result<Value, CompileError> {
+        let (loop_var, var_ty) = self
+            .variables
+            .get(var)
+            .map(|(v, ty)| (*v, ty.clone()))
+            .ok_or_else(|| self.error(format!("loop variable '{}' not defined", var)))?;
+        let iterable_ty = self.infer_type(&iterable);
+        // 只有调用结果和数组字面量这类右值是临时的；变量、字段、下标都是借用
+        let is_temporary = matches!(
+            iterable.kind,
+            ExprKind::Call(..) | ExprKind::DynamicArrayLiteral(..)
+        );
+        let (elem_ty, fixed_len) = match &iterable_ty {
+            FrontendType::Array(elem, len) => (elem.as_ref().clone(), Some(*len as i64)),
+            FrontendType::DynamicArray(elem) => (elem.as_ref().clone(), None),
+            other => {
+                return Err(self.error(format!("cannot iterate over a value of type {}", other)));
+            }
+        };
+        let elem_size = self.type_checker.element_size(&elem_ty);
+
+        let base = self.translate_expr(iterable)?;
+        let temporary = match (fixed_len, is_temporary) {
+            (None, true) => Some((base, Self::drop_func_for(&elem_ty))),
+            _ => None,
+        };
+
+        let len_fn = type_checker::dispatch_array_fn("array_len", Some(&iterable_ty));
+        let get_ptr_fn = type_checker::dispatch_array_fn("array_get_ptr", Some(&iterable_ty));
+        let mut len_sig = self.module.make_signature();
+        len_sig.params.push(AbiParam::new(types::I64)); // arr_ptr
+        len_sig.returns.push(AbiParam::new(types::I64)); // 长度
+        let mut get_ptr_sig = len_sig.clone();
+        get_ptr_sig.params.push(AbiParam::new(types::I64)); // index
+
+        let index = self.builder.declare_var(types::I64);
+        let zero = self.builder.ins().iconst(types::I64, 0);
+        self.builder.def_var(index, zero);
+
         let header_block = self.builder.create_block();
         let body_block = self.builder.create_block();
+        let step_block = self.builder.create_block();
         let exit_block = self.builder.create_block();
 
         self.builder.ins().jump(header_block, &[]);
         self.builder.switch_to_block(header_block);
-
-        let condition_value = self.translate_expr(condition);
+        let len = match fixed_len {
+            Some(len) => self.builder.ins().iconst(types::I64, len),
+            None => {
+                let callee = self.import_function(&len_fn, &len_sig)?;
+                let call = self.builder.ins().call(callee, &[base]);
+                self.builder.inst_results(call)[0]
+            }
+        };
+        let i = self.builder.use_var(index);
+        let in_range = self.builder.ins().icmp(IntCC::SignedLessThan, i, len);
         self.builder
             .ins()
-            .brif(condition_value, body_block, &[], exit_block, &[]);
+            .brif(in_range, body_block, &[], exit_block, &[]);
 
         self.builder.switch_to_block(body_block);
         self.builder.seal_block(body_block);
-
-        for expr in loop_body {
-            self.translate_expr(expr);
+        let i = self.builder.use_var(index);
+        let addr = match fixed_len {
+            Some(_) => {
+                let offset = self.builder.ins().imul_imm(i, elem_size as i64);
+                self.builder.ins().iadd(base, offset)
+            }
+            None => {
+                let callee = self.import_function(&get_ptr_fn, &get_ptr_sig)?;
+                let call = self.builder.ins().call(callee, &[base, i]);
+                self.builder.inst_results(call)[0]
+            }
+        };
+        let elem = self.load_value(addr, &elem_ty, false);
+        if matches!(var_ty, FrontendType::Struct(_)) {
+            // 结构体元素复制到循环变量自己的存储中
+            let dest = self.builder.use_var(loop_var);
+            self.emit_copy(dest, elem, &var_ty);
+        } else {
+            let elem = self.translate_cast(elem, to_cranelift_type(&var_ty))?;
+            self.builder.def_var(loop_var, elem);
         }
-
-        // 每次迭代结束时释放循环作用域内的 DynamicArray
-        self.emit_scope_drop(loop_scope_depth, None);
-
+        self.translate_loop_body(
+            loop_body,
+            step_block,
+            exit_block,
+            loop_scope_depth,
+            temporary,
+        )?;
+
+        self.builder.switch_to_block(step_block);
+        self.builder.seal_block(step_block);
+        let i = self.builder.use_var(index);
+        let next = self.builder.ins().iadd_imm(i, 1);
+        self.builder.def_var(index, next);
         self.builder.ins().jump(header_block, &[]);
 
         self.builder.switch_to_block(exit_block);
         self.builder.seal_block(header_block);
         self.builder.seal_block(exit_block);
+        if let Some((val, drop_func)) = temporary {
+            self.emit_drop_call(drop_func, val)?;
+        }
 
-        InstBuilder::iconst(self.builder.ins(), types::I64, 0)
+        Ok(InstBuilder::iconst(self.builder.ins(), types::I64, 0))
+    }
+
+    /// 翻译循环体：每次迭代结束时释放循环作用域内的 DynamicArray，然后与 continue 一样
+    /// 跳到 `continue_block`（while 为头块，for 为步进块）
+    fn translate_loop_body(
+        &mut self,
+        loop_body: Vec<Expr>,
+        continue_block: Block,
+        exit_block: Block,
+        loop_scope_depth: usize,
+        temporary: Option<(Value, &'static str)>,
+    ) -> Result<(), CompileError> {
+        self.loops.push(LoopContext {
+            continue_block,
+            exit_block,
+            scope_depth: loop_scope_depth,
+            temporary,
+        });
+        for expr in loop_body {
+            self.translate_expr(expr)?;
+        }
+        self.loops.pop();
+
+        self.emit_scope_drop(loop_scope_depth, None)?;
+        self.builder.ins().jump(continue_block, &[]);
+        Ok(())
     }
 
     /// 将泛型 DynamicArray 函数名按元素类型分发到具体实现
     fn dispatch_array_fn(&self, name: String, args: &[Expr]) -> String {
-        // 只处理泛型名称（无后缀的）
-        let base = match name.as_str() {
-            "array_push" | "array_pop" | "array_len" | "array_cap" | "array_set"
-            | "array_get_ptr" | "array_drop" => name.as_str(),
-            _ => return name,
-        };
-        if args.is_empty() {
-            return name;
-        }
-        let arg_ty =
-            type_checker::infer_type(&args[0], &|n| self.variables.get(n).map(|(_, t)| t.clone()));
-        if let FrontendType::DynamicArray(elem_ty) = arg_ty {
-            let suffix = match elem_ty.as_ref() {
-                FrontendType::F64 => "_f64",
-                FrontendType::Complex128 => "_complex128",
-                _ => "",
-            };
-            if !suffix.is_empty() {
-                return format!("{}{}", base, suffix);
-            }
-        }
-        name
+        let first_arg_ty = args.first().map(|a| self.infer_type(a));
+        type_checker::dispatch_array_fn(&name, first_arg_ty.as_ref())
     }
 
     /// 函数调用
-    fn translate_call(&mut self, name: String, args: Vec<Expr>) -> Value {
+    fn translate_call(&mut self, name: String, args: Vec<Expr>) -> Result<Value, CompileError> {
         // 对泛型 DynamicArray 函数按元素类型分发
         let name = self.dispatch_array_fn(name, &args);
 
-        let mut sig = self.module.make_signature();
-
         let signature = self.type_checker.resolve_func(&name);
 
+        // JIT 编译的 toy 函数（含前向引用与递归）：按登记的签名调用
+        if let Some(s) = signature
+            && !s.is_external
+        {
+            let (params, ret) = (s.params.clone(), s.ret.clone());
+            return self.translate_user_call(&name, &params, &ret, args);
+        }
+
+        let mut sig = self.module.make_signature();
+
         let mut arg_values = Vec::new();
         for arg in args {
             // 推断类型以检查它是否为数组
-            let arg_ty =
-                type_checker::infer_type(&arg, &|n| self.variables.get(n).map(|(_, t)| t.clone()));
+            let arg_ty = self.infer_type(&arg);
 
-            let val = self.translate_expr(arg);
+            let val = self.translate_expr(arg)?;
 
             let should_expand = if let FrontendType::Array(_, _) = arg_ty {
                 // 仅对外部函数将数组展开为 (ptr, len)
@@ -910,8 +2321,6 @@ impl<'a> FunctionTranslator<'a> {
         // 返回类型？
         let ret_ty = if let Some(s) = signature {
             to_cranelift_type(&s.ret)
-        } else if name == self.current_func_name {
-            self.current_func_ret_type
         } else if name == "printf" || name == "puts" {
             types::I32
         } else {
@@ -920,52 +2329,134 @@ impl<'a> FunctionTranslator<'a> {
         };
         sig.returns.push(AbiParam::new(ret_ty));
 
-        let callee = self
-            .module
-            .declare_function(&name, Linkage::Import, &sig)
-            .expect("problem declaring function");
-        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+        let local_callee = self.import_function(&name, &sig)?;
+        let call = self.builder.ins().call(local_callee, &arg_values);
+        Ok(self.builder.inst_results(call)[0])
+    }
 
+    /// 调用 JIT 编译的 toy 函数：实参按形参类型做隐式转换
+    /// (参数个数与类型已由 TypeChecker::check_function 预先检查)
+    fn translate_user_call(
+        &mut self,
+        name: &str,
+        params: &[FrontendType],
+        ret: &FrontendType,
+        args: Vec<Expr>,
+    ) -> Result<Value, CompileError> {
+        let mut sig = self.module.make_signature();
+        let mut arg_values = Vec::with_capacity(args.len() + 1);
+        // 返回结构体：在调用者的栈上分配返回值存储，地址作为隐藏的首参数
+        if matches!(ret, FrontendType::Struct(_)) {
+            arg_values.push(self.alloc_zeroed(ret));
+            sig.params.push(AbiParam::new(types::I64));
+        }
+        for (arg, param_ty) in args.into_iter().zip(params) {
+            let cl_ty = to_cranelift_type(param_ty);
+            let val = self.translate_expr(arg)?;
+            arg_values.push(self.translate_cast(val, cl_ty)?);
+            sig.params.push(AbiParam::new(cl_ty));
+        }
+        sig.returns.push(AbiParam::new(to_cranelift_type(ret)));
+
+        let local_callee = self.import_function(name, &sig)?;
         let call = self.builder.ins().call(local_callee, &arg_values);
-        self.builder.inst_results(call)[0]
+        Ok(self.builder.inst_results(call)[0])
     }
 
     /// 获取全局数据的内存地址
-    fn translate_global_data_addr(&mut self, name: String) -> Value {
+    fn translate_global_data_addr(&mut self, name: String) -> Result<Value, CompileError> {
         let sym = self
             .module
             .declare_data(&name, Linkage::Export, true, false)
-            .expect("problem declaring data object");
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
+        let local_id = self.module.declare_data_in_func(sym, self.builder.func);
+
+        let pointer = self.module.target_config().pointer_type();
+        Ok(self.builder.ins().symbol_value(pointer, local_id))
+    }
+
+    /// 静态变量 `name` 的地址（`symbol_value`）与类型
+    fn static_addr(&mut self, name: &str) -> Result<(Value, FrontendType), CompileError> {
+        let def = self
+            .type_checker
+            .globals
+            .get(name)
+            .ok_or_else(|| self.error(format!("variable '{}' not defined", name)))?;
+        let writable = def.kind == GlobalKind::StaticMut;
+        let sym = self
+            .module
+            .declare_data(name, Linkage::Export, writable, false)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
         let local_id = self.module.declare_data_in_func(sym, self.builder.func);
 
         let pointer = self.module.target_config().pointer_type();
-        self.builder.ins().symbol_value(pointer, local_id)
+        let addr = self.builder.ins().symbol_value(pointer, local_id);
+        Ok((addr, def.ty.clone()))
+    }
+
+    /// 读取静态变量。常量的使用处已被替换为字面量，这里只在遮蔽规则之外兜底
+    fn translate_static_load(&mut self, name: &str) -> Result<Value, CompileError> {
+        if let Some(def) = self.type_checker.globals.get(name)
+            && def.kind == GlobalKind::Const
+        {
+            return self.translate_expr(def.value.clone());
+        }
+        let (addr, ty) = self.static_addr(name)?;
+        Ok(self
+            .builder
+            .ins()
+            .load(to_cranelift_type(&ty), MemFlags::trusted(), addr, 0))
+    }
+
+    /// 给 `static mut` 变量赋值：转换为声明的类型后写回数据对象，返回写入的值
+    fn translate_static_store(&mut self, name: &str, value: Value) -> Result<Value, CompileError> {
+        let (addr, ty) = self.static_addr(name)?;
+        let target_ty = to_cranelift_type(&ty);
+        let value = if self.builder.func.dfg.value_type(value) != target_ty {
+            self.translate_cast(value, target_ty)?
+        } else {
+            value
+        };
+        let flags = MemFlags::trusted();
+        self.builder.ins().store(flags, value, addr, 0);
+        Ok(value)
     }
 
-    fn translate_string_literal(&mut self, s: String) -> Value {
+    fn translate_string_literal(&mut self, s: String) -> Result<Value, CompileError> {
         self.string_counter += 1;
         let name = format!("str_{}_{}", self.current_func_name, self.string_counter);
 
         let data_id = self
             .module
             .declare_data(&name, Linkage::Local, false, false)
-            .unwrap();
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
 
         let mut data_ctx = DataDescription::new();
-        // 以 Null 结尾的字符串，以兼容 printf
-        let mut bytes = s.as_bytes().to_vec();
-        bytes.push(0);
-        data_ctx.define(bytes.into_boxed_slice());
+        // 长度头 + 以 Null 结尾的内容：printf 只看内容，str_len 读长度头
+        data_ctx.define(string::encode(s.as_bytes()).into_boxed_slice());
+        data_ctx.set_align(string::HEADER_SIZE as u64);
 
-        self.module.define_data(data_id, &data_ctx).unwrap();
+        self.module
+            .define_data(data_id, &data_ctx)
+            .map_err(|e| CompileError::Linking(e.to_string()))?;
 
         let local_id = self.module.declare_data_in_func(data_id, self.builder.func);
         let pointer = self.module.target_config().pointer_type();
-        self.builder.ins().symbol_value(pointer, local_id)
+        let header = self.builder.ins().symbol_value(pointer, local_id);
+        // 字符串的值指向长度头之后的内容
+        Ok(self
+            .builder
+            .ins()
+            .iadd_imm(header, string::HEADER_SIZE as i64))
     }
 
-    fn translate_complex_literal(&mut self, re: f64, im: f64, ty: FrontendType) -> Value {
-        match ty {
+    fn translate_complex_literal(
+        &mut self,
+        re: f64,
+        im: f64,
+        ty: FrontendType,
+    ) -> Result<Value, CompileError> {
+        let value = match ty {
             FrontendType::Complex64 => {
                 let re_bits = (re as f32).to_bits() as u64;
                 let im_bits = (im as f32).to_bits() as u64;
@@ -988,32 +2479,31 @@ impl<'a> FunctionTranslator<'a> {
                 self.builder.ins().stack_store(high, ss, 8);
                 self.builder.ins().stack_load(types::I128, ss, 0)
             }
-            _ => panic!("Invalid complex type"),
-        }
+            other => return Err(self.error(format!("invalid complex type {}", other))),
+        };
+        Ok(value)
     }
 
-    fn translate_array_literal(&mut self, elems: Vec<Expr>, _ty: FrontendType) -> Value {
+    fn translate_array_literal(
+        &mut self,
+        elems: Vec<Expr>,
+        _ty: FrontendType,
+    ) -> Result<Value, CompileError> {
         // 重新推断类型，因为解析器使用占位符
-        let actual_ty = if elems.is_empty() {
-            FrontendType::Array(Box::new(FrontendType::I64), 0)
-        } else {
-            let elem_ty = type_checker::infer_type(&elems[0], &|n| {
-                self.variables.get(n).map(|(_, t)| t.clone())
-            });
-            FrontendType::Array(Box::new(elem_ty), elems.len())
-        };
-
-        let (elem_ty, len) = match actual_ty {
-            FrontendType::Array(t, l) => (*t, l),
-            _ => panic!("Expected array type"),
+        let (elem_ty, len) = match elems.first() {
+            None => (FrontendType::I64, 0),
+            Some(first) => (self.infer_type(first), elems.len()),
         };
 
-        let cl_elem_ty = to_cranelift_type(&elem_ty);
-        let elem_size = cl_elem_ty.bytes();
+        let elem_size = self.type_checker.element_size(&elem_ty);
         let total_size = elem_size * (len as u32);
 
-        // 对元素使用自然对齐
-        let align_shift = (elem_size as f64).log2().ceil() as u8;
+        // 对元素使用自然对齐（结构体元素按其字段的最大对齐）
+        let align = match elem_ty {
+            FrontendType::Struct(_) => self.type_checker.align_of(&elem_ty),
+            _ => elem_size,
+        };
+        let align_shift = (align as f64).log2().ceil() as u8;
 
         let slot = self.builder.create_sized_stack_slot(StackSlotData {
             kind: StackSlotKind::ExplicitSlot,
@@ -1022,17 +2512,31 @@ impl<'a> FunctionTranslator<'a> {
         });
 
         for (i, elem) in elems.into_iter().enumerate() {
-            let val = self.translate_expr(elem);
+            let val = self.translate_expr(elem)?;
             let offset = (i as i32) * (elem_size as i32);
-            self.builder.ins().stack_store(val, slot, offset);
+            if matches!(elem_ty, FrontendType::Struct(_)) {
+                let addr = self.builder.ins().stack_addr(types::I64, slot, offset);
+                self.emit_copy(addr, val, &elem_ty);
+            } else {
+                self.builder.ins().stack_store(val, slot, offset);
+            }
         }
 
-        self.builder.ins().stack_addr(types::I64, slot, 0)
+        Ok(self.builder.ins().stack_addr(types::I64, slot, 0))
     }
 
-    fn translate_dynamic_array_literal(&mut self, elems: Vec<Expr>, ty: FrontendType) -> Value {
+    fn translate_dynamic_array_literal(
+        &mut self,
+        elems: Vec<Expr>,
+        ty: FrontendType,
+    ) -> Result<Value, CompileError> {
         // 根据元素类型选择正确的函数
+        // 解析器给出的是占位符，浮点 / 复数元素按第一个元素重新推断（整数元素仍存为 i64）；
         // ty 可能是 DynamicArray(inner) 或直接是元素类型 (I64, F64 等)
+        let ty = match elems.first().map(|first| self.infer_type(first)) {
+            Some(inferred @ (FrontendType::F64 | FrontendType::Complex128)) => inferred,
+            _ => ty,
+        };
         let elem_ty = match &ty {
             FrontendType::DynamicArray(inner) => *inner.clone(),
             FrontendType::I64 | FrontendType::I32 | FrontendType::I16 | FrontendType::I8 => {
@@ -1040,7 +2544,11 @@ impl<'a> FunctionTranslator<'a> {
             }
             FrontendType::F64 | FrontendType::F32 => ty.clone(),
             FrontendType::Complex128 | FrontendType::Complex64 => ty.clone(),
-            _ => panic!("Unexpected type for dynamic array literal: {:?}", ty),
+            _ => {
+                return Err(
+                    self.error(format!("unexpected type for dynamic array literal: {}", ty))
+                );
+            }
         };
 
         // 根据元素类型选择 new 和 push 函数名
@@ -1050,18 +2558,19 @@ impl<'a> FunctionTranslator<'a> {
             FrontendType::Complex128 => {
                 ("array_new_complex128", "array_push_complex128", types::I128)
             }
-            _ => panic!("Unsupported dynamic array element type: {:?}", elem_ty),
+            _ => {
+                return Err(self.error(format!(
+                    "unsupported dynamic array element type: {}",
+                    elem_ty
+                )));
+            }
         };
 
         // 创建数组
         let mut sig = self.module.make_signature();
         sig.returns.push(AbiParam::new(types::I64));
 
-        let callee = self
-            .module
-            .declare_function(new_fn, Linkage::Import, &sig)
-            .unwrap();
-        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+        let local_callee = self.import_function(new_fn, &sig)?;
         let call = self.builder.ins().call(local_callee, &[]);
         let arr_ptr = self.builder.inst_results(call)[0];
 
@@ -1072,41 +2581,240 @@ impl<'a> FunctionTranslator<'a> {
             push_sig.params.push(AbiParam::new(cl_elem_ty)); // elem
             push_sig.returns.push(AbiParam::new(types::I64)); // return 0
 
-            let push_callee = self
-                .module
-                .declare_function(push_fn, Linkage::Import, &push_sig)
-                .unwrap();
-            let push_local_callee = self
-                .module
-                .declare_func_in_func(push_callee, self.builder.func);
+            let push_local_callee = self.import_function(push_fn, &push_sig)?;
 
             for elem in elems {
-                let val = self.translate_expr(elem);
+                let val = self.translate_expr(elem)?;
                 // 如果需要，将值转换为正确的元素类型
-                let val_cast = self.translate_cast(val, cl_elem_ty);
+                let val_cast = self.translate_cast(val, cl_elem_ty)?;
                 self.builder
                     .ins()
                     .call(push_local_callee, &[arr_ptr, val_cast]);
             }
         }
 
-        arr_ptr
+        Ok(arr_ptr)
+    }
+
+    fn translate_index(&mut self, base: Expr, idx: Expr) -> Result<Value, CompileError> {
+        let (addr, elem_ty) = self.translate_element_addr(base, idx)?;
+        Ok(self.load_value(addr, &elem_ty, false))
+    }
+
+    /// 翻译 `arr[i] = v` / `arr[i] op= v`。
+    ///
+    /// 与 Rust 相同，先求值右侧再计算元素地址：右侧若向同一个动态数组
+    /// push 导致扩容，之前算出的地址会失效。复合赋值的索引只求值一次，
+    /// 读出旧值、运算后写回。返回写入的新值。
+    fn translate_index_assign(
+        &mut self,
+        base: Expr,
+        idx: Expr,
+        op: Option<AssignOp>,
+        value: Expr,
+    ) -> Result<Value, CompileError> {
+        let rhs = self.translate_expr(value)?;
+        let (addr, elem_ty) = self.translate_element_addr(base, idx)?;
+        self.store_compound(addr, &elem_ty, false, op, rhs)
+    }
+
+    /// 翻译 `p.f = v` / `p.f op= v`，求值顺序与 translate_index_assign 相同。返回写入的新值
+    fn translate_field_assign(
+        &mut self,
+        base: Expr,
+        field: String,
+        op: Option<AssignOp>,
+        value: Expr,
+    ) -> Result<Value, CompileError> {
+        let rhs = self.translate_expr(value)?;
+        let (addr, field_ty) = self.translate_field_addr(base, &field)?;
+        self.store_compound(addr, &field_ty, true, op, rhs)
+    }
+
+    /// 把 `rhs` 写入 `addr` 处类型为 `ty` 的位置；复合赋值先读出旧值运算。
+    /// `in_struct` 的含义见 load_value
+    fn store_compound(
+        &mut self,
+        addr: Value,
+        ty: &FrontendType,
+        in_struct: bool,
+        op: Option<AssignOp>,
+        rhs: Value,
+    ) -> Result<Value, CompileError> {
+        let new_value = match op {
+            Some(op) => {
+                let old = self.load_value(addr, ty, in_struct);
+                self.apply_assign_op(op, ty, old, rhs)?
+            }
+            None => rhs,
+        };
+        if is_inline(ty, in_struct) {
+            self.emit_copy(addr, new_value, ty);
+            return Ok(addr);
+        }
+        let new_value = self.translate_cast(new_value, to_cranelift_type(ty))?;
+        self.builder
+            .ins()
+            .store(MemFlags::new(), new_value, addr, 0);
+        Ok(new_value)
+    }
+
+    /// 读取 `addr` 处类型为 `ty` 的值。按值内嵌的结构体（以及结构体字段中的定长数组，
+    /// `in_struct`）没有单独的指针，它的值就是所在的地址
+    fn load_value(&mut self, addr: Value, ty: &FrontendType, in_struct: bool) -> Value {
+        if is_inline(ty, in_struct) {
+            return addr;
+        }
+        self.builder
+            .ins()
+            .load(to_cranelift_type(ty), MemFlags::new(), addr, 0)
+    }
+
+    /// 把 `src` 处类型为 `ty` 的结构体 / 定长数组整体复制到 `dest`（两者可以重叠）
+    fn emit_copy(&mut self, dest: Value, src: Value, ty: &FrontendType) {
+        let config = self.module.target_config();
+        let size = self.type_checker.size_of(ty) as u64;
+        let align = self.type_checker.align_of(ty) as u8;
+        self.builder.emit_small_memory_copy(
+            config,
+            dest,
+            src,
+            size,
+            align,
+            align,
+            false,
+            MemFlags::new(),
+        );
+    }
+
+    /// 在当前函数的栈上分配一块清零的 `ty` 存储，返回其地址
+    fn alloc_zeroed(&mut self, ty: &FrontendType) -> Value {
+        let config = self.module.target_config();
+        alloc_zeroed(&mut self.builder, config, self.type_checker, ty)
+    }
+
+    /// 结构体字面量：在栈上分配临时存储并逐个写入字段，值为存储地址
+    fn translate_struct_literal(
+        &mut self,
+        name: String,
+        fields: Vec<(String, Expr)>,
+    ) -> Result<Value, CompileError> {
+        let layout = self
+            .type_checker
+            .struct_layout(&name)
+            .ok_or_else(|| self.error(format!("undefined type '{}'", name)))?;
+        let addr = self.alloc_zeroed(&FrontendType::Struct(name));
+        for (field, value) in fields {
+            let (offset, ty) = layout
+                .field(&field)
+                .map(|(offset, ty)| (offset, ty.clone()))
+                .ok_or_else(|| self.error(format!("unknown field '{}'", field)))?;
+            let val = self.translate_expr(value)?;
+            let field_addr = self.builder.ins().iadd_imm(addr, offset as i64);
+            self.store_compound(field_addr, &ty, true, None, val)?;
+        }
+        Ok(addr)
+    }
+
+    /// 枚举值：在栈上分配临时存储，写入标签（变体序号）和载荷，值为存储地址
+    fn translate_enum_literal(
+        &mut self,
+        name: String,
+        variant: &str,
+        args: Vec<Expr>,
+    ) -> Result<Value, CompileError> {
+        let (index, payload) = self
+            .type_checker
+            .enums
+            .get(&name)
+            .and_then(|def| def.variant(variant))
+            .map(|(index, payload)| (index, payload.to_vec()))
+            .ok_or_else(|| self.error(format!("no variant '{}' in '{}'", variant, name)))?;
+        let layout = self
+            .type_checker
+            .enum_layout(&name)
+            .ok_or_else(|| self.error(format!("undefined type '{}'", name)))?;
+        let addr = self.alloc_zeroed(&FrontendType::Struct(name));
+        let tag = InstBuilder::iconst(self.builder.ins(), types::I32, index as i64);
+        self.builder.ins().store(MemFlags::new(), tag, addr, 0);
+        for ((arg, ty), offset) in args.into_iter().zip(&payload).zip(&layout.payloads[index]) {
+            let val = self.translate_expr(arg)?;
+            let field_addr = self.builder.ins().iadd_imm(addr, *offset as i64);
+            self.store_compound(field_addr, ty, true, None, val)?;
+        }
+        Ok(addr)
+    }
+
+    /// 计算 `base.field` 的地址，返回 (地址, 字段类型)
+    fn translate_field_addr(
+        &mut self,
+        base: Expr,
+        field: &str,
+    ) -> Result<(Value, FrontendType), CompileError> {
+        let base_ty = self.infer_type(&base);
+        let (offset, ty) = match &base_ty {
+            FrontendType::Struct(name) => self
+                .type_checker
+                .struct_layout(name)
+                .and_then(|layout| layout.field(field).map(|(o, t)| (o, t.clone()))),
+            _ => None,
+        }
+        .ok_or_else(|| self.error(format!("no field '{}' on type {}", field, base_ty)))?;
+        let base_val = self.translate_expr(base)?;
+        Ok((self.builder.ins().iadd_imm(base_val, offset as i64), ty))
+    }
+
+    /// 对已求值的旧值和右侧执行复合赋值的运算，结果与对应的二元表达式相同
+    fn apply_assign_op(
+        &mut self,
+        op: AssignOp,
+        ty: &FrontendType,
+        l_val: Value,
+        r_val: Value,
+    ) -> Result<Value, CompileError> {
+        let bin_op = match op {
+            AssignOp::Add => BinOp::Add,
+            AssignOp::Sub => BinOp::Sub,
+            AssignOp::Mul => BinOp::Mul,
+            AssignOp::Div => BinOp::Div,
+        };
+        if is_complex(ty) {
+            return self.complex_binop_values(l_val, r_val, bin_op);
+        }
+        let (l, r) = self.promote_operands(l_val, r_val)?;
+        let is_float = self.builder.func.dfg.value_type(l).is_float();
+        if matches!(bin_op, BinOp::Div) && !is_float {
+            return self.translate_int_div(l, r, false);
+        }
+        let ins = self.builder.ins();
+        Ok(match (bin_op, is_float) {
+            (BinOp::Add, true) => ins.fadd(l, r),
+            (BinOp::Add, false) => ins.iadd(l, r),
+            (BinOp::Sub, true) => ins.fsub(l, r),
+            (BinOp::Sub, false) => ins.isub(l, r),
+            (BinOp::Mul, true) => ins.fmul(l, r),
+            (BinOp::Mul, false) => ins.imul(l, r),
+            (BinOp::Div, true) => ins.fdiv(l, r),
+            (BinOp::Div, false) => unreachable!(),
+        })
     }
 
-    fn translate_index(&mut self, base: Expr, idx: Expr) -> Value {
-        let base_ty =
-            type_checker::infer_type(&base, &|n| self.variables.get(n).map(|(_, t)| t.clone()));
+    /// 计算 `base[idx]` 的元素地址并做边界检查（越界时 trap），返回 (地址, 元素类型)
+    fn translate_element_addr(
+        &mut self,
+        base: Expr,
+        idx: Expr,
+    ) -> Result<(Value, FrontendType), CompileError> {
+        let base_ty = self.infer_type(&base);
         let (elem_ty, len, is_dynamic) = match base_ty {
             FrontendType::Array(t, l) => (*t, l, false),
             FrontendType::DynamicArray(t) => (*t, 0, true),
             FrontendType::String => (FrontendType::I8, 0, false), // 字符串暂无边界检查
-            _ => panic!("Cannot index non-array type: {:?}", base_ty),
+            _ => return Err(self.error(format!("cannot index a value of type {}", base_ty))),
         };
 
-        let base_val = self.translate_expr(base);
-        let idx_val = self.translate_expr(idx);
-
-        let cl_elem_ty = to_cranelift_type(&elem_ty);
+        let base_val = self.translate_expr(base)?;
+        let idx_val = self.translate_expr(idx)?;
 
         let idx_val_i64 = if self.builder.func.dfg.value_type(idx_val) != types::I64 {
             self.builder.ins().uextend(types::I64, idx_val)
@@ -1120,10 +2828,12 @@ impl<'a> FunctionTranslator<'a> {
                 FrontendType::I64 => "array_get_ptr",
                 FrontendType::F64 => "array_get_ptr_f64",
                 FrontendType::Complex128 => "array_get_ptr_complex128",
-                _ => panic!(
-                    "Unsupported dynamic array element type for index: {:?}",
-                    elem_ty
-                ),
+                _ => {
+                    return Err(self.error(format!(
+                        "unsupported dynamic array element type for index: {}",
+                        elem_ty
+                    )));
+                }
             };
 
             let mut sig = self.module.make_signature();
@@ -1131,11 +2841,7 @@ impl<'a> FunctionTranslator<'a> {
             sig.params.push(AbiParam::new(types::I64)); // index
             sig.returns.push(AbiParam::new(types::I64)); // 元素指针
 
-            let callee = self
-                .module
-                .declare_function(get_ptr_fn, Linkage::Import, &sig)
-                .unwrap();
-            let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+            let local_callee = self.import_function(get_ptr_fn, &sig)?;
             let call = self
                 .builder
                 .ins()
@@ -1145,11 +2851,9 @@ impl<'a> FunctionTranslator<'a> {
             // 如果 addr 为空（索引越界），则触发陷阱
             self.builder.ins().trapz(addr, TrapCode::unwrap_user(1));
 
-            self.builder
-                .ins()
-                .load(cl_elem_ty, MemFlags::new(), addr, 0)
+            Ok((addr, elem_ty))
         } else {
-            let elem_size = cl_elem_ty.bytes() as i64;
+            let elem_size = self.type_checker.element_size(&elem_ty) as i64;
             // 边界检查
             if len > 0 {
                 let len_val = self.builder.ins().iconst(types::I64, len as i64);
@@ -1168,16 +2872,29 @@ impl<'a> FunctionTranslator<'a> {
             let offset = self.builder.ins().imul_imm(idx_val_i64, elem_size);
             let addr = self.builder.ins().iadd(base_val, offset);
 
-            self.builder
-                .ins()
-                .load(cl_elem_ty, MemFlags::new(), addr, 0)
+            Ok((addr, elem_ty))
         }
     }
 
-    fn translate_complex_binop(&mut self, lhs: Expr, rhs: Expr, op: BinOp) -> Value {
-        let l_val = self.translate_expr(lhs);
-        let r_val = self.translate_expr(rhs);
-        // 假设类型匹配（infer_type 确保这一点，否则我们会 panic 或提升）。
+    fn translate_complex_binop(
+        &mut self,
+        lhs: Expr,
+        rhs: Expr,
+        op: BinOp,
+    ) -> Result<Value, CompileError> {
+        let l_val = self.translate_expr(lhs)?;
+        let r_val = self.translate_expr(rhs)?;
+        self.complex_binop_values(l_val, r_val, op)
+    }
+
+    /// 对已求值的两个复数执行 `+ - * /`
+    fn complex_binop_values(
+        &mut self,
+        l_val: Value,
+        r_val: Value,
+        op: BinOp,
+    ) -> Result<Value, CompileError> {
+        // 假设类型匹配（类型检查器已确保这一点，否则返回错误）。
         let ty = self.builder.func.dfg.value_type(l_val);
 
         if ty == types::I64 {
@@ -1268,7 +2985,7 @@ impl<'a> FunctionTranslator<'a> {
             let res_re_i64 = self.builder.ins().uextend(types::I64, res_re_bits);
             let res_im_i64 = self.builder.ins().uextend(types::I64, res_im_bits);
             let res_im_shifted = self.builder.ins().ishl_imm(res_im_i64, 32);
-            self.builder.ins().bor(res_re_i64, res_im_shifted)
+            Ok(self.builder.ins().bor(res_re_i64, res_im_shifted))
         } else if ty == types::I128 {
             // Complex128
             let ss = self.builder.create_sized_stack_slot(StackSlotData::new(
@@ -1337,9 +3054,9 @@ impl<'a> FunctionTranslator<'a> {
             // 重新打包
             self.builder.ins().stack_store(res_re, ss, 0);
             self.builder.ins().stack_store(res_im, ss, 8);
-            self.builder.ins().stack_load(types::I128, ss, 0)
+            Ok(self.builder.ins().stack_load(types::I128, ss, 0))
         } else {
-            panic!("不支持的复数类型 IR: {:?}", ty);
+            Err(self.error(format!("不支持的复数类型 IR: {}", ty)))
         }
     }
 }
@@ -1347,20 +3064,41 @@ impl<'a> FunctionTranslator<'a> {
 /// 在 JIT 编译开始前 扫描并声明所有变量
 fn declare_variables(
     builder: &mut FunctionBuilder,
+    config: TargetFrontendConfig,
+    type_checker: &TypeChecker,
     params: &[(String, FrontendType)],
     stmts: &[Expr],
     entry_block: Block,
     return_info: &(String, FrontendType),
 ) -> HashMap<String, (Variable, FrontendType)> {
     let mut variables = HashMap::new();
+    // 返回结构体时第 0 个入口参数是 sret 地址，toy 参数从第 1 个开始
+    let (ret_name, ret_ty) = return_info;
+    let sret = matches!(ret_ty, FrontendType::Struct(_));
 
     // - 注册 ：为每个函数参数创建一个 Cranelift 变量（ declare_var ）。
     // - 绑定 ：把函数的 入口参数值 （ block_params ）赋给这个变量（ def_var ）。Cranelift 允许我们定义一个 Variable 作为占位符，后续用 def_var 不断把新的 SSA Value 绑定给它。这样，当变量 a 在函数体内被重新赋值时，无需重建所有引用链，只需调用 def_var(var, new_value) 更新即可
-    // - 跟踪 ：将变量名、Variable 句柄和类型存入 HashMap，以便后续查找和类型检查。variables.insert(name.clone(), (var, ty.clone()));把 "a" → (var_0, I32) 存入映射表。后续翻译函数体遇到 Expr::Identifier("a") 时，通过这张表就能找到对应的 Cranelift Variable，再用 use_var 读取它的当前值
+    // - 跟踪 ：将变量名、Variable 句柄和类型存入 HashMap，以便后续查找和类型检查。variables.insert(name.clone(), (var, ty.clone()));把 "a" → (var_0, I32) 存入映射表。后续翻译函数体遇到 ExprKind::Identifier("a") 时，通过这张表就能找到对应的 Cranelift Variable，再用 use_var 读取它的当前值
     for (i, (name, ty)) in params.iter().enumerate() {
-        let val = builder.block_params(entry_block)[i];
+        let mut val = builder.block_params(entry_block)[i + sret as usize];
         let var = builder.declare_var(to_cranelift_type(ty));
         variables.insert(name.clone(), (var, ty.clone()));
+        // 结构体按指针传入，先复制一份，函数内修改字段不影响调用者（值语义）
+        if matches!(ty, FrontendType::Struct(_)) {
+            let copy = alloc_zeroed(builder, config, type_checker, ty);
+            let (size, align) = (type_checker.size_of(ty), type_checker.align_of(ty) as u8);
+            builder.emit_small_memory_copy(
+                config,
+                copy,
+                val,
+                size as u64,
+                align,
+                align,
+                true,
+                MemFlags::new(),
+            );
+            val = copy;
+        }
         builder.def_var(var, val);
 
         // 跟踪作为参数传递的动态数组（调用者拥有它们？通常调用者拥有，但在 Toy 中，如果是最后一个拥有者，我们可能希望被调用者丢弃。
@@ -1372,16 +3110,27 @@ fn declare_variables(
     }
 
     // 声明返回值变量
-    let (ret_name, ret_ty) = return_info;
     if !variables.contains_key(ret_name) {
         let cl_ty = to_cranelift_type(ret_ty);
         let var = builder.declare_var(cl_ty);
         variables.insert(ret_name.clone(), (var, ret_ty.clone()));
 
-        // 将返回变量初始化为 0 或等效值
+        // 将返回变量初始化为 0 或等效值；结构体直接在调用者提供的 sret 存储中构造
         let zero = match ret_ty {
+            FrontendType::Struct(_) => {
+                let addr = builder.block_params(entry_block)[0];
+                let size = type_checker.size_of(ret_ty) as u64;
+                let align = type_checker.align_of(ret_ty) as u8;
+                builder.emit_small_memset(config, addr, 0, size, align, MemFlags::new());
+                addr
+            }
             FrontendType::F32 => builder.ins().f32const(0.0),
             FrontendType::F64 => builder.ins().f64const(0.0),
+            // iconst 不支持 i128，由 i64 的 0 零扩展得到
+            _ if cl_ty == types::I128 => {
+                let zero = InstBuilder::iconst(builder.ins(), types::I64, 0);
+                builder.ins().uextend(types::I128, zero)
+            }
             _ => InstBuilder::iconst(builder.ins(), cl_ty, 0),
         };
         builder.def_var(var, zero);
@@ -1389,44 +3138,110 @@ fn declare_variables(
 
     // 扫描语句中的隐式变量
     for expr in stmts {
-        declare_variables_in_stmt(builder, &mut variables, expr);
+        declare_variables_in_stmt(builder, config, type_checker, &mut variables, expr);
     }
 
     variables
 }
 
-/// 递归扫描表达式中的变量声明
+/// 递归扫描表达式中的变量声明。结构体变量在入口块分配好清零的栈上存储，
+/// 变量的值是存储地址，之后的赋值都复制到这块存储中
 fn declare_variables_in_stmt(
     builder: &mut FunctionBuilder,
+    config: TargetFrontendConfig,
+    type_checker: &TypeChecker,
     variables: &mut HashMap<String, (Variable, FrontendType)>,
     expr: &Expr,
 ) {
-    match *expr {
-        Expr::Assign(ref name, ref val_expr) if !variables.contains_key(name) => {
-            // 推断类型
+    match expr.kind {
+        ExprKind::Assign(ref name, ref val_expr) => {
+            // 右边先扫描（例如 `r = match ...` 中分支绑定的变量），与 TypeChecker::var_types 一致
+            declare_variables_in_stmt(builder, config, type_checker, variables, val_expr);
+            // 给静态变量赋值不声明局部变量（同名的参数除外，它已在 variables 中）
+            if !variables.contains_key(name) && !type_checker.globals.contains_key(name) {
+                // 推断类型
+                let ty = type_checker
+                    .infer_type(val_expr, &|n| variables.get(n).map(|(_, t)| t.clone()));
+                declare_variable(builder, config, type_checker, variables, name, ty);
+            }
+        }
+        // 有类型标注时取标注的类型
+        ExprKind::Let(ref name, _, ref ty, ref val_expr) => {
+            declare_variables_in_stmt(builder, config, type_checker, variables, val_expr);
+            if !variables.contains_key(name) {
+                let ty = ty.clone().unwrap_or_else(|| {
+                    type_checker.infer_type(val_expr, &|n| variables.get(n).map(|(_, t)| t.clone()))
+                });
+                declare_variable(builder, config, type_checker, variables, name, ty);
+            }
+        }
+        ExprKind::Match(ref scrutinee, ref arms) => {
             let ty =
-                type_checker::infer_type(val_expr, &|n| variables.get(n).map(|(_, t)| t.clone()));
-            let var = builder.declare_var(to_cranelift_type(&ty));
-            variables.insert(name.clone(), (var, ty));
+                type_checker.infer_type(scrutinee, &|n| variables.get(n).map(|(_, t)| t.clone()));
+            for arm in arms {
+                for (name, binding_ty) in type_checker.pattern_bindings(&ty, &arm.pattern) {
+                    if !variables.contains_key(&name) {
+                        declare_variable(
+                            builder,
+                            config,
+                            type_checker,
+                            variables,
+                            &name,
+                            binding_ty,
+                        );
+                    }
+                }
+                for stmt in &arm.body {
+                    declare_variables_in_stmt(builder, config, type_checker, variables, stmt);
+                }
+            }
         }
-        Expr::IfElse(ref _condition, ref then_body, ref else_body) => {
+        ExprKind::IfElse(ref _condition, ref then_body, ref else_body) => {
             for stmt in then_body {
-                declare_variables_in_stmt(builder, variables, stmt);
+                declare_variables_in_stmt(builder, config, type_checker, variables, stmt);
             }
             for stmt in else_body {
-                declare_variables_in_stmt(builder, variables, stmt);
+                declare_variables_in_stmt(builder, config, type_checker, variables, stmt);
             }
         }
-        Expr::WhileLoop(ref _condition, ref loop_body) => {
+        ExprKind::WhileLoop(ref _condition, ref loop_body) => {
             for stmt in loop_body {
-                declare_variables_in_stmt(builder, variables, stmt);
+                declare_variables_in_stmt(builder, config, type_checker, variables, stmt);
             }
         }
-        Expr::Block(ref body) => {
+        ExprKind::Block(ref body) => {
             for stmt in body {
-                declare_variables_in_stmt(builder, variables, stmt);
+                declare_variables_in_stmt(builder, config, type_checker, variables, stmt);
+            }
+        }
+        ExprKind::ForRange(ref var, .., ref loop_body)
+        | ExprKind::ForEach(ref var, _, ref loop_body) => {
+            if !variables.contains_key(var) {
+                let ty =
+                    type_checker.loop_var_type(expr, &|n| variables.get(n).map(|(_, t)| t.clone()));
+                declare_variable(builder, config, type_checker, variables, var, ty);
+            }
+            for stmt in loop_body {
+                declare_variables_in_stmt(builder, config, type_checker, variables, stmt);
             }
         }
         _ => (),
     }
 }
+
+/// 声明一个局部变量；结构体与枚举变量同时分配清零的存储，变量的值是存储地址
+fn declare_variable(
+    builder: &mut FunctionBuilder,
+    config: TargetFrontendConfig,
+    type_checker: &TypeChecker,
+    variables: &mut HashMap<String, (Variable, FrontendType)>,
+    name: &str,
+    ty: FrontendType,
+) {
+    let var = builder.declare_var(to_cranelift_type(&ty));
+    if matches!(ty, FrontendType::Struct(_)) {
+        let addr = alloc_zeroed(builder, config, type_checker, &ty);
+        builder.def_var(var, addr);
+    }
+    variables.insert(name.to_string(), (var, ty));
+}
@@ -2,9 +2,17 @@
 extern crate intel_mkl_src;
 
 pub mod cli;
+pub mod diagnostic;
+pub mod error;
+pub mod formatter;
 pub mod frontend;
+pub mod func;
 pub mod jit;
 pub mod optimizer;
 pub mod ownership;
+pub mod pipeline;
+pub mod repl;
+pub mod resolver;
 pub mod runtime;
 pub mod type_checker;
+pub mod value;
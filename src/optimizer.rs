@@ -1,8 +1,10 @@
 //! 常量折叠优化 Pass
 //!
 //! 在 AST 层面计算编译时可确定的常量表达式，避免运行时的冗余计算。
+//! 折叠之前先用 `inline_constants` 把模块级常量（`const N: i64 = 64`）替换成它的值。
 
-use crate::frontend::{Expr, Type};
+use crate::frontend::{Expr, ExprKind, MatchArm, Type};
+use std::collections::HashMap;
 
 /// 对函数体中的所有语句应用常量折叠优化
 pub fn fold_constants_in_stmts(stmts: Vec<Expr>) -> Vec<Expr> {
@@ -11,63 +13,258 @@ pub fn fold_constants_in_stmts(stmts: Vec<Expr>) -> Vec<Expr> {
 //因为fold_constants 是单表达式版本，这里要递归处理每个语句中的子表达式，确保整个函数体都被优化到。
 //stmts.into_iter() 是 Vec 的标准"消耗式迭代器入口"（来自 IntoIterator trait），把 Vec<Expr> 拆成一个一个 owned Expr 喂给 fold_constants，避免任何 clone 或借用冲突。
 
-/// 对单个表达式应用常量折叠优化
+/// 对单个表达式应用常量折叠优化，折叠结果沿用原表达式的源码位置
 pub fn fold_constants(expr: Expr) -> Expr {
-    match expr {                                    //match expr 是 Rust 内置的穷尽性模式匹配
-        // 算术运算
-        Expr::Add(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Add, |a, b| a + b),
-        Expr::Sub(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Sub, |a, b| a - b),
-        Expr::Mul(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Mul, |a, b| a * b),
-        Expr::Div(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Div, |a, b| a / b),
+    let span = expr.span;
+    // match 是 Rust 内置的穷尽性模式匹配
+    let kind = match expr.kind {
+        // 算术运算：与 iadd / isub / imul 一样按补码回绕
+        ExprKind::Add(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Add, i64::wrapping_add),
+        ExprKind::Sub(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Sub, i64::wrapping_sub),
+        ExprKind::Mul(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Mul, i64::wrapping_mul),
+        ExprKind::Div(lhs, rhs) => fold_binary_op(*lhs, *rhs, OpType::Div, |a, b| a / b),
+        ExprKind::Rem(lhs, rhs) => fold_rem(*lhs, *rhs),
+        ExprKind::Neg(inner) => fold_neg(*inner),
+
+        // 位运算与移位：与 Cranelift 一致，移位量按 64 取模，>> 为算术右移
+        ExprKind::BitAnd(lhs, rhs) => fold_int_op(*lhs, *rhs, |a, b| a & b, ExprKind::BitAnd),
+        ExprKind::BitOr(lhs, rhs) => fold_int_op(*lhs, *rhs, |a, b| a | b, ExprKind::BitOr),
+        ExprKind::BitXor(lhs, rhs) => fold_int_op(*lhs, *rhs, |a, b| a ^ b, ExprKind::BitXor),
+        ExprKind::Shl(lhs, rhs) => {
+            fold_int_op(*lhs, *rhs, |a, b| a.wrapping_shl(b as u32), ExprKind::Shl)
+        }
+        ExprKind::Shr(lhs, rhs) => {
+            fold_int_op(*lhs, *rhs, |a, b| a.wrapping_shr(b as u32), ExprKind::Shr)
+        }
 
         // 比较运算
-        Expr::Eq(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a == b, Expr::Eq),
-        Expr::Ne(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a != b, Expr::Ne),
-        Expr::Lt(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a < b, Expr::Lt),
-        Expr::Le(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a <= b, Expr::Le),
-        Expr::Gt(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a > b, Expr::Gt),
-        Expr::Ge(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a >= b, Expr::Ge),
+        ExprKind::Eq(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a == b, ExprKind::Eq),
+        ExprKind::Ne(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a != b, ExprKind::Ne),
+        ExprKind::Lt(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a < b, ExprKind::Lt),
+        ExprKind::Le(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a <= b, ExprKind::Le),
+        ExprKind::Gt(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a > b, ExprKind::Gt),
+        ExprKind::Ge(lhs, rhs) => fold_cmp(*lhs, *rhs, |a, b| a >= b, ExprKind::Ge),
+
+        // 逻辑运算
+        ExprKind::And(lhs, rhs) => fold_logical(*lhs, *rhs, true),
+        ExprKind::Or(lhs, rhs) => fold_logical(*lhs, *rhs, false),
+        ExprKind::Not(inner) => fold_not(*inner),
 
         // 赋值语句
-        Expr::Assign(name, val) => Expr::Assign(name, Box::new(fold_constants(*val))),
+        ExprKind::Assign(name, val) => ExprKind::Assign(name, Box::new(fold_constants(*val))),
+        ExprKind::Let(name, mutable, ty, val) => {
+            ExprKind::Let(name, mutable, ty, Box::new(fold_constants(*val)))
+        }
+        ExprKind::CompoundAssign(name, op, val) => {
+            ExprKind::CompoundAssign(name, op, Box::new(fold_constants(*val)))
+        }
+        ExprKind::IndexAssign(base, idx, op, val) => ExprKind::IndexAssign(
+            Box::new(fold_constants(*base)),
+            Box::new(fold_constants(*idx)),
+            op,
+            Box::new(fold_constants(*val)),
+        ),
+        ExprKind::FieldAssign(base, field, op, val) => ExprKind::FieldAssign(
+            Box::new(fold_constants(*base)),
+            field,
+            op,
+            Box::new(fold_constants(*val)),
+        ),
 
         // 条件分支 - 递归处理
-        Expr::IfElse(cond, then_body, else_body) => Expr::IfElse(
+        ExprKind::IfElse(cond, then_body, else_body) => ExprKind::IfElse(
             Box::new(fold_constants(*cond)),
             then_body.into_iter().map(fold_constants).collect(),
             else_body.into_iter().map(fold_constants).collect(),
         ),
 
         // While 循环 - 递归处理
-        Expr::WhileLoop(cond, body) => Expr::WhileLoop(
+        ExprKind::WhileLoop(cond, body) => ExprKind::WhileLoop(
             Box::new(fold_constants(*cond)),
             body.into_iter().map(fold_constants).collect(),
         ),
 
+        // for 循环 - 递归处理区间、被遍历的数组和循环体
+        ExprKind::ForRange(var, start, end, inclusive, step, body) => ExprKind::ForRange(
+            var,
+            Box::new(fold_constants(*start)),
+            Box::new(fold_constants(*end)),
+            inclusive,
+            step.map(|s| Box::new(fold_constants(*s))),
+            body.into_iter().map(fold_constants).collect(),
+        ),
+        ExprKind::ForEach(var, iterable, body) => ExprKind::ForEach(
+            var,
+            Box::new(fold_constants(*iterable)),
+            body.into_iter().map(fold_constants).collect(),
+        ),
+
         // 块作用域 - 递归处理
-        Expr::Block(body) => {
-            Expr::Block(body.into_iter().map(fold_constants).collect())
-        }
+        ExprKind::Block(body) => ExprKind::Block(body.into_iter().map(fold_constants).collect()),
+
+        // 提前返回
+        ExprKind::Return(val) => ExprKind::Return(Box::new(fold_constants(*val))),
 
         // 函数调用 - 递归处理参数
-        Expr::Call(name, args) => Expr::Call(name, args.into_iter().map(fold_constants).collect()),
+        ExprKind::Call(name, args) => {
+            ExprKind::Call(name, args.into_iter().map(fold_constants).collect())
+        }
 
         // 数组索引
-        Expr::Index(base, idx) => Expr::Index(
+        ExprKind::Index(base, idx) => ExprKind::Index(
             Box::new(fold_constants(*base)),
             Box::new(fold_constants(*idx)),
         ),
 
+        // 结构体字面量与字段访问
+        ExprKind::StructLiteral(name, fields) => ExprKind::StructLiteral(
+            name,
+            fields
+                .into_iter()
+                .map(|(field, value)| (field, fold_constants(value)))
+                .collect(),
+        ),
+        ExprKind::Field(base, field) => ExprKind::Field(Box::new(fold_constants(*base)), field),
+
+        // 枚举值与 match
+        ExprKind::EnumLiteral(name, variant, args) => ExprKind::EnumLiteral(
+            name,
+            variant,
+            args.into_iter().map(fold_constants).collect(),
+        ),
+        ExprKind::Match(scrutinee, arms) => ExprKind::Match(
+            Box::new(fold_constants(*scrutinee)),
+            arms.into_iter()
+                .map(|arm| MatchArm {
+                    body: arm.body.into_iter().map(fold_constants).collect(),
+                    ..arm
+                })
+                .collect(),
+        ),
+
         // 类型转换
-        Expr::Cast(expr, ty) => fold_cast(*expr, ty),
+        ExprKind::Cast(expr, ty) => fold_cast(*expr, ty),
 
         // 字面量、标识符、全局地址等保持不变
-        _ => expr,
+        kind => kind,
+    };
+    Expr::new(kind, span)
+}
+
+/// 把语句中对常量的引用替换成常量的值（字面量），替换结果沿用引用处的源码位置。
+/// 名字解析已把遮蔽常量的局部变量改名，这里遇到的同名标识符都是常量；
+/// 调用者需去掉被参数或返回变量遮蔽的常量
+pub fn inline_constants(mut stmts: Vec<Expr>, constants: &HashMap<String, Expr>) -> Vec<Expr> {
+    if !constants.is_empty() {
+        for stmt in &mut stmts {
+            inline_in(stmt, constants);
+        }
+    }
+    stmts
+}
+
+fn inline_in(expr: &mut Expr, constants: &HashMap<String, Expr>) {
+    if let ExprKind::Identifier(name) = &expr.kind
+        && let Some(value) = constants.get(name)
+    {
+        expr.kind = value.kind.clone();
+        return;
+    }
+    let inline_all = |exprs: &mut Vec<Expr>| {
+        for e in exprs {
+            inline_in(e, constants);
+        }
+    };
+    match &mut expr.kind {
+        ExprKind::Eq(l, r)
+        | ExprKind::Ne(l, r)
+        | ExprKind::Lt(l, r)
+        | ExprKind::Le(l, r)
+        | ExprKind::Gt(l, r)
+        | ExprKind::Ge(l, r)
+        | ExprKind::And(l, r)
+        | ExprKind::Or(l, r)
+        | ExprKind::Add(l, r)
+        | ExprKind::Sub(l, r)
+        | ExprKind::Mul(l, r)
+        | ExprKind::Div(l, r)
+        | ExprKind::Rem(l, r)
+        | ExprKind::BitAnd(l, r)
+        | ExprKind::BitOr(l, r)
+        | ExprKind::BitXor(l, r)
+        | ExprKind::Shl(l, r)
+        | ExprKind::Shr(l, r)
+        | ExprKind::Index(l, r) => {
+            inline_in(l, constants);
+            inline_in(r, constants);
+        }
+        ExprKind::Not(inner)
+        | ExprKind::Neg(inner)
+        | ExprKind::Return(inner)
+        | ExprKind::Cast(inner, _)
+        | ExprKind::Field(inner, _)
+        | ExprKind::Assign(_, inner)
+        | ExprKind::Let(_, _, _, inner)
+        | ExprKind::CompoundAssign(_, _, inner) => inline_in(inner, constants),
+        ExprKind::IndexAssign(base, idx, _, value) => {
+            inline_in(base, constants);
+            inline_in(idx, constants);
+            inline_in(value, constants);
+        }
+        ExprKind::FieldAssign(base, _, _, value) => {
+            inline_in(base, constants);
+            inline_in(value, constants);
+        }
+        ExprKind::IfElse(cond, then_body, else_body) => {
+            inline_in(cond, constants);
+            inline_all(then_body);
+            inline_all(else_body);
+        }
+        ExprKind::WhileLoop(cond, body) => {
+            inline_in(cond, constants);
+            inline_all(body);
+        }
+        ExprKind::ForRange(_, start, end, _, step, body) => {
+            inline_in(start, constants);
+            inline_in(end, constants);
+            if let Some(step) = step {
+                inline_in(step, constants);
+            }
+            inline_all(body);
+        }
+        ExprKind::ForEach(_, iterable, body) => {
+            inline_in(iterable, constants);
+            inline_all(body);
+        }
+        ExprKind::Match(scrutinee, arms) => {
+            inline_in(scrutinee, constants);
+            for arm in arms {
+                inline_all(&mut arm.body);
+            }
+        }
+        ExprKind::Block(body)
+        | ExprKind::Call(_, body)
+        | ExprKind::ArrayLiteral(body, _)
+        | ExprKind::DynamicArrayLiteral(body, _)
+        | ExprKind::EnumLiteral(_, _, body) => inline_all(body),
+        ExprKind::StructLiteral(_, fields) => {
+            for (_, value) in fields {
+                inline_in(value, constants);
+            }
+        }
+        ExprKind::Identifier(_)
+        | ExprKind::Literal(..)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::ComplexLiteral(..)
+        | ExprKind::GlobalDataAddr(_)
+        | ExprKind::Drop(_)
+        | ExprKind::Break
+        | ExprKind::Continue => {}
     }
 }
 
 /// 二元运算常量折叠
-fn fold_binary_op<F>(lhs: Expr, rhs: Expr, op_type: OpType, int_op: F) -> Expr
+fn fold_binary_op<F>(lhs: Expr, rhs: Expr, op_type: OpType, int_op: F) -> ExprKind
 where
     F: Fn(i64, i64) -> i64,
 {
@@ -90,25 +287,25 @@ enum OpType {
 }
 
 /// 加法常量折叠
-fn fold_add<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> Expr
+fn fold_add<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> ExprKind
 where
     F: Fn(i64, i64) -> i64,
 {
-    match (lhs.as_ref(), rhs.as_ref()) {
+    match (&lhs.kind, &rhs.kind) {
         // 0 + x = x
-        (Expr::Literal(v, t), r) if is_zero(v, t) => (*r).clone(),
+        (ExprKind::Literal(v, t), r) if is_zero(v, t) => r.clone(),
         // x + 0 = x
-        (l, Expr::Literal(v, t)) if is_zero(v, t) => (*l).clone(),
+        (l, ExprKind::Literal(v, t)) if is_zero(v, t) => l.clone(),
         // 两个整数常量
-        (Expr::Literal(v1, Type::I64), Expr::Literal(v2, Type::I64)) => {
+        (ExprKind::Literal(v1, Type::I64), ExprKind::Literal(v2, Type::I64)) => {
             if let (Ok(a), Ok(b)) = (v1.parse::<i64>(), v2.parse::<i64>()) {
-                Expr::Literal(int_op(a, b).to_string(), Type::I64)
+                ExprKind::Literal(int_op(a, b).to_string(), Type::I64)
             } else {
-                Expr::Add(lhs, rhs)
+                ExprKind::Add(lhs, rhs)
             }
         }
         // 无法折叠
-        _ => Expr::Add(lhs, rhs),
+        _ => ExprKind::Add(lhs, rhs),
     }
 }
 
@@ -116,118 +313,184 @@ where
 //to_string()来自 std::string::ToString trait，将类型转换成String
 
 /// 乘法常量折叠
-fn fold_mul<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> Expr
+fn fold_mul<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> ExprKind
 where
     F: Fn(i64, i64) -> i64,
 {
-    match (lhs.as_ref(), rhs.as_ref()) {
+    match (&lhs.kind, &rhs.kind) {
         // 0 * x = 0
-        (Expr::Literal(v, t), _) if is_zero(v, t) => Expr::Literal("0".to_string(), Type::I64),
+        (ExprKind::Literal(v, t), _) if is_zero(v, t) => {
+            ExprKind::Literal("0".to_string(), Type::I64)
+        }
         // x * 0 = 0
-        (_, Expr::Literal(v, t)) if is_zero(v, t) => Expr::Literal("0".to_string(), Type::I64),
+        (_, ExprKind::Literal(v, t)) if is_zero(v, t) => {
+            ExprKind::Literal("0".to_string(), Type::I64)
+        }
         // 1 * x = x
-        (Expr::Literal(v, t), r) if is_one(v, t) => (*r).clone(),
+        (ExprKind::Literal(v, t), r) if is_one(v, t) => r.clone(),
         // x * 1 = x
-        (l, Expr::Literal(v, t)) if is_one(v, t) => (*l).clone(),
+        (l, ExprKind::Literal(v, t)) if is_one(v, t) => l.clone(),
         // 两个整数常量
-        (Expr::Literal(v1, Type::I64), Expr::Literal(v2, Type::I64)) => {
+        (ExprKind::Literal(v1, Type::I64), ExprKind::Literal(v2, Type::I64)) => {
             if let (Ok(a), Ok(b)) = (v1.parse::<i64>(), v2.parse::<i64>()) {
-                Expr::Literal(int_op(a, b).to_string(), Type::I64)
+                ExprKind::Literal(int_op(a, b).to_string(), Type::I64)
             } else {
-                Expr::Mul(lhs, rhs)
+                ExprKind::Mul(lhs, rhs)
             }
         }
         // 无法折叠
-        _ => Expr::Mul(lhs, rhs),
+        _ => ExprKind::Mul(lhs, rhs),
     }
 }
 
 /// 减法常量折叠
-fn fold_sub<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> Expr
+fn fold_sub<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> ExprKind
 where
     F: Fn(i64, i64) -> i64,
 {
-    match (lhs.as_ref(), rhs.as_ref()) {
+    match (&lhs.kind, &rhs.kind) {
         // x - 0 = x
-        (l, Expr::Literal(v, t)) if is_zero(v, t) => (*l).clone(),
+        (l, ExprKind::Literal(v, t)) if is_zero(v, t) => l.clone(),
         // 两个整数常量
-        (Expr::Literal(v1, Type::I64), Expr::Literal(v2, Type::I64)) => {
+        (ExprKind::Literal(v1, Type::I64), ExprKind::Literal(v2, Type::I64)) => {
             if let (Ok(a), Ok(b)) = (v1.parse::<i64>(), v2.parse::<i64>()) {
-                Expr::Literal(int_op(a, b).to_string(), Type::I64)
+                ExprKind::Literal(int_op(a, b).to_string(), Type::I64)
             } else {
-                Expr::Sub(lhs, rhs)
+                ExprKind::Sub(lhs, rhs)
             }
         }
         // 无法折叠
-        _ => Expr::Sub(lhs, rhs),
+        _ => ExprKind::Sub(lhs, rhs),
     }
 }
 
 /// 除法常量折叠 (需要特殊处理除零)
-fn fold_div<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> Expr
+fn fold_div<F>(lhs: Box<Expr>, rhs: Box<Expr>, int_op: F) -> ExprKind
 where
     F: Fn(i64, i64) -> i64,
 {
-    match (lhs.as_ref(), rhs.as_ref()) {
+    match (&lhs.kind, &rhs.kind) {
         // x / 1 = x
-        (l, Expr::Literal(v, t)) if is_one(v, t) => (*l).clone(),
+        (l, ExprKind::Literal(v, t)) if is_one(v, t) => l.clone(),
 
         // 0 / x = 0 (x != 0)
-        (Expr::Literal(v, t), _) if is_zero(v, t) => (*lhs).clone(),
+        (ExprKind::Literal(v, t), _) if is_zero(v, t) => lhs.kind.clone(),
 
         // 两个整数常量
-        (Expr::Literal(v1, Type::I64), Expr::Literal(v2, Type::I64)) => {
+        (ExprKind::Literal(v1, Type::I64), ExprKind::Literal(v2, Type::I64)) => {
+            // 除数为 0 或 i64::MIN / -1 留给运行时 trap
             if let (Ok(a), Ok(b)) = (v1.parse::<i64>(), v2.parse::<i64>())
                 && b != 0
+                && !(a == i64::MIN && b == -1)
             {
                 let result = int_op(a, b);
-                return Expr::Literal(result.to_string(), Type::I64);
+                return ExprKind::Literal(result.to_string(), Type::I64);
             }
-            Expr::Div(lhs, rhs)
+            ExprKind::Div(lhs, rhs)
         }
 
         // 两个浮点常量
-        (Expr::Literal(v1, Type::F64), Expr::Literal(v2, Type::F64)) => {
+        (ExprKind::Literal(v1, Type::F64), ExprKind::Literal(v2, Type::F64)) => {
             if let (Ok(a), Ok(b)) = (v1.parse::<f64>(), v2.parse::<f64>())
                 && b != 0.0
                 && !b.is_nan()
             {
                 let result = a / b;
-                return Expr::Literal(result.to_string(), Type::F64);
+                return ExprKind::Literal(result.to_string(), Type::F64);
             }
-            Expr::Div(lhs, rhs)
+            ExprKind::Div(lhs, rhs)
+        }
+
+        _ => ExprKind::Div(lhs, rhs),
+    }
+}
+
+/// 取余常量折叠：除数为 0 时保留，运行时 trap（整数）或得到 NaN（浮点）
+fn fold_rem(lhs: Expr, rhs: Expr) -> ExprKind {
+    let lhs = Box::new(fold_constants(lhs));
+    let rhs = Box::new(fold_constants(rhs));
+
+    if let (ExprKind::Literal(v1, Type::F64), ExprKind::Literal(v2, Type::F64)) =
+        (&lhs.kind, &rhs.kind)
+        && let (Ok(a), Ok(b)) = (v1.parse::<f64>(), v2.parse::<f64>())
+        && b != 0.0
+    {
+        // Rust 的 f64 % 与 C 的 fmod 相同
+        return ExprKind::Literal((a % b).to_string(), Type::F64);
+    }
+    // i64::MIN % -1 与 srem 一样得到 0
+    match int_literals(&lhs, &rhs) {
+        Some((a, b)) if b != 0 => ExprKind::Literal(a.wrapping_rem(b).to_string(), Type::I64),
+        _ => ExprKind::Rem(lhs, rhs),
+    }
+}
+
+/// 两个整数常量的二元运算（位运算、移位）
+fn fold_int_op<F, G>(lhs: Expr, rhs: Expr, op: F, default: G) -> ExprKind
+where
+    F: Fn(i64, i64) -> i64,
+    G: Fn(Box<Expr>, Box<Expr>) -> ExprKind,
+{
+    let lhs = Box::new(fold_constants(lhs));
+    let rhs = Box::new(fold_constants(rhs));
+
+    match int_literals(&lhs, &rhs) {
+        Some((a, b)) => ExprKind::Literal(op(a, b).to_string(), Type::I64),
+        None => default(lhs, rhs),
+    }
+}
+
+/// 两侧都是 i64 字面量时取出它们的值
+fn int_literals(lhs: &Expr, rhs: &Expr) -> Option<(i64, i64)> {
+    match (&lhs.kind, &rhs.kind) {
+        (ExprKind::Literal(v1, Type::I64), ExprKind::Literal(v2, Type::I64)) => {
+            Some((v1.parse().ok()?, v2.parse().ok()?))
         }
+        _ => None,
+    }
+}
 
-        _ => Expr::Div(lhs, rhs),
+/// 取负常量折叠：-3 解析为 Neg(3)，在这里还原为字面量 "-3"。
+/// 直接改写字面量文本，`-9223372036854775808` 这样的最小值也不会溢出
+fn fold_neg(inner: Expr) -> ExprKind {
+    let inner = Box::new(fold_constants(inner));
+    match &inner.kind {
+        ExprKind::Literal(v, ty @ (Type::I64 | Type::F64)) => {
+            let negated = match v.strip_prefix('-') {
+                Some(positive) => positive.to_string(),
+                None => format!("-{}", v),
+            };
+            ExprKind::Literal(negated, ty.clone())
+        }
+        _ => ExprKind::Neg(inner),
     }
 }
 
 /// 比较运算常量折叠
-fn fold_cmp<F, G>(lhs: Expr, rhs: Expr, cmp: F, default: G) -> Expr
+fn fold_cmp<F, G>(lhs: Expr, rhs: Expr, cmp: F, default: G) -> ExprKind
 where
     F: Fn(i64, i64) -> bool,
-    G: Fn(Box<Expr>, Box<Expr>) -> Expr,
+    G: Fn(Box<Expr>, Box<Expr>) -> ExprKind,
 {
     let lhs = Box::new(fold_constants(lhs));
     let rhs = Box::new(fold_constants(rhs));
 
-    match (lhs.as_ref(), rhs.as_ref()) {
+    match (&lhs.kind, &rhs.kind) {
         // x == x = true
         // x != x = false
         // x < x = false
         // x > x = false
-        (Expr::Identifier(n1), Expr::Identifier(n2)) if n1 == n2 => {
-            // 比较运算结果是 i64 (0 或 1)
+        (ExprKind::Identifier(n1), ExprKind::Identifier(n2)) if n1 == n2 => {
+            // 比较运算结果是 bool
             let result = cmp(0, 0); // 用 0,0 调用 cmp 来获取默认值
-            let val = if result { 1 } else { 0 };
-            Expr::Literal(val.to_string(), Type::I64)
+            ExprKind::Literal(result.to_string(), Type::Bool)
         }
 
         // 两个整数常量: 折叠
-        (Expr::Literal(v1, Type::I64), Expr::Literal(v2, Type::I64)) => {
+        (ExprKind::Literal(v1, Type::I64), ExprKind::Literal(v2, Type::I64)) => {
             if let (Ok(a), Ok(b)) = (v1.parse::<i64>(), v2.parse::<i64>()) {
                 let result = cmp(a, b);
-                Expr::Literal((if result { 1 } else { 0 }).to_string(), Type::I64)
+                ExprKind::Literal(result.to_string(), Type::Bool)
             } else {
                 default(lhs, rhs)
             }
@@ -238,43 +501,70 @@ where
     }
 }
 
+/// `&&` / `||` 常量折叠：只看左操作数，与运行时的短路求值一致
+/// （右操作数可能有副作用，`x && false` 不能折叠）
+fn fold_logical(lhs: Expr, rhs: Expr, is_and: bool) -> ExprKind {
+    let lhs = Box::new(fold_constants(lhs));
+    let rhs = Box::new(fold_constants(rhs));
+
+    match &lhs.kind {
+        // true && x = x, false || x = x
+        ExprKind::Literal(v, Type::Bool) if (v == "true") == is_and => rhs.kind,
+        // false && x = false, true || x = true
+        ExprKind::Literal(_, Type::Bool) => lhs.kind,
+        _ if is_and => ExprKind::And(lhs, rhs),
+        _ => ExprKind::Or(lhs, rhs),
+    }
+}
+
+/// `!` 常量折叠
+fn fold_not(inner: Expr) -> ExprKind {
+    let inner = Box::new(fold_constants(inner));
+    match &inner.kind {
+        ExprKind::Literal(v, Type::Bool) => {
+            ExprKind::Literal((v != "true").to_string(), Type::Bool)
+        }
+        _ => ExprKind::Not(inner),
+    }
+}
+
 /// 类型转换常量折叠
-fn fold_cast(expr: Expr, target_ty: Type) -> Expr {
+fn fold_cast(expr: Expr, target_ty: Type) -> ExprKind {
     let expr = Box::new(fold_constants(expr));
 
-    match (expr.as_ref(), &target_ty) {
+    match (&expr.kind, &target_ty) {
         // 字面量之间的转换: 直接计算
-        (Expr::Literal(v, Type::I64), Type::F64) => {
+        (ExprKind::Literal(v, Type::I64), Type::F64) => {
             if let Ok(n) = v.parse::<i64>() {
-                Expr::Literal((n as f64).to_string(), Type::F64)
+                ExprKind::Literal((n as f64).to_string(), Type::F64)
             } else {
-                Expr::Cast(expr, target_ty)
+                ExprKind::Cast(expr, target_ty)
             }
         }
-        (Expr::Literal(v, Type::F64), Type::I64) => {
+        (ExprKind::Literal(v, Type::F64), Type::I64) => {
             if let Ok(n) = v.parse::<f64>() {
-                Expr::Literal((n as i64).to_string(), Type::I64)
+                ExprKind::Literal((n as i64).to_string(), Type::I64)
             } else {
-                Expr::Cast(expr, target_ty)
+                ExprKind::Cast(expr, target_ty)
             }
         }
-        (Expr::Literal(v, Type::I64), Type::I32) => {
+        (ExprKind::Literal(v, Type::I64), Type::I32) => {
             if let Ok(n) = v.parse::<i64>() {
-                Expr::Literal((n as i32).to_string(), Type::I32)
+                ExprKind::Literal((n as i32).to_string(), Type::I32)
             } else {
-                Expr::Cast(expr, target_ty)
+                ExprKind::Cast(expr, target_ty)
             }
         }
-        (Expr::Literal(v, Type::I32), Type::I64) => {
+        (ExprKind::Literal(v, Type::I32), Type::I64) => {
             if let Ok(n) = v.parse::<i32>() {
-                Expr::Literal((n as i64).to_string(), Type::I64)
+                ExprKind::Literal((n as i64).to_string(), Type::I64)
             } else {
-                Expr::Cast(expr, target_ty)
+                ExprKind::Cast(expr, target_ty)
             }
         }
 
         // 无法折叠
-        _ => Expr::Cast(expr, target_ty),
+        _ => ExprKind::Cast(expr, target_ty),
     }
 }
 
@@ -310,129 +600,229 @@ mod tests {
 
     #[test]
     fn test_fold_add_constants() {
-        let expr = Expr::Add(
-            Box::new(Expr::Literal("1".to_string(), Type::I64)),
-            Box::new(Expr::Literal("2".to_string(), Type::I64)),
+        let expr = ExprKind::Add(
+            Box::new(ExprKind::Literal("1".to_string(), Type::I64).into()),
+            Box::new(ExprKind::Literal("2".to_string(), Type::I64).into()),
         );
-        let result = fold_constants(expr);
-        assert_eq!(result, Expr::Literal("3".to_string(), Type::I64));
+        let result = fold_constants(expr.into());
+        assert_eq!(result.kind, ExprKind::Literal("3".to_string(), Type::I64));
     }
 
     #[test]
     fn test_fold_add_with_zero() {
-        let expr = Expr::Add(
-            Box::new(Expr::Identifier("x".to_string())),
-            Box::new(Expr::Literal("0".to_string(), Type::I64)),
+        let expr = ExprKind::Add(
+            Box::new(ExprKind::Identifier("x".to_string()).into()),
+            Box::new(ExprKind::Literal("0".to_string(), Type::I64).into()),
         );
-        let result = fold_constants(expr);
-        assert_eq!(result, Expr::Identifier("x".to_string()));
+        let result = fold_constants(expr.into());
+        assert_eq!(result.kind, ExprKind::Identifier("x".to_string()));
     }
 
     #[test]
     fn test_fold_mul_with_one() {
         // 1 * y = y
-        let expr = Expr::Mul(
-            Box::new(Expr::Literal("1".to_string(), Type::I64)),
-            Box::new(Expr::Identifier("y".to_string())),
+        let expr = ExprKind::Mul(
+            Box::new(ExprKind::Literal("1".to_string(), Type::I64).into()),
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
         );
-        let result = fold_constants(expr);
-        assert_eq!(result, Expr::Identifier("y".to_string()));
+        let result = fold_constants(expr.into());
+        assert_eq!(result.kind, ExprKind::Identifier("y".to_string()));
     }
 
     #[test]
     fn test_fold_mul_with_one_rhs() {
         // y * 1 = y
-        let expr = Expr::Mul(
-            Box::new(Expr::Identifier("y".to_string())),
-            Box::new(Expr::Literal("1".to_string(), Type::I64)),
+        let expr = ExprKind::Mul(
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
+            Box::new(ExprKind::Literal("1".to_string(), Type::I64).into()),
         );
-        let result = fold_constants(expr);
-        assert_eq!(result, Expr::Identifier("y".to_string()));
+        let result = fold_constants(expr.into());
+        assert_eq!(result.kind, ExprKind::Identifier("y".to_string()));
     }
 
     #[test]
     fn test_fold_mul_with_zero() {
-        let expr = Expr::Mul(
-            Box::new(Expr::Literal("0".to_string(), Type::I64)),
-            Box::new(Expr::Identifier("z".to_string())),
+        let expr = ExprKind::Mul(
+            Box::new(ExprKind::Literal("0".to_string(), Type::I64).into()),
+            Box::new(ExprKind::Identifier("z".to_string()).into()),
         );
-        let result = fold_constants(expr);
-        assert_eq!(result, Expr::Literal("0".to_string(), Type::I64));
+        let result = fold_constants(expr.into());
+        assert_eq!(result.kind, ExprKind::Literal("0".to_string(), Type::I64));
     }
 
     #[test]
     fn test_nested_fold() {
         // (1 + 2) + (3 + 4) -> 3 + 7 -> 10
-        let expr = Expr::Add(
-            Box::new(Expr::Add(
-                Box::new(Expr::Literal("1".to_string(), Type::I64)),
-                Box::new(Expr::Literal("2".to_string(), Type::I64)),
-            )),
-            Box::new(Expr::Add(
-                Box::new(Expr::Literal("3".to_string(), Type::I64)),
-                Box::new(Expr::Literal("4".to_string(), Type::I64)),
-            )),
+        let expr = ExprKind::Add(
+            Box::new(
+                ExprKind::Add(
+                    Box::new(ExprKind::Literal("1".to_string(), Type::I64).into()),
+                    Box::new(ExprKind::Literal("2".to_string(), Type::I64).into()),
+                )
+                .into(),
+            ),
+            Box::new(
+                ExprKind::Add(
+                    Box::new(ExprKind::Literal("3".to_string(), Type::I64).into()),
+                    Box::new(ExprKind::Literal("4".to_string(), Type::I64).into()),
+                )
+                .into(),
+            ),
         );
-        let result = fold_constants(expr);
-        assert_eq!(result, Expr::Literal("10".to_string(), Type::I64));
+        let result = fold_constants(expr.into());
+        assert_eq!(result.kind, ExprKind::Literal("10".to_string(), Type::I64));
     }
 
     #[test]
     fn test_fold_cmp_preserves_operator() {
         // 非聚友比较运算: x < y 不能变成 x == y
-        let expr = Expr::Lt(
-            Box::new(Expr::Identifier("x".to_string())),
-            Box::new(Expr::Identifier("y".to_string())),
+        let expr = ExprKind::Lt(
+            Box::new(ExprKind::Identifier("x".to_string()).into()),
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
         );
-        let result = fold_constants(expr);
+        let result = fold_constants(expr.into());
         assert!(
-            matches!(result, Expr::Lt(..)),
+            matches!(result.kind, ExprKind::Lt(..)),
             "Lt should stay Lt, got {:?}",
             result
         );
 
-        let expr = Expr::Ne(
-            Box::new(Expr::Identifier("x".to_string())),
-            Box::new(Expr::Identifier("y".to_string())),
+        let expr = ExprKind::Ne(
+            Box::new(ExprKind::Identifier("x".to_string()).into()),
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
         );
-        let result = fold_constants(expr);
+        let result = fold_constants(expr.into());
         assert!(
-            matches!(result, Expr::Ne(..)),
+            matches!(result.kind, ExprKind::Ne(..)),
             "Ne should stay Ne, got {:?}",
             result
         );
 
-        let expr = Expr::Le(
-            Box::new(Expr::Identifier("x".to_string())),
-            Box::new(Expr::Identifier("y".to_string())),
+        let expr = ExprKind::Le(
+            Box::new(ExprKind::Identifier("x".to_string()).into()),
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
         );
-        let result = fold_constants(expr);
+        let result = fold_constants(expr.into());
         assert!(
-            matches!(result, Expr::Le(..)),
+            matches!(result.kind, ExprKind::Le(..)),
             "Le should stay Le, got {:?}",
             result
         );
 
-        let expr = Expr::Gt(
-            Box::new(Expr::Identifier("x".to_string())),
-            Box::new(Expr::Identifier("y".to_string())),
+        let expr = ExprKind::Gt(
+            Box::new(ExprKind::Identifier("x".to_string()).into()),
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
         );
-        let result = fold_constants(expr);
+        let result = fold_constants(expr.into());
         assert!(
-            matches!(result, Expr::Gt(..)),
+            matches!(result.kind, ExprKind::Gt(..)),
             "Gt should stay Gt, got {:?}",
             result
         );
 
-        let expr = Expr::Ge(
-            Box::new(Expr::Identifier("x".to_string())),
-            Box::new(Expr::Identifier("y".to_string())),
+        let expr = ExprKind::Ge(
+            Box::new(ExprKind::Identifier("x".to_string()).into()),
+            Box::new(ExprKind::Identifier("y".to_string()).into()),
         );
-        let result = fold_constants(expr);
+        let result = fold_constants(expr.into());
         assert!(
-            matches!(result, Expr::Ge(..)),
+            matches!(result.kind, ExprKind::Ge(..)),
             "Ge should stay Ge, got {:?}",
             result
         );
     }
+
+    #[test]
+    fn test_fold_inside_else_if_chain() {
+        // else if 脱糖为嵌套在 else 分支里的 IfElse，折叠同样作用到每一层
+        let code = "fn f(x: i64) -> (r: i64) {\n    if x > 1 + 1 {\n        r = 2 * 3\n    } else if x > 0 {\n        r = 4 + 4\n    }\n}\n";
+        let (_, _, _, stmts) = crate::frontend::parser::function(code).unwrap();
+        let folded = fold_constants(stmts[0].clone());
+        let ExprKind::IfElse(cond, then_body, else_body) = folded.kind else {
+            panic!("expected IfElse, got {:?}", folded);
+        };
+        let lit = |v: &str| ExprKind::Literal(v.to_string(), Type::I64);
+        assert!(matches!(&cond.kind, ExprKind::Gt(_, rhs) if rhs.kind == lit("2")));
+        assert!(matches!(&then_body[0].kind, ExprKind::Assign(_, v) if v.kind == lit("6")));
+        let [inner] = else_body.as_slice() else {
+            panic!("expected a single else-if, got {:?}", else_body);
+        };
+        let ExprKind::IfElse(_, inner_then, inner_else) = &inner.kind else {
+            panic!("expected IfElse, got {:?}", inner);
+        };
+        assert!(matches!(&inner_then[0].kind, ExprKind::Assign(_, v) if v.kind == lit("8")));
+        assert!(inner_else.is_empty());
+    }
+
+    #[test]
+    fn test_fold_logical_operators() {
+        let fold = |code: &str| {
+            let code = format!("fn f(x: bool) -> (r: bool) {{\n    r = {}\n}}\n", code);
+            let (_, _, _, stmts) = crate::frontend::parser::function(&code).unwrap();
+            match fold_constants(stmts[0].clone()).kind {
+                ExprKind::Assign(_, v) => v.kind,
+                other => panic!("expected Assign, got {:?}", other),
+            }
+        };
+        let lit = |v: &str| ExprKind::Literal(v.to_string(), Type::Bool);
+        let x = ExprKind::Identifier("x".to_string());
+        // 比较的结果是 bool 字面量
+        assert_eq!(fold("1 < 2"), lit("true"));
+        assert_eq!(fold("!(2 == 2)"), lit("false"));
+        // 左操作数决定结果时丢弃右操作数，否则只剩右操作数
+        assert_eq!(fold("1 > 2 && x"), lit("false"));
+        assert_eq!(fold("true || x"), lit("true"));
+        assert_eq!(fold("true && x"), x);
+        assert_eq!(fold("false || x"), x);
+        // 右操作数是常量时不能折叠：左操作数仍要求值
+        assert!(matches!(fold("x && false"), ExprKind::And(..)));
+    }
+
+    #[test]
+    fn test_fold_negation_remainder_and_bitwise() {
+        let fold = |code: &str| {
+            let code = format!("fn f(x: i64) -> (r: i64) {{\n    r = {}\n}}\n", code);
+            let (_, _, _, stmts) = crate::frontend::parser::function(&code).unwrap();
+            match fold_constants(stmts[0].clone()).kind {
+                ExprKind::Assign(_, v) => v.kind,
+                other => panic!("expected Assign, got {:?}", other),
+            }
+        };
+        let int = |v: &str| ExprKind::Literal(v.to_string(), Type::I64);
+        let float = |v: &str| ExprKind::Literal(v.to_string(), Type::F64);
+        // 负数字面量还原为字面量，最小值不溢出
+        assert_eq!(fold("-3"), int("-3"));
+        assert_eq!(fold("- -3"), int("3"));
+        assert_eq!(fold("-9223372036854775808"), int("-9223372036854775808"));
+        assert_eq!(fold("-1.5"), float("-1.5"));
+        assert_eq!(fold("-7 % 3"), int("-1"));
+        assert_eq!(fold("7.5 % 2.0"), float("1.5"));
+        assert_eq!(fold("12 & 10 | 1 ^ 3"), int("10"));
+        assert_eq!(fold("1 << 65"), int("2"));
+        assert_eq!(fold("-16 >> 2"), int("-4"));
+        // 与运行时一样回绕，而不是让编译器溢出
+        assert_eq!(fold("9223372036854775807 + 1"), int("-9223372036854775808"));
+        // 除数为 0 留给运行时 trap
+        assert!(matches!(fold("x % 0"), ExprKind::Rem(..)));
+        assert!(matches!(fold("1 % 0"), ExprKind::Rem(..)));
+        assert!(matches!(fold("-x"), ExprKind::Neg(..)));
+    }
+
+    #[test]
+    fn test_inline_constants_then_fold() {
+        let code = "fn f(x: i64) -> (r: i64) {\n    r = x + N * 2\n}\n";
+        let (_, _, _, stmts) = crate::frontend::parser::function(code).unwrap();
+        let n = ExprKind::Literal("64".to_string(), Type::I64).into();
+        let constants = HashMap::from([("N".to_string(), n)]);
+        let folded = fold_constants_in_stmts(inline_constants(stmts, &constants));
+        let ExprKind::Assign(_, value) = &folded[0].kind else {
+            panic!("expected Assign, got {:?}", folded);
+        };
+        let ExprKind::Add(_, rhs) = &value.kind else {
+            panic!("expected Add, got {:?}", value);
+        };
+        assert_eq!(rhs.kind, ExprKind::Literal("128".to_string(), Type::I64));
+        // 替换后的字面量指向源码中的常量名
+        assert_eq!(rhs.span.start, code.find("N * 2").unwrap());
+    }
 }
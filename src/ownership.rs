@@ -18,13 +18,20 @@
 //! array [1,2,3] → Owned
 //!     ├── drop(arr)         → Dropped  (不能再访问)
 //!     ├── r = arr           → Returned (所有权转移给调用者)
+//!     ├── return arr        → Returned (仅在该路径上；提前 return 时其余数组由 JIT 释放)
+//!     ├── break / continue  → 该路径上的状态并入循环之后的状态
 //!     ├── array_push(arr,x) → Passed   (已消费, JIT 兜底释放)
 //!     └── (函数结束)         → 顶层 Owned 报 LeakedArray
 //! ```
 //!
+//! 结构体中的 `array<T>` 字段按字段路径（如 `p.items`）与数组变量一样追踪，
+//! 区别是字段数组归结构体所有，在任何深度都由 JIT 在作用域退出时释放，不报泄漏。
+//!
 //! 详见 docs/MEMORY_RECLAMATION.md。
 
-use crate::frontend::{Expr, Type};
+use crate::diagnostic::Diagnostic;
+use crate::frontend::{Expr, ExprKind, Span, Type};
+use crate::resolver::source_name;
 use std::collections::HashMap;
 
 /// DynamicArray 的所有权状态
@@ -48,56 +55,160 @@ pub enum ArrayDisposition {
 pub struct ArrayInfo {
     pub disposition: ArrayDisposition,
     pub name: String,
+    /// 最近一次状态转换发生的位置（分配 / drop / 传参 / 返回），用于报错
+    pub span: Span,
 }
 
 /// 所有权错误类型
+///
+/// `span` 指向出错的位置；其余位置字段指向导致错误的先前操作，
+/// 渲染诊断时作为次要标注（例如 "array 'a' dropped here"）。
 #[derive(Clone, Debug)]
 pub enum OwnershipError {
     /// 数组泄漏：既没返回也没 drop
-    LeakedArray { name: String },
+    /// span = 数组分配的位置；overwritten_at = 覆盖旧值的赋值（若因覆盖而泄漏）
+    LeakedArray {
+        name: String,
+        span: Span,
+        overwritten_at: Option<Span>,
+    },
     /// drop 后使用
-    UseAfterDrop { name: String },
+    UseAfterDrop {
+        name: String,
+        span: Span,
+        dropped_at: Option<Span>,
+    },
     /// 重复 drop
-    DoubleDrop { name: String },
+    DoubleDrop {
+        name: String,
+        span: Span,
+        dropped_at: Option<Span>,
+    },
     /// drop 一个已经通过函数调用"消费"的数组
     /// (实际上所有内置函数都是借用，但静态分析统一按消费处理)
-    DropAfterPassed { name: String },
+    DropAfterPassed {
+        name: String,
+        span: Span,
+        passed_at: Span,
+    },
 }
 
-/// 实现 Display trait 以便更友好地打印错误信息
-impl std::fmt::Display for OwnershipError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl OwnershipError {
+    pub fn span(&self) -> Span {
         match self {
-            OwnershipError::LeakedArray { name } => {
-                write!(
-                    f,
-                    "ownership error: array '{}' is leaked (neither returned nor dropped)",
-                    name
-                )
-            }
-            OwnershipError::UseAfterDrop { name } => {
-                write!(
-                    f,
-                    "ownership error: array '{}' used after being dropped",
-                    name
-                )
+            OwnershipError::LeakedArray { span, .. }
+            | OwnershipError::UseAfterDrop { span, .. }
+            | OwnershipError::DoubleDrop { span, .. }
+            | OwnershipError::DropAfterPassed { span, .. } => *span,
+        }
+    }
+
+    /// 稳定的错误代码，诊断输出中用于按类别匹配
+    pub fn code(&self) -> &'static str {
+        match self {
+            OwnershipError::LeakedArray { .. } => "ownership::leaked_array",
+            OwnershipError::UseAfterDrop { .. } => "ownership::use_after_drop",
+            OwnershipError::DoubleDrop { .. } => "ownership::double_drop",
+            OwnershipError::DropAfterPassed { .. } => "ownership::drop_after_passed",
+        }
+    }
+
+    /// 出错的数组变量（或结构体字段路径）
+    fn name(&self) -> &str {
+        match self {
+            OwnershipError::LeakedArray { name, .. }
+            | OwnershipError::UseAfterDrop { name, .. }
+            | OwnershipError::DoubleDrop { name, .. }
+            | OwnershipError::DropAfterPassed { name, .. } => name,
+        }
+    }
+
+    /// 不带 "ownership error:" 前缀的错误描述
+    fn message(&self) -> String {
+        // 名字解析可能把被遮蔽的变量改名为 `x#1`，报错时还原
+        let name = source_name(self.name());
+        match self {
+            OwnershipError::LeakedArray { .. } => {
+                format!("array '{}' is leaked (neither returned nor dropped)", name)
             }
-            OwnershipError::DoubleDrop { name } => {
-                write!(f, "ownership error: array '{}' dropped twice", name)
+            OwnershipError::UseAfterDrop { .. } => {
+                format!("array '{}' used after being dropped", name)
             }
-            OwnershipError::DropAfterPassed { name } => {
-                write!(
-                    f,
-                    "ownership error: array '{}' cannot be dropped because it was already \
-                     passed to a function call; the array will be auto-freed at function exit, \
-                     so just remove the explicit drop()",
-                    name
-                )
+            OwnershipError::DoubleDrop { .. } => {
+                format!("array '{}' dropped twice", name)
             }
+            OwnershipError::DropAfterPassed { .. } => format!(
+                "array '{}' cannot be dropped because it was already \
+                 passed to a function call; the array will be auto-freed at function exit, \
+                 so just remove the explicit drop()",
+                name
+            ),
         }
     }
+
+    /// 转换为带源码位置的诊断
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let name = source_name(self.name());
+        let diag = match self {
+            OwnershipError::LeakedArray {
+                span,
+                overwritten_at,
+                ..
+            } => {
+                let diag = Diagnostic::error(self.message(), *span, "array allocated here");
+                match overwritten_at {
+                    Some(at) => diag.with_secondary(*at, "overwritten here without being dropped"),
+                    None => diag.with_note("return it or drop() it before the function ends"),
+                }
+            }
+            OwnershipError::UseAfterDrop {
+                span, dropped_at, ..
+            } => {
+                let diag = Diagnostic::error(self.message(), *span, "used here");
+                match dropped_at {
+                    Some(at) => diag.with_secondary(*at, format!("array '{}' dropped here", name)),
+                    None => diag,
+                }
+            }
+            OwnershipError::DoubleDrop {
+                span, dropped_at, ..
+            } => {
+                let diag = Diagnostic::error(self.message(), *span, "dropped again here");
+                match dropped_at {
+                    Some(at) => {
+                        diag.with_secondary(*at, format!("array '{}' first dropped here", name))
+                    }
+                    None => diag,
+                }
+            }
+            OwnershipError::DropAfterPassed {
+                span, passed_at, ..
+            } => Diagnostic::error(
+                format!(
+                    "array '{}' cannot be dropped because it was already passed to a function call",
+                    name
+                ),
+                *span,
+                "dropped here",
+            )
+            .with_secondary(*passed_at, format!("array '{}' passed here", name))
+            .with_note(
+                "the array will be auto-freed at function exit, so just remove the explicit drop()",
+            ),
+        };
+        diag.with_code(self.code())
+    }
 }
 
+/// 实现 Display trait 以便更友好地打印错误信息
+impl std::fmt::Display for OwnershipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ownership error: {}", self.message())
+    }
+}
+
+impl std::error::Error for OwnershipError {}
+
 /// 由 OwnershipChecker 输出的作用域分析结果。
 /// JIT 编译器消费此结构，无需独立追踪作用域。
 ///
@@ -117,11 +228,17 @@ impl std::fmt::Display for OwnershipError {
 ///     → 作用域退出时 JIT 自动释放, 不报错 (RAII)
 /// scope_vars[2] = ["d"]       → 嵌套二层内定义的 d
 ///     → 同上, 先于外层释放
+///
+/// 同一深度可以有多个先后出现的作用域 (如两个相邻的 while),
+/// scope_vars 中只留下最后一个的清单; JIT 按 `scopes` 逐个作用域释放。
 #[derive(Debug, Clone)]
 pub struct ScopeAnalysis {
     /// scope_depth -> 该作用域内定义的 DynamicArray 变量名列表
     /// scope_depth=0 为函数体顶层
     pub scope_vars: HashMap<usize, Vec<String>>,
+    /// 嵌套作用域 (Block / 循环体) 内定义的 DynamicArray 变量名列表。
+    /// 键 = 创建该作用域的 Block / 循环表达式的位置
+    pub scopes: HashMap<Span, Vec<String>>,
 }
 
 /// 所有权检查器，把整个函数体（AST 节点列表）过一遍，对每个 Expr 做状态追踪和违规检测，最后返回发现的错误列表。
@@ -140,6 +257,17 @@ pub struct OwnershipChecker {
     scope_depth: usize,
     /// 每个作用域内定义的 DynamicArray 变量名集合。键 = 作用域深度
     scope_vars: HashMap<usize, Vec<String>>,
+    /// 已经关闭的嵌套作用域的清单。键 = Block / 循环表达式的位置
+    scopes: HashMap<Span, Vec<String>>,
+    /// 当前路径已经执行了 `return`，之后的语句不可达
+    returned: bool,
+    /// 当前路径已经执行了 `break` / `continue`，直到所在循环结束前的语句不可达
+    jumped: bool,
+    /// 由外到内每层 while 循环收集的 break / continue 处的数组状态
+    loop_exits: Vec<Vec<HashMap<String, (ArrayInfo, usize)>>>,
+    /// 结构体变量 → 其中（含嵌套结构体）动态数组字段的路径，如 p → [p.items]。
+    /// 由调用方按类型检查的结果提供，见 `with_struct_arrays`
+    struct_arrays: HashMap<String, Vec<String>>,
 }
 
 /// OwnershipChecker::new() 或 OwnershipChecker::default()，提供两种语法糖让调用方随意用
@@ -156,9 +284,21 @@ impl OwnershipChecker {
             errors: Vec::new(),
             scope_depth: 0,
             scope_vars: HashMap::new(),
+            scopes: HashMap::new(),
+            returned: false,
+            jumped: false,
+            loop_exits: Vec::new(),
+            struct_arrays: HashMap::new(),
         }
     }
 
+    /// 提供结构体变量中动态数组字段的路径。检查器本身不做类型推断，
+    /// 没有提供时结构体字段中的数组不被追踪
+    pub fn with_struct_arrays(mut self, struct_arrays: HashMap<String, Vec<String>>) -> Self {
+        self.struct_arrays = struct_arrays;
+        self
+    }
+
     /// 分析函数体，返回 (作用域分析结果, 错误列表)。
     pub fn analyze_function(
         &mut self,
@@ -169,24 +309,72 @@ impl OwnershipChecker {
         // 清理上一次分析的状态（支持复用）
         self.scope_depth = 0;
         self.scope_vars.clear();
+        self.scopes.clear();
         self.arrays.clear();
         self.errors.clear();
+        self.returned = false;
+        self.jumped = false;
+        self.loop_exits.clear();
 
         self.analyze_stmts(stmts, return_var);
 
-        // 函数体顶层作用域退出时检查泄漏
-        self.close_scope(0);
+        // 函数体顶层作用域退出时检查泄漏。
+        // 所有路径都以 return 结束时函数末尾不可达，数组已在各个 return 处由 JIT 释放
+        if self.returned {
+            self.arrays.clear();
+        } else {
+            self.close_scope(0);
+        }
 
         let analysis = ScopeAnalysis {
             scope_vars: self.scope_vars.clone(),
+            scopes: self.scopes.clone(),
         };
         (analysis, self.errors.clone())
     }
 
-    /// 按作用域递归分析语句列表
+    /// 按作用域递归分析语句列表。遇到 return / break / continue 后其余语句不可达，不再分析
     fn analyze_stmts(&mut self, stmts: &[Expr], return_var: &str) {
         for stmt in stmts {
             self.analyze_expr(stmt, return_var);
+            if self.returned || self.jumped {
+                break;
+            }
+        }
+    }
+
+    /// 分析一条可能不执行或提前跳出的分支，返回该分支是否以 (return, break/continue) 结束。
+    /// 提前跳出的分支对数组状态的修改只属于那条路径，分析完后恢复
+    fn analyze_branch(&mut self, stmts: &[Expr], return_var: &str) -> (bool, bool) {
+        let before = self.arrays.clone();
+        self.analyze_stmts(stmts, return_var);
+        let returned = std::mem::take(&mut self.returned);
+        let jumped = std::mem::take(&mut self.jumped);
+        if returned || jumped {
+            self.arrays = before;
+        }
+        (returned, jumped)
+    }
+
+    /// 把 break / continue 处的状态并入循环之后的状态：同一数组在任一路径上
+    /// 被 drop / 传参 / 返回，循环之后都按该状态处理（宁可多拦，也不放行 double-free）
+    fn merge_loop_exits(&mut self, exits: Vec<HashMap<String, (ArrayInfo, usize)>>) {
+        fn rank(d: &ArrayDisposition) -> u8 {
+            match d {
+                ArrayDisposition::Uninitialized | ArrayDisposition::Owned => 0,
+                ArrayDisposition::Returned => 1,
+                ArrayDisposition::Passed => 2,
+                ArrayDisposition::Dropped => 3,
+            }
+        }
+        for exit in exits {
+            for (name, (info, _)) in exit {
+                if let Some((current, _)) = self.arrays.get_mut(&name)
+                    && rank(&info.disposition) > rank(&current.disposition)
+                {
+                    *current = info;
+                }
+            }
         }
     }
 
@@ -203,17 +391,29 @@ impl OwnershipChecker {
     /// 无后续引用, 自动释放安全且无悬垂指针风险。
     ///
     /// 一句话: 顶层强制显式(防泄漏滥用), 嵌套自动释放(防啰嗦)。
+    ///
+    /// 结构体的数组字段（`p.items`）在任何深度都由 JIT 释放，不报泄漏；
+    /// 顶层已经交给调用者（`r = p`）的字段从清单中去掉，JIT 不再释放
     fn close_scope(&mut self, depth: usize) {
         if let Some(vars) = self.scope_vars.get(&depth) {
             let vars = vars.clone();
             for name in &vars {
-                if depth == 0 {
+                if depth == 0 && name.contains('.') {
+                    if let Some((info, _)) = self.arrays.get(name)
+                        && info.disposition == ArrayDisposition::Returned
+                        && let Some(list) = self.scope_vars.get_mut(&0)
+                    {
+                        list.retain(|n| n != name);
+                    }
+                } else if depth == 0 {
                     // 仅顶层作用域的 Owned 数组视为泄漏
                     if let Some(tuple) = self.arrays.get(name)
                         && tuple.0.disposition == ArrayDisposition::Owned
                     {
                         self.errors.push(OwnershipError::LeakedArray {
                             name: name.clone(),
+                            span: tuple.0.span,
+                            overwritten_at: None,
                         });
                     }
                 }
@@ -223,8 +423,9 @@ impl OwnershipChecker {
     }
 
     fn analyze_expr(&mut self, expr: &Expr, return_var: &str) {
-        match expr {
-            Expr::Assign(name, value) => {
+        match &expr.kind {
+            // let x = e 与 x = e 相同（名字解析之后 x 在函数内唯一）
+            ExprKind::Assign(name, value) | ExprKind::Let(name, _, _, value) => {
                 let produces_array = self.produces_dynamic_array(value);
 
                 // ═══════════════════════════════════════════════════
@@ -243,16 +444,18 @@ impl OwnershipChecker {
                 if name == return_var {
                     self.arrays.insert(
                         name.clone(),
-                        (ArrayInfo {
-                            disposition: ArrayDisposition::Returned,
-                            name: name.clone(),
-                        }, self.scope_depth),
+                        (
+                            ArrayInfo {
+                                disposition: ArrayDisposition::Returned,
+                                name: name.clone(),
+                                span: expr.span,
+                            },
+                            self.scope_depth,
+                        ),
                     );
                     // 源数组也标记为 Returned (防止 close_scope 误报泄漏)
-                    if let Expr::Identifier(src_name) = value.as_ref()
-                        && let Some((info, _)) = self.arrays.get_mut(src_name)
-                    {
-                        info.disposition = ArrayDisposition::Returned;
+                    if let ExprKind::Identifier(src_name) = &value.kind {
+                        self.mark_returned(src_name, expr.span);
                     }
 
                 // ═══════════════════════════════════════════════════
@@ -273,34 +476,32 @@ impl OwnershipChecker {
                 //例如a = array [1, 2, 3]
                 //a = array [4, 5, 6]  旧数组永远丢失，泄漏了
                 } else if produces_array { 
-                    // ① 覆盖检测
-                    if let Some((old_info, _)) = self.arrays.get(name)
-                        && old_info.disposition == ArrayDisposition::Owned
-                    {
-                        self.errors.push(OwnershipError::LeakedArray {
-                            name: format!("{} (previous value overwritten)", name),
-                        });
+                    self.take_ownership(name, value.span, expr.span, self.scope_depth);
+
+                // ═══════════════════════════════════════════════════
+                // 情况 3: RHS 产生新的结构体 → 其中的数组字段登记为 Owned
+                // ═══════════════════════════════════════════════════
+                //
+                // p = Bag { items: array [1, 2] }   ← 字面量
+                // p = make_bag()                    ← 返回结构体的函数
+                //
+                // 字段路径 p.items 与数组变量一样做覆盖检测和登记。
+                // 复制已有的结构体 (q = p) 只是别名, 数组仍归 p 所有, 不登记。
+                } else if !matches!(
+                    value.kind,
+                    ExprKind::Identifier(_) | ExprKind::Field(..) | ExprKind::Index(..)
+                ) {
+                    for path in self.struct_arrays.get(name).cloned().unwrap_or_default() {
+                        self.take_ownership(&path, value.span, expr.span, self.scope_depth);
                     }
-                    // ② 登记到当前作用域
-                    self.arrays.insert(
-                        name.clone(),
-                        (ArrayInfo {
-                            disposition: ArrayDisposition::Owned,
-                            name: name.clone(),
-                        }, self.scope_depth),
-                    );
-                    self.scope_vars
-                        .entry(self.scope_depth)
-                        .or_default()
-                        .push(name.clone());
                 }
 
                 // 递归分析 RHS: 处理嵌套的 Call(所有权传递) / Index(UseAfterDrop)
                 self.analyze_expr(value, return_var);
             }
 
-            Expr::Drop(name) => {
-                self.mark_dropped(name);
+            ExprKind::Drop(name) => {
+                self.mark_dropped(name, expr.span);
             }
 
             // ═══════════════════════════════════════════════════
@@ -321,13 +522,14 @@ impl OwnershipChecker {
             //   宁可多拦合法操作, 也不能放行 double-free。
             //
             // 过近似: 无法区分"真消费"和"借用"。所有内置函数统一按消费处理。
-            Expr::Call(_func_name, args) => {
+            ExprKind::Call(_func_name, args) => {
                 for arg in args {
-                    if let Expr::Identifier(name) = arg
-                        && let Some((info, _)) = self.arrays.get_mut(name)
+                    if let Some(name) = place_name(arg)
+                        && let Some((info, _)) = self.arrays.get_mut(&name)
                         && info.disposition == ArrayDisposition::Owned
                     {
                         info.disposition = ArrayDisposition::Passed;
+                        info.span = expr.span;
                     }
                 }
             }
@@ -347,14 +549,31 @@ impl OwnershipChecker {
             // 如果发生在 depth>0 块内: b → JIT 自动释放, 不报错
             //
             // 增强方向: 分支快照 + meet-point 取交集, 可消除 else 路径的假阳性。
-            Expr::IfElse(cond, then_body, else_body) => {
+            //
+            // 以 return / break / continue 结束的分支单独处理（见 analyze_branch），
+            // 两个分支都提前跳出时 if 之后的语句不可达。
+            ExprKind::IfElse(cond, then_body, else_body) => {
                 self.analyze_expr(cond, return_var);
-                for stmt in then_body {
-                    self.analyze_expr(stmt, return_var);
-                }
-                for stmt in else_body {
-                    self.analyze_expr(stmt, return_var);
-                }
+                let (then_returned, then_jumped) = self.analyze_branch(then_body, return_var);
+                let (else_returned, else_jumped) = self.analyze_branch(else_body, return_var);
+                self.returned = then_returned && else_returned;
+                self.jumped = !self.returned
+                    && (then_returned || then_jumped)
+                    && (else_returned || else_jumped);
+            }
+
+            // match 的每个分支与 if 的分支相同: 不创建新作用域, 各自单独处理
+            // 提前跳出; 所有分支都提前跳出时 match 之后的语句不可达。
+            ExprKind::Match(scrutinee, arms) => {
+                self.analyze_expr(scrutinee, return_var);
+                let exits: Vec<(bool, bool)> = arms
+                    .iter()
+                    .map(|arm| self.analyze_branch(&arm.body, return_var))
+                    .collect();
+                self.returned = !exits.is_empty() && exits.iter().all(|(returned, _)| *returned);
+                self.jumped = !self.returned
+                    && !exits.is_empty()
+                    && exits.iter().all(|(returned, jumped)| *returned || *jumped);
             }
 
             // ═══════════════════════════════════════════════════
@@ -374,13 +593,44 @@ impl OwnershipChecker {
             //   → 不释放会导致前 N-1 次迭代的数组泄漏
             //
             // 与 Block 的区别: 仅在 JIT 端 —— Block 释放一次, While 每次迭代释放。
-            Expr::WhileLoop(cond, body) => {
+            //
+            // 循环体可能一次也不执行，体内的 return 不会让循环之后的语句不可达。
+            // break / continue 处的状态在循环结束后并入（见 merge_loop_exits）。
+            ExprKind::WhileLoop(cond, body) => {
                 self.analyze_expr(cond, return_var);
-                self.scope_depth += 1;
-                self.scope_vars.insert(self.scope_depth, Vec::new());
-                self.analyze_stmts(body, return_var);
-                self.close_scope(self.scope_depth);
-                self.scope_depth -= 1;
+                self.analyze_loop_body(body, expr.span, return_var, None);
+            }
+
+            // ═══════════════════════════════════════════════════
+            // for 循环 — 循环体与 While 相同, 每次迭代结束时释放
+            // ═══════════════════════════════════════════════════
+            //
+            // 例:
+            //   for x in arr {
+            //       tmp = array [x]   // scope_vars[1] = ["tmp"]
+            //       drop(arr)         // ❌ 下一次迭代还要读 arr → UseAfterDrop
+            //   }
+            //
+            // 遍历数组只是借用 (同 Index), 已 drop 的数组不能遍历。
+            // `for x in array [1, 2]` 这种临时数组由 JIT 在循环结束后释放。
+            ExprKind::ForRange(_, start, end, _, step, body) => {
+                self.analyze_expr(start, return_var);
+                self.analyze_expr(end, return_var);
+                if let Some(step) = step {
+                    self.analyze_expr(step, return_var);
+                }
+                self.analyze_loop_body(body, expr.span, return_var, None);
+            }
+            ExprKind::ForEach(_, iterable, body) => {
+                self.analyze_expr(iterable, return_var);
+                let name = place_name(iterable);
+                let iterated = match &name {
+                    Some(name) if self.check_not_dropped(name, iterable.span) => {
+                        Some((name.as_str(), iterable.span))
+                    }
+                    _ => None,
+                };
+                self.analyze_loop_body(body, expr.span, return_var, iterated);
             }
 
             // ═══════════════════════════════════════════════════
@@ -395,12 +645,60 @@ impl OwnershipChecker {
             //
             // 所有权检查器按"无需显式 drop"对待: close_scope(depth>0)
             // 不报泄漏, 只从 arrays 中移除记录。释放责任在 JIT。
-            Expr::Block(body) => {
-                self.scope_depth += 1;
-                self.scope_vars.insert(self.scope_depth, Vec::new());
+            ExprKind::Block(body) => {
+                self.open_scope();
                 self.analyze_stmts(body, return_var);
-                self.close_scope(self.scope_depth);
-                self.scope_depth -= 1;
+                self.exit_scope(expr.span);
+            }
+
+            // ═══════════════════════════════════════════════════
+            // 提前 return — 该路径到此结束
+            // ═══════════════════════════════════════════════════
+            //
+            // 例:
+            //   if i == 0 {
+            //       return arr      → arr: Owned → Returned (仅该路径)
+            //   }
+            //
+            // 被返回的数组所有权转移给调用者；其他仍为 Owned 的数组
+            // (包括顶层的) 由 JIT 在 return 前按 ScopeAnalysis 逐层释放，不报泄漏。
+            ExprKind::Return(value) => {
+                self.analyze_expr(value, return_var);
+                if let ExprKind::Identifier(name) = &value.kind {
+                    if let Some((info, _)) = self.arrays.get(name)
+                        && info.disposition == ArrayDisposition::Dropped
+                    {
+                        self.errors.push(OwnershipError::UseAfterDrop {
+                            name: name.clone(),
+                            span: value.span,
+                            dropped_at: Some(info.span),
+                        });
+                    } else {
+                        self.mark_returned(name, expr.span);
+                    }
+                }
+                self.returned = true;
+            }
+
+            // ═══════════════════════════════════════════════════
+            // break / continue — 该路径在本轮循环内到此结束
+            // ═══════════════════════════════════════════════════
+            //
+            // 例:
+            //   while i < n {
+            //       tmp = array [i]
+            //       if tmp[0] == k {
+            //           drop(a)      → a: Dropped (仅该路径, 循环之后并入)
+            //           break        → tmp 由 JIT 在跳转前释放
+            //       }
+            //   }
+            //
+            // 循环体内的数组由 JIT 在跳转前按 ScopeAnalysis 逐层释放，不报泄漏。
+            ExprKind::Break | ExprKind::Continue => {
+                if let Some(exits) = self.loop_exits.last_mut() {
+                    exits.push(self.arrays.clone());
+                }
+                self.jumped = true;
             }
 
             // ═══════════════════════════════════════════════════
@@ -418,30 +716,160 @@ impl OwnershipChecker {
             //
             // 只有用户显式 drop() 后的访问被拦截。Passed 状态下数组
             // 仍存活 (只是检查器不再追踪显式释放), 允许读访问。
-            Expr::Index(base, idx) => {
-                if let Expr::Identifier(name) = base.as_ref()
-                    && let Some((info, _)) = self.arrays.get(name)
-                    && matches!(info.disposition, ArrayDisposition::Dropped)
-                {
-                    self.errors
-                        .push(OwnershipError::UseAfterDrop { name: name.clone() });
+            ExprKind::Index(base, idx) => {
+                if let Some(name) = place_name(base) {
+                    self.check_not_dropped(&name, base.span);
                 }
                 self.analyze_expr(idx, return_var);
             }
 
+            // ═══════════════════════════════════════════════════
+            // 索引赋值 — 借用, 不转移所有权
+            // ═══════════════════════════════════════════════════
+            //
+            // 例:
+            //   arr[0] = 7     → arr 的 disposition 不变 (Owned 仍需 drop/return)
+            //   drop(arr)
+            //   arr[1] += 1    → UseAfterDrop("arr") ❌ 写已释放的内存
+            //
+            // 与读访问相同, 只拦截显式 drop() 之后的写入。
+            ExprKind::IndexAssign(base, idx, _, value) => {
+                self.analyze_expr(value, return_var);
+                self.analyze_expr(idx, return_var);
+                if let Some(name) = place_name(base) {
+                    self.check_not_dropped(&name, base.span);
+                }
+            }
+
+            // ═══════════════════════════════════════════════════
+            // 字段赋值 — RHS 产生新数组时字段路径登记为 Owned
+            // ═══════════════════════════════════════════════════
+            //
+            // 例:
+            //   p.items = array [4]   → 旧值仍 Owned 时报 LeakedArray (覆盖)
+            //                         → p.items: Owned
+            //
+            // 沿用字段之前登记的作用域深度 (即 p 所在的作用域); 之前没有登记过
+            // (如参数的副本) 时登记到函数顶层。返回变量的字段归调用者, 不登记。
+            ExprKind::FieldAssign(base, field, _, value) => {
+                self.analyze_expr(value, return_var);
+                if let Some(root) = place_name(base)
+                    && self.produces_dynamic_array(value)
+                    && root.split('.').next() != Some(return_var)
+                {
+                    let path = format!("{}.{}", root, field);
+                    let depth = self.arrays.get(&path).map_or(0, |(_, depth)| *depth);
+                    self.take_ownership(&path, value.span, expr.span, depth);
+                }
+            }
+            ExprKind::CompoundAssign(_, _, value) => self.analyze_expr(value, return_var),
+
             _ => {}
         }
     }
 
+    /// 循环体作为独立作用域分析；break / continue 处的状态在循环结束后并入。
+    /// `span` 是循环表达式的位置，`iterated` 是 for 正在遍历的数组：
+    /// 循环体正常走完时它必须仍然可用，因为下一次迭代还要读取它
+    fn analyze_loop_body(
+        &mut self,
+        body: &[Expr],
+        span: Span,
+        return_var: &str,
+        iterated: Option<(&str, Span)>,
+    ) {
+        self.open_scope();
+        self.loop_exits.push(Vec::new());
+        self.analyze_branch(body, return_var);
+        if let Some((name, span)) = iterated {
+            self.check_not_dropped(name, span);
+        }
+        let exits = self.loop_exits.pop().unwrap_or_default();
+        self.merge_loop_exits(exits);
+        self.exit_scope(span);
+    }
+
+    /// 进入一层嵌套作用域（Block / 循环体）
+    fn open_scope(&mut self) {
+        self.scope_depth += 1;
+        self.scope_vars.insert(self.scope_depth, Vec::new());
+    }
+
+    /// 离开 `span` 处的 Block / 循环创建的作用域，把它的清单按位置记入 `scopes`：
+    /// 之后同一深度的作用域会覆盖 scope_vars 中的清单
+    fn exit_scope(&mut self, span: Span) {
+        self.close_scope(self.scope_depth);
+        let vars = self.scope_vars.get(&self.scope_depth).cloned();
+        self.scopes.insert(span, vars.unwrap_or_default());
+        self.scope_depth -= 1;
+    }
+
+    /// 把 `name`（变量或字段路径）登记为 `depth` 层新分配的 Owned 数组，`span` 是分配的位置。
+    /// 旧值仍为 Owned 时它的指针被 `assign_span` 处的赋值覆盖而泄漏，报 LeakedArray
+    fn take_ownership(&mut self, name: &str, span: Span, assign_span: Span, depth: usize) {
+        // ① 覆盖检测
+        if let Some((old_info, _)) = self.arrays.get(name)
+            && old_info.disposition == ArrayDisposition::Owned
+        {
+            self.errors.push(OwnershipError::LeakedArray {
+                name: format!("{} (previous value overwritten)", name),
+                span: old_info.span,
+                overwritten_at: Some(assign_span),
+            });
+        }
+        // ② 登记到作用域（记录分配位置，泄漏时指向这里）
+        self.arrays.insert(
+            name.to_string(),
+            (
+                ArrayInfo {
+                    disposition: ArrayDisposition::Owned,
+                    name: name.to_string(),
+                    span,
+                },
+                depth,
+            ),
+        );
+        self.scope_vars
+            .entry(depth)
+            .or_default()
+            .push(name.to_string());
+    }
+
+    /// 数组 `name`（若是结构体，则连同它的数组字段）的所有权转交给调用者
+    fn mark_returned(&mut self, name: &str, span: Span) {
+        let fields = self.struct_arrays.get(name).cloned().unwrap_or_default();
+        for name in std::iter::once(name.to_string()).chain(fields) {
+            if let Some((info, _)) = self.arrays.get_mut(&name) {
+                info.disposition = ArrayDisposition::Returned;
+                info.span = span;
+            }
+        }
+    }
+
+    /// 读取数组（索引 / 遍历）前检查它没有被 drop，已 drop 时报错并返回 false
+    fn check_not_dropped(&mut self, name: &str, span: Span) -> bool {
+        if let Some((info, _)) = self.arrays.get(name)
+            && matches!(info.disposition, ArrayDisposition::Dropped)
+        {
+            self.errors.push(OwnershipError::UseAfterDrop {
+                name: name.to_string(),
+                span,
+                dropped_at: Some(info.span),
+            });
+            return false;
+        }
+        true
+    }
+
     /// 右边的表达式是不是会生成一个新的、归我拥有的 DynamicArray
     /// `true` → 是，下游应该把这个数组登记到 `arrays` 表里；`false` → 不是
     /// 目前只有两种情况会生成新的 DynamicArray：
     ///   1) 直接的动态数组字面量 `array [...]`
     ///   2) 调用返回 DynamicArray 的内置函数（`array_new_i64` / `array_new_f64` / `array_new_complex128`）
     fn produces_dynamic_array(&self, expr: &Expr) -> bool {
-        match expr {
-            Expr::DynamicArrayLiteral(_, _) => true,
-            Expr::Call(name, _) => {
+        match &expr.kind {
+            ExprKind::DynamicArrayLiteral(_, _) => true,
+            ExprKind::Call(name, _) => {
                 matches!(
                     name.as_str(),
                     "array_new_i64" | "array_new_f64" | "array_new_complex128"
@@ -451,31 +879,41 @@ impl OwnershipChecker {
         }
     }
 
-    fn mark_dropped(&mut self, name: &str) {
+    /// `span` 是 drop(name) 语句的位置
+    fn mark_dropped(&mut self, name: &str, span: Span) {
         if let Some((info, _)) = self.arrays.get_mut(name) {
             match info.disposition {
                 ArrayDisposition::Owned => {
                     info.disposition = ArrayDisposition::Dropped;
+                    info.span = span;
                 }
                 ArrayDisposition::Returned => {
                     self.errors.push(OwnershipError::DoubleDrop {
                         name: name.to_string(),
+                        span,
+                        dropped_at: None,
                     });
                 }
                 ArrayDisposition::Dropped => {
                     self.errors.push(OwnershipError::DoubleDrop {
                         name: name.to_string(),
+                        span,
+                        dropped_at: Some(info.span),
                     });
                 }
                 ArrayDisposition::Passed => {
                     self.errors.push(OwnershipError::DropAfterPassed {
                         name: name.to_string(),
+                        span,
+                        passed_at: info.span,
                     });
                 }
                 ArrayDisposition::Uninitialized => {
                     // drop 未初始化的变量：错误
                     self.errors.push(OwnershipError::UseAfterDrop {
                         name: name.to_string(),
+                        span,
+                        dropped_at: None,
                     });
                 }
             }
@@ -483,12 +921,23 @@ impl OwnershipChecker {
             // drop 未声明的变量：错误
             self.errors.push(OwnershipError::UseAfterDrop {
                 name: name.to_string(),
+                span,
+                dropped_at: None,
             });
         }
     }
 
 }
 
+/// 变量或字段路径表达式（`a` / `p.items`）对应的名字，其他表达式返回 None
+fn place_name(expr: &Expr) -> Option<String> {
+    match &expr.kind {
+        ExprKind::Identifier(name) => Some(name.clone()),
+        ExprKind::Field(base, field) => place_name(base).map(|base| format!("{}.{}", base, field)),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -608,6 +1057,51 @@ fn test() -> (r: i64) {
         assert!(matches!(errors[0], OwnershipError::UseAfterDrop { .. }));
     }
 
+    #[test]
+    fn test_index_assign_borrows_and_rejects_dropped_array() {
+        // arr[i] = v 只是借用：之后仍需 drop，drop 之后再写入报 UseAfterDrop
+        let code = "fn test() -> (r: i64) {\n    arr = array [1, 2, 3]\n    arr[0] = 7\n    arr[1] += arr[0]\n    drop(arr)\n    arr[2] *= 2\n    r = 0\n}\n";
+        let errors = analyze_errors(code);
+        assert_eq!(errors.len(), 1, "got: {:?}", errors);
+        let OwnershipError::UseAfterDrop { span, .. } = &errors[0] else {
+            panic!("expected UseAfterDrop, got {:?}", errors);
+        };
+        assert_eq!(&code[span.start..span.end], "arr");
+        assert_eq!(code[..span.start].lines().count(), 6);
+
+        // 索引赋值之后不 drop 仍是泄漏
+        let leak =
+            "fn test() -> (r: i64) {\n    arr = array [1, 2, 3]\n    arr[0] = 7\n    r = 0\n}\n";
+        let errors = analyze_errors(leak);
+        assert!(matches!(errors[..], [OwnershipError::LeakedArray { .. }]));
+    }
+
+    #[test]
+    fn test_use_after_drop_reports_drop_site() {
+        // 错误同时指向使用位置和先前的 drop 位置
+        let code = "fn test() -> (r: i64) {\n    arr = array [1, 2, 3]\n    drop(arr)\n    r = arr[0]\n}\n";
+        let errors = analyze_errors(code);
+        let OwnershipError::UseAfterDrop {
+            span,
+            dropped_at: Some(dropped_at),
+            ..
+        } = &errors[0]
+        else {
+            panic!("expected UseAfterDrop with drop site, got {:?}", errors);
+        };
+        assert_eq!(&code[span.start..span.end], "arr");
+        assert_eq!(&code[dropped_at.start..dropped_at.end], "drop(arr)");
+
+        let rendered = errors[0].to_diagnostic().render("test.toy", code);
+        assert!(rendered.contains("--> test.toy:4:9"), "got:\n{}", rendered);
+        assert!(
+            rendered.contains("--------- array 'arr' dropped here"),
+            "got:\n{}",
+            rendered
+        );
+        assert!(rendered.contains("^^^ used here"), "got:\n{}", rendered);
+    }
+
     // ══════════════════════════════════════════════════════
     // Phase 2: 作用域感知所有权检查测试
     // ══════════════════════════════════════════════════════
@@ -711,6 +1205,173 @@ fn test() -> (r: i64) {
         assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
     }
 
+    #[test]
+    fn test_early_return_of_array() {
+        // return arr 只在该路径上转移所有权；fall-through 路径仍需 drop
+        let code = r#"
+fn test(n: i64) -> (r: array<i64>) {
+    arr = array [1, 2, 3]
+    other = array [4]
+    if n == 0 {
+        return arr
+    } else {
+        n = n
+    }
+    drop(arr)
+    r = other
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
+
+        // 以 return 结束的函数体：末尾不可达，其余数组由 JIT 在 return 前释放
+        let code = r#"
+fn test() -> (r: array<i64>) {
+    arr = array [1, 2, 3]
+    tmp = array [4]
+    return arr
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
+    }
+
+    #[test]
+    fn test_early_return_errors() {
+        // fall-through 路径上没有处理的数组仍然报泄漏
+        let code = r#"
+fn test(n: i64) -> (r: array<i64>) {
+    arr = array [1, 2, 3]
+    if n == 0 {
+        return arr
+    } else {
+        n = n
+    }
+    r = array [0]
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(
+            matches!(errors.as_slice(), [OwnershipError::LeakedArray { name, .. }] if name == "arr"),
+            "got {:?}",
+            errors
+        );
+
+        // 返回已经 drop 的数组
+        let code = r#"
+fn test() -> (r: array<i64>) {
+    arr = array [1, 2, 3]
+    drop(arr)
+    return arr
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(
+            matches!(errors[0], OwnershipError::UseAfterDrop { .. }),
+            "got {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        // 循环体内的数组在 break / continue 前由 JIT 释放；
+        // 以 break 结束的分支里的 drop 不影响循环体后续语句
+        let code = r#"
+fn test(n: i64) -> (r: i64) {
+    i = 0
+    while i < n {
+        tmp = array [i]
+        i = i + 1
+        if i == 2 {
+            continue
+        } else {
+            i = i
+        }
+        if tmp[0] == 5 {
+            drop(tmp)
+            break
+        } else {
+            i = i
+        }
+        drop(tmp)
+    }
+    r = i
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
+
+        // break 路径上 drop 了外层数组：循环之后按已 drop 处理
+        let code = r#"
+fn test(n: i64) -> (r: i64) {
+    arr = array [1, 2, 3]
+    while n > 0 {
+        if n == 3 {
+            drop(arr)
+            break
+        } else {
+            n = n - 1
+        }
+    }
+    drop(arr)
+    r = 0
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(
+            matches!(errors.as_slice(), [OwnershipError::DoubleDrop { name, dropped_at: Some(_), .. }] if name == "arr"),
+            "got {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_for_loops() {
+        // for 循环体与 while 一样是独立作用域；遍历数组是借用，之后仍可 drop
+        let code = r#"
+fn test(n: i64) -> (r: i64) {
+    arr = array [1, 2, 3]
+    r = 0
+    for x in arr {
+        tmp = array [x]
+        r = r + tmp[0]
+    }
+    for i in 0..n {
+        tmp2 = array [i]
+    }
+    drop(arr)
+}
+"#;
+        let (analysis, errors) = parse_and_analyze(code);
+        assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
+        assert_eq!(analysis.scope_vars[&0], vec!["arr".to_string()]);
+        // 同一深度的两个循环体各有各的清单，后一个不会覆盖前一个
+        let mut scopes: Vec<Vec<String>> = analysis.scopes.values().cloned().collect();
+        scopes.sort();
+        assert_eq!(scopes, vec![vec!["tmp".to_string()], vec!["tmp2".to_string()]]);
+
+        // 遍历中 drop 被遍历的数组，或遍历已 drop 的数组
+        let code = r#"
+fn test() -> (r: i64) {
+    arr = array [1, 2, 3]
+    for x in arr {
+        drop(arr)
+    }
+    for y in arr {
+        r = y
+    }
+}
+"#;
+        let errors = analyze_errors(code);
+        assert_eq!(errors.len(), 2, "got {:?}", errors);
+        assert!(
+            errors
+                .iter()
+                .all(|e| matches!(e, OwnershipError::UseAfterDrop { .. }))
+        );
+    }
+
     #[test]
     fn test_while_loop_nested_block() {
         // 循环内的嵌套块同样正确释放
@@ -729,4 +1390,69 @@ fn test() -> (r: i64) {
         let errors = analyze_errors(code);
         assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
     }
+
+    #[test]
+    fn test_struct_array_fields() {
+        // 结构体中的 array<T> 字段按 "变量.字段" 路径登记，随结构体所在作用域释放
+        let code = r#"
+fn test() -> (r: i64) {
+    {
+        b = Bag { items: array [1], n: 1 }
+        r = b.items[0]
+    }
+    c = Bag { items: array [2], n: 2 }
+    drop(c.items)
+    drop(c.items)
+}
+"#;
+        let (_name, params, the_return, stmts) = crate::frontend::parser::function(code).unwrap();
+        let struct_arrays = HashMap::from([
+            ("b".to_string(), vec!["b.items".to_string()]),
+            ("c".to_string(), vec!["c.items".to_string()]),
+        ]);
+        let (analysis, errors) = OwnershipChecker::new()
+            .with_struct_arrays(struct_arrays)
+            .analyze_function(&params, &stmts, &the_return.0);
+        assert_eq!(analysis.scope_vars[&1], vec!["b.items".to_string()]);
+        assert_eq!(errors.len(), 1, "got {:?}", errors);
+        assert!(matches!(&errors[0], OwnershipError::DoubleDrop { name, .. } if name == "c.items"));
+    }
+
+    #[test]
+    fn test_match_arms_are_branches() {
+        // 每个分支单独处理提前返回，与 if 的分支相同
+        let code = r#"
+fn test(n: i64) -> (r: array<i64>) {
+    arr = array [1, 2, 3]
+    match n {
+        0 => {
+            return arr
+        }
+        _ => drop(arr),
+    }
+    r = array [0]
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(errors.is_empty(), "got {:?}", errors);
+
+        let code = r#"
+fn test(n: i64) -> (r: array<i64>) {
+    arr = array [1, 2, 3]
+    match n {
+        0 => {
+            return arr
+        }
+        _ => n,
+    }
+    r = array [0]
+}
+"#;
+        let errors = analyze_errors(code);
+        assert!(
+            matches!(errors.as_slice(), [OwnershipError::LeakedArray { name, .. }] if name == "arr"),
+            "got {:?}",
+            errors
+        );
+    }
 }
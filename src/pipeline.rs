@@ -0,0 +1,328 @@
+//! 编译流水线 —— 命令行各子命令与 `JIT` 共用的前端检查和编译选项
+//!
+//! ```text
+//! 解析 → 登记常量/静态变量与类型 → 名字解析 → 类型检查 → 常量折叠 → 所有权检查 ──▶ check 到此为止
+//!                                                  └──▶ IR 翻译 → Cranelift 优化 (OptLevel) → 机器码
+//!                                                                      └── 产物 (Emit): IR / 汇编
+//! ```
+
+use crate::error::CompileError;
+use crate::frontend::{EnumDef, Expr, Function, GlobalDef, Program, Span, StructDef, Type, parser};
+use crate::optimizer;
+use crate::ownership::{OwnershipChecker, ScopeAnalysis};
+use crate::resolver;
+use crate::type_checker::{TypeChecker, TypeError, resolve_array_lengths};
+
+/// Cranelift 优化级别（对应 `opt_level` 设置）
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OptLevel {
+    /// 不优化，编译最快
+    #[default]
+    None,
+    /// 优化运行速度
+    Speed,
+    /// 同时优化速度与代码大小
+    SpeedAndSize,
+}
+
+impl OptLevel {
+    pub(crate) fn as_setting(self) -> &'static str {
+        match self {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        }
+    }
+}
+
+/// 编译时额外输出的产物
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Emit {
+    /// 优化后的 Cranelift IR
+    Clif,
+    /// 生成的机器码（反汇编文本）
+    Asm,
+}
+
+/// 编译选项，由 `JIT::new` 使用
+#[derive(Clone, Debug, Default)]
+pub struct CompileOptions {
+    pub opt_level: OptLevel,
+    pub emit: Vec<Emit>,
+    /// 严格模式：局部变量必须用 `let` 声明，给未声明的名字赋值是错误
+    pub strict: bool,
+}
+
+/// 一个函数的一种编译产物
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    pub function: String,
+    pub kind: Emit,
+    pub text: String,
+}
+
+/// 对单个函数执行 名字解析 → 类型检查 → 常量折叠 → 所有权检查，
+/// 返回折叠后的语句与所有权检查输出的 ScopeAnalysis，供翻译阶段使用。
+/// `type_checker` 中必须已登记了同一程序中所有函数的签名
+pub(crate) fn check_function(
+    type_checker: &TypeChecker,
+    params: &[(String, Type)],
+    the_return: &(String, Type),
+    stmts: Vec<Expr>,
+    strict: bool,
+) -> Result<(Vec<Expr>, ScopeAnalysis), CompileError> {
+    // 名字解析：let 的块作用域与遮蔽。之后的阶段中每个名字在函数内只对应一个变量
+    let (stmts, mut type_errors) =
+        resolver::resolve_function(params, the_return, stmts, &type_checker.globals, strict);
+
+    // 静态类型检查：在常量折叠之前进行，避免折叠掩盖错误（如 s * 0）
+    type_errors.extend(type_checker.check_function(params, the_return, &stmts));
+    if !type_errors.is_empty() {
+        return Err(CompileError::Type(type_errors));
+    }
+
+    // 应用常量折叠优化：先把常量替换成它的值（被参数或返回变量遮蔽的除外）
+    let mut constants = type_checker.constants();
+    for (name, _) in params.iter().chain([the_return]) {
+        constants.remove(name);
+    }
+    let stmts = optimizer::inline_constants(stmts, &constants);
+    let stmts = optimizer::fold_constants_in_stmts(stmts);
+
+    // ═══════════════════════════════════════════════════════════
+    // 编译期所有权检查 (内存回收机制 — 编译期静态分析)
+    // ═══════════════════════════════════════════════════════════
+    //
+    // 所有权检查器遍历 AST，追踪每个 DynamicArray 的状态。
+    // 成功时输出 ScopeAnalysis — 每个作用域应释放哪些数组的"清单"。
+    // 失败时返回错误（LeakedArray / DoubleDrop / UseAfterDrop 等）。
+    //
+    // ScopeAnalysis 随后传递给 FunctionTranslator，实现统一的
+    // "编译期检查 → JIT 运行时释放"数据流。
+    // 结构体变量中的 array<T> 字段按路径（如 p.items）追踪，路径由变量类型展开
+    let struct_arrays = type_checker
+        .var_types(params, the_return, &stmts)
+        .into_iter()
+        .map(|(name, ty)| {
+            let paths = type_checker.array_field_paths(&name, &ty);
+            (name, paths)
+        })
+        .filter(|(_, paths)| !paths.is_empty())
+        .collect();
+    let mut checker = OwnershipChecker::new().with_struct_arrays(struct_arrays);
+    let (analysis, errors) = checker.analyze_function(params, &stmts, &the_return.0);
+    if !errors.is_empty() {
+        return Err(CompileError::Ownership(errors));
+    }
+    Ok((stmts, analysis))
+}
+
+/// 按声明顺序检查并登记程序中的常量与静态变量，返回发现的错误。
+/// 初值中可以使用之前声明的常量，折叠后必须是字面量。
+/// 检查失败的声明仍按原样登记，使用它的函数不会再报告名字未定义
+pub(crate) fn register_globals(
+    type_checker: &mut TypeChecker,
+    globals: &[GlobalDef],
+) -> Vec<CompileError> {
+    let mut errors = Vec::new();
+    let mut type_errors = Vec::new();
+    for (i, def) in globals.iter().enumerate() {
+        if globals[..i].iter().any(|g| g.name == def.name)
+            || type_checker.globals.contains_key(&def.name)
+        {
+            type_errors.push(TypeError::DuplicateGlobal {
+                name: def.name.clone(),
+                span: def.span,
+            });
+            continue;
+        }
+        let value = optimizer::inline_constants(vec![def.value.clone()], &type_checker.constants());
+        let folded = optimizer::fold_constants_in_stmts(value).remove(0);
+        let value = match type_checker.check_global(def, &folded) {
+            Ok(value) => value,
+            Err(e) => {
+                type_errors.push(e);
+                def.value.clone()
+            }
+        };
+        type_checker.register_global(GlobalDef {
+            value,
+            ..def.clone()
+        });
+    }
+    if !type_errors.is_empty() {
+        errors.push(CompileError::Type(type_errors));
+    }
+    errors
+}
+
+/// 把类型中的 `[T; N]` 换成常量 N 的值；N 不是整数常量时记录错误并保持原样
+fn resolve_type(
+    type_checker: &TypeChecker,
+    ty: &Type,
+    span: Span,
+    errors: &mut Vec<TypeError>,
+) -> Type {
+    resolve_array_lengths(ty, &type_checker.globals).unwrap_or_else(|name| {
+        errors.push(TypeError::ArrayLengthNotConst { name, span });
+        ty.clone()
+    })
+}
+
+/// 把函数签名中的 `[T; N]` 换成常量 N 的值。
+/// 函数头没有记录位置，与签名中未声明的结构体一样报在文件开头
+pub(crate) fn resolve_signature(
+    type_checker: &TypeChecker,
+    function: Function,
+) -> Result<Function, CompileError> {
+    let (name, params, (ret_name, ret_ty), stmts) = function;
+    let mut errors = Vec::new();
+    let params = params
+        .iter()
+        .map(|(p, ty)| {
+            let ty = resolve_type(type_checker, ty, Span::default(), &mut errors);
+            (p.clone(), ty)
+        })
+        .collect();
+    let ret_ty = resolve_type(type_checker, &ret_ty, Span::default(), &mut errors);
+    if !errors.is_empty() {
+        return Err(CompileError::Type(errors));
+    }
+    Ok((name, params, (ret_name, ret_ty), stmts))
+}
+
+/// 登记程序中的全部结构体与枚举并检查其声明，返回发现的错误。
+/// 类型之间可以前向引用，所以先全部登记再检查。
+/// 字段与载荷中的 `[T; N]` 换成常量 N 的值，常量须已由 `register_globals` 登记
+pub(crate) fn register_types(
+    type_checker: &mut TypeChecker,
+    structs: &[StructDef],
+    enums: &[EnumDef],
+) -> Vec<CompileError> {
+    let mut type_errors = Vec::new();
+    let structs: Vec<StructDef> = structs
+        .iter()
+        .map(|def| StructDef {
+            fields: def
+                .fields
+                .iter()
+                .map(|(field, ty)| {
+                    let ty = resolve_type(type_checker, ty, def.span, &mut type_errors);
+                    (field.clone(), ty)
+                })
+                .collect(),
+            ..def.clone()
+        })
+        .collect();
+    let enums: Vec<EnumDef> = enums
+        .iter()
+        .map(|def| EnumDef {
+            variants: def
+                .variants
+                .iter()
+                .map(|(variant, payload)| {
+                    let payload = payload
+                        .iter()
+                        .map(|ty| resolve_type(type_checker, ty, def.span, &mut type_errors))
+                        .collect();
+                    (variant.clone(), payload)
+                })
+                .collect(),
+            ..def.clone()
+        })
+        .collect();
+    let mut names: Vec<&str> = Vec::new();
+    for def in &structs {
+        if names.contains(&def.name.as_str()) {
+            type_errors.push(TypeError::DuplicateType {
+                name: def.name.clone(),
+                span: def.span,
+            });
+            continue;
+        }
+        names.push(&def.name);
+        type_checker.register_struct(def.clone());
+    }
+    for def in &enums {
+        if names.contains(&def.name.as_str()) {
+            type_errors.push(TypeError::DuplicateType {
+                name: def.name.clone(),
+                span: def.span,
+            });
+            continue;
+        }
+        names.push(&def.name);
+        type_checker.register_enum(def.clone());
+    }
+    type_errors.extend(type_checker.check_structs(&structs));
+    type_errors.extend(type_checker.check_enums(&enums));
+    // 递归的类型没有有限的布局，不能留在登记表里
+    for e in &type_errors {
+        if let TypeError::RecursiveStruct { name, .. } = e {
+            type_checker.structs.remove(name);
+            type_checker.enums.remove(name);
+        }
+    }
+    if type_errors.is_empty() {
+        Vec::new()
+    } else {
+        vec![CompileError::Type(type_errors)]
+    }
+}
+
+/// 只做前端检查（解析、名字解析、类型检查、常量折叠、所有权检查），不生成机器码。
+/// 与编译不同，一个函数出错后继续检查其余函数，返回全部错误（按函数顺序）
+pub fn check_program(input: &str) -> Vec<CompileError> {
+    check_program_with(input, &CompileOptions::default())
+}
+
+/// 与 `check_program` 相同，按 `options.strict` 决定是否允许隐式声明变量
+pub fn check_program_with(input: &str, options: &CompileOptions) -> Vec<CompileError> {
+    match parser::program(input) {
+        Ok(program) => check_module(TypeChecker::new(), program, options.strict),
+        Err(e) => vec![CompileError::Parse(e)],
+    }
+}
+
+/// 检查已解析的程序，其中可以使用 `type_checker` 中已登记的函数、类型与全局变量
+/// （REPL 在编译新的输入之前用 JIT 现有的登记表检查它）
+pub(crate) fn check_module(
+    mut type_checker: TypeChecker,
+    program: Program,
+    strict: bool,
+) -> Vec<CompileError> {
+    let (structs, enums, functions, globals) = program.split();
+
+    // 先登记全部常量、类型与签名，函数之间可以前向引用
+    let mut errors = register_globals(&mut type_checker, &globals);
+    errors.extend(register_types(&mut type_checker, &structs, &enums));
+    let mut resolved = Vec::with_capacity(functions.len());
+    for function in functions {
+        match resolve_signature(&type_checker, function) {
+            Ok(function) => resolved.push(function),
+            Err(e) => errors.push(e),
+        }
+    }
+    let functions = resolved;
+    for (i, (name, params, the_return, _)) in functions.iter().enumerate() {
+        if functions[..i].iter().any(|(n, ..)| n == name) {
+            errors.push(CompileError::Linking(format!(
+                "duplicate definition of function '{}'",
+                name
+            )));
+            continue;
+        }
+        type_checker.register_function(
+            name,
+            params.iter().map(|(_, ty)| ty.clone()).collect(),
+            the_return.1.clone(),
+        );
+    }
+
+    for (_, params, the_return, stmts) in functions {
+        if let Err(e) = check_function(&type_checker, &params, &the_return, stmts, strict) {
+            errors.push(e);
+        }
+    }
+    errors
+}
@@ -0,0 +1,564 @@
+//! 交互式会话 —— `toy repl` 的求值逻辑
+//!
+//! 会话持有一个长期存在的 `JIT`：
+//! - 输入 `fn` / `struct` / `enum` / `const` / `static` 定义：编译并登记，之后的输入可以使用它。
+//!   JITModule 中的符号不能重新定义，所以重新定义同名的函数、类型或全局变量时用全部现存定义重建 JIT，
+//!   使用它的函数也随之改为使用新版本（静态变量恢复为初值）
+//! - 静态变量保存在 JIT 模块的数据对象中，`static mut` 的值在多次输入之间保留。
+//!   每次输入先检查再编译，有错的输入不改动 JIT，也就不会重置静态变量
+//! - 输入语句或表达式：包装成无参数的匿名函数 `__repl_N` 编译并调用，
+//!   最后一条是表达式时按推断出的类型打印它的值。变量只在同一次输入内有效
+//! - `:` 开头的命令见 `HELP`
+//!
+//! 多行输入由调用方用 `is_complete` 判断何时读完（括号配平）。
+
+use crate::error::{CallError, CompileError};
+use crate::formatter;
+use crate::frontend::{
+    Expr, ExprKind, GlobalKind, Item, Program, Type, parser, string_literal_end,
+};
+use crate::jit::JIT;
+use crate::pipeline::{Artifact, CompileOptions, Emit};
+use std::fmt;
+use std::slice;
+
+/// 诊断中代表 REPL 输入的源码名
+const ORIGIN: &str = "<repl>";
+
+/// 包装表达式的匿名函数中保存结果的返回变量
+const RESULT_VAR: &str = "__value";
+
+pub const HELP: &str = "\
+fn NAME(...) -> (r: T) { ... }  define or redefine a function
+struct NAME { field: T, ... }   define or redefine a struct
+enum NAME { A, B(T, ...), ... } define or redefine an enum
+const NAME: T = VALUE           define or redefine a constant
+static [mut] NAME: T = VALUE    define or redefine a global variable
+STATEMENTS                      run them and print the value of the last expression
+:type EXPR                      show the inferred type of an expression
+:ast INPUT                      show the parsed AST of a definition or statements
+:ir [NAME]                      show the Cranelift IR of a function (default: the last input)
+:reset                          forget every definition
+:help                           show this message
+:quit                           leave the REPL";
+
+/// 一次输入的错误
+#[derive(Debug)]
+pub enum ReplError {
+    /// 编译失败；`source` 是诊断中 span 所指的源码
+    Compile { error: CompileError, source: String },
+    /// 调用包装函数失败（例如结果类型无法通过 `JIT::call` 返回）
+    Call(CallError),
+    /// 未知命令或命令参数错误
+    Command(String),
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplError::Compile { error, source } => write!(f, "{}", error.render(ORIGIN, source)),
+            ReplError::Call(e) => write!(f, "error: {}", e),
+            ReplError::Command(message) => write!(f, "error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ReplError {}
+
+pub struct Repl {
+    jit: JIT,
+    /// 创建 JIT 的选项，总是包含 `Emit::Clif`，供 `:ir` 使用
+    options: CompileOptions,
+    /// 用户通过 --emit 请求的产物，每次编译后随结果输出
+    emit: Vec<Emit>,
+    /// 现存的定义：(名字, 格式化后的源码)，按定义顺序。函数之外的定义记为
+    /// `struct NAME` / `enum NAME` / `const NAME` / `static NAME`，与同名函数区分
+    definitions: Vec<(String, String)>,
+    /// 最近一次输入编译出的函数，`:ir` 不带参数时显示它们
+    last_compiled: Vec<String>,
+    counter: usize,
+}
+
+impl Repl {
+    pub fn new(options: CompileOptions) -> Self {
+        let emit = options.emit.clone();
+        let mut options = options;
+        if !options.emit.contains(&Emit::Clif) {
+            options.emit.push(Emit::Clif);
+        }
+        Repl {
+            jit: JIT::new(options.clone()),
+            options,
+            emit,
+            definitions: Vec::new(),
+            last_compiled: Vec::new(),
+            counter: 0,
+        }
+    }
+
+    /// 求值一次完整的输入（可以是多行），返回要打印的文本（可能为空）
+    pub fn eval(&mut self, input: &str) -> Result<String, ReplError> {
+        let input = input.trim();
+        if let Some(command) = input.strip_prefix(':') {
+            return self.command(command);
+        }
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+        let source = input.to_string();
+        if is_definition(input) {
+            self.define(source)
+        } else {
+            self.evaluate(source)
+        }
+    }
+
+    /// 编译一个或多个函数 / 结构体 / 枚举 / 常量 / 静态变量定义
+    fn define(&mut self, source: String) -> Result<String, ReplError> {
+        let program = parser::program(&source).map_err(|e| compile_error(e, &source))?;
+        let names: Vec<String> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Struct(def) => format!("struct {}", def.name),
+                Item::Enum(def) => format!("enum {}", def.name),
+                // static 与 static mut 是同一个数据对象，重新定义时互相替换
+                Item::Global(def) if def.kind == GlobalKind::Const => {
+                    format!("const {}", def.name)
+                }
+                Item::Global(def) => format!("static {}", def.name),
+                Item::Function(f) => f.0.clone(),
+            })
+            .collect();
+        let formatted: Vec<(String, String)> = names
+            .iter()
+            .cloned()
+            .zip(formatter::format_items(&program.items, &source))
+            .collect();
+
+        let redefined: Vec<bool> = names.iter().map(|n| self.is_defined(n)).collect();
+        if redefined.contains(&true) {
+            // 用替换后的全部定义重建 JIT；失败时保留原来的会话
+            let mut definitions = self.definitions.clone();
+            for (name, text) in formatted {
+                match definitions.iter_mut().find(|(n, _)| *n == name) {
+                    Some(definition) => definition.1 = text,
+                    None => definitions.push((name, text)),
+                }
+            }
+            self.jit = self.rebuild(&definitions)?;
+            self.definitions = definitions;
+        } else {
+            self.check(&source, program.clone())?;
+            self.compile(&source, |jit| jit.compile_module(program))?;
+            self.definitions.extend(formatted);
+        }
+
+        let functions: Vec<String> = names
+            .iter()
+            .filter(|n| is_function_name(n))
+            .cloned()
+            .collect();
+        let mut out: Vec<String> = names
+            .iter()
+            .zip(redefined)
+            .map(|(name, redefined)| {
+                let verb = if redefined { "redefined" } else { "defined" };
+                if !is_function_name(name) {
+                    return format!("{} {}", verb, name);
+                }
+                let sig = self.jit.signature(name).expect("compiled function");
+                format!("{} {}: {}", verb, name, sig)
+            })
+            .collect();
+        out.extend(self.emitted(&functions));
+        self.last_compiled = functions;
+        Ok(out.join("\n"))
+    }
+
+    /// 把语句包装成匿名函数编译并调用
+    fn evaluate(&mut self, source: String) -> Result<String, ReplError> {
+        let mut stmts = parser::statements(&source).map_err(|e| compile_error(e, &source))?;
+        let ty = self.jit.type_checker().infer_result_type(&stmts);
+        let Some(last) = stmts.pop() else {
+            return Ok(String::new());
+        };
+
+        // 最后一条是表达式时把它的值赋给返回变量；否则返回变量保持为 0，不打印
+        let has_value = !matches!(
+            last.kind,
+            ExprKind::Assign(..)
+                | ExprKind::Let(..)
+                | ExprKind::CompoundAssign(..)
+                | ExprKind::IndexAssign(..)
+                | ExprKind::FieldAssign(..)
+                | ExprKind::WhileLoop(..)
+                | ExprKind::ForRange(..)
+                | ExprKind::ForEach(..)
+                | ExprKind::Drop(_)
+                | ExprKind::Block(_)
+                | ExprKind::Return(_)
+                | ExprKind::Break
+                | ExprKind::Continue
+        );
+        let (ty, last) = match ty {
+            Some(ty) if has_value => {
+                let span = last.span;
+                let assign = ExprKind::Assign(RESULT_VAR.to_string(), Box::new(last));
+                (ty, Expr::new(assign, span))
+            }
+            _ => (Type::I64, last),
+        };
+        stmts.push(last);
+
+        let name = format!("__repl_{}", self.counter);
+        self.counter += 1;
+        let function = (
+            name.clone(),
+            Vec::new(),
+            (RESULT_VAR.to_string(), ty),
+            stmts,
+        );
+        let program = Program {
+            items: vec![Item::Function(function.clone())],
+        };
+        self.check(&source, program)?;
+        self.compile(&source, |jit| jit.compile_functions(vec![function]))?;
+        self.last_compiled = vec![name.clone()];
+
+        let value = self.jit.call(&name, &[]).map_err(ReplError::Call)?;
+        let mut out = self.emitted(slice::from_ref(&name));
+        if has_value {
+            out.push(value.to_string());
+        }
+        Ok(out.join("\n"))
+    }
+
+    /// 编译之前先检查输入，输入有误时 JIT 保持原样，`static mut` 的值不受影响
+    fn check(&self, source: &str, program: Program) -> Result<(), ReplError> {
+        self.jit
+            .check_module(program)
+            .map_err(|error| ReplError::Compile {
+                error,
+                source: source.to_string(),
+            })
+    }
+
+    /// 在当前 JIT 上编译已通过检查的输入。代码生成失败的函数可能已经在模块中声明、
+    /// 登记了签名，所以失败后用现存定义重建 JIT，丢弃这些残留
+    fn compile<T>(
+        &mut self,
+        source: &str,
+        f: impl FnOnce(&mut JIT) -> Result<T, CompileError>,
+    ) -> Result<T, ReplError> {
+        f(&mut self.jit).map_err(|error| {
+            match self.rebuild(&self.definitions) {
+                Ok(jit) => self.jit = jit,
+                Err(e) => return e,
+            }
+            ReplError::Compile {
+                error,
+                source: source.to_string(),
+            }
+        })
+    }
+
+    /// 用给定的定义创建新的 JIT。出错时诊断指向所有定义拼接成的源码
+    fn rebuild(&self, definitions: &[(String, String)]) -> Result<JIT, ReplError> {
+        let source = definitions
+            .iter()
+            .map(|(_, text)| text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut jit = JIT::new(self.options.clone());
+        jit.compile_program(&source)
+            .map_err(|error| ReplError::Compile { error, source })?;
+        Ok(jit)
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.definitions.iter().any(|(n, _)| n == name)
+    }
+
+    /// 函数最近一次编译出的某种产物
+    fn artifact(&self, name: &str, kind: Emit) -> Option<&Artifact> {
+        self.jit
+            .artifacts()
+            .iter()
+            .rev()
+            .find(|a| a.function == name && a.kind == kind)
+    }
+
+    /// 按 --emit 输出刚编译的函数的产物
+    fn emitted(&self, names: &[String]) -> Vec<String> {
+        names
+            .iter()
+            .flat_map(|name| self.emit.iter().map(move |kind| (name, *kind)))
+            .filter_map(|(name, kind)| self.artifact(name, kind))
+            .map(|a| a.text.trim_end().to_string())
+            .collect()
+    }
+
+    fn command(&mut self, command: &str) -> Result<String, ReplError> {
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let arg = arg.trim();
+        match name {
+            "type" => {
+                let source = format!("{}\n", arg);
+                let stmts = parser::statements(&source).map_err(|e| compile_error(e, &source))?;
+                self.jit
+                    .type_checker()
+                    .infer_result_type(&stmts)
+                    .map(|ty| ty.to_string())
+                    .ok_or_else(|| ReplError::Command("usage: :type EXPR".to_string()))
+            }
+            "ast" => {
+                let source = format!("{}\n", arg);
+                let ast = if is_definition(arg) {
+                    parser::program(&source).map(|f| format!("{:#?}", f))
+                } else {
+                    parser::statements(&source).map(|s| format!("{:#?}", s))
+                };
+                ast.map_err(|e| compile_error(e, &source))
+            }
+            "ir" => {
+                let names = if arg.is_empty() {
+                    self.last_compiled.clone()
+                } else {
+                    vec![arg.to_string()]
+                };
+                if names.is_empty() {
+                    return Err(ReplError::Command(
+                        "nothing has been compiled yet".to_string(),
+                    ));
+                }
+                names
+                    .iter()
+                    .map(|name| {
+                        self.artifact(name, Emit::Clif)
+                            .map(|a| a.text.trim_end().to_string())
+                            .ok_or_else(|| {
+                                ReplError::Command(format!("no compiled function named '{}'", name))
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|texts| texts.join("\n\n"))
+            }
+            "reset" => {
+                self.jit = JIT::new(self.options.clone());
+                self.definitions.clear();
+                self.last_compiled.clear();
+                Ok("cleared all definitions".to_string())
+            }
+            "help" => Ok(HELP.to_string()),
+            _ => Err(ReplError::Command(format!(
+                "unknown command ':{}' (try :help)",
+                name
+            ))),
+        }
+    }
+}
+
+fn compile_error(e: peg::error::ParseError<peg::str::LineCol>, source: &str) -> ReplError {
+    ReplError::Compile {
+        error: CompileError::Parse(e),
+        source: source.to_string(),
+    }
+}
+
+/// 以 `fn` / `struct` / `enum` / `const` / `static` 关键字开头的输入是定义
+fn is_definition(input: &str) -> bool {
+    ["fn", "struct", "enum", "const", "static"]
+        .iter()
+        .any(|keyword| {
+            input
+                .strip_prefix(keyword)
+                .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+        })
+}
+
+/// 定义表中的名字是否是函数（其他定义的名字以关键字开头）
+fn is_function_name(name: &str) -> bool {
+    !name.contains(' ')
+}
+
+/// 输入的括号是否已经配平（字符串字面量和注释中的不算）。
+/// 未配平或块注释还没结束时应继续读入下一行
+pub fn is_complete(input: &str) -> bool {
+    let bytes = input.as_bytes();
+    let mut depth = 0i32;
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(end) = string_literal_end(input, i) {
+            i = end;
+            continue;
+        }
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                i = input[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                continue;
+            }
+            (b'/', Some(b'*')) => match input[i + 2..].find("*/") {
+                Some(n) => i += 2 + n + 1,
+                None => return false,
+            },
+            (b'(' | b'[' | b'{', _) => depth += 1,
+            (b')' | b']' | b'}', _) => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    depth <= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(repl: &mut Repl, input: &str) -> String {
+        repl.eval(input)
+            .unwrap_or_else(|e| panic!("{} failed:\n{}", input, e))
+    }
+
+    #[test]
+    fn test_eval_prints_value_by_inferred_type() {
+        let mut repl = Repl::new(CompileOptions::default());
+        assert_eq!(eval(&mut repl, "1 + 2"), "3");
+        assert_eq!(eval(&mut repl, "x = 1.5\nx * 2.0"), "3");
+        assert_eq!(eval(&mut repl, "1.0 + 2.0i"), "1+2i");
+        assert_eq!(eval(&mut repl, "\"hi\""), "\"hi\"");
+        assert_eq!(eval(&mut repl, "x = 1"), "");
+        assert_eq!(eval(&mut repl, ":type 2 as f32"), "f32");
+        assert_eq!(eval(&mut repl, "x = 2; x * 3 // six"), "6");
+    }
+
+    #[test]
+    fn test_redefinition_replaces_function() {
+        let mut repl = Repl::new(CompileOptions::default());
+        let double = "fn double(x: i64) -> (r: i64) {\n    r = x * 2\n}";
+        assert_eq!(eval(&mut repl, double), "defined double: (i64) -> i64");
+        eval(
+            &mut repl,
+            "fn quad(x: i64) -> (r: i64) {\n    r = double(double(x))\n}",
+        );
+        assert_eq!(eval(&mut repl, "quad(1)"), "4");
+
+        // 重新定义后，已有的调用方也使用新版本
+        let triple = "fn double(x: i64) -> (r: i64) {\n    r = x * 3\n}";
+        assert_eq!(eval(&mut repl, triple), "redefined double: (i64) -> i64");
+        assert_eq!(eval(&mut repl, "quad(1)"), "9");
+
+        // 失败的定义不影响会话
+        assert!(
+            repl.eval("fn bad() -> (r: i64) {\n    r = nope\n}")
+                .is_err()
+        );
+        assert!(repl.eval("bad()").is_err());
+        assert_eq!(eval(&mut repl, "double(2)"), "6");
+
+        eval(&mut repl, ":reset");
+        assert!(repl.eval("double(2)").is_err());
+    }
+
+    #[test]
+    fn test_struct_definitions() {
+        let mut repl = Repl::new(CompileOptions::default());
+        assert_eq!(
+            eval(&mut repl, "struct P { x: i64, y: i64 }"),
+            "defined struct P"
+        );
+        eval(
+            &mut repl,
+            "fn sum(p: P) -> (r: i64) {\n    r = p.x + p.y\n}",
+        );
+        assert_eq!(
+            eval(&mut repl, "p = P { x: 2, y: 3 }\np.x += 1\nsum(p)"),
+            "6"
+        );
+
+        // 重新定义结构体后，使用它的函数按新布局重新编译
+        assert_eq!(
+            eval(&mut repl, "struct P { z: i8, y: i64, x: i64 }"),
+            "redefined struct P"
+        );
+        assert_eq!(eval(&mut repl, "sum(P { z: 0i8, x: 2, y: 3 })"), "5");
+    }
+
+    #[test]
+    fn test_enum_definitions() {
+        let mut repl = Repl::new(CompileOptions::default());
+        assert_eq!(
+            eval(&mut repl, "enum Op { Add(i64), Neg }"),
+            "defined enum Op"
+        );
+        eval(
+            &mut repl,
+            "fn apply(op: Op, x: i64) -> (r: i64) {\n    r = match op {\n        Op::Add(n) => x + n,\n        Op::Neg => -x,\n    }\n}",
+        );
+        assert_eq!(eval(&mut repl, "apply(Op::Add(2), 5)"), "7");
+        assert_eq!(
+            eval(
+                &mut repl,
+                "match apply(Op::Neg, 5) {\n    -5 => 1,\n    _ => 0,\n}"
+            ),
+            "1"
+        );
+        assert!(repl.eval("match 1 {\n    0 => 1,\n}").is_err());
+    }
+
+    #[test]
+    fn test_global_definitions() {
+        let mut repl = Repl::new(CompileOptions::default());
+        assert_eq!(eval(&mut repl, "const N: i64 = 4"), "defined const N");
+        assert_eq!(
+            eval(&mut repl, "static mut count: i64 = 0"),
+            "defined static count"
+        );
+        eval(
+            &mut repl,
+            "fn tick() -> (r: i64) {\n    count += N\n    r = count\n}",
+        );
+        // 静态变量的值在多次输入之间保留，输入中的语句也能直接读写它
+        eval(&mut repl, "tick()");
+        assert_eq!(eval(&mut repl, "count += 1\ntick()"), "9");
+        assert_eq!(eval(&mut repl, "count"), "9");
+
+        // 有错的输入不会重建 JIT，静态变量保持原值
+        assert!(repl.eval("count += nope").is_err());
+        assert!(
+            repl.eval("fn bad() -> (r: i64) {\n    r = count + nope\n}")
+                .is_err()
+        );
+        assert_eq!(eval(&mut repl, "count"), "9");
+
+        // 重新定义后重建 JIT，静态变量恢复为初值
+        assert_eq!(
+            eval(&mut repl, "static mut count: i64 = 100"),
+            "redefined static count"
+        );
+        assert_eq!(eval(&mut repl, "tick()"), "104");
+        assert!(repl.eval("N = 5").is_err());
+    }
+
+    #[test]
+    fn test_commands_and_multiline_input() {
+        assert!(!is_complete("fn f() -> (r: i64) {\n"));
+        assert!(!is_complete("if x {\n    1\n} else {\n"));
+        assert!(is_complete("s = \"{\"\n"));
+        assert!(is_complete("x = 1 // {\n"));
+        assert!(!is_complete("x = 1 /* {\n"));
+        assert!(is_complete("x = 1 /* { */\n"));
+        assert!(is_complete("s = r\"{\\\"\n"));
+        assert!(!is_complete("s = r#\"}\"# + \"\\\"}\" {\n"));
+
+        let mut repl = Repl::new(CompileOptions::default());
+        assert_eq!(eval(&mut repl, "2 * 3"), "6");
+        assert!(eval(&mut repl, ":ir").contains("return"));
+        assert!(eval(&mut repl, ":ast a = 1").contains("Assign"));
+        assert!(matches!(repl.eval(":nope"), Err(ReplError::Command(_))));
+        let err = repl.eval("1 +").unwrap_err().to_string();
+        assert!(err.contains("--> <repl>:1:4"), "got:\n{}", err);
+    }
+}
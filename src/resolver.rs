@@ -0,0 +1,513 @@
+//! 名字解析 —— 把每个变量引用对应到它的声明，实现 `let` 的块作用域与遮蔽
+//!
+//! 类型检查、所有权检查与 IR 翻译都按名字查找变量，一个名字在整个函数内只对应一个变量。
+//! 本 Pass 在它们之前运行，把同名的不同变量改写成互不相同的内部名字：
+//!
+//! ```text
+//! let x = 1              let x = 1
+//! {                      {
+//!     let x = 2.5   ──▶      let x#1 = 2.5
+//!     print_f64(x)           print_f64(x#1)
+//! }                      }
+//! print_i64(x)           print_i64(x)
+//! ```
+//!
+//! 作用域规则：
+//! - `let`、for 循环变量与 match 分支中的绑定声明新变量，所在的块（`{}`、while / for 循环体、
+//!   if 的分支、match 的分支）结束时离开作用域。`{}` 与循环体也是 `OwnershipChecker` 的作用域，
+//!   其中声明的动态数组在块结束时自动释放
+//! - 同一作用域或内层作用域中同名的新声明遮蔽之前的变量
+//! - 参数、返回变量和隐式声明（给从未声明的名字赋值）的变量属于整个函数，与以前的行为相同
+//! - 没有 `mut` 的 `let` 变量不能再赋值，也不能写入其中的元素或字段。
+//!   for 循环变量和 match 绑定与以前一样可以赋值
+//! - 严格模式（`CompileOptions::strict`）下不允许隐式声明
+//! - 常量与静态变量在所有函数中可见，被参数、返回变量和同名的局部变量遮蔽；
+//!   常量不能赋值。`let` 类型标注中的 `[T; N]` 在这里换成常量 N 的值
+//!
+//! 内部名字的形式是 `名字#序号`。`#` 不能出现在标识符中，不会与源码中的名字冲突；
+//! 错误信息用 `source_name` 还原为源码中的名字。
+
+use crate::frontend::{Expr, ExprKind, GlobalDef, GlobalKind, MatchArm, Pattern, Span, Type};
+use crate::type_checker::{TypeError, resolve_array_lengths};
+use std::collections::{HashMap, HashSet};
+
+/// 内部名字中分隔源码名字与序号的字符
+const SEPARATOR: char = '#';
+
+/// 解析函数体中的变量名，返回改写后的语句与发现的错误。参数与返回变量属于函数作用域，可以赋值。
+/// 出错时改写结果仍然完整（例如离开作用域的名字仍指向原来的变量），可以继续做类型检查
+pub fn resolve_function(
+    params: &[(String, Type)],
+    the_return: &(String, Type),
+    stmts: Vec<Expr>,
+    globals: &HashMap<String, GlobalDef>,
+    strict: bool,
+) -> (Vec<Expr>, Vec<TypeError>) {
+    let mut resolver = Resolver::new(globals, strict);
+    for (name, _) in params.iter().chain([the_return]) {
+        resolver.declare_in_function(name.clone(), Span::default());
+    }
+    // 参数占用的名字之外，常量与静态变量的名字也不能再分配给局部变量
+    resolver.used.extend(globals.keys().cloned());
+    let stmts = resolver.stmts(stmts);
+    (stmts, resolver.errors)
+}
+
+/// 解析不属于任何函数的语句序列（例如 REPL 的输入），忽略错误。
+/// 用于在编译之前推断类型，结果与 `resolve_function` 的改写一致
+pub fn resolve_statements(stmts: Vec<Expr>, globals: &HashMap<String, GlobalDef>) -> Vec<Expr> {
+    let mut resolver = Resolver::new(globals, false);
+    resolver.used.extend(globals.keys().cloned());
+    resolver.stmts(stmts)
+}
+
+/// 内部名字对应的源码写法：`x#1` → `x`，`p#2.items` → `p.items`
+pub fn source_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == SEPARATOR {
+            while chars.next_if(char::is_ascii_digit).is_some() {}
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// 作用域中的一个变量
+#[derive(Clone, Debug)]
+struct Binding {
+    /// 改写后的内部名字
+    name: String,
+    mutable: bool,
+    /// 声明的位置（参数与返回变量为空区间）
+    span: Span,
+}
+
+struct Resolver<'a> {
+    strict: bool,
+    /// 常量与静态变量，在所有作用域之外
+    globals: &'a HashMap<String, GlobalDef>,
+    /// 常量与静态变量的绑定：内部名字就是源码名字，常量不可赋值
+    global_bindings: HashMap<String, Binding>,
+    /// 由外到内的作用域：源码名字 → 变量。`scopes[0]` 是函数作用域
+    scopes: Vec<HashMap<String, Binding>>,
+    /// 已经分配出去的内部名字
+    used: HashSet<String>,
+    /// 已经离开作用域的变量：源码名字 → 最近离开的那个，用于报告 OutOfScope
+    ended: HashMap<String, Binding>,
+    errors: Vec<TypeError>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(globals: &'a HashMap<String, GlobalDef>, strict: bool) -> Self {
+        let global_bindings = globals
+            .values()
+            .map(|def| {
+                let binding = Binding {
+                    name: def.name.clone(),
+                    mutable: def.kind == GlobalKind::StaticMut,
+                    span: def.span,
+                };
+                (def.name.clone(), binding)
+            })
+            .collect();
+        Resolver {
+            strict,
+            globals,
+            global_bindings,
+            scopes: vec![HashMap::new()],
+            used: HashSet::new(),
+            ended: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.global_bindings.get(name))
+    }
+
+    /// 把 `let` 类型标注中的 `[T; N]` 换成常量 N 的值。N 不是整数常量时报错并去掉标注，
+    /// 按初值推断类型，避免再报告一个类型不匹配
+    fn resolve_type(&mut self, ty: Type, span: Span) -> Option<Type> {
+        resolve_array_lengths(&ty, self.globals)
+            .map_err(|name| {
+                self.errors
+                    .push(TypeError::ArrayLengthNotConst { name, span })
+            })
+            .ok()
+    }
+
+    /// 为源码名字分配一个未使用的内部名字：第一次使用时就是它本身
+    fn fresh(&mut self, name: &str) -> String {
+        let mut internal = name.to_string();
+        let mut n = 0;
+        while self.used.contains(&internal) {
+            n += 1;
+            internal = format!("{}{}{}", name, SEPARATOR, n);
+        }
+        self.used.insert(internal.clone());
+        internal
+    }
+
+    /// 在最内层作用域声明变量，返回内部名字
+    fn declare(&mut self, name: String, mutable: bool, span: Span) -> String {
+        let internal = self.fresh(&name);
+        let binding = Binding {
+            name: internal.clone(),
+            mutable,
+            span,
+        };
+        self.scopes.last_mut().unwrap().insert(name, binding);
+        internal
+    }
+
+    /// 在函数作用域声明可赋值的变量（参数、返回变量、隐式声明）
+    fn declare_in_function(&mut self, name: String, span: Span) -> String {
+        let internal = self.fresh(&name);
+        let binding = Binding {
+            name: internal.clone(),
+            mutable: true,
+            span,
+        };
+        self.scopes[0].insert(name, binding);
+        internal
+    }
+
+    /// 在新的块作用域中执行 `f`，结束后其中声明的变量离开作用域
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = f(self);
+        let scope = self.scopes.pop().unwrap();
+        self.ended.extend(scope);
+        result
+    }
+
+    /// 读取变量。没有声明过的名字：非严格模式下隐式声明（以前的行为，读到 0）；
+    /// 严格模式下保留原名，由类型检查报告未定义
+    fn read(&mut self, name: String, span: Span) -> String {
+        if let Some(binding) = self.lookup(&name) {
+            return binding.name.clone();
+        }
+        if let Some(binding) = self.ended.get(&name) {
+            let internal = binding.name.clone();
+            self.errors.push(TypeError::OutOfScope {
+                name,
+                span,
+                declared_at: binding.span,
+            });
+            return internal;
+        }
+        if self.strict {
+            self.used.insert(name.clone());
+            return name;
+        }
+        self.declare_in_function(name, span)
+    }
+
+    /// 给变量赋值 `x = e`：目标没有声明时隐式声明（严格模式下报错）
+    fn assign(&mut self, name: String, span: Span) -> String {
+        if let Some(binding) = self.lookup(&name) {
+            let internal = binding.name.clone();
+            self.check_mutable(&name, span);
+            return internal;
+        }
+        if self.strict {
+            self.errors.push(TypeError::ImplicitDeclaration {
+                name: name.clone(),
+                span,
+            });
+        }
+        self.declare_in_function(name, span)
+    }
+
+    /// 不可变的变量不能再赋值（也不能写入其中的元素或字段）
+    fn check_mutable(&mut self, name: &str, span: Span) {
+        if let Some(binding) = self.lookup(name)
+            && !binding.mutable
+        {
+            self.errors.push(TypeError::ImmutableAssign {
+                name: name.to_string(),
+                span,
+                declared_at: binding.span,
+            });
+        }
+    }
+
+    fn stmts(&mut self, stmts: Vec<Expr>) -> Vec<Expr> {
+        stmts.into_iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn boxed(&mut self, mut expr: Box<Expr>) -> Box<Expr> {
+        *expr = self.expr(*expr);
+        expr
+    }
+
+    fn binary(
+        &mut self,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        make: fn(Box<Expr>, Box<Expr>) -> ExprKind,
+    ) -> ExprKind {
+        let lhs = self.boxed(lhs);
+        let rhs = self.boxed(rhs);
+        make(lhs, rhs)
+    }
+
+    /// 模式中的绑定在当前（分支的）作用域中声明为新变量
+    fn pattern(&mut self, pattern: Pattern, span: Span) -> Pattern {
+        match pattern {
+            Pattern::Binding(name) => Pattern::Binding(self.declare(name, true, span)),
+            Pattern::Variant(name, variant, fields) => Pattern::Variant(
+                name,
+                variant,
+                fields.into_iter().map(|p| self.pattern(p, span)).collect(),
+            ),
+            other => other,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let span = expr.span;
+        let kind = match expr.kind {
+            ExprKind::Identifier(name) => ExprKind::Identifier(self.read(name, span)),
+            // 右边先解析：`let x = x + 1` 中右边的 x 是之前的变量
+            ExprKind::Let(name, mutable, ty, value) => {
+                let value = self.boxed(value);
+                let ty = ty.and_then(|ty| self.resolve_type(ty, span));
+                ExprKind::Let(self.declare(name, mutable, span), mutable, ty, value)
+            }
+            ExprKind::Assign(name, value) => {
+                let value = self.boxed(value);
+                ExprKind::Assign(self.assign(name, span), value)
+            }
+            ExprKind::CompoundAssign(name, op, value) => {
+                let value = self.boxed(value);
+                self.check_mutable(&name, span);
+                ExprKind::CompoundAssign(self.read(name, span), op, value)
+            }
+            ExprKind::IndexAssign(base, idx, op, value) => {
+                if let Some(root) = place_root(&base) {
+                    self.check_mutable(root, span);
+                }
+                let value = self.boxed(value);
+                let idx = self.boxed(idx);
+                ExprKind::IndexAssign(self.boxed(base), idx, op, value)
+            }
+            ExprKind::FieldAssign(base, field, op, value) => {
+                if let Some(root) = place_root(&base) {
+                    self.check_mutable(root, span);
+                }
+                let value = self.boxed(value);
+                ExprKind::FieldAssign(self.boxed(base), field, op, value)
+            }
+            // drop(p.items) 只改写开头的变量名
+            ExprKind::Drop(path) => {
+                let (root, fields) = match path.split_once('.') {
+                    Some((root, fields)) => (root.to_string(), format!(".{}", fields)),
+                    None => (path, String::new()),
+                };
+                ExprKind::Drop(format!("{}{}", self.read(root, span), fields))
+            }
+            ExprKind::Block(body) => ExprKind::Block(self.scoped(|r| r.stmts(body))),
+            ExprKind::IfElse(cond, then_body, else_body) => {
+                let cond = self.boxed(cond);
+                let then_body = self.scoped(|r| r.stmts(then_body));
+                let else_body = self.scoped(|r| r.stmts(else_body));
+                ExprKind::IfElse(cond, then_body, else_body)
+            }
+            ExprKind::WhileLoop(cond, body) => {
+                let cond = self.boxed(cond);
+                ExprKind::WhileLoop(cond, self.scoped(|r| r.stmts(body)))
+            }
+            // 区间与被遍历的数组在循环之外求值，循环变量属于循环体
+            ExprKind::ForRange(var, start, end, inclusive, step, body) => {
+                let start = self.boxed(start);
+                let end = self.boxed(end);
+                let step = step.map(|s| self.boxed(s));
+                let (var, body) = self.scoped(|r| (r.declare(var, true, span), r.stmts(body)));
+                ExprKind::ForRange(var, start, end, inclusive, step, body)
+            }
+            ExprKind::ForEach(var, iterable, body) => {
+                let iterable = self.boxed(iterable);
+                let (var, body) = self.scoped(|r| (r.declare(var, true, span), r.stmts(body)));
+                ExprKind::ForEach(var, iterable, body)
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                let scrutinee = self.boxed(scrutinee);
+                let arms = arms
+                    .into_iter()
+                    .map(|arm| {
+                        self.scoped(|r| MatchArm {
+                            pattern: r.pattern(arm.pattern, arm.span),
+                            body: r.stmts(arm.body),
+                            span: arm.span,
+                        })
+                    })
+                    .collect();
+                ExprKind::Match(scrutinee, arms)
+            }
+            ExprKind::Eq(l, r) => self.binary(l, r, ExprKind::Eq),
+            ExprKind::Ne(l, r) => self.binary(l, r, ExprKind::Ne),
+            ExprKind::Lt(l, r) => self.binary(l, r, ExprKind::Lt),
+            ExprKind::Le(l, r) => self.binary(l, r, ExprKind::Le),
+            ExprKind::Gt(l, r) => self.binary(l, r, ExprKind::Gt),
+            ExprKind::Ge(l, r) => self.binary(l, r, ExprKind::Ge),
+            ExprKind::And(l, r) => self.binary(l, r, ExprKind::And),
+            ExprKind::Or(l, r) => self.binary(l, r, ExprKind::Or),
+            ExprKind::Add(l, r) => self.binary(l, r, ExprKind::Add),
+            ExprKind::Sub(l, r) => self.binary(l, r, ExprKind::Sub),
+            ExprKind::Mul(l, r) => self.binary(l, r, ExprKind::Mul),
+            ExprKind::Div(l, r) => self.binary(l, r, ExprKind::Div),
+            ExprKind::Rem(l, r) => self.binary(l, r, ExprKind::Rem),
+            ExprKind::BitAnd(l, r) => self.binary(l, r, ExprKind::BitAnd),
+            ExprKind::BitOr(l, r) => self.binary(l, r, ExprKind::BitOr),
+            ExprKind::BitXor(l, r) => self.binary(l, r, ExprKind::BitXor),
+            ExprKind::Shl(l, r) => self.binary(l, r, ExprKind::Shl),
+            ExprKind::Shr(l, r) => self.binary(l, r, ExprKind::Shr),
+            ExprKind::Index(base, idx) => self.binary(base, idx, ExprKind::Index),
+            ExprKind::Not(inner) => ExprKind::Not(self.boxed(inner)),
+            ExprKind::Neg(inner) => ExprKind::Neg(self.boxed(inner)),
+            ExprKind::Return(inner) => ExprKind::Return(self.boxed(inner)),
+            ExprKind::Cast(inner, ty) => ExprKind::Cast(self.boxed(inner), ty),
+            ExprKind::Field(base, field) => ExprKind::Field(self.boxed(base), field),
+            ExprKind::Call(name, args) => ExprKind::Call(name, self.stmts(args)),
+            ExprKind::ArrayLiteral(elems, ty) => ExprKind::ArrayLiteral(self.stmts(elems), ty),
+            ExprKind::DynamicArrayLiteral(elems, ty) => {
+                ExprKind::DynamicArrayLiteral(self.stmts(elems), ty)
+            }
+            ExprKind::StructLiteral(name, fields) => ExprKind::StructLiteral(
+                name,
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (field, self.expr(value)))
+                    .collect(),
+            ),
+            ExprKind::EnumLiteral(name, variant, args) => {
+                ExprKind::EnumLiteral(name, variant, self.stmts(args))
+            }
+            kind @ (ExprKind::Literal(..)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::ComplexLiteral(..)
+            | ExprKind::GlobalDataAddr(_)
+            | ExprKind::Break
+            | ExprKind::Continue) => kind,
+        };
+        Expr::new(kind, span)
+    }
+}
+
+/// 赋值目标 `a[i].x` 开头的变量名
+fn place_root(expr: &Expr) -> Option<&str> {
+    match &expr.kind {
+        ExprKind::Identifier(name) => Some(name),
+        ExprKind::Index(base, _) | ExprKind::Field(base, _) => place_root(base),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(code: &str, strict: bool) -> (Vec<Expr>, Vec<TypeError>) {
+        let (_name, params, the_return, stmts) = crate::frontend::parser::function(code).unwrap();
+        resolve_function(&params, &the_return, stmts, &HashMap::new(), strict)
+    }
+
+    /// 按出现顺序收集语句中声明或读取的名字（只处理测试用到的语句形式）
+    fn names(stmts: &[Expr], out: &mut Vec<String>) {
+        for stmt in stmts {
+            match &stmt.kind {
+                ExprKind::Let(name, _, _, value) | ExprKind::Assign(name, value) => {
+                    names(std::slice::from_ref(value), out);
+                    out.push(name.clone());
+                }
+                ExprKind::Identifier(name) => out.push(name.clone()),
+                ExprKind::Call(_, args) => names(args, out),
+                ExprKind::Block(body) => names(body, out),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_shadowing_renames_inner_variable() {
+        let code = r#"
+fn f() -> (r: i64) {
+    let x = 1
+    {
+        let x = 2.5
+        print_f64(x)
+    }
+    r = x
+}
+"#;
+        let (stmts, errors) = resolve(code, false);
+        assert!(errors.is_empty(), "got {:?}", errors);
+        let mut found = Vec::new();
+        names(&stmts, &mut found);
+        assert_eq!(found, vec!["x", "x#1", "x#1", "x", "r"]);
+    }
+
+    #[test]
+    fn test_source_name() {
+        assert_eq!(source_name("x#12"), "x");
+        assert_eq!(source_name("p#2.items"), "p.items");
+        assert_eq!(source_name("plain"), "plain");
+    }
+
+    #[test]
+    fn test_out_of_scope_and_immutable() {
+        let code = r#"
+fn f() -> (r: i64) {
+    let a = 1
+    a = 2
+    {
+        let b = 3
+    }
+    r = b
+}
+"#;
+        let (_, errors) = resolve(code, false);
+        assert!(
+            matches!(
+                errors.as_slice(),
+                [
+                    TypeError::ImmutableAssign { name: a, .. },
+                    TypeError::OutOfScope { name: b, .. },
+                ] if a == "a" && b == "b"
+            ),
+            "got {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_implicit_declaration() {
+        let code = r#"
+fn f() -> (r: i64) {
+    let mut a = 1
+    a = 2
+    c = a
+    r = c
+}
+"#;
+        let (_, errors) = resolve(code, false);
+        assert!(errors.is_empty(), "got {:?}", errors);
+        let (_, errors) = resolve(code, true);
+        assert!(
+            matches!(
+                errors.as_slice(),
+                [TypeError::ImplicitDeclaration { name, .. }] if name == "c"
+            ),
+            "got {:?}",
+            errors
+        );
+    }
+}
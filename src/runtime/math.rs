@@ -26,6 +26,24 @@ pub extern "C" fn toy_pow(base: c_double, exp: c_double) -> c_double {
     base.powf(exp)
 }
 
+/// 与 C 的 fmod 相同：结果与被除数同号，也是浮点 `%` 的实现
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_fmod(x: c_double, y: c_double) -> c_double {
+    x % y
+}
+
+/// i128 的 `/`：x64 后端没有 128 位除法指令。除数为 0 和 `MIN / -1` 已在调用前 trap
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_i128_div(x: i128, y: i128) -> i128 {
+    x.wrapping_div(y)
+}
+
+/// i128 的 `%`，结果与被除数同号；`MIN % -1` 为 0
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_i128_rem(x: i128, y: i128) -> i128 {
+    x.wrapping_rem(y)
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn toy_exp(x: c_double) -> c_double {
     x.exp()
@@ -18,6 +18,7 @@ pub fn register_builtins(builder: &mut JITBuilder) {
     // Register basic IO and runtime functions
     builder.symbol("printf", string::printf as *const u8);
     builder.symbol("puts", string::puts as *const u8);
+    builder.symbol("str_len", string::toy_str_len as *const u8);
     builder.symbol("putchar", io::toy_putchar as *const u8);
     builder.symbol("rand", io::toy_rand as *const u8);
     builder.symbol("toy_sum_array", io::toy_sum_array as *const u8);
@@ -90,6 +91,11 @@ pub fn register_builtins(builder: &mut JITBuilder) {
     builder.symbol("tan", math::toy_tan as *const u8);
     builder.symbol("sqrt", math::toy_sqrt as *const u8);
     builder.symbol("pow", math::toy_pow as *const u8);
+    // 浮点 `%` 的实现，不作为内置函数暴露，用户定义的 fmod 不会与之冲突
+    builder.symbol("toy_fmod", math::toy_fmod as *const u8);
+    // i128 的 `/` 与 `%`，同样只供代码生成使用
+    builder.symbol("toy_i128_div", math::toy_i128_div as *const u8);
+    builder.symbol("toy_i128_rem", math::toy_i128_rem as *const u8);
     builder.symbol("exp", math::toy_exp as *const u8);
     builder.symbol("log", math::toy_log as *const u8);
     builder.symbol("ceil", math::toy_ceil as *const u8);
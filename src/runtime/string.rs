@@ -1,3 +1,71 @@
-// Currently no specific string functions, but could include wrappers for libc
-// or toy-specific string manipulation.
+//! toy 字符串的内存布局：
+//!
+//! ```text
+//! [字节长度: i64][UTF-8 内容 ...][0]
+//!                ^ 字符串的值指向这里
+//! ```
+//!
+//! 字符串的值是指向内容首字节的指针，内容以 NUL 结尾，可以直接传给 `printf` / `puts`；
+//! 内容之前的 8 字节记录字节长度（不含结尾的 NUL，内容中可以有 `\0`），`str_len` 直接读取。
+//! 字面量由 `translate_string_literal` 按这个布局写入数据段，宿主传入的字符串用 `ToyString` 构造。
+#![allow(clippy::missing_safety_doc)]
+use std::ffi::c_char;
+use std::slice;
+
 pub use libc::{printf, puts};
+
+/// 长度头的字节数
+pub const HEADER_SIZE: usize = 8;
+
+/// 按字符串布局编码：长度头 + 内容 + NUL
+pub fn encode(content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + content.len() + 1);
+    bytes.extend_from_slice(&(content.len() as i64).to_ne_bytes());
+    bytes.extend_from_slice(content);
+    bytes.push(0);
+    bytes
+}
+
+/// `str_len(s)`：字符串的字节数
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn toy_str_len(s: *const c_char) -> i64 {
+    if s.is_null() {
+        return 0;
+    }
+    unsafe {
+        s.cast::<u8>()
+            .sub(HEADER_SIZE)
+            .cast::<i64>()
+            .read_unaligned()
+    }
+}
+
+/// 字符串的内容（不含结尾的 NUL）
+pub unsafe fn content<'a>(s: *const c_char) -> &'a [u8] {
+    let len = unsafe { toy_str_len(s) } as usize;
+    if len == 0 {
+        return &[];
+    }
+    unsafe { slice::from_raw_parts(s.cast::<u8>(), len) }
+}
+
+/// 宿主构造、传给 toy 函数的字符串，布局与字面量相同
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToyString(Vec<u8>);
+
+impl ToyString {
+    pub fn new(s: &str) -> Self {
+        ToyString(encode(s.as_bytes()))
+    }
+
+    /// 指向内容首字节的指针，即 toy 中字符串的值
+    pub fn as_ptr(&self) -> *const c_char {
+        self.0[HEADER_SIZE..].as_ptr().cast()
+    }
+}
+
+impl From<&str> for ToyString {
+    fn from(s: &str) -> Self {
+        ToyString::new(s)
+    }
+}
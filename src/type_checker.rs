@@ -1,5 +1,10 @@
-use crate::frontend::{Expr, Type};
-use std::collections::HashMap;
+use crate::diagnostic::Diagnostic;
+use crate::frontend::{
+    AssignOp, EnumDef, Expr, ExprKind, GlobalDef, GlobalKind, MatchArm, Pattern, Span, StructDef,
+    Type,
+};
+use crate::resolver::{self, source_name};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Debug)]
 pub struct FunctionSignature {
@@ -8,8 +13,56 @@ pub struct FunctionSignature {
     pub is_external: bool,
 }
 
+/// 显示为 `(f64, f64) -> f64`
+impl std::fmt::Display for FunctionSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|t| t.to_string()).collect();
+        write!(f, "({}) -> {}", params.join(", "), self.ret)
+    }
+}
+
+/// 结构体的 C 兼容内存布局：字段按声明顺序、各自按自然对齐放置，
+/// 整体大小向上取整到最大的字段对齐
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructLayout {
+    pub size: u32,
+    pub align: u32,
+    /// (字段名, 类型, 相对结构体起始的字节偏移)
+    pub fields: Vec<(String, Type, u32)>,
+}
+
+impl StructLayout {
+    /// 字段的 (偏移, 类型)
+    pub fn field(&self, name: &str) -> Option<(u32, &Type)> {
+        self.fields
+            .iter()
+            .find(|(n, ..)| n == name)
+            .map(|(_, ty, offset)| (*offset, ty))
+    }
+}
+
+/// 枚举的内存布局，与 `#[repr(C)]` 的 Rust 枚举相同：偏移 0 处是 i32 标签（变体序号），
+/// 之后是各变体载荷的联合体，每个变体的载荷按结构体的规则排列
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumLayout {
+    pub size: u32,
+    pub align: u32,
+    /// 每个变体各载荷字段相对枚举起始的字节偏移
+    pub payloads: Vec<Vec<u32>>,
+}
+
+/// 枚举标签（i32）的字节数
+pub const ENUM_TAG_SIZE: u32 = 4;
+
+#[derive(Clone)]
 pub struct TypeChecker {
     pub functions: HashMap<String, FunctionSignature>,
+    /// 结构体声明：名字 → 声明
+    pub structs: HashMap<String, StructDef>,
+    /// 枚举声明：名字 → 声明
+    pub enums: HashMap<String, EnumDef>,
+    /// 常量与静态变量：名字 → 声明，初值已折叠为声明类型的字面量
+    pub globals: HashMap<String, GlobalDef>,
 }
 
 impl Default for TypeChecker {
@@ -22,6 +75,9 @@ impl TypeChecker {
     pub fn new() -> Self {
         let mut tc = TypeChecker {
             functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            globals: HashMap::new(),
         };
         tc.register_builtins();
         tc
@@ -94,6 +150,16 @@ impl TypeChecker {
             },
         );
 
+        // 字符串的字节数（读取字面量之前的长度头，内容中的 \0 也计入）
+        self.functions.insert(
+            "str_len".to_string(),
+            FunctionSignature {
+                params: vec![Type::String],
+                ret: Type::I64,
+                is_external: true,
+            },
+        );
+
         // DynamicArray methods
         self.functions.insert(
             "array_push".to_string(),
@@ -140,6 +206,15 @@ impl TypeChecker {
             },
         );
 
+        self.functions.insert(
+            "array_new_i64".to_string(),
+            FunctionSignature {
+                params: vec![],
+                ret: Type::DynamicArray(Box::new(Type::I64)),
+                is_external: true,
+            },
+        );
+
         // F64 DynamicArray methods
         self.functions.insert(
             "array_new_f64".to_string(),
@@ -262,6 +337,7 @@ impl TypeChecker {
         // or ensure the caller passes arrays which will be expanded.
         // The toy language expands arrays to (ptr, len) automatically for external calls.
         // So signature here should use Array type if we want that expansion logic to trigger.
+        #[cfg(feature = "mkl")]
         self.functions.insert(
             "toy_mkl_dgemm".to_string(),
             FunctionSignature {
@@ -284,45 +360,2095 @@ impl TypeChecker {
     pub fn resolve_func(&self, name: &str) -> Option<&FunctionSignature> {
         self.functions.get(name)
     }
+
+    /// Register a user-defined (JIT-compiled) function signature.
+    /// Re-registering a name replaces the previous signature.
+    pub fn register_function(&mut self, name: &str, params: Vec<Type>, ret: Type) {
+        self.functions.insert(
+            name.to_string(),
+            FunctionSignature {
+                params,
+                ret,
+                is_external: false,
+            },
+        );
+    }
+
+    /// 登记一个结构体声明，同名的旧声明（结构体或枚举）被替换
+    pub fn register_struct(&mut self, def: StructDef) {
+        self.enums.remove(&def.name);
+        self.structs.insert(def.name.clone(), def);
+    }
+
+    /// 登记一个枚举声明，同名的旧声明（结构体或枚举）被替换
+    pub fn register_enum(&mut self, def: EnumDef) {
+        self.structs.remove(&def.name);
+        self.enums.insert(def.name.clone(), def);
+    }
+
+    /// 登记一个常量或静态变量，同名的旧定义被替换。`def.value` 应是 `check_global` 返回的字面量
+    pub fn register_global(&mut self, def: GlobalDef) {
+        self.globals.insert(def.name.clone(), def);
+    }
+
+    /// 检查常量或静态变量的声明：类型必须是 bool、整数或浮点，`folded`（做过常量折叠的初值）
+    /// 必须是能隐式转换为该类型、且在其范围内的字面量。返回按声明类型重写的字面量
+    pub fn check_global(&self, def: &GlobalDef, folded: &Expr) -> Result<Expr, TypeError> {
+        let ty = &def.ty;
+        if !(is_integer(ty) || is_float(ty) || *ty == Type::Bool) {
+            return Err(TypeError::UnsupportedGlobalType {
+                name: def.name.clone(),
+                ty: ty.clone(),
+                span: def.span,
+            });
+        }
+        // 折叠后的负数是带 '-' 的字面量，带类型后缀的负数仍是 Neg(字面量)
+        let (text, negative) = match &folded.kind {
+            ExprKind::Literal(text, _) => match text.strip_prefix('-') {
+                Some(abs) => (abs, true),
+                None => (text.as_str(), false),
+            },
+            ExprKind::Neg(inner) => match &inner.kind {
+                ExprKind::Literal(text, _) => (text.as_str(), true),
+                _ => ("", false),
+            },
+            _ => ("", false),
+        };
+        if text.is_empty() {
+            return Err(TypeError::NonConstantInitializer {
+                name: def.name.clone(),
+                span: def.value.span,
+            });
+        }
+        let found = self.infer_type(folded, &|_| None);
+        if !is_arg_compatible(folded, &found, ty) {
+            return Err(TypeError::AssignTypeMismatch {
+                name: def.name.clone(),
+                expected: ty.clone(),
+                found,
+                span: def.value.span,
+            });
+        }
+        if !literal_in_range(text, ty, negative) {
+            return Err(TypeError::LiteralOutOfRange {
+                ty: ty.clone(),
+                span: def.value.span,
+            });
+        }
+        let text = if negative {
+            format!("-{}", text)
+        } else {
+            text.to_string()
+        };
+        Ok(Expr::new(
+            ExprKind::Literal(text, ty.clone()),
+            def.value.span,
+        ))
+    }
+
+    /// 全部常量的值（字面量），常量折叠用它替换常量的使用处
+    pub fn constants(&self) -> HashMap<String, Expr> {
+        self.globals
+            .values()
+            .filter(|def| def.kind == GlobalKind::Const)
+            .map(|def| (def.name.clone(), def.value.clone()))
+            .collect()
+    }
+
+    /// 检查结构体声明：字段不重名、字段类型都已定义、没有按值包含自身的结构体。
+    /// `defs` 必须已经全部登记，字段可以引用之后才声明的结构体
+    pub fn check_structs(&self, defs: &[StructDef]) -> Vec<TypeError> {
+        let mut errors = Vec::new();
+        for def in defs {
+            for (i, (field, ty)) in def.fields.iter().enumerate() {
+                if def.fields[..i].iter().any(|(f, _)| f == field) {
+                    errors.push(TypeError::DuplicateField {
+                        field: field.clone(),
+                        span: def.span,
+                    });
+                }
+                if let Some(name) = self.undefined_type(ty) {
+                    errors.push(TypeError::UndefinedType {
+                        name,
+                        span: def.span,
+                    });
+                }
+            }
+            if self.is_recursive(&def.name) {
+                errors.push(TypeError::RecursiveStruct {
+                    name: def.name.clone(),
+                    span: def.span,
+                });
+            }
+        }
+        errors
+    }
+
+    /// 检查枚举声明：变体不重名、载荷类型都已定义且不含动态数组、没有按值包含自身。
+    /// 枚举按位复制，不跟踪所有权，所以载荷中不能有需要释放的动态数组
+    pub fn check_enums(&self, defs: &[EnumDef]) -> Vec<TypeError> {
+        let mut errors = Vec::new();
+        for def in defs {
+            for (i, (variant, payload)) in def.variants.iter().enumerate() {
+                if def.variants[..i].iter().any(|(v, _)| v == variant) {
+                    errors.push(TypeError::DuplicateVariant {
+                        variant: variant.clone(),
+                        span: def.span,
+                    });
+                }
+                for ty in payload {
+                    if let Some(name) = self.undefined_type(ty) {
+                        errors.push(TypeError::UndefinedType {
+                            name,
+                            span: def.span,
+                        });
+                    } else if self.contains_dynamic_array(ty, &mut Vec::new()) {
+                        errors.push(TypeError::OwnedPayload {
+                            variant: format!("{}::{}", def.name, variant),
+                            span: def.span,
+                        });
+                    }
+                }
+            }
+            if self.is_recursive(&def.name) {
+                errors.push(TypeError::RecursiveStruct {
+                    name: def.name.clone(),
+                    span: def.span,
+                });
+            }
+        }
+        errors
+    }
+
+    /// 结构体的字段类型或枚举的全部载荷类型
+    fn member_types(&self, name: &str) -> Vec<&Type> {
+        match (self.structs.get(name), self.enums.get(name)) {
+            (Some(def), _) => def.fields.iter().map(|(_, ty)| ty).collect(),
+            (None, Some(def)) => def.variants.iter().flat_map(|(_, tys)| tys).collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// 结构体或枚举是否按值包含自身
+    fn is_recursive(&self, name: &str) -> bool {
+        let mut visiting = vec![name.to_string()];
+        self.member_types(name)
+            .into_iter()
+            .any(|ty| self.contains_struct(ty, name, &mut visiting))
+    }
+
+    /// `ty` 是否按值包含动态数组（含嵌套结构体、枚举的成员与定长数组元素）
+    fn contains_dynamic_array(&self, ty: &Type, visiting: &mut Vec<String>) -> bool {
+        match ty {
+            Type::DynamicArray(_) => true,
+            Type::Array(elem, _) => self.contains_dynamic_array(elem, visiting),
+            Type::Struct(s) if !visiting.contains(s) => {
+                visiting.push(s.clone());
+                self.member_types(s)
+                    .into_iter()
+                    .any(|t| self.contains_dynamic_array(t, visiting))
+            }
+            _ => false,
+        }
+    }
+
+    /// 类型中第一个没有声明的结构体或枚举名
+    pub fn undefined_type(&self, ty: &Type) -> Option<String> {
+        match ty {
+            Type::Struct(name)
+                if !self.structs.contains_key(name) && !self.enums.contains_key(name) =>
+            {
+                Some(name.clone())
+            }
+            Type::Array(elem, _) | Type::ConstLenArray(elem, _) | Type::DynamicArray(elem) => {
+                self.undefined_type(elem)
+            }
+            _ => None,
+        }
+    }
+
+    /// `ty` 的字段（含嵌套结构体、枚举载荷与定长数组元素，不含动态数组元素）
+    /// 是否按值包含结构体或枚举 `name`
+    fn contains_struct(&self, ty: &Type, name: &str, visiting: &mut Vec<String>) -> bool {
+        match ty {
+            Type::Array(elem, _) => self.contains_struct(elem, name, visiting),
+            Type::Struct(s) if s == name => true,
+            Type::Struct(s) if !visiting.contains(s) => {
+                visiting.push(s.clone());
+                self.member_types(s)
+                    .into_iter()
+                    .any(|t| self.contains_struct(t, name, visiting))
+            }
+            _ => false,
+        }
+    }
+
+    /// 结构体的内存布局（未声明的结构体返回 None）。
+    /// 与 C 相同：字段按自身对齐放置，整体大小是最大对齐的整数倍
+    pub fn struct_layout(&self, name: &str) -> Option<StructLayout> {
+        let def = self.structs.get(name)?;
+        let (offsets, size, align) = self.c_layout(def.fields.iter().map(|(_, ty)| ty));
+        let fields = def
+            .fields
+            .iter()
+            .zip(offsets)
+            .map(|((field, ty), offset)| (field.clone(), ty.clone(), offset))
+            .collect();
+        Some(StructLayout {
+            size,
+            align,
+            fields,
+        })
+    }
+
+    /// 按 C 规则依次排列一组成员，返回 (各成员偏移, 总大小, 对齐)
+    fn c_layout<'t>(&self, types: impl Iterator<Item = &'t Type>) -> (Vec<u32>, u32, u32) {
+        let mut offsets = Vec::new();
+        let (mut size, mut align) = (0u32, 1);
+        for ty in types {
+            let member_align = self.align_of(ty);
+            let offset = size.next_multiple_of(member_align);
+            offsets.push(offset);
+            size = offset + self.size_of(ty);
+            align = align.max(member_align);
+        }
+        (offsets, size.next_multiple_of(align), align)
+    }
+
+    /// 枚举的内存布局（未声明的枚举返回 None）。标签是 4 字节的 i32，
+    /// 载荷联合体放在标签之后、按所有变体中最大的对齐放置
+    pub fn enum_layout(&self, name: &str) -> Option<EnumLayout> {
+        let def = self.enums.get(name)?;
+        let (mut union_size, mut union_align) = (0u32, 1);
+        let mut payloads = Vec::with_capacity(def.variants.len());
+        for (_, tys) in &def.variants {
+            let (offsets, size, align) = self.c_layout(tys.iter());
+            union_size = union_size.max(size);
+            union_align = union_align.max(align);
+            payloads.push(offsets);
+        }
+        let union_offset = ENUM_TAG_SIZE.next_multiple_of(union_align);
+        for offset in payloads.iter_mut().flatten() {
+            *offset += union_offset;
+        }
+        let align = union_align.max(ENUM_TAG_SIZE);
+        Some(EnumLayout {
+            size: (union_offset + union_size).next_multiple_of(align),
+            align,
+            payloads,
+        })
+    }
+
+    /// 值在内存中占用的字节数。结构体（以及结构体字段中的定长数组）按值内嵌，
+    /// 字符串与动态数组是指针
+    pub fn size_of(&self, ty: &Type) -> u32 {
+        match ty {
+            Type::Bool | Type::I8 => 1,
+            Type::I16 => 2,
+            Type::I32 | Type::F32 => 4,
+            Type::I64 | Type::F64 | Type::String | Type::DynamicArray(_) => 8,
+            Type::Complex64 => 8,
+            Type::I128 | Type::Complex128 => 16,
+            Type::Array(elem, len) => self.element_size(elem) * *len as u32,
+            Type::Struct(name) => match self.struct_layout(name) {
+                Some(layout) => layout.size,
+                None => self.enum_layout(name).map_or(0, |l| l.size),
+            },
+            // 长度不是常量，已报告 ArrayLengthNotConst
+            Type::ConstLenArray(..) => 0,
+        }
+    }
+
+    /// 定长数组中元素的步长：结构体元素按值内嵌，嵌套的定长数组存的是指针
+    pub fn element_size(&self, elem: &Type) -> u32 {
+        match elem {
+            Type::Array(..) => 8,
+            _ => self.size_of(elem),
+        }
+    }
+
+    /// 值在内存中的对齐要求，与 C 的对应类型一致（complex64 即 `float _Complex`，按 4 字节对齐）
+    pub fn align_of(&self, ty: &Type) -> u32 {
+        match ty {
+            Type::Complex64 => 4,
+            Type::Complex128 => 8,
+            Type::Array(elem, _) if matches!(**elem, Type::Array(..)) => 8,
+            Type::Array(elem, _) => self.align_of(elem),
+            Type::Struct(name) => match self.struct_layout(name) {
+                Some(layout) => layout.align,
+                None => self.enum_layout(name).map_or(1, |l| l.align),
+            },
+            _ => self.size_of(ty),
+        }
+    }
+
+    /// 结构体类型 `ty` 中字段 `field` 的类型；`ty` 不是结构体或没有该字段时返回 None
+    pub fn field_type(&self, ty: &Type, field: &str) -> Option<Type> {
+        let Type::Struct(name) = ty else {
+            return None;
+        };
+        self.structs
+            .get(name)?
+            .fields
+            .iter()
+            .find(|(f, _)| f == field)
+            .map(|(_, t)| t.clone())
+    }
+
+    /// `pattern` 绑定的变量及其类型，`ty` 是被匹配值的类型。
+    /// 变体模式中的绑定取对应载荷的类型，其余绑定取被匹配值的类型
+    pub fn pattern_bindings(&self, ty: &Type, pattern: &Pattern) -> Vec<(String, Type)> {
+        match pattern {
+            Pattern::Binding(name) => vec![(name.clone(), ty.clone())],
+            Pattern::Variant(name, variant, fields) => {
+                let payload = self
+                    .enums
+                    .get(name)
+                    .and_then(|def| def.variant(variant))
+                    .map_or(&[][..], |(_, tys)| tys);
+                fields
+                    .iter()
+                    .zip(payload)
+                    .filter_map(|(field, ty)| match field {
+                        Pattern::Binding(name) => Some((name.clone(), ty.clone())),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Infer type of expression, using the declared return type of
+    /// registered user functions for calls.
+    /// 不在变量表中的名字按常量与静态变量查找
+    pub fn infer_type(&self, expr: &Expr, get_var_type: &impl Fn(&str) -> Option<Type>) -> Type {
+        infer_type_with(
+            expr,
+            &|name| get_var_type(name).or_else(|| self.globals.get(name).map(|def| def.ty.clone())),
+            &|name| {
+                self.functions
+                    .get(name)
+                    .filter(|sig| !sig.is_external)
+                    .map(|sig| sig.ret.clone())
+            },
+            &|ty, field| self.field_type(ty, field),
+        )
+    }
+
+    /// 静态类型检查：遍历函数体中的每个表达式，返回发现的所有类型错误。
+    ///
+    /// 变量表的建立方式与 JIT 的 `declare_variables` 一致：参数、返回变量、`let` 声明的变量，
+    /// 以及按先序遍历第一次赋值推断出类型的隐式变量。`stmts` 必须已经过名字解析
+    /// （`resolver::resolve_function`），同名的不同变量已改写为不同的名字。
+    pub fn check_function(
+        &self,
+        params: &[(String, Type)],
+        the_return: &(String, Type),
+        stmts: &[Expr],
+    ) -> Vec<TypeError> {
+        let vars = self.var_types(params, the_return, stmts);
+        // 静态变量与 let 声明的变量一样，只接受能隐式转换为其类型的值
+        let declared = self
+            .globals
+            .keys()
+            .filter(|name| !params.iter().chain([the_return]).any(|(n, _)| n == *name))
+            .cloned()
+            .collect();
+        let mut checker = FunctionCheck {
+            tc: self,
+            vars,
+            the_return,
+            errors: Vec::new(),
+            loop_depth: 0,
+            declared,
+            statement: false,
+        };
+        // 函数头没有记录位置，签名中未声明的结构体报在文件开头
+        for ty in params.iter().chain([the_return]).map(|(_, ty)| ty) {
+            if let Some(name) = self.undefined_type(ty) {
+                checker.errors.push(TypeError::UndefinedType {
+                    name,
+                    span: Span::default(),
+                });
+            }
+        }
+        checker.check_body(stmts, false);
+        checker.errors
+    }
+
+    /// for 循环变量的类型：区间取起点的类型，遍历数组取元素类型
+    pub fn loop_var_type(&self, expr: &Expr, get_var_type: &impl Fn(&str) -> Option<Type>) -> Type {
+        match &expr.kind {
+            ExprKind::ForRange(_, start, ..) => self.infer_type(start, get_var_type),
+            ExprKind::ForEach(_, iterable, _) => {
+                element_type(&self.infer_type(iterable, get_var_type)).unwrap_or(Type::I64)
+            }
+            _ => Type::I64,
+        }
+    }
+
+    /// 函数中每个变量（参数、返回变量、`let` 声明与隐式声明的局部变量）以及可见的常量、
+    /// 静态变量的类型
+    pub fn var_types(
+        &self,
+        params: &[(String, Type)],
+        the_return: &(String, Type),
+        stmts: &[Expr],
+    ) -> HashMap<String, Type> {
+        let mut vars: HashMap<String, Type> = params.iter().cloned().collect();
+        vars.entry(the_return.0.clone())
+            .or_insert_with(|| the_return.1.clone());
+        // 同名的参数与返回变量遮蔽常量和静态变量
+        for (name, def) in &self.globals {
+            vars.entry(name.clone()).or_insert_with(|| def.ty.clone());
+        }
+        for stmt in stmts {
+            self.collect_var_types(stmt, &mut vars);
+        }
+        vars
+    }
+
+    /// 结构体类型 `ty` 中所有动态数组字段的路径（含嵌套结构体的字段），
+    /// 以 `prefix` 开头，例如 `p` → [`p.items`, `p.inner.xs`]
+    pub fn array_field_paths(&self, prefix: &str, ty: &Type) -> Vec<String> {
+        let Type::Struct(name) = ty else {
+            return Vec::new();
+        };
+        let Some(def) = self.structs.get(name) else {
+            return Vec::new();
+        };
+        let mut paths = Vec::new();
+        for (field, field_ty) in &def.fields {
+            let path = format!("{}.{}", prefix, field);
+            match field_ty {
+                Type::DynamicArray(_) => paths.push(path),
+                Type::Struct(_) => paths.extend(self.array_field_paths(&path, field_ty)),
+                _ => {}
+            }
+        }
+        paths
+    }
+
+    /// 语句序列中最后一条语句的类型（空序列返回 None）。
+    /// 变量表与 `check_function` 的建立方式一致，但没有参数和返回变量
+    pub fn infer_result_type(&self, stmts: &[Expr]) -> Option<Type> {
+        // 与编译时一样先解析名字，被遮蔽的变量各有各的类型
+        let stmts = resolver::resolve_statements(stmts.to_vec(), &self.globals);
+        let mut vars = HashMap::new();
+        for stmt in &stmts {
+            self.collect_var_types(stmt, &mut vars);
+        }
+        let last = stmts.last()?;
+        Some(self.infer_type(last, &|n| vars.get(n).cloned()))
+    }
+
+    fn collect_var_types(&self, expr: &Expr, vars: &mut HashMap<String, Type>) {
+        match &expr.kind {
+            ExprKind::Assign(name, value) => {
+                // 右边先登记（例如 match 分支中的绑定），左边的类型可能依赖它们
+                self.collect_var_types(value, vars);
+                if !vars.contains_key(name) {
+                    let ty = self.infer_type(value, &|n| vars.get(n).cloned());
+                    vars.insert(name.clone(), ty);
+                }
+            }
+            // 有类型标注时取标注的类型
+            ExprKind::Let(name, _, ty, value) => {
+                self.collect_var_types(value, vars);
+                if !vars.contains_key(name) {
+                    let ty = match ty {
+                        Some(ty) => ty.clone(),
+                        None => self.infer_type(value, &|n| vars.get(n).cloned()),
+                    };
+                    vars.insert(name.clone(), ty);
+                }
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                let ty = self.infer_type(scrutinee, &|n| vars.get(n).cloned());
+                for arm in arms {
+                    for (name, binding_ty) in self.pattern_bindings(&ty, &arm.pattern) {
+                        vars.entry(name).or_insert(binding_ty);
+                    }
+                    for stmt in &arm.body {
+                        self.collect_var_types(stmt, vars);
+                    }
+                }
+            }
+            ExprKind::IfElse(_, then_body, else_body) => {
+                for stmt in then_body.iter().chain(else_body) {
+                    self.collect_var_types(stmt, vars);
+                }
+            }
+            ExprKind::WhileLoop(_, body) | ExprKind::Block(body) => {
+                for stmt in body {
+                    self.collect_var_types(stmt, vars);
+                }
+            }
+            ExprKind::ForRange(var, _, _, _, _, body) | ExprKind::ForEach(var, _, body) => {
+                if !vars.contains_key(var) {
+                    let ty = self.loop_var_type(expr, &|n| vars.get(n).cloned());
+                    vars.insert(var.clone(), ty);
+                }
+                for stmt in body {
+                    self.collect_var_types(stmt, vars);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 类型错误，`span` 指向出错的表达式
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeError {
+    /// 使用了从未定义（既不是参数也从未赋值）的变量
+    UndefinedVariable { name: String, span: Span },
+    /// 调用了未定义的函数
+    UndefinedFunction { name: String, span: Span },
+    /// 运算符的操作数类型不合法或互不兼容
+    InvalidOperands {
+        op: String,
+        lhs: Type,
+        rhs: Type,
+        span: Span,
+    },
+    /// 一元运算符的操作数类型不合法
+    InvalidOperand { op: String, ty: Type, span: Span },
+    /// 对非数组类型做索引
+    IndexNonArray { ty: Type, span: Span },
+    /// 索引不是整数
+    NonIntegerIndex { ty: Type, span: Span },
+    /// 实参个数错误
+    ArgumentCount {
+        func: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// 实参类型错误
+    ArgumentType {
+        func: String,
+        index: usize,
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// 返回变量被赋予与声明不同类型的值
+    ReturnTypeMismatch {
+        name: String,
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// `arr[i] = v` 中 v 的类型与数组元素类型不兼容
+    ElementTypeMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// 不支持的 `as` 转换
+    InvalidCast { from: Type, to: Type, span: Span },
+    /// if / while 的条件不是 bool
+    InvalidCondition { ty: Type, span: Span },
+    /// 作为值使用的 if / match 中，某个分支的值不能隐式转换为第一个分支的类型
+    BranchTypeMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// 值为结构体或枚举的 if 没有 else 分支，条件不成立时没有值可用
+    MissingElse { ty: Type, span: Span },
+    /// `break` / `continue` 不在任何循环内
+    JumpOutsideLoop { keyword: String, span: Span },
+    /// `for x in e` 中 e 不是数组
+    NotIterable { ty: Type, span: Span },
+    /// `for i in a..b step 0`：步长为 0 的循环不会结束
+    ZeroStep { span: Span },
+    /// 数字字面量超出其类型的取值范围
+    LiteralOutOfRange { ty: Type, span: Span },
+    /// 使用了没有声明的结构体
+    UndefinedType { name: String, span: Span },
+    /// 结构体没有这个字段（或对非结构体取字段）
+    UnknownField { ty: Type, field: String, span: Span },
+    /// 结构体字面量缺少字段
+    MissingFields {
+        name: String,
+        fields: Vec<String>,
+        span: Span,
+    },
+    /// 结构体声明或字面量中的字段重名
+    DuplicateField { field: String, span: Span },
+    /// 字段的值与字段类型不兼容
+    FieldTypeMismatch {
+        field: String,
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// 结构体按值包含自身，大小无限
+    RecursiveStruct { name: String, span: Span },
+    /// 同名的结构体或枚举声明了不止一次
+    DuplicateType { name: String, span: Span },
+    /// 结构体变量被赋予其他类型的值（或反之）
+    AssignTypeMismatch {
+        name: String,
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// 枚举没有这个变体
+    UnknownVariant {
+        name: String,
+        variant: String,
+        span: Span,
+    },
+    /// 枚举声明中的变体重名
+    DuplicateVariant { variant: String, span: Span },
+    /// 枚举值或变体模式的载荷个数与声明不符
+    VariantArity {
+        variant: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// 枚举载荷中含有动态数组
+    OwnedPayload { variant: String, span: Span },
+    /// 模式与被匹配值的类型不符，例如用整数匹配枚举
+    PatternTypeMismatch { ty: Type, span: Span },
+    /// match 的分支没有覆盖所有可能的值，`missing` 是未覆盖的变体或整数区间
+    NonExhaustiveMatch { missing: Vec<String>, span: Span },
+    /// 给没有 `mut` 的 `let` 变量赋值
+    ImmutableAssign {
+        name: String,
+        span: Span,
+        declared_at: Span,
+    },
+    /// 使用了已经离开作用域的变量
+    OutOfScope {
+        name: String,
+        span: Span,
+        declared_at: Span,
+    },
+    /// 严格模式下给没有用 `let` 声明的变量赋值
+    ImplicitDeclaration { name: String, span: Span },
+    /// 常量或静态变量的初值不是常量表达式
+    NonConstantInitializer { name: String, span: Span },
+    /// 常量或静态变量的类型不是 bool、整数或浮点
+    UnsupportedGlobalType { name: String, ty: Type, span: Span },
+    /// 同名的常量或静态变量声明了不止一次
+    DuplicateGlobal { name: String, span: Span },
+    /// `[T; N]` 中的 N 不是非负的整数常量
+    ArrayLengthNotConst { name: String, span: Span },
+}
+
+impl TypeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::UndefinedVariable { span, .. }
+            | TypeError::UndefinedFunction { span, .. }
+            | TypeError::InvalidOperands { span, .. }
+            | TypeError::InvalidOperand { span, .. }
+            | TypeError::IndexNonArray { span, .. }
+            | TypeError::NonIntegerIndex { span, .. }
+            | TypeError::ArgumentCount { span, .. }
+            | TypeError::ArgumentType { span, .. }
+            | TypeError::ReturnTypeMismatch { span, .. }
+            | TypeError::ElementTypeMismatch { span, .. }
+            | TypeError::InvalidCast { span, .. }
+            | TypeError::InvalidCondition { span, .. }
+            | TypeError::BranchTypeMismatch { span, .. }
+            | TypeError::MissingElse { span, .. }
+            | TypeError::JumpOutsideLoop { span, .. }
+            | TypeError::NotIterable { span, .. }
+            | TypeError::ZeroStep { span, .. }
+            | TypeError::LiteralOutOfRange { span, .. }
+            | TypeError::UndefinedType { span, .. }
+            | TypeError::UnknownField { span, .. }
+            | TypeError::MissingFields { span, .. }
+            | TypeError::DuplicateField { span, .. }
+            | TypeError::FieldTypeMismatch { span, .. }
+            | TypeError::RecursiveStruct { span, .. }
+            | TypeError::DuplicateType { span, .. }
+            | TypeError::AssignTypeMismatch { span, .. }
+            | TypeError::UnknownVariant { span, .. }
+            | TypeError::DuplicateVariant { span, .. }
+            | TypeError::VariantArity { span, .. }
+            | TypeError::OwnedPayload { span, .. }
+            | TypeError::PatternTypeMismatch { span, .. }
+            | TypeError::NonExhaustiveMatch { span, .. }
+            | TypeError::ImmutableAssign { span, .. }
+            | TypeError::OutOfScope { span, .. }
+            | TypeError::ImplicitDeclaration { span, .. }
+            | TypeError::NonConstantInitializer { span, .. }
+            | TypeError::UnsupportedGlobalType { span, .. }
+            | TypeError::DuplicateGlobal { span, .. }
+            | TypeError::ArrayLengthNotConst { span, .. } => *span,
+        }
+    }
+
+    /// 稳定的错误代码，诊断输出中用于按类别匹配
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::UndefinedVariable { .. } => "type::undefined_variable",
+            TypeError::UndefinedFunction { .. } => "type::undefined_function",
+            TypeError::InvalidOperands { .. } => "type::invalid_operands",
+            TypeError::InvalidOperand { .. } => "type::invalid_operand",
+            TypeError::IndexNonArray { .. } => "type::index_non_array",
+            TypeError::NonIntegerIndex { .. } => "type::non_integer_index",
+            TypeError::ArgumentCount { .. } => "type::argument_count",
+            TypeError::ArgumentType { .. } => "type::argument_type",
+            TypeError::ReturnTypeMismatch { .. } => "type::return_type_mismatch",
+            TypeError::ElementTypeMismatch { .. } => "type::element_type_mismatch",
+            TypeError::InvalidCast { .. } => "type::invalid_cast",
+            TypeError::InvalidCondition { .. } => "type::invalid_condition",
+            TypeError::BranchTypeMismatch { .. } => "type::branch_type_mismatch",
+            TypeError::MissingElse { .. } => "type::missing_else",
+            TypeError::JumpOutsideLoop { .. } => "type::jump_outside_loop",
+            TypeError::NotIterable { .. } => "type::not_iterable",
+            TypeError::ZeroStep { .. } => "type::zero_step",
+            TypeError::LiteralOutOfRange { .. } => "type::literal_out_of_range",
+            TypeError::UndefinedType { .. } => "type::undefined_type",
+            TypeError::UnknownField { .. } => "type::unknown_field",
+            TypeError::MissingFields { .. } => "type::missing_fields",
+            TypeError::DuplicateField { .. } => "type::duplicate_field",
+            TypeError::FieldTypeMismatch { .. } => "type::field_type_mismatch",
+            TypeError::RecursiveStruct { .. } => "type::recursive_struct",
+            TypeError::DuplicateType { .. } => "type::duplicate_type",
+            TypeError::AssignTypeMismatch { .. } => "type::assign_type_mismatch",
+            TypeError::UnknownVariant { .. } => "type::unknown_variant",
+            TypeError::DuplicateVariant { .. } => "type::duplicate_variant",
+            TypeError::VariantArity { .. } => "type::variant_arity",
+            TypeError::OwnedPayload { .. } => "type::owned_payload",
+            TypeError::PatternTypeMismatch { .. } => "type::pattern_type_mismatch",
+            TypeError::NonExhaustiveMatch { .. } => "type::non_exhaustive_match",
+            TypeError::ImmutableAssign { .. } => "type::immutable_assign",
+            TypeError::OutOfScope { .. } => "type::out_of_scope",
+            TypeError::ImplicitDeclaration { .. } => "type::implicit_declaration",
+            TypeError::NonConstantInitializer { .. } => "type::non_constant_initializer",
+            TypeError::UnsupportedGlobalType { .. } => "type::unsupported_global_type",
+            TypeError::DuplicateGlobal { .. } => "type::duplicate_global",
+            TypeError::ArrayLengthNotConst { .. } => "type::invalid_array_length",
+        }
+    }
+
+    /// 不带 "type error:" 前缀的错误描述
+    fn message(&self) -> String {
+        match self {
+            TypeError::UndefinedVariable { name, .. } => format!("undefined variable '{}'", name),
+            TypeError::UndefinedFunction { name, .. } => format!("undefined function '{}'", name),
+            TypeError::InvalidOperands { op, lhs, rhs, .. } => {
+                format!("operator '{}' cannot be applied to {} and {}", op, lhs, rhs)
+            }
+            TypeError::InvalidOperand { op, ty, .. } => {
+                format!("operator '{}' cannot be applied to {}", op, ty)
+            }
+            TypeError::IndexNonArray { ty, .. } => {
+                format!("cannot index a value of type {}", ty)
+            }
+            TypeError::NonIntegerIndex { ty, .. } => {
+                format!("array index must be an integer, found {}", ty)
+            }
+            TypeError::ArgumentCount {
+                func,
+                expected,
+                found,
+                ..
+            } => format!(
+                "function '{}' expects {} argument(s), got {}",
+                func, expected, found
+            ),
+            TypeError::ArgumentType {
+                func,
+                index,
+                expected,
+                found,
+                ..
+            } => format!(
+                "argument {} of '{}' has type {}, expected {}",
+                index, func, found, expected
+            ),
+            TypeError::ReturnTypeMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => format!(
+                "return variable '{}' is declared as {} but assigned a value of type {}",
+                name, expected, found
+            ),
+            TypeError::ElementTypeMismatch {
+                expected, found, ..
+            } => format!(
+                "cannot assign a value of type {} to an element of type {}",
+                found, expected
+            ),
+            TypeError::InvalidCast { from, to, .. } => {
+                format!("cannot cast {} to {}", from, to)
+            }
+            TypeError::InvalidCondition { ty, .. } => {
+                format!("condition must be bool, found {}", ty)
+            }
+            TypeError::BranchTypeMismatch {
+                expected, found, ..
+            } => format!(
+                "this branch has type {} but the first branch has type {}",
+                found, expected
+            ),
+            TypeError::MissingElse { ty, .. } => {
+                format!("`if` without `else` cannot produce a value of type {}", ty)
+            }
+            TypeError::JumpOutsideLoop { keyword, .. } => {
+                format!("'{}' outside of a loop", keyword)
+            }
+            TypeError::NotIterable { ty, .. } => {
+                format!("cannot iterate over a value of type {}", ty)
+            }
+            TypeError::ZeroStep { .. } => "step of a range loop must not be zero".to_string(),
+            TypeError::LiteralOutOfRange { ty, .. } => {
+                format!("literal out of range for {}", ty)
+            }
+            TypeError::UndefinedType { name, .. } => format!("undefined type '{}'", name),
+            TypeError::UnknownField { ty, field, .. } => {
+                format!("no field '{}' on type {}", field, ty)
+            }
+            TypeError::MissingFields { name, fields, .. } => format!(
+                "missing field(s) {} in initializer of '{}'",
+                fields.join(", "),
+                name
+            ),
+            TypeError::DuplicateField { field, .. } => {
+                format!("field '{}' is specified more than once", field)
+            }
+            TypeError::FieldTypeMismatch {
+                field,
+                expected,
+                found,
+                ..
+            } => format!(
+                "field '{}' has type {} but is given a value of type {}",
+                field, expected, found
+            ),
+            TypeError::RecursiveStruct { name, .. } => {
+                format!("type '{}' contains itself and has infinite size", name)
+            }
+            TypeError::DuplicateType { name, .. } => {
+                format!("the type '{}' is defined more than once", name)
+            }
+            TypeError::AssignTypeMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => format!(
+                "variable '{}' has type {} but is assigned a value of type {}",
+                source_name(name),
+                expected,
+                found
+            ),
+            TypeError::UnknownVariant { name, variant, .. } => {
+                format!("no variant '{}' in enum '{}'", variant, name)
+            }
+            TypeError::DuplicateVariant { variant, .. } => {
+                format!("variant '{}' is declared more than once", variant)
+            }
+            TypeError::VariantArity {
+                variant,
+                expected,
+                found,
+                ..
+            } => format!(
+                "variant '{}' has {} field(s), found {}",
+                variant, expected, found
+            ),
+            TypeError::OwnedPayload { variant, .. } => {
+                format!("variant '{}' cannot hold a dynamic array", variant)
+            }
+            TypeError::PatternTypeMismatch { ty, .. } => {
+                format!("pattern cannot match a value of type {}", ty)
+            }
+            TypeError::NonExhaustiveMatch { missing, .. } => {
+                format!("non-exhaustive match: {} not covered", missing.join(", "))
+            }
+            TypeError::ImmutableAssign { name, .. } => {
+                format!("cannot assign twice to immutable variable '{}'", name)
+            }
+            TypeError::OutOfScope { name, .. } => {
+                format!("variable '{}' is not in scope", name)
+            }
+            TypeError::ImplicitDeclaration { name, .. } => {
+                format!("variable '{}' is assigned without being declared", name)
+            }
+            TypeError::NonConstantInitializer { name, .. } => {
+                format!("initializer of '{}' is not a constant expression", name)
+            }
+            TypeError::UnsupportedGlobalType { name, ty, .. } => {
+                format!("'{}' cannot have type {}", name, ty)
+            }
+            TypeError::DuplicateGlobal { name, .. } => {
+                format!("the global '{}' is defined more than once", name)
+            }
+            TypeError::ArrayLengthNotConst { name, .. } => {
+                format!("array length '{}' is not an integer constant", name)
+            }
+        }
+    }
+
+    /// 转换为带源码位置的诊断
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let label = match self {
+            TypeError::UndefinedVariable { .. } => "not found in this function".to_string(),
+            TypeError::UndefinedFunction { .. } => "not found in this module".to_string(),
+            TypeError::InvalidOperands { lhs, rhs, .. } => format!("{} and {}", lhs, rhs),
+            TypeError::InvalidOperand { ty, .. } => format!("this has type {}", ty),
+            TypeError::IndexNonArray { ty, .. } => format!("this has type {}", ty),
+            TypeError::NonIntegerIndex { ty, .. } => format!("this has type {}", ty),
+            TypeError::ArgumentCount { expected, .. } => {
+                format!("expected {} argument(s)", expected)
+            }
+            TypeError::ArgumentType { expected, .. } => format!("expected {}", expected),
+            TypeError::ReturnTypeMismatch { expected, .. } => format!("expected {}", expected),
+            TypeError::ElementTypeMismatch { expected, .. } => format!("expected {}", expected),
+            TypeError::InvalidCast { from, .. } => format!("this has type {}", from),
+            TypeError::InvalidCondition { ty, .. } => format!("this has type {}", ty),
+            TypeError::BranchTypeMismatch { expected, .. } => format!("expected {}", expected),
+            TypeError::MissingElse { ty, .. } => format!("this branch has type {}", ty),
+            TypeError::JumpOutsideLoop { .. } => "cannot leave a function body".to_string(),
+            TypeError::NotIterable { ty, .. } => format!("this has type {}", ty),
+            TypeError::ZeroStep { .. } => "the loop would never end".to_string(),
+            TypeError::LiteralOutOfRange { ty, .. } => format!("does not fit in {}", ty),
+            TypeError::UndefinedType { .. } => "not found in this module".to_string(),
+            TypeError::UnknownField { ty, .. } => format!("this has type {}", ty),
+            TypeError::MissingFields { fields, .. } => format!("missing {}", fields.join(", ")),
+            TypeError::DuplicateField { .. } => "duplicate field".to_string(),
+            TypeError::FieldTypeMismatch { expected, .. } => format!("expected {}", expected),
+            TypeError::RecursiveStruct { .. } => "contains itself by value".to_string(),
+            TypeError::DuplicateType { .. } => "redefined here".to_string(),
+            TypeError::AssignTypeMismatch { expected, .. } => format!("expected {}", expected),
+            TypeError::UnknownVariant { name, .. } => format!("not found in '{}'", name),
+            TypeError::DuplicateVariant { .. } => "duplicate variant".to_string(),
+            TypeError::VariantArity { expected, .. } => format!("expected {} field(s)", expected),
+            TypeError::OwnedPayload { .. } => "enums are copied bitwise".to_string(),
+            TypeError::PatternTypeMismatch { ty, .. } => format!("expected a pattern for {}", ty),
+            TypeError::NonExhaustiveMatch { missing, .. } => {
+                format!("{} not covered", missing.join(", "))
+            }
+            TypeError::ImmutableAssign { .. } => "cannot assign twice".to_string(),
+            TypeError::OutOfScope { .. } => "not found in this scope".to_string(),
+            TypeError::ImplicitDeclaration { .. } => "not declared with 'let'".to_string(),
+            TypeError::NonConstantInitializer { .. } => "not a constant".to_string(),
+            TypeError::UnsupportedGlobalType { .. } => {
+                "expected bool, an integer or a float type".to_string()
+            }
+            TypeError::DuplicateGlobal { .. } => "redefined here".to_string(),
+            TypeError::ArrayLengthNotConst { .. } => {
+                "expected a non-negative integer constant".to_string()
+            }
+        };
+        let diag = Diagnostic::error(self.message(), self.span(), label).with_code(self.code());
+        match self {
+            TypeError::ImmutableAssign { declared_at, .. } => {
+                diag.with_secondary(*declared_at, "declared here without 'mut'")
+            }
+            TypeError::OutOfScope { declared_at, .. } => {
+                diag.with_secondary(*declared_at, "declared in a block that has already ended")
+            }
+            TypeError::ImplicitDeclaration { .. } => diag
+                .with_note("strict mode requires every local variable to be declared with 'let'"),
+            TypeError::NonConstantInitializer { .. } => diag.with_note(
+                "constant expressions may only use literals, operators, 'as' and earlier constants",
+            ),
+            _ => diag,
+        }
+    }
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "type error: {}", self.message())
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// 不在签名表中、参数个数可变的内置函数（不检查参数）
+const VARIADIC_BUILTINS: &[&str] = &["printf", "puts"];
+
+/// 单个函数体的检查状态
+struct FunctionCheck<'a> {
+    tc: &'a TypeChecker,
+    vars: HashMap<String, Type>,
+    the_return: &'a (String, Type),
+    errors: Vec<TypeError>,
+    /// 当前嵌套的 while 循环层数，为 0 时不允许 break / continue
+    loop_depth: usize,
+    /// 已经检查过的 `let` 声明的变量，之后的赋值不做隐式转换以外的类型转换
+    declared: HashSet<String>,
+    /// 下一个被检查的表达式处在语句位置，它的值不会被使用
+    statement: bool,
+}
+
+impl FunctionCheck<'_> {
+    fn infer(&self, expr: &Expr) -> Type {
+        self.tc.infer_type(expr, &|n| self.vars.get(n).cloned())
+    }
+
+    /// 检查语句序列。`value_used` 表示最后一条语句的值是整个序列（if / match 分支）的值
+    fn check_body(&mut self, body: &[Expr], value_used: bool) {
+        for (i, stmt) in body.iter().enumerate() {
+            self.statement = !(value_used && i + 1 == body.len());
+            self.check_expr(stmt);
+        }
+    }
+
+    /// 检查表达式及其全部子表达式，返回表达式的类型
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        let span = expr.span;
+        // 子表达式的值总会被使用，只有 check_body 把语句标记为语句位置
+        let statement = std::mem::take(&mut self.statement);
+        match &expr.kind {
+            ExprKind::Identifier(name) => {
+                if !self.vars.contains_key(name) {
+                    self.errors.push(TypeError::UndefinedVariable {
+                        name: name.clone(),
+                        span,
+                    });
+                }
+            }
+            ExprKind::Assign(name, value) => {
+                let ty = self.check_expr(value);
+                if *name == self.the_return.0 && !is_arg_compatible(value, &ty, &self.the_return.1)
+                {
+                    self.errors.push(TypeError::ReturnTypeMismatch {
+                        name: name.clone(),
+                        expected: self.the_return.1.clone(),
+                        found: ty,
+                        span: value.span,
+                    });
+                } else if let Some(var_ty) = self.vars.get(name)
+                    && *var_ty != ty
+                    && (matches!(var_ty, Type::Struct(_))
+                        || matches!(ty, Type::Struct(_))
+                        || (self.declared.contains(name) && !is_arg_compatible(value, &ty, var_ty)))
+                {
+                    // 结构体之间、结构体与其他类型之间没有隐式转换；
+                    // let 声明的变量只接受能隐式转换为其类型的值，隐式变量则按第一次赋值的类型转换
+                    self.errors.push(TypeError::AssignTypeMismatch {
+                        name: name.clone(),
+                        expected: var_ty.clone(),
+                        found: ty,
+                        span: value.span,
+                    });
+                } else if let Some(var_ty) = self.vars.get(name) {
+                    let var_ty = var_ty.clone();
+                    self.check_coerced_literal(value, &var_ty);
+                }
+            }
+            ExprKind::Let(name, _, annotation, value) => {
+                self.check_let(name, annotation.as_ref(), value)
+            }
+            // x op= e 按 x = x op e 检查
+            ExprKind::CompoundAssign(name, op, value) => {
+                let target = Expr::new(ExprKind::Identifier(name.clone()), span);
+                self.check_arithmetic(op.symbol(), &target, value, span);
+            }
+            ExprKind::IndexAssign(base, idx, op, value) => {
+                self.check_index_assign(base, idx, *op, value, span)
+            }
+            ExprKind::FieldAssign(base, field, op, value) => {
+                self.check_field_assign(base, field, *op, value, span)
+            }
+            ExprKind::StructLiteral(name, fields) => self.check_struct_literal(name, fields, span),
+            ExprKind::EnumLiteral(name, variant, args) => {
+                self.check_enum_literal(name, variant, args, span)
+            }
+            ExprKind::Match(scrutinee, arms) => self.check_match(scrutinee, arms, statement),
+            ExprKind::Field(base, field) => {
+                let base_ty = self.check_expr(base);
+                if self.tc.field_type(&base_ty, field).is_none() {
+                    self.errors.push(TypeError::UnknownField {
+                        ty: base_ty,
+                        field: field.clone(),
+                        span,
+                    });
+                }
+            }
+            // return expr 等价于给返回变量赋值后退出
+            ExprKind::Return(value) => {
+                let ty = self.check_expr(value);
+                if !is_arg_compatible(value, &ty, &self.the_return.1) {
+                    self.errors.push(TypeError::ReturnTypeMismatch {
+                        name: self.the_return.0.clone(),
+                        expected: self.the_return.1.clone(),
+                        found: ty,
+                        span: value.span,
+                    });
+                } else {
+                    let expected = self.the_return.1.clone();
+                    self.check_coerced_literal(value, &expected);
+                }
+            }
+            ExprKind::Add(l, r) => self.check_arithmetic("+", l, r, span),
+            ExprKind::Sub(l, r) => self.check_arithmetic("-", l, r, span),
+            ExprKind::Mul(l, r) => self.check_arithmetic("*", l, r, span),
+            ExprKind::Div(l, r) => self.check_arithmetic("/", l, r, span),
+            ExprKind::Rem(l, r) => self.check_remainder(l, r, span),
+            ExprKind::BitAnd(l, r) => self.check_bitwise("&", l, r, span),
+            ExprKind::BitOr(l, r) => self.check_bitwise("|", l, r, span),
+            ExprKind::BitXor(l, r) => self.check_bitwise("^", l, r, span),
+            ExprKind::Shl(l, r) => self.check_shift("<<", l, r, span),
+            ExprKind::Shr(l, r) => self.check_shift(">>", l, r, span),
+            ExprKind::Eq(l, r) => self.check_comparison("==", l, r, span),
+            ExprKind::Ne(l, r) => self.check_comparison("!=", l, r, span),
+            ExprKind::Lt(l, r) => self.check_comparison("<", l, r, span),
+            ExprKind::Le(l, r) => self.check_comparison("<=", l, r, span),
+            ExprKind::Gt(l, r) => self.check_comparison(">", l, r, span),
+            ExprKind::Ge(l, r) => self.check_comparison(">=", l, r, span),
+            ExprKind::And(l, r) => self.check_logical("&&", l, r, span),
+            ExprKind::Or(l, r) => self.check_logical("||", l, r, span),
+            ExprKind::Not(inner) => {
+                let ty = self.check_expr(inner);
+                if ty != Type::Bool {
+                    self.errors.push(TypeError::InvalidOperand {
+                        op: "!".to_string(),
+                        ty,
+                        span,
+                    });
+                }
+            }
+            ExprKind::Neg(inner) => {
+                // -128i8 这样的最小值只有带上负号才在范围内
+                let ty = match &inner.kind {
+                    ExprKind::Literal(text, ty) => {
+                        self.check_literal(text, ty, true, inner.span);
+                        ty.clone()
+                    }
+                    _ => self.check_expr(inner),
+                };
+                if !(is_integer(&ty) || is_float(&ty)) {
+                    self.errors.push(TypeError::InvalidOperand {
+                        op: "-".to_string(),
+                        ty,
+                        span,
+                    });
+                }
+            }
+            ExprKind::Index(base, idx) => {
+                let base_ty = self.check_expr(base);
+                let idx_ty = self.check_expr(idx);
+                if !matches!(
+                    base_ty,
+                    Type::Array(..) | Type::DynamicArray(_) | Type::String
+                ) {
+                    self.errors.push(TypeError::IndexNonArray {
+                        ty: base_ty,
+                        span: base.span,
+                    });
+                }
+                if !is_integer(&idx_ty) {
+                    self.errors.push(TypeError::NonIntegerIndex {
+                        ty: idx_ty,
+                        span: idx.span,
+                    });
+                }
+            }
+            ExprKind::Call(name, args) => self.check_call(name, args, span),
+            ExprKind::Cast(inner, to) => {
+                let from = self.check_expr(inner);
+                // bool 只能与整数互转，不能与浮点互转
+                let numeric = |t: &Type| is_integer(t) || is_float(t);
+                let bool_int = |a: &Type, b: &Type| *a == Type::Bool && is_integer(b);
+                let ok = from == *to
+                    || (numeric(&from) && numeric(to))
+                    || bool_int(&from, to)
+                    || bool_int(to, &from);
+                if !ok {
+                    self.errors.push(TypeError::InvalidCast {
+                        from,
+                        to: to.clone(),
+                        span,
+                    });
+                }
+            }
+            ExprKind::IfElse(cond, then_body, else_body) => {
+                self.check_condition(cond);
+                self.check_body(then_body, !statement);
+                self.check_body(else_body, !statement);
+                // 没有 else 时 else 分支的值是 0，与 JIT 一致按第一个分支的类型转换；
+                // 结构体与枚举按指针传递，0 不是合法的值
+                if !statement && !else_body.is_empty() {
+                    self.check_branch_types(&[then_body, else_body]);
+                } else if !statement
+                    && !diverges(then_body)
+                    && let Some(last) = then_body.last()
+                    && let ty @ Type::Struct(_) = self.infer(last)
+                {
+                    self.errors.push(TypeError::MissingElse {
+                        ty,
+                        span: last.span,
+                    });
+                }
+            }
+            ExprKind::WhileLoop(cond, body) => {
+                self.check_condition(cond);
+                self.check_loop_body(body);
+            }
+            ExprKind::ForRange(_, start, end, _, step, body) => {
+                let start_ty = self.check_expr(start);
+                let end_ty = self.check_expr(end);
+                if !(is_integer(&start_ty) && is_integer(&end_ty)) {
+                    self.errors.push(TypeError::InvalidOperands {
+                        op: "..".to_string(),
+                        lhs: start_ty.clone(),
+                        rhs: end_ty,
+                        span: Span::new(start.span.start, end.span.end),
+                    });
+                }
+                if let Some(step) = step {
+                    let step_ty = self.check_expr(step);
+                    if !is_integer(&step_ty) {
+                        self.errors.push(TypeError::InvalidOperands {
+                            op: "step".to_string(),
+                            lhs: start_ty,
+                            rhs: step_ty,
+                            span: step.span,
+                        });
+                    } else if is_zero_literal(step) {
+                        // 步长不是字面量时由 JIT 在运行时检查
+                        self.errors.push(TypeError::ZeroStep { span: step.span });
+                    }
+                }
+                self.check_loop_body(body);
+            }
+            ExprKind::ForEach(_, iterable, body) => {
+                let ty = self.check_expr(iterable);
+                if element_type(&ty).is_none() {
+                    self.errors.push(TypeError::NotIterable {
+                        ty,
+                        span: iterable.span,
+                    });
+                }
+                self.check_loop_body(body);
+            }
+            ExprKind::Break | ExprKind::Continue if self.loop_depth == 0 => {
+                let keyword = match expr.kind {
+                    ExprKind::Break => "break",
+                    _ => "continue",
+                };
+                self.errors.push(TypeError::JumpOutsideLoop {
+                    keyword: keyword.to_string(),
+                    span,
+                });
+            }
+            ExprKind::Block(body) => self.check_body(body, false),
+            ExprKind::ArrayLiteral(elems, _) | ExprKind::DynamicArrayLiteral(elems, _) => {
+                for elem in elems {
+                    self.check_expr(elem);
+                }
+            }
+            ExprKind::Literal(text, ty) => self.check_literal(text, ty, false, span),
+            ExprKind::StringLiteral(_)
+            | ExprKind::ComplexLiteral(..)
+            | ExprKind::GlobalDataAddr(_)
+            | ExprKind::Drop(_)
+            | ExprKind::Break
+            | ExprKind::Continue => {}
+        }
+        self.infer(expr)
+    }
+
+    /// `let x: T = e`：标注的类型必须已定义，e 必须能隐式转换为 T，字面量必须在 T 的范围内
+    fn check_let(&mut self, name: &str, annotation: Option<&Type>, value: &Expr) {
+        let ty = self.check_expr(value);
+        self.declared.insert(name.to_string());
+        let Some(annotation) = annotation else {
+            return;
+        };
+        if let Some(undefined) = self.tc.undefined_type(annotation) {
+            self.errors.push(TypeError::UndefinedType {
+                name: undefined,
+                span: value.span,
+            });
+        } else if !is_arg_compatible(value, &ty, annotation) {
+            self.errors.push(TypeError::AssignTypeMismatch {
+                name: name.to_string(),
+                expected: annotation.clone(),
+                found: ty,
+                span: value.span,
+            });
+        } else {
+            self.check_coerced_literal(value, annotation);
+        }
+    }
+
+    /// 隐式转换为 `ty` 的值若是数字字面量（可以带负号），必须在 `ty` 的取值范围内。
+    /// 字面量按自身类型的检查已在 check_expr 中做过；整数与浮点之间的转换不在此列
+    fn check_coerced_literal(&mut self, value: &Expr, ty: &Type) {
+        let (literal, negative) = match &value.kind {
+            ExprKind::Neg(inner) => (inner.as_ref(), true),
+            _ => (value, false),
+        };
+        if let ExprKind::Literal(text, lit_ty) = &literal.kind
+            && lit_ty != ty
+            && ((is_integer(lit_ty) && is_integer(ty)) || (is_float(lit_ty) && is_float(ty)))
+        {
+            self.check_literal(text, ty, negative, value.span);
+        }
+    }
+
+    /// 数字字面量必须在类型的取值范围内，`negative` 表示字面量前有负号
+    fn check_literal(&mut self, text: &str, ty: &Type, negative: bool, span: Span) {
+        if !literal_in_range(text, ty, negative) {
+            self.errors.push(TypeError::LiteralOutOfRange {
+                ty: ty.clone(),
+                span,
+            });
+        }
+    }
+
+    /// `arr[i] = v` / `arr[i] op= v`：目标必须是数组（字符串不可写），
+    /// 复合赋值按 `arr[i] op v` 检查，普通赋值要求 v 能隐式转换为元素类型
+    fn check_index_assign(
+        &mut self,
+        base: &Expr,
+        idx: &Expr,
+        op: Option<AssignOp>,
+        value: &Expr,
+        span: Span,
+    ) {
+        let is_string = self.infer(base) == Type::String;
+        if is_string {
+            self.errors.push(TypeError::InvalidOperand {
+                op: "[]=".to_string(),
+                ty: Type::String,
+                span,
+            });
+        }
+        let target = Expr::new(
+            ExprKind::Index(Box::new(base.clone()), Box::new(idx.clone())),
+            span,
+        );
+        if let Some(op) = op {
+            self.check_arithmetic(op.symbol(), &target, value, span);
+            return;
+        }
+        let elem_ty = self.check_expr(&target);
+        let ty = self.check_expr(value);
+        // 字符串已在上面报错，不再重复报元素类型
+        if !is_string && !is_arg_compatible(value, &ty, &elem_ty) {
+            self.errors.push(TypeError::ElementTypeMismatch {
+                expected: elem_ty,
+                found: ty,
+                span: value.span,
+            });
+        } else if !is_string {
+            self.check_coerced_literal(value, &elem_ty);
+        }
+    }
+
+    /// `p.f = v` / `p.f op= v`：复合赋值按 `p.f op v` 检查，
+    /// 普通赋值要求 v 能隐式转换为字段类型
+    fn check_field_assign(
+        &mut self,
+        base: &Expr,
+        field: &str,
+        op: Option<AssignOp>,
+        value: &Expr,
+        span: Span,
+    ) {
+        let target = Expr::new(
+            ExprKind::Field(Box::new(base.clone()), field.to_string()),
+            span,
+        );
+        if let Some(op) = op {
+            self.check_arithmetic(op.symbol(), &target, value, span);
+            return;
+        }
+        let base_ty = self.check_expr(base);
+        let ty = self.check_expr(value);
+        match self.tc.field_type(&base_ty, field) {
+            Some(expected) if !is_arg_compatible(value, &ty, &expected) => {
+                self.errors.push(TypeError::FieldTypeMismatch {
+                    field: field.to_string(),
+                    expected,
+                    found: ty,
+                    span: value.span,
+                })
+            }
+            Some(expected) => self.check_coerced_literal(value, &expected),
+            None => self.errors.push(TypeError::UnknownField {
+                ty: base_ty,
+                field: field.to_string(),
+                span,
+            }),
+        }
+    }
+
+    /// `Name { f: v, ... }`：结构体必须已声明，每个字段恰好给出一次，
+    /// 值能隐式转换为字段类型
+    fn check_struct_literal(&mut self, name: &str, fields: &[(String, Expr)], span: Span) {
+        let tys: Vec<Type> = fields.iter().map(|(_, e)| self.check_expr(e)).collect();
+        let tc = self.tc;
+        let Some(def) = tc.structs.get(name) else {
+            self.errors.push(TypeError::UndefinedType {
+                name: name.to_string(),
+                span,
+            });
+            return;
+        };
+        for (i, ((field, value), ty)) in fields.iter().zip(tys).enumerate() {
+            if fields[..i].iter().any(|(f, _)| f == field) {
+                self.errors.push(TypeError::DuplicateField {
+                    field: field.clone(),
+                    span: value.span,
+                });
+                continue;
+            }
+            match def.fields.iter().find(|(f, _)| f == field) {
+                None => self.errors.push(TypeError::UnknownField {
+                    ty: Type::Struct(name.to_string()),
+                    field: field.clone(),
+                    span: value.span,
+                }),
+                Some((_, expected)) if !is_arg_compatible(value, &ty, expected) => {
+                    self.errors.push(TypeError::FieldTypeMismatch {
+                        field: field.clone(),
+                        expected: expected.clone(),
+                        found: ty,
+                        span: value.span,
+                    })
+                }
+                Some((_, expected)) => self.check_coerced_literal(value, expected),
+            }
+        }
+        let missing: Vec<String> = def
+            .fields
+            .iter()
+            .filter(|(f, _)| !fields.iter().any(|(g, _)| g == f))
+            .map(|(f, _)| f.clone())
+            .collect();
+        if !missing.is_empty() {
+            self.errors.push(TypeError::MissingFields {
+                name: name.to_string(),
+                fields: missing,
+                span,
+            });
+        }
+    }
+
+    /// `Enum::Variant(args)`：枚举与变体必须已声明，载荷的个数与类型与声明一致
+    fn check_enum_literal(&mut self, name: &str, variant: &str, args: &[Expr], span: Span) {
+        let arg_tys: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        let Some(payload) = self.resolve_variant(name, variant, span) else {
+            return;
+        };
+        let func = format!("{}::{}", name, variant);
+        if payload.len() != args.len() {
+            self.errors.push(TypeError::VariantArity {
+                variant: func,
+                expected: payload.len(),
+                found: args.len(),
+                span,
+            });
+            return;
+        }
+        for (i, ((arg, arg_ty), expected)) in args.iter().zip(arg_tys).zip(payload).enumerate() {
+            if !is_arg_compatible(arg, &arg_ty, &expected) {
+                self.errors.push(TypeError::ArgumentType {
+                    func: func.clone(),
+                    index: i + 1,
+                    expected,
+                    found: arg_ty,
+                    span: arg.span,
+                });
+            } else {
+                self.check_coerced_literal(arg, &expected);
+            }
+        }
+    }
+
+    /// 变体的载荷类型；枚举或变体不存在时记录错误并返回 None
+    fn resolve_variant(&mut self, name: &str, variant: &str, span: Span) -> Option<Vec<Type>> {
+        let Some(def) = self.tc.enums.get(name) else {
+            self.errors.push(TypeError::UndefinedType {
+                name: name.to_string(),
+                span,
+            });
+            return None;
+        };
+        match def.variant(variant) {
+            Some((_, payload)) => Some(payload.to_vec()),
+            None => {
+                self.errors.push(TypeError::UnknownVariant {
+                    name: name.to_string(),
+                    variant: variant.to_string(),
+                    span,
+                });
+                None
+            }
+        }
+    }
+
+    /// `match`：被匹配的值必须是整数或枚举，每个模式都与它的类型相符，
+    /// 分支合起来覆盖所有可能的值；绑定的变量与同名变量的类型一致
+    fn check_match(&mut self, scrutinee: &Expr, arms: &[MatchArm], statement: bool) {
+        let ty = self.check_expr(scrutinee);
+        let tc = self.tc;
+        let enum_def = match &ty {
+            Type::Struct(name) => tc.enums.get(name),
+            _ => None,
+        };
+        if enum_def.is_none() && !is_integer(&ty) {
+            self.errors.push(TypeError::InvalidOperand {
+                op: "match".to_string(),
+                ty: ty.clone(),
+                span: scrutinee.span,
+            });
+        } else {
+            let before = self.errors.len();
+            for arm in arms {
+                self.check_pattern(&ty, &arm.pattern, arm.span);
+            }
+            // 模式本身有错时覆盖情况没有意义
+            let missing = match enum_def {
+                _ if self.errors.len() > before => Vec::new(),
+                Some(def) => missing_variants(def, arms),
+                None => missing_ranges(&ty, arms),
+            };
+            if !missing.is_empty() {
+                self.errors.push(TypeError::NonExhaustiveMatch {
+                    missing,
+                    span: scrutinee.span,
+                });
+            }
+        }
+        for arm in arms {
+            for (name, binding_ty) in tc.pattern_bindings(&ty, &arm.pattern) {
+                if let Some(var_ty) = self.vars.get(&name)
+                    && *var_ty != binding_ty
+                {
+                    self.errors.push(TypeError::AssignTypeMismatch {
+                        name,
+                        expected: var_ty.clone(),
+                        found: binding_ty,
+                        span: arm.span,
+                    });
+                }
+            }
+            self.check_body(&arm.body, !statement);
+        }
+        // JIT 按第一个分支的类型合并各分支的值，不相容的值（如把整数当作枚举）不能转换
+        if !statement {
+            let bodies: Vec<&Vec<Expr>> = arms.iter().map(|arm| &arm.body).collect();
+            self.check_branch_types(&bodies);
+        }
+    }
+
+    /// 模式与被匹配值的类型 `ty` 相符：整数与区间只能匹配整数且在其取值范围内，
+    /// 变体模式只能匹配同一个枚举且载荷个数一致
+    fn check_pattern(&mut self, ty: &Type, pattern: &Pattern, span: Span) {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => {}
+            Pattern::Int(_) | Pattern::Range(..) if !is_integer(ty) => {
+                self.errors.push(TypeError::PatternTypeMismatch {
+                    ty: ty.clone(),
+                    span,
+                })
+            }
+            Pattern::Int(n) | Pattern::Range(n, _, _) | Pattern::Range(_, n, _)
+                if !int_range(ty).contains(&(*n as i128)) =>
+            {
+                self.errors.push(TypeError::LiteralOutOfRange {
+                    ty: ty.clone(),
+                    span,
+                })
+            }
+            Pattern::Int(_) | Pattern::Range(..) => {}
+            Pattern::Variant(name, variant, fields) => {
+                if *ty != Type::Struct(name.clone()) {
+                    self.errors.push(TypeError::PatternTypeMismatch {
+                        ty: ty.clone(),
+                        span,
+                    });
+                    return;
+                }
+                let Some(payload) = self.resolve_variant(name, variant, span) else {
+                    return;
+                };
+                if payload.len() != fields.len() {
+                    self.errors.push(TypeError::VariantArity {
+                        variant: format!("{}::{}", name, variant),
+                        expected: payload.len(),
+                        found: fields.len(),
+                        span,
+                    });
+                }
+            }
+            Pattern::Or(alternatives) => {
+                for alternative in alternatives {
+                    self.check_pattern(ty, alternative, span);
+                }
+            }
+        }
+    }
+
+    /// `+ - * /`：两侧必须同为整数、同为浮点或同为同一种复数
+    fn check_arithmetic(&mut self, op: &str, lhs: &Expr, rhs: &Expr, span: Span) {
+        let l = self.check_expr(lhs);
+        let r = self.check_expr(rhs);
+        let ok = (is_integer(&l) && is_integer(&r))
+            || (is_float(&l) && is_float(&r))
+            || (matches!(l, Type::Complex64 | Type::Complex128) && l == r);
+        if !ok {
+            self.errors.push(TypeError::InvalidOperands {
+                op: op.to_string(),
+                lhs: l,
+                rhs: r,
+                span,
+            });
+        }
+    }
+
+    /// `%`：两侧必须同为整数或同为浮点（浮点取余即 fmod）
+    fn check_remainder(&mut self, lhs: &Expr, rhs: &Expr, span: Span) {
+        let l = self.check_expr(lhs);
+        let r = self.check_expr(rhs);
+        if !((is_integer(&l) && is_integer(&r)) || (is_float(&l) && is_float(&r))) {
+            self.errors.push(TypeError::InvalidOperands {
+                op: "%".to_string(),
+                lhs: l,
+                rhs: r,
+                span,
+            });
+        }
+    }
+
+    /// `& | ^`：两侧同为整数，或同为 bool（不短路）
+    fn check_bitwise(&mut self, op: &str, lhs: &Expr, rhs: &Expr, span: Span) {
+        let l = self.check_expr(lhs);
+        let r = self.check_expr(rhs);
+        let ok = (is_integer(&l) && is_integer(&r)) || (l == Type::Bool && r == Type::Bool);
+        if !ok {
+            self.errors.push(TypeError::InvalidOperands {
+                op: op.to_string(),
+                lhs: l,
+                rhs: r,
+                span,
+            });
+        }
+    }
+
+    /// `<< >>`：两侧都是整数，移位量的位宽不必与左侧相同
+    fn check_shift(&mut self, op: &str, lhs: &Expr, rhs: &Expr, span: Span) {
+        let l = self.check_expr(lhs);
+        let r = self.check_expr(rhs);
+        if !(is_integer(&l) && is_integer(&r)) {
+            self.errors.push(TypeError::InvalidOperands {
+                op: op.to_string(),
+                lhs: l,
+                rhs: r,
+                span,
+            });
+        }
+    }
+
+    /// 比较运算：两侧必须同为整数或同为浮点；`==` / `!=` 还可以比较两个 bool
+    fn check_comparison(&mut self, op: &str, lhs: &Expr, rhs: &Expr, span: Span) {
+        let l = self.check_expr(lhs);
+        let r = self.check_expr(rhs);
+        let ok = (is_integer(&l) && is_integer(&r))
+            || (is_float(&l) && is_float(&r))
+            || (matches!(op, "==" | "!=") && l == Type::Bool && r == Type::Bool);
+        if !ok {
+            self.errors.push(TypeError::InvalidOperands {
+                op: op.to_string(),
+                lhs: l,
+                rhs: r,
+                span,
+            });
+        }
+    }
+
+    /// `&&` / `||`：两侧都必须是 bool
+    fn check_logical(&mut self, op: &str, lhs: &Expr, rhs: &Expr, span: Span) {
+        let l = self.check_expr(lhs);
+        let r = self.check_expr(rhs);
+        if l != Type::Bool || r != Type::Bool {
+            self.errors.push(TypeError::InvalidOperands {
+                op: op.to_string(),
+                lhs: l,
+                rhs: r,
+                span,
+            });
+        }
+    }
+
+    /// 循环体内允许 break / continue
+    fn check_loop_body(&mut self, body: &[Expr]) {
+        self.loop_depth += 1;
+        self.check_body(body, false);
+        self.loop_depth -= 1;
+    }
+
+    /// 作为值使用的 if / match：每个分支的值（最后一条语句，空分支为 0）
+    /// 必须能隐式转换为第一个分支的类型，JIT 按这个类型合并各分支的值。
+    /// 以 return / break / continue 结束的分支不产生值，不参与比较
+    fn check_branch_types(&mut self, branches: &[&Vec<Expr>]) {
+        let values = branches.iter().filter(|body| !diverges(body));
+        let mut values = values.map(|body| match body.last() {
+            Some(last) => (self.infer(last), Some(last)),
+            None => (Type::I64, None),
+        });
+        let Some((expected, _)) = values.next() else {
+            return;
+        };
+        let mismatches: Vec<TypeError> = values
+            .filter_map(|(found, last)| {
+                let last = last?;
+                (!is_arg_compatible(last, &found, &expected)).then(|| {
+                    TypeError::BranchTypeMismatch {
+                        expected: expected.clone(),
+                        found,
+                        span: last.span,
+                    }
+                })
+            })
+            .collect();
+        self.errors.extend(mismatches);
+    }
+
+    fn check_condition(&mut self, cond: &Expr) {
+        let ty = self.check_expr(cond);
+        if ty != Type::Bool {
+            self.errors.push(TypeError::InvalidCondition {
+                ty,
+                span: cond.span,
+            });
+        }
+    }
+
+    fn check_call(&mut self, name: &str, args: &[Expr], span: Span) {
+        let arg_tys: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        if VARIADIC_BUILTINS.contains(&name) {
+            return;
+        }
+        let name = dispatch_array_fn(name, arg_tys.first());
+        let Some(sig) = self.tc.resolve_func(&name) else {
+            self.errors
+                .push(TypeError::UndefinedFunction { name, span });
+            return;
+        };
+        if sig.params.len() != args.len() {
+            self.errors.push(TypeError::ArgumentCount {
+                func: name,
+                expected: sig.params.len(),
+                found: args.len(),
+                span,
+            });
+            return;
+        }
+        for (i, ((arg, arg_ty), param_ty)) in args.iter().zip(&arg_tys).zip(&sig.params).enumerate()
+        {
+            // 外部函数的固定数组形参不关心长度（按 (ptr, len) 传递）
+            let ok = match (arg_ty, param_ty) {
+                (Type::Array(a, _), Type::Array(p, 0)) if sig.is_external => a == p,
+                _ => is_arg_compatible(arg, arg_ty, param_ty),
+            };
+            if !ok {
+                self.errors.push(TypeError::ArgumentType {
+                    func: name.clone(),
+                    index: i + 1,
+                    expected: param_ty.clone(),
+                    found: arg_ty.clone(),
+                    span: arg.span,
+                });
+            } else {
+                self.check_coerced_literal(arg, param_ty);
+            }
+        }
+    }
+}
+
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::I128)
+}
+
+fn is_float(t: &Type) -> bool {
+    matches!(t, Type::F32 | Type::F64)
+}
+
+/// 将泛型 DynamicArray 函数名（array_push / array_len ...）按第一个实参的
+/// 元素类型分发到具体实现（array_push_f64 ...）
+pub fn dispatch_array_fn(name: &str, first_arg_ty: Option<&Type>) -> String {
+    // 只处理泛型名称（无后缀的）
+    if !matches!(
+        name,
+        "array_push"
+            | "array_pop"
+            | "array_len"
+            | "array_cap"
+            | "array_set"
+            | "array_get_ptr"
+            | "array_drop"
+    ) {
+        return name.to_string();
+    }
+    let suffix = match first_arg_ty {
+        Some(Type::DynamicArray(elem_ty)) => match elem_ty.as_ref() {
+            Type::F64 => "_f64",
+            Type::Complex128 => "_complex128",
+            _ => "",
+        },
+        _ => "",
+    };
+    format!("{}{}", name, suffix)
+}
+
+/// 把类型中以常量名表示的数组长度 `[T; N]` 换成常量 N 的值（含嵌套的元素类型）。
+/// N 不是非负的整数常量时返回它的名字
+pub fn resolve_array_lengths(
+    ty: &Type,
+    globals: &HashMap<String, GlobalDef>,
+) -> Result<Type, String> {
+    Ok(match ty {
+        Type::ConstLenArray(elem, name) => {
+            let len = globals
+                .get(name)
+                .filter(|def| def.kind == GlobalKind::Const && is_integer(&def.ty))
+                .and_then(|def| match &def.value.kind {
+                    ExprKind::Literal(text, _) => text.parse::<usize>().ok(),
+                    _ => None,
+                })
+                .ok_or_else(|| name.clone())?;
+            Type::Array(Box::new(resolve_array_lengths(elem, globals)?), len)
+        }
+        Type::Array(elem, len) => {
+            Type::Array(Box::new(resolve_array_lengths(elem, globals)?), *len)
+        }
+        Type::DynamicArray(elem) => {
+            Type::DynamicArray(Box::new(resolve_array_lengths(elem, globals)?))
+        }
+        _ => ty.clone(),
+    })
+}
+
+/// 可被 `for x in e` 遍历的类型（[T; N] / array<T>）的元素类型
+pub fn element_type(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Array(elem, _) | Type::DynamicArray(elem) => Some(elem.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// 实参能否传给类型为 `param_ty` 的形参：类型相同，或与运算符相同的隐式拓宽
+/// (int → 更宽的 int, f32 → f64)。整数/浮点字面量可直接传给任意整数/浮点形参。
+fn is_arg_compatible(arg: &Expr, arg_ty: &Type, param_ty: &Type) -> bool {
+    if arg_ty == param_ty {
+        return true;
+    }
+    // 负数字面量 -3 解析为 Neg(3)，与字面量同样对待
+    let literal = match &arg.kind {
+        ExprKind::Neg(inner) => inner,
+        _ => arg,
+    };
+    if let ExprKind::Literal(..) = literal.kind {
+        return (is_integer(arg_ty) && is_integer(param_ty))
+            || (is_float(arg_ty) && is_float(param_ty));
+    }
+    match (arg_ty, param_ty) {
+        (a, p) if is_integer(a) && is_integer(p) => int_bits(a) <= int_bits(p),
+        (Type::F32, Type::F64) => true,
+        _ => false,
+    }
+}
+
+/// 字面量文本（已由 parser 转换为十进制）能否表示为 `ty` 类型的值。
+/// 带负号时整数可以多取一个值，例如 `-128i8`
+fn literal_in_range(text: &str, ty: &Type, negative: bool) -> bool {
+    match ty {
+        Type::F32 => text.parse::<f32>().is_ok_and(f32::is_finite),
+        Type::F64 => text.parse::<f64>().is_ok_and(f64::is_finite),
+        t if is_integer(t) => {
+            let max = (1u128 << (int_bits(t) - 1)) - 1 + negative as u128;
+            text.parse::<u128>().is_ok_and(|n| n <= max)
+        }
+        _ => true,
+    }
+}
+
+/// 字面量 `0`（可以带负号）
+fn is_zero_literal(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Literal(text, _) => text.parse::<u128>() == Ok(0),
+        ExprKind::Neg(inner) => is_zero_literal(inner),
+        _ => false,
+    }
+}
+
+/// 以 return / break / continue 结束的语句序列不产生值（结尾的 if / match 的全部分支都如此时也是）。
+/// 作为值使用的 if / match 的类型由其余的分支决定
+pub fn diverges(body: &[Expr]) -> bool {
+    body.last().is_some_and(|last| match &last.kind {
+        ExprKind::Return(_) | ExprKind::Break | ExprKind::Continue => true,
+        ExprKind::IfElse(_, then_body, else_body) => diverges(then_body) && diverges(else_body),
+        ExprKind::Match(_, arms) => !arms.is_empty() && arms.iter().all(|arm| diverges(&arm.body)),
+        _ => false,
+    })
+}
+
+/// 整数类型的取值范围
+fn int_range(t: &Type) -> std::ops::RangeInclusive<i128> {
+    match t {
+        Type::I128 => i128::MIN..=i128::MAX,
+        _ => {
+            let max = (1i128 << (int_bits(t) - 1)) - 1;
+            -max - 1..=max
+        }
+    }
+}
+
+/// 枚举中没有被任何分支覆盖的变体，例如 `Shape::Rect`
+fn missing_variants(def: &EnumDef, arms: &[MatchArm]) -> Vec<String> {
+    def.variants
+        .iter()
+        .filter(|(variant, _)| !arms.iter().any(|arm| arm.pattern.matches_variant(variant)))
+        .map(|(variant, _)| format!("{}::{}", def.name, variant))
+        .collect()
+}
+
+/// 整数类型 `ty` 中没有被任何分支覆盖的区间（最多报告前三个），
+/// 例如 `i64::MIN..=-1`、`10..=i64::MAX`
+fn missing_ranges(ty: &Type, arms: &[MatchArm]) -> Vec<String> {
+    fn intervals(pattern: &Pattern, out: &mut Vec<(i128, i128)>, full: (i128, i128)) {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => out.push(full),
+            Pattern::Int(n) => out.push((*n as i128, *n as i128)),
+            Pattern::Range(a, b, inclusive) => {
+                out.push((*a as i128, *b as i128 - !inclusive as i128))
+            }
+            Pattern::Or(alternatives) => {
+                for p in alternatives {
+                    intervals(p, out, full);
+                }
+            }
+            Pattern::Variant(..) => {}
+        }
+    }
+    let domain = int_range(ty);
+    let (min, max) = (*domain.start(), *domain.end());
+    let mut covered = Vec::new();
+    for arm in arms {
+        intervals(&arm.pattern, &mut covered, (min, max));
+    }
+    covered.retain(|(a, b)| a <= b);
+    covered.sort();
+    let bound = |v: i128| match v {
+        _ if v == min => format!("{}::MIN", ty),
+        _ if v == max => format!("{}::MAX", ty),
+        _ => v.to_string(),
+    };
+    let mut missing = Vec::new();
+    // next：下一个尚未覆盖的值，None 表示已经覆盖到类型的最大值
+    let mut next = Some(min);
+    for (a, b) in covered {
+        let Some(n) = next else { break };
+        if a > n {
+            missing.push(match a - 1 {
+                end if end == n => bound(n),
+                end => format!("{}..={}", bound(n), bound(end)),
+            });
+        }
+        if b >= n {
+            next = if b == max { None } else { Some(b + 1) };
+        }
+    }
+    if let Some(n) = next {
+        missing.push(match n {
+            _ if n == max => bound(n),
+            _ => format!("{}..={}", bound(n), bound(max)),
+        });
+    }
+    missing.truncate(3);
+    missing
+}
+
+fn int_bits(t: &Type) -> u32 {
+    match t {
+        Type::I8 => 8,
+        Type::I16 => 16,
+        Type::I32 => 32,
+        Type::I64 => 64,
+        _ => 128,
+    }
 }
 
 /// Infer type of expression.
 /// `get_var_type` is a callback to look up variable types from the current scope.
 pub fn infer_type(expr: &Expr, get_var_type: &impl Fn(&str) -> Option<Type>) -> Type {
-    match expr {
-        Expr::Literal(_, ty) => ty.clone(),
-        Expr::StringLiteral(_) => Type::String,
-        Expr::ComplexLiteral(_, _, ty) => ty.clone(),
-        Expr::ArrayLiteral(elems, _) => {
+    infer_type_with(expr, get_var_type, &|_| None, &|_, _| None)
+}
+
+/// `infer_type` 的通用版本：`get_func_ret` 用于查询用户函数的返回类型，
+/// 查不到时退回内置函数表；`get_field_type` 用于查询结构体字段的类型。
+fn infer_type_with(
+    expr: &Expr,
+    get_var_type: &impl Fn(&str) -> Option<Type>,
+    get_func_ret: &impl Fn(&str) -> Option<Type>,
+    get_field_type: &impl Fn(&Type, &str) -> Option<Type>,
+) -> Type {
+    match &expr.kind {
+        ExprKind::Literal(_, ty) => ty.clone(),
+        ExprKind::StringLiteral(_) => Type::String,
+        ExprKind::ComplexLiteral(_, _, ty) => ty.clone(),
+        ExprKind::ArrayLiteral(elems, _) => {
             if elems.is_empty() {
                 Type::Array(Box::new(Type::I64), 0)
             } else {
-                let elem_ty = infer_type(&elems[0], get_var_type);
+                let elem_ty =
+                    infer_type_with(&elems[0], get_var_type, get_func_ret, get_field_type);
                 Type::Array(Box::new(elem_ty), elems.len())
             }
         }
-        Expr::DynamicArrayLiteral(elems, _) => {
+        ExprKind::DynamicArrayLiteral(elems, _) => {
             if elems.is_empty() {
                 Type::DynamicArray(Box::new(Type::I64))
             } else {
-                let elem_ty = infer_type(&elems[0], get_var_type);
+                let elem_ty =
+                    infer_type_with(&elems[0], get_var_type, get_func_ret, get_field_type);
                 Type::DynamicArray(Box::new(elem_ty))
             }
         }
-        Expr::Cast(_, ty) => ty.clone(),
-        Expr::Add(lhs, _) | Expr::Sub(lhs, _) | Expr::Mul(lhs, _) | Expr::Div(lhs, _) => {
-            infer_type(lhs, get_var_type)
-        }
-        Expr::Eq(_, _)
-        | Expr::Ne(_, _)
-        | Expr::Lt(_, _)
-        | Expr::Le(_, _)
-        | Expr::Gt(_, _)
-        | Expr::Ge(_, _) => {
-            Type::I64 // Booleans are I64 (0 or 1)
-        }
-        Expr::Identifier(name) => get_var_type(name).unwrap_or(Type::I64),
-        Expr::Call(name, _) => {
+        ExprKind::Cast(_, ty) => ty.clone(),
+        ExprKind::Add(lhs, _)
+        | ExprKind::Sub(lhs, _)
+        | ExprKind::Mul(lhs, _)
+        | ExprKind::Div(lhs, _)
+        | ExprKind::Rem(lhs, _)
+        | ExprKind::BitAnd(lhs, _)
+        | ExprKind::BitOr(lhs, _)
+        | ExprKind::BitXor(lhs, _)
+        | ExprKind::Shl(lhs, _)
+        | ExprKind::Shr(lhs, _)
+        | ExprKind::Neg(lhs) => infer_type_with(lhs, get_var_type, get_func_ret, get_field_type),
+        ExprKind::Eq(_, _)
+        | ExprKind::Ne(_, _)
+        | ExprKind::Lt(_, _)
+        | ExprKind::Le(_, _)
+        | ExprKind::Gt(_, _)
+        | ExprKind::Ge(_, _)
+        | ExprKind::And(_, _)
+        | ExprKind::Or(_, _)
+        | ExprKind::Not(_) => Type::Bool,
+        ExprKind::Identifier(name) => get_var_type(name).unwrap_or(Type::I64),
+        ExprKind::Call(name, args) => {
+            if let Some(ret) = get_func_ret(name) {
+                return ret;
+            }
+            // 泛型数组函数先按元素类型分发，例如 array_pop(farr) -> array_pop_f64
+            let first_arg_ty = args
+                .first()
+                .map(|a| infer_type_with(a, get_var_type, get_func_ret, get_field_type));
+            let name = dispatch_array_fn(name, first_arg_ty.as_ref());
             // 查表获取函数返回类型，而不是硬编码
             match name.as_str() {
                 // 数学函数 -> F64
@@ -354,22 +2480,62 @@ pub fn infer_type(expr: &Expr, get_var_type: &impl Fn(&str) -> Option<Type>) ->
                 _ => Type::I64,
             }
         }
-        Expr::Index(base, _) => match infer_type(base, get_var_type) {
-            Type::Array(inner, _) => *inner,
-            Type::DynamicArray(inner) => *inner,
-            _ => Type::I64,
-        },
-        Expr::Assign(_, expr) => infer_type(expr, get_var_type),
-        Expr::IfElse(_, then_body, _) => {
-            if let Some(last) = then_body.last() {
-                infer_type(last, get_var_type)
+        ExprKind::Index(base, _) => {
+            match infer_type_with(base, get_var_type, get_func_ret, get_field_type) {
+                Type::Array(inner, _) => *inner,
+                Type::DynamicArray(inner) => *inner,
+                _ => Type::I64,
+            }
+        }
+        ExprKind::Assign(_, expr) => {
+            infer_type_with(expr, get_var_type, get_func_ret, get_field_type)
+        }
+        ExprKind::Let(_, _, Some(ty), _) => ty.clone(),
+        ExprKind::Let(_, _, None, expr) => {
+            infer_type_with(expr, get_var_type, get_func_ret, get_field_type)
+        }
+        // 复合赋值与索引赋值的值为写入后的新值，类型即目标的类型
+        ExprKind::CompoundAssign(name, ..) => get_var_type(name).unwrap_or(Type::I64),
+        ExprKind::IndexAssign(base, ..) => {
+            match infer_type_with(base, get_var_type, get_func_ret, get_field_type) {
+                Type::Array(inner, _) | Type::DynamicArray(inner) => *inner,
+                _ => Type::I64,
+            }
+        }
+        ExprKind::StructLiteral(name, _) | ExprKind::EnumLiteral(name, ..) => {
+            Type::Struct(name.clone())
+        }
+        // match 的类型与 if 一样取第一个产生值的分支的值
+        ExprKind::Match(_, arms) => {
+            let arm = arms.iter().find(|arm| !diverges(&arm.body));
+            match arm.or(arms.first()).and_then(|arm| arm.body.last()) {
+                Some(last) => infer_type_with(last, get_var_type, get_func_ret, get_field_type),
+                None => Type::I64,
+            }
+        }
+        ExprKind::Field(base, field) | ExprKind::FieldAssign(base, field, ..) => {
+            let base_ty = infer_type_with(base, get_var_type, get_func_ret, get_field_type);
+            get_field_type(&base_ty, field).unwrap_or(Type::I64)
+        }
+        // 以 return / break / continue 结束的 then 分支不产生值，类型取 else 分支
+        ExprKind::IfElse(_, then_body, else_body) => {
+            let body = if diverges(then_body) {
+                else_body
+            } else {
+                then_body
+            };
+            if let Some(last) = body.last() {
+                infer_type_with(last, get_var_type, get_func_ret, get_field_type)
             } else {
                 Type::I64
             }
         }
-        Expr::WhileLoop(_, _) => Type::I64,
-        Expr::GlobalDataAddr(_) => Type::I64, // Pointer
-        Expr::Drop(_) => Type::I64,           // drop() 不返回有用值
-        Expr::Block(_) => Type::I64,          // 块作用域，返回占位值
+        ExprKind::WhileLoop(_, _) => Type::I64,
+        ExprKind::ForRange(..) | ExprKind::ForEach(..) => Type::I64,
+        ExprKind::GlobalDataAddr(_) => Type::I64, // Pointer
+        ExprKind::Drop(_) => Type::I64,           // drop() 不返回有用值
+        ExprKind::Block(_) => Type::I64,          // 块作用域，返回占位值
+        ExprKind::Return(_) => Type::I64,         // return 之后的代码不可达，占位值
+        ExprKind::Break | ExprKind::Continue => Type::I64,
     }
 }
@@ -0,0 +1,491 @@
+//! 运行时值 —— `JIT::call` 的动态调用接口
+//!
+//! 宿主在运行时才知道要调用哪个 toy 函数、传什么参数时，无法写出静态的
+//! Rust 函数类型（见 `func.rs`）。`JIT::call(name, &[Value])` 按函数登记的签名
+//! 检查并编组实参，通过一个 Cranelift 生成的调用跳板完成调用：
+//!
+//! ```text
+//! &[Value] ──CallFrame::new──▶ 16 字节参数槽 ──跳板──▶ toy / 外部函数
+//!                                                        │
+//! Value ◀──CallFrame::read_return── 16 字节返回槽 ◀───────┘
+//! ```
+//!
+//! 编组规则与 `FunctionTranslator::translate_call` 一致：
+//! - 标量按 Cranelift 类型放入各自的槽，complex64 打包为 i64，complex128 打包为 i128
+//! - 字符串按 toy 的布局（长度头 + 内容 + NUL，见 `runtime::string`）构造，传内容的指针
+//! - 定长数组 `[T; N]` 传指针；调用外部函数时展开为 (ptr, len)
+//! - 动态数组 `array<T>` 在调用期间由宿主创建并持有，调用结束后释放
+
+use crate::frontend::Type;
+use crate::jit::to_cranelift_type;
+use crate::runtime::string::{self, ToyString};
+use crate::type_checker::FunctionSignature;
+use cranelift::prelude::types;
+use std::ffi::c_char;
+use std::fmt;
+
+/// 参数槽 / 返回槽的大小：足以容纳最宽的 i128 / complex128
+pub(crate) const SLOT_SIZE: usize = 16;
+
+/// 一个可以传给 toy 函数或从 toy 函数返回的值
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    F32(f32),
+    F64(f64),
+    /// (实部, 虚部)
+    Complex64(f32, f32),
+    Complex128(f64, f64),
+    String(String),
+    /// 定长数组 `[T; N]` 或动态数组 `array<T>`，由签名决定
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// 值的 toy 类型名，用于签名不匹配时的错误信息
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Bool(_) => "bool".to_string(),
+            Value::I8(_) => "i8".to_string(),
+            Value::I16(_) => "i16".to_string(),
+            Value::I32(_) => "i32".to_string(),
+            Value::I64(_) => "i64".to_string(),
+            Value::I128(_) => "i128".to_string(),
+            Value::F32(_) => "f32".to_string(),
+            Value::F64(_) => "f64".to_string(),
+            Value::Complex64(..) => "complex64".to_string(),
+            Value::Complex128(..) => "complex128".to_string(),
+            Value::String(_) => "string".to_string(),
+            Value::Array(elems) => match elems.first() {
+                Some(first) => format!("[{}; {}]", first.type_name(), elems.len()),
+                None => "[_; 0]".to_string(),
+            },
+        }
+    }
+
+    /// 按 toy 类型解析一段文本（如命令行参数）：
+    /// 布尔 `true` / `false`、整数 `42`、浮点 `1.5`、复数 `1-2i`、字符串原样、数组 `1,2,3` 或 `[1, 2, 3]`
+    pub fn parse(ty: &Type, text: &str) -> Result<Value, String> {
+        let invalid = || format!("invalid {} value: '{}'", ty, text);
+        let trimmed = text.trim();
+        let value = match ty {
+            Type::Bool => trimmed.parse().ok().map(Value::Bool),
+            Type::I8 => trimmed.parse().ok().map(Value::I8),
+            Type::I16 => trimmed.parse().ok().map(Value::I16),
+            Type::I32 => trimmed.parse().ok().map(Value::I32),
+            Type::I64 => trimmed.parse().ok().map(Value::I64),
+            Type::I128 => trimmed.parse().ok().map(Value::I128),
+            Type::F32 => trimmed.parse().ok().map(Value::F32),
+            Type::F64 => trimmed.parse().ok().map(Value::F64),
+            Type::Complex64 => {
+                parse_complex(trimmed).map(|(re, im)| Value::Complex64(re as f32, im as f32))
+            }
+            Type::Complex128 => parse_complex(trimmed).map(|(re, im)| Value::Complex128(re, im)),
+            Type::String => Some(Value::String(text.to_string())),
+            // 结构体没有文本表示；签名中的数组长度在编译时已换成常量的值
+            Type::Struct(_) | Type::ConstLenArray(..) => None,
+            Type::Array(elem_ty, _) | Type::DynamicArray(elem_ty) => {
+                let inner = trimmed
+                    .strip_prefix('[')
+                    .and_then(|t| t.strip_suffix(']'))
+                    .unwrap_or(trimmed);
+                if inner.trim().is_empty() {
+                    return Ok(Value::Array(Vec::new()));
+                }
+                let elems = inner
+                    .split(',')
+                    .map(|e| Value::parse(elem_ty, e))
+                    .collect::<Result<_, _>>()?;
+                Some(Value::Array(elems))
+            }
+        };
+        value.ok_or_else(invalid)
+    }
+
+    /// 整数值（用作进程退出码等）；非整数返回 None
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::I8(v) => Some(v as i64),
+            Value::I16(v) => Some(v as i64),
+            Value::I32(v) => Some(v as i64),
+            Value::I64(v) => Some(v),
+            Value::I128(v) => Some(v as i64),
+            _ => None,
+        }
+    }
+
+    /// 是否可以作为 `ty` 类型的实参。
+    /// 外部函数声明的 `[T; 0]` 表示接受任意长度的数组
+    fn matches(&self, ty: &Type, is_external: bool) -> bool {
+        match (self, ty) {
+            (Value::Array(elems), Type::Array(elem_ty, len)) => {
+                (elems.len() == *len || (is_external && *len == 0))
+                    && elems.iter().all(|e| e.matches(elem_ty, false))
+            }
+            (Value::Array(elems), Type::DynamicArray(elem_ty)) => {
+                elems.iter().all(|e| e.matches(elem_ty, false))
+            }
+            (Value::String(_), Type::String) => true,
+            _ => self.scalar_type().as_ref() == Some(ty),
+        }
+    }
+
+    /// 标量（可直接放进一个槽的值）对应的 toy 类型
+    fn scalar_type(&self) -> Option<Type> {
+        Some(match self {
+            Value::Bool(_) => Type::Bool,
+            Value::I8(_) => Type::I8,
+            Value::I16(_) => Type::I16,
+            Value::I32(_) => Type::I32,
+            Value::I64(_) => Type::I64,
+            Value::I128(_) => Type::I128,
+            Value::F32(_) => Type::F32,
+            Value::F64(_) => Type::F64,
+            Value::Complex64(..) => Type::Complex64,
+            Value::Complex128(..) => Type::Complex128,
+            Value::String(_) | Value::Array(_) => return None,
+        })
+    }
+
+    /// 把标量按其 Cranelift 表示写入 `out` 的开头
+    pub(crate) fn encode_scalar(&self, out: &mut [u8]) {
+        let mut put = |bytes: &[u8]| out[..bytes.len()].copy_from_slice(bytes);
+        match *self {
+            Value::Bool(v) => put(&[v as u8]),
+            Value::I8(v) => put(&v.to_ne_bytes()),
+            Value::I16(v) => put(&v.to_ne_bytes()),
+            Value::I32(v) => put(&v.to_ne_bytes()),
+            Value::I64(v) => put(&v.to_ne_bytes()),
+            Value::I128(v) => put(&v.to_ne_bytes()),
+            Value::F32(v) => put(&v.to_ne_bytes()),
+            Value::F64(v) => put(&v.to_ne_bytes()),
+            // 与 translate_complex_literal 相同：低 32 位实部，高 32 位虚部
+            Value::Complex64(re, im) => {
+                let packed = re.to_bits() as u64 | ((im.to_bits() as u64) << 32);
+                put(&packed.to_ne_bytes())
+            }
+            // 与 translate_complex_literal 相同：内存中实部在前、虚部在后
+            Value::Complex128(re, im) => {
+                put(&re.to_ne_bytes());
+                out[8..16].copy_from_slice(&im.to_ne_bytes());
+            }
+            Value::String(_) | Value::Array(_) => unreachable!("not a scalar"),
+        }
+    }
+
+    /// 按 toy 类型从 `bytes` 的开头读出一个标量
+    fn decode_scalar(ty: &Type, bytes: &[u8]) -> Option<Value> {
+        fn take<const N: usize>(bytes: &[u8]) -> [u8; N] {
+            bytes[..N].try_into().unwrap()
+        }
+        Some(match ty {
+            Type::Bool => Value::Bool(bytes[0] != 0),
+            Type::I8 => Value::I8(i8::from_ne_bytes(take(bytes))),
+            Type::I16 => Value::I16(i16::from_ne_bytes(take(bytes))),
+            Type::I32 => Value::I32(i32::from_ne_bytes(take(bytes))),
+            Type::I64 => Value::I64(i64::from_ne_bytes(take(bytes))),
+            Type::I128 => Value::I128(i128::from_ne_bytes(take(bytes))),
+            Type::F32 => Value::F32(f32::from_ne_bytes(take(bytes))),
+            Type::F64 => Value::F64(f64::from_ne_bytes(take(bytes))),
+            Type::Complex64 => {
+                let packed = u64::from_ne_bytes(take(bytes));
+                Value::Complex64(
+                    f32::from_bits(packed as u32),
+                    f32::from_bits((packed >> 32) as u32),
+                )
+            }
+            Type::Complex128 => Value::Complex128(
+                f64::from_ne_bytes(take(bytes)),
+                f64::from_ne_bytes(take(&bytes[8..])),
+            ),
+            Type::String
+            | Type::Array(..)
+            | Type::ConstLenArray(..)
+            | Type::DynamicArray(_)
+            | Type::Struct(_) => {
+                return None;
+            }
+        })
+    }
+}
+
+/// 与 `Value::parse` 接受的写法一致：`1.5+2i`、`1.5-2i`
+fn write_complex(f: &mut fmt::Formatter<'_>, re: f64, im: f64) -> fmt::Result {
+    if im.is_sign_negative() {
+        write!(f, "{}-{}i", re, -im)
+    } else {
+        write!(f, "{}+{}i", re, im)
+    }
+}
+
+/// 解析 `a+bi` / `a-bi` / `bi` / `a` 形式的复数
+fn parse_complex(text: &str) -> Option<(f64, f64)> {
+    let Some(body) = text.strip_suffix('i') else {
+        return Some((text.parse().ok()?, 0.0));
+    };
+    // 实部与虚部之间的符号：不在开头、也不是指数 (1e-3) 的一部分
+    let split = body
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| (c == '+' || c == '-') && i > 0 && !body[..i].ends_with(['e', 'E']))
+        .map(|(i, _)| i);
+    let (re, im) = match split {
+        Some(i) => (body[..i].parse().ok()?, &body[i..]),
+        None => (0.0, body),
+    };
+    let im = match im {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        _ => im.parse().ok()?,
+    };
+    Some((re, im))
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::I8(v) => write!(f, "{}", v),
+            Value::I16(v) => write!(f, "{}", v),
+            Value::I32(v) => write!(f, "{}", v),
+            Value::I64(v) => write!(f, "{}", v),
+            Value::I128(v) => write!(f, "{}", v),
+            Value::F32(v) => write!(f, "{}", v),
+            Value::F64(v) => write!(f, "{}", v),
+            Value::Complex64(re, im) => write_complex(f, *re as f64, *im as f64),
+            Value::Complex128(re, im) => write_complex(f, *re, *im),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Array(elems) => {
+                write!(f, "[")?;
+                for (i, e) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// 编组失败的原因，由 `JIT::call` 转换为 `CallError`
+pub(crate) enum MarshalError {
+    /// 实参个数或类型与签名不一致
+    Mismatch,
+    /// 该类型无法通过动态调用传递
+    Unsupported(Type),
+}
+
+/// 调用期间由宿主持有的动态数组（对应 runtime::array 中的 `Box<Vec<T>>`）
+enum HostArray {
+    I64(*mut Vec<i64>),
+    F64(*mut Vec<f64>),
+    Complex128(*mut Vec<i128>),
+}
+
+impl HostArray {
+    fn new(elem_ty: &Type, elems: &[Value]) -> Option<Self> {
+        let mut buf = [0u8; SLOT_SIZE];
+        let mut scalar = |e: &Value| {
+            e.encode_scalar(&mut buf);
+            buf
+        };
+        Some(match elem_ty {
+            Type::I64 => HostArray::I64(Box::into_raw(Box::new(
+                elems
+                    .iter()
+                    .map(|e| i64::from_ne_bytes(scalar(e)[..8].try_into().unwrap()))
+                    .collect(),
+            ))),
+            Type::F64 => HostArray::F64(Box::into_raw(Box::new(
+                elems
+                    .iter()
+                    .map(|e| f64::from_ne_bytes(scalar(e)[..8].try_into().unwrap()))
+                    .collect(),
+            ))),
+            Type::Complex128 => HostArray::Complex128(Box::into_raw(Box::new(
+                elems
+                    .iter()
+                    .map(|e| i128::from_ne_bytes(scalar(e)))
+                    .collect(),
+            ))),
+            _ => return None,
+        })
+    }
+
+    fn addr(&self) -> usize {
+        match *self {
+            HostArray::I64(p) => p as usize,
+            HostArray::F64(p) => p as usize,
+            HostArray::Complex128(p) => p as usize,
+        }
+    }
+
+    /// 接管 toy 函数返回的动态数组并转换为 `Value::Array`
+    ///
+    /// # Safety
+    /// `addr` 必须是 runtime::array 创建、且尚未释放的 `elem_ty` 类型数组
+    unsafe fn take(elem_ty: &Type, addr: usize) -> Option<Vec<Value>> {
+        Some(match elem_ty {
+            Type::I64 => unsafe { Box::from_raw(addr as *mut Vec<i64>) }
+                .into_iter()
+                .map(Value::I64)
+                .collect(),
+            Type::F64 => unsafe { Box::from_raw(addr as *mut Vec<f64>) }
+                .into_iter()
+                .map(Value::F64)
+                .collect(),
+            Type::Complex128 => unsafe { Box::from_raw(addr as *mut Vec<i128>) }
+                .into_iter()
+                .map(|v| Value::decode_scalar(&Type::Complex128, &v.to_ne_bytes()).unwrap())
+                .collect(),
+            _ => return None,
+        })
+    }
+}
+
+impl Drop for HostArray {
+    fn drop(&mut self) {
+        // SAFETY: 指针由 HostArray::new 通过 Box::into_raw 创建，只释放一次
+        unsafe {
+            match *self {
+                HostArray::I64(p) => drop(Box::from_raw(p)),
+                HostArray::F64(p) => drop(Box::from_raw(p)),
+                HostArray::Complex128(p) => drop(Box::from_raw(p)),
+            }
+        }
+    }
+}
+
+/// 一次动态调用的实参：按 ABI 展开后的参数槽，以及调用期间必须存活的内存
+pub(crate) struct CallFrame {
+    /// 每个 ABI 参数占一个 16 字节对齐的槽
+    pub slots: Vec<u128>,
+    /// 每个槽的 Cranelift 类型，用于生成调用跳板
+    pub abi_params: Vec<types::Type>,
+    strings: Vec<ToyString>,
+    arrays: Vec<Vec<u128>>,
+    host_arrays: Vec<HostArray>,
+}
+
+impl CallFrame {
+    pub fn new(sig: &FunctionSignature, args: &[Value]) -> Result<Self, MarshalError> {
+        if args.len() != sig.params.len() {
+            return Err(MarshalError::Mismatch);
+        }
+        // 定长数组返回值指向被调函数的栈帧，调用前就拒绝；
+        // 结构体按指针 / sret 传递，Value 没有对应的表示
+        if matches!(sig.ret, Type::Array(..) | Type::Struct(_)) {
+            return Err(MarshalError::Unsupported(sig.ret.clone()));
+        }
+        if let Some(ty) = sig.params.iter().find(|t| matches!(t, Type::Struct(_))) {
+            return Err(MarshalError::Unsupported(ty.clone()));
+        }
+        let mut frame = CallFrame {
+            slots: Vec::new(),
+            abi_params: Vec::new(),
+            strings: Vec::new(),
+            arrays: Vec::new(),
+            host_arrays: Vec::new(),
+        };
+        for (arg, ty) in args.iter().zip(&sig.params) {
+            if !arg.matches(ty, sig.is_external) {
+                return Err(MarshalError::Mismatch);
+            }
+            frame.push_arg(arg, ty, sig.is_external)?;
+        }
+        Ok(frame)
+    }
+
+    fn push_slot(&mut self, ty: types::Type, write: impl FnOnce(&mut [u8])) {
+        let mut bytes = [0u8; SLOT_SIZE];
+        write(&mut bytes);
+        self.slots.push(u128::from_ne_bytes(bytes));
+        self.abi_params.push(ty);
+    }
+
+    fn push_pointer(&mut self, addr: usize) {
+        self.push_slot(types::I64, |out| {
+            out[..8].copy_from_slice(&(addr as u64).to_ne_bytes())
+        });
+    }
+
+    fn push_arg(&mut self, arg: &Value, ty: &Type, is_external: bool) -> Result<(), MarshalError> {
+        match (arg, ty) {
+            (Value::String(s), _) => {
+                let s = ToyString::new(s);
+                self.push_pointer(s.as_ptr() as usize);
+                self.strings.push(s);
+            }
+            (Value::Array(elems), Type::Array(elem_ty, _)) => {
+                let elem_size = to_cranelift_type(elem_ty).bytes() as usize;
+                let mut buf = vec![0u128; (elems.len() * elem_size).div_ceil(SLOT_SIZE)];
+                // SAFETY: buf 的长度按字节数向上取整到槽大小，且 u128 对齐满足所有元素类型
+                let bytes = unsafe {
+                    std::slice::from_raw_parts_mut(
+                        buf.as_mut_ptr() as *mut u8,
+                        buf.len() * SLOT_SIZE,
+                    )
+                };
+                let mut scratch = [0u8; SLOT_SIZE];
+                for (i, e) in elems.iter().enumerate() {
+                    e.encode_scalar(&mut scratch);
+                    bytes[i * elem_size..(i + 1) * elem_size]
+                        .copy_from_slice(&scratch[..elem_size]);
+                }
+                self.push_pointer(buf.as_ptr() as usize);
+                self.arrays.push(buf);
+                // 外部函数的数组参数展开为 (ptr, len)
+                if is_external {
+                    let len = elems.len() as i64;
+                    self.push_slot(types::I64, |out| {
+                        out[..8].copy_from_slice(&len.to_ne_bytes())
+                    });
+                }
+            }
+            (Value::Array(elems), Type::DynamicArray(elem_ty)) => {
+                let array = HostArray::new(elem_ty, elems)
+                    .ok_or_else(|| MarshalError::Unsupported(ty.clone()))?;
+                self.push_pointer(array.addr());
+                self.host_arrays.push(array);
+            }
+            _ => self.push_slot(to_cranelift_type(ty), |out| arg.encode_scalar(out)),
+        }
+        Ok(())
+    }
+
+    /// 按返回类型解读跳板写回的返回槽
+    ///
+    /// # Safety
+    /// `ret` 必须是对该 frame 对应函数调用后得到的返回槽
+    pub unsafe fn read_return(&mut self, ty: &Type, ret: u128) -> Result<Value, MarshalError> {
+        let bytes = ret.to_ne_bytes();
+        let addr = u64::from_ne_bytes(bytes[..8].try_into().unwrap()) as usize;
+        match ty {
+            Type::String if addr == 0 => Ok(Value::String(String::new())),
+            Type::String => {
+                // SAFETY: toy 字符串带长度头（字面量的静态数据或调用方传入的 ToyString）
+                let s = unsafe { string::content(addr as *const c_char) };
+                Ok(Value::String(String::from_utf8_lossy(s).into_owned()))
+            }
+            Type::DynamicArray(elem_ty) => {
+                // 如果返回的就是传入的某个数组，所有权随返回值交回宿主，不能再释放一次
+                if let Some(i) = self.host_arrays.iter().position(|a| a.addr() == addr) {
+                    std::mem::forget(self.host_arrays.remove(i));
+                }
+                // SAFETY: 返回变量的所有权转移给调用者（见 emit_scope_drop 跳过返回变量）
+                unsafe { HostArray::take(elem_ty, addr) }
+                    .map(Value::Array)
+                    .ok_or_else(|| MarshalError::Unsupported(ty.clone()))
+            }
+            Type::Array(..) => Err(MarshalError::Unsupported(ty.clone())),
+            _ => Ok(Value::decode_scalar(ty, &bytes).expect("scalar type")),
+        }
+    }
+}
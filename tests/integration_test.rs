@@ -1,4 +1,10 @@
+use cranelift_jit_demo::error::{CallError, CompileError};
 use cranelift_jit_demo::jit::JIT;
+use cranelift_jit_demo::ownership::OwnershipError;
+use cranelift_jit_demo::pipeline::{self, CompileOptions, Emit};
+use cranelift_jit_demo::runtime::string::ToyString;
+use cranelift_jit_demo::type_checker::TypeError;
+use cranelift_jit_demo::value::Value;
 
 #[test]
 fn test_math_functions() {
@@ -9,10 +15,10 @@ fn test_math_functions() {
     }
     "#;
 
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn(f64) -> f64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(f64,), f64>("test_sin").unwrap();
 
-    let result = func(std::f64::consts::PI / 2.0);
+    let result = func.call((std::f64::consts::PI / 2.0,));
     assert!((result - 1.0).abs() < 1e-6);
 }
 
@@ -25,10 +31,10 @@ fn test_pow() {
     }
     "#;
 
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn(f64, f64) -> f64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(f64, f64), f64>("test_pow").unwrap();
 
-    let result = func(2.0, 3.0);
+    let result = func.call((2.0, 3.0));
     assert!((result - 8.0).abs() < 1e-6);
 }
 
@@ -48,14 +54,12 @@ fn test_mkl_dgemm() {
     }
     "#;
 
-    let func_ptr = jit.compile(code).unwrap();
-    // The JIT function signature will be: extern "C" fn(*mut f64) -> i64
-    // Wait, the JIT function itself ONLY gets the pointer for its own parameters.
-    // It doesn't get the length expanded for its OWN parameters.
-    let func: fn(*mut f64) -> i64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    // toy 函数自己的数组形参只接收指针，不展开 (ptr, len)
+    let func = jit.get_func::<(&mut [f64; 4],), i64>("test_dgemm").unwrap();
 
     let mut c = [0.0f64; 4];
-    func(c.as_mut_ptr());
+    func.call((&mut c,));
 
     assert_eq!(c[0], 19.0);
     assert_eq!(c[1], 22.0);
@@ -71,11 +75,115 @@ fn test_signed_division() {
         r = a / b
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn(i64, i64) -> i64 = unsafe { std::mem::transmute(func_ptr) };
-    assert_eq!(func(-10, 3), -3);
-    assert_eq!(func(10, -3), -3);
-    assert_eq!(func(-10, -3), 3);
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(i64, i64), i64>("test_sdiv").unwrap();
+    assert_eq!(func.call((-10, 3)), -3);
+    assert_eq!(func.call((10, -3)), -3);
+    assert_eq!(func.call((-10, -3)), 3);
+}
+
+#[test]
+fn test_unary_remainder_bitwise_and_shift() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn rem(a: i64, b: i64) -> (r: i64) {
+        r = a % b
+    }
+
+    fn bits(a: i32, b: i32) -> (r: i32) {
+        r = (a & b) ^ (a | -b)
+    }
+
+    fn shr8(a: i8, n: i64) -> (r: i8) {
+        r = a >> n
+    }
+
+    fn shl(a: i64, n: i64) -> (r: i64) {
+        r = a << n
+    }
+
+    fn fmod(x: f64, y: f64) -> (r: f64) {
+        r = -x % y
+    }
+
+    fn fmod32(x: f32, y: f32) -> (r: f32) {
+        r = x % y
+    }
+
+    fn wide_rem(a: i128, b: i128) -> (r: i128) {
+        r = -a % b
+    }
+
+    fn wide_div(a: i128, b: i128) -> (r: i128) {
+        r = a / b
+        r /= 2i128
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // 余数与被除数同号
+    let rem = jit.get_func::<(i64, i64), i64>("rem").unwrap();
+    assert_eq!(rem.call((-17, 5)), -17 % 5);
+    assert_eq!(rem.call((17, -5)), 17 % -5);
+    let bits = jit.get_func::<(i32, i32), i32>("bits").unwrap();
+    assert_eq!(
+        bits.call((0b1100, 0b1010)),
+        (0b1100 & 0b1010) ^ (0b1100 | -0b1010)
+    );
+    // >> 是算术右移，移位量可以比左侧宽
+    let shr8 = jit.get_func::<(i8, i64), i8>("shr8").unwrap();
+    assert_eq!(shr8.call((-128, 3)), -16);
+    // 与 Cranelift 一致，移位量按位宽取模
+    let shl = jit.get_func::<(i64, i64), i64>("shl").unwrap();
+    assert_eq!(shl.call((3, 4)), 48);
+    assert_eq!(shl.call((1, 65)), 2);
+    let fmod = jit.get_func::<(f64, f64), f64>("fmod").unwrap();
+    assert_eq!(fmod.call((7.5, 2.0)), -1.5);
+    let fmod32 = jit.get_func::<(f32, f32), f32>("fmod32").unwrap();
+    assert_eq!(fmod32.call((7.5, -2.0)), 1.5);
+    // i128 的 `/` 与 `%` 走运行时函数
+    let wide_rem = jit.get_func::<(i128, i128), i128>("wide_rem").unwrap();
+    assert_eq!(wide_rem.call((5, 3)), -2);
+    assert_eq!(wide_rem.call((-(1 << 100) - 1, 1 << 64)), 1);
+    assert_eq!(wide_rem.call((i128::MAX, -1)), 0);
+    let wide_div = jit.get_func::<(i128, i128), i128>("wide_div").unwrap();
+    assert_eq!(wide_div.call((-(1 << 100), 3)), -(1 << 100) / 3 / 2);
+}
+
+#[test]
+fn test_equal_precedence_operators_are_left_associative() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn chain(a: i64, b: i64, c: i64) -> (r: i64) {
+        r = (a << b << c) + (a * 64 >> b >> c) * 1000
+    }
+
+    fn arith(a: i64, b: i64, c: i64) -> (r: i64) {
+        r = (a - b - c) * 100 + a / b / c
+    }
+
+    fn rem(a: i64, b: i64, c: i64) -> (r: i64) {
+        r = a % b % c * 10 + a * b % c
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // (1 << 2) << 3 = 32，(64 >> 2) >> 3 = 2
+    let chain = jit.get_func::<(i64, i64, i64), i64>("chain").unwrap();
+    assert_eq!(chain.call((1, 2, 3)), 32 + 2 * 1000);
+    // (20 - 4) - 2 = 14，(20 / 4) / 2 = 2
+    let arith = jit.get_func::<(i64, i64, i64), i64>("arith").unwrap();
+    assert_eq!(arith.call((20, 4, 2)), 1400 + 2);
+    // (17 % 7) % 2 = 1，(17 * 7) % 2 = 1
+    let rem = jit.get_func::<(i64, i64, i64), i64>("rem").unwrap();
+    assert_eq!(rem.call((17, 7, 2)), 10 + 1);
+
+    // 常量折叠与运行时的结合方向一致
+    let mut jit = JIT::default();
+    jit.compile(
+        "fn folded() -> (r: i64) {\n    r = (1 << 2 << 3) + (64 >> 2 >> 1) * 100 + (17 % 7 % 2) * 10000\n}\n",
+    )
+    .unwrap();
+    let folded = jit.get_func::<(), i64>("folded").unwrap();
+    assert_eq!(folded.call(()), 32 + 800 + 10000);
 }
 
 // ══════════════════════════════════════════════════════
@@ -94,9 +202,9 @@ fn test_block_scope_basic() {
         }
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn() -> i64 = unsafe { std::mem::transmute(func_ptr) };
-    assert_eq!(func(), 1);
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(), i64>("test").unwrap();
+    assert_eq!(func.call(()), 1);
 }
 
 #[test]
@@ -113,9 +221,9 @@ fn test_nested_block_scope() {
         }
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn() -> i64 = unsafe { std::mem::transmute(func_ptr) };
-    assert_eq!(func(), 42);
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(), i64>("test").unwrap();
+    assert_eq!(func.call(()), 42);
 }
 
 #[test]
@@ -125,7 +233,7 @@ fn test_block_with_if() {
     let code = r#"
     fn test(flag: i64) -> (r: i64) {
         {
-            if flag {
+            if flag != 0 {
                 a = array [10]
                 r = a[0]
             } else {
@@ -135,10 +243,10 @@ fn test_block_with_if() {
         }
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn(i64) -> i64 = unsafe { std::mem::transmute(func_ptr) };
-    assert_eq!(func(1), 10);
-    assert_eq!(func(0), 20);
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(i64,), i64>("test").unwrap();
+    assert_eq!(func.call((1,)), 10);
+    assert_eq!(func.call((0,)), 20);
 }
 
 #[test]
@@ -158,10 +266,10 @@ fn test_while_loop_no_leak() {
         r = sum
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn() -> i64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(), i64>("test").unwrap();
     // sum 0..99 = 4950
-    assert_eq!(func(), 4950);
+    assert_eq!(func.call(()), 4950);
 }
 
 #[test]
@@ -181,8 +289,2011 @@ fn test_while_loop_auto_drop() {
         drop(outer)
     }
     "#;
-    let func_ptr = jit.compile(code).unwrap();
-    let func: fn() -> i64 = unsafe { std::mem::transmute(func_ptr) };
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(), i64>("test").unwrap();
     // 循环体数组迭代释放，外层数组在循环后仍可访问
-    assert_eq!(func(), 300);
+    assert_eq!(func.call(()), 300);
+}
+
+#[test]
+fn test_early_return() {
+    // return 可以出现在嵌套的 if / while / {} 中
+    let mut jit = JIT::default();
+    let code = r#"
+    fn find(n: i64, target: i64) -> (r: i64) {
+        i = 0
+        while i < n {
+            {
+                if i == target {
+                    return i * 10
+                } else {
+                    i = i
+                }
+            }
+            i = i + 1
+        }
+        r = 0 - 1
+    }
+
+    fn sign(x: f64) -> (r: i64) {
+        if x < 0.0 {
+            return 0 - 1
+        } else {
+            r = 0
+        }
+        if x == 0.0 {
+            return 0
+        } else {
+            return 1
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let find = jit.get_func::<(i64, i64), i64>("find").unwrap();
+    assert_eq!(find.call((10, 3)), 30);
+    assert_eq!(find.call((10, 42)), -1);
+    let sign = jit.get_func::<(f64,), i64>("sign").unwrap();
+    assert_eq!(sign.call((-2.5,)), -1);
+    assert_eq!(sign.call((0.0,)), 0);
+    assert_eq!(sign.call((7.0,)), 1);
+}
+
+#[test]
+fn test_diverging_branch_has_no_value() {
+    // 以 return / break / continue 结束的分支不产生值，if / match 的类型由其余分支决定
+    let mut jit = JIT::default();
+    let code = r#"
+    fn half(c: bool) -> (r: f64) {
+        r = if c { return 0.5 } else { 2.5 }
+        r = r + 1.0
+    }
+
+    fn sum(n: i64) -> (r: f64) {
+        r = 0.0
+        for i in 0..n {
+            y = match i {
+                3 => break,
+                _ => if i == 1 { continue } else { 1.5 },
+            }
+            r = r + y
+        }
+    }
+    "#;
+    assert!(pipeline::check_program(code).is_empty());
+    jit.compile_program(code).unwrap();
+    let half = jit.get_func::<(bool,), f64>("half").unwrap();
+    assert_eq!(half.call((true,)), 0.5);
+    assert_eq!(half.call((false,)), 3.5);
+    let sum = jit.get_func::<(i64,), f64>("sum").unwrap();
+    assert_eq!(sum.call((10,)), 3.0);
+}
+
+/// 按 `Emit::Clif` 输出统计每个基本块中对 `array_drop*` 的调用：(块的结束指令, 调用次数)
+fn drop_calls_by_block(clif: &str) -> Vec<(String, usize)> {
+    let drop_refs: Vec<&str> = clif
+        .lines()
+        .filter(|line| line.contains("; array_drop"))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    let is_drop = |line: &str| {
+        drop_refs
+            .iter()
+            .any(|f| line.contains(&format!("call {}(", f)))
+    };
+    let mut blocks: Vec<(String, usize)> = Vec::new();
+    for line in clif.lines() {
+        if line.starts_with("block") {
+            blocks.push((String::new(), 0));
+        } else if let Some((last, drops)) = blocks.last_mut()
+            && line.starts_with("    ")
+        {
+            *last = line.trim().to_string();
+            *drops += is_drop(line) as usize;
+        }
+    }
+    blocks
+}
+
+/// 编译 `code` 并返回函数 `name` 的 CLIF 文本
+fn clif_of(code: &str, name: &str) -> String {
+    let options = CompileOptions {
+        emit: vec![Emit::Clif],
+        ..CompileOptions::default()
+    };
+    let mut jit = JIT::new(options);
+    jit.compile_program(code).unwrap();
+    jit.artifacts()
+        .iter()
+        .find(|a| a.function == name)
+        .map(|a| a.text.clone())
+        .unwrap()
+}
+
+#[test]
+fn test_early_return_releases_arrays() {
+    // 提前 return 前释放各层作用域的数组：被返回的数组交给调用者，
+    // 上一轮迭代已释放的 tmp 不会被再次释放
+    let mut jit = JIT::default();
+    let code = r#"
+    fn pick(n: i64) -> (r: array<i64>) {
+        kept = array [1, 2, 3]
+        i = 0
+        while i < 5 {
+            if i == n {
+                return kept
+            } else {
+                tmp = array [i]
+            }
+            i = i + 1
+        }
+        r = array [n]
+        drop(kept)
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let kept = Value::Array(vec![Value::I64(1), Value::I64(2), Value::I64(3)]);
+    assert_eq!(jit.call("pick", &[Value::I64(3)]), Ok(kept.clone()));
+    assert_eq!(jit.call("pick", &[Value::I64(0)]), Ok(kept));
+    assert_eq!(
+        jit.call("pick", &[Value::I64(9)]),
+        Ok(Value::Array(vec![Value::I64(9)]))
+    );
+
+    // 两个 return 各释放一个数组：循环中的 return 释放 tmp，函数末尾释放 kept
+    let clif = clif_of(code, "pick");
+    let returns: Vec<usize> = drop_calls_by_block(&clif)
+        .into_iter()
+        .filter(|(last, _)| last.starts_with("return"))
+        .map(|(_, drops)| drops)
+        .collect();
+    assert_eq!(returns, [1, 1], "{}", clif);
+}
+
+#[test]
+fn test_early_return_after_branch_drop() {
+    // then 分支 drop(a) 不影响 else 分支：return 5 的路径上 a 仍未释放，return 前要释放它
+    let code = r#"
+    fn f(c: bool) -> (r: i64) {
+        a = array [1, 2]
+        if c {
+            drop(a)
+        } else {
+            return 5
+        }
+        r = 1
+    }
+    "#;
+    let clif = clif_of(code, "f");
+    for (last, drops) in drop_calls_by_block(&clif) {
+        if last.starts_with("return") {
+            assert_eq!(drops, 1, "{}", clif);
+        }
+    }
+    let mut jit = JIT::default();
+    jit.compile_program(code).unwrap();
+    let f = jit.get_func::<(bool,), i64>("f").unwrap();
+    assert_eq!(f.call((true,)), 1);
+    assert_eq!(f.call((false,)), 5);
+}
+
+#[test]
+fn test_else_if_and_optional_else() {
+    // else if 链；省略 else 时 if 表达式在条件不成立时的值为 0（按 then 分支的类型）
+    let mut jit = JIT::default();
+    let code = r#"
+    fn grade(score: i64) -> (r: i64) {
+        if score >= 90 {
+            r = 4
+        } else if score >= 80 {
+            r = 3
+        } else if score >= 70 {
+            r = 2
+        } else {
+            r = 1
+        }
+    }
+
+    fn bonus(x: i64) -> (r: i64) {
+        r = 10
+        if x > 0 {
+            r = r + x
+        }
+    }
+
+    fn half(x: i64) -> (r: f64) {
+        r = if x > 0 {
+            x as f64 / 2.0
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let grade = jit.get_func::<(i64,), i64>("grade").unwrap();
+    let grades: Vec<i64> = [95, 85, 75, 10].iter().map(|&s| grade.call((s,))).collect();
+    assert_eq!(grades, vec![4, 3, 2, 1]);
+    let bonus = jit.get_func::<(i64,), i64>("bonus").unwrap();
+    assert_eq!(bonus.call((5,)), 15);
+    assert_eq!(bonus.call((-5,)), 10);
+    let half = jit.get_func::<(i64,), f64>("half").unwrap();
+    assert_eq!(half.call((3,)), 1.5);
+    assert_eq!(half.call((0,)), 0.0);
+}
+
+#[test]
+fn test_break_and_continue() {
+    // break / continue 作用于最内层循环，可以出现在嵌套的 if / {} 中
+    let mut jit = JIT::default();
+    let code = r#"
+    fn odd_sum_until(n: i64, stop: i64) -> (r: i64) {
+        r = 0
+        i = 0
+        odd = 0
+        while i < n {
+            i = i + 1
+            odd = 1 - odd
+            if i == stop {
+                break
+            } else {
+                r = r
+            }
+            {
+                if odd == 0 {
+                    continue
+                } else {
+                    r = r + i
+                }
+            }
+        }
+    }
+
+    fn pairs(n: i64) -> (r: i64) {
+        r = 0
+        i = 0
+        while i < n {
+            j = 0
+            while true {
+                if j == i {
+                    break
+                } else {
+                    r = r + 1
+                }
+                j = j + 1
+            }
+            i = i + 1
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let odd_sum_until = jit.get_func::<(i64, i64), i64>("odd_sum_until").unwrap();
+    assert_eq!(odd_sum_until.call((10, 100)), 25);
+    assert_eq!(odd_sum_until.call((10, 6)), 9);
+    let pairs = jit.get_func::<(i64,), i64>("pairs").unwrap();
+    assert_eq!(pairs.call((5,)), 10);
+
+    let err = jit
+        .compile("fn bad() -> (r: i64) {\n    break\n}\n")
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("'break' outside of a loop"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_break_and_continue_release_arrays() {
+    // 跳转前释放循环体内的数组。skip 那一轮在 tmp 重新赋值前就 break，
+    // 此时 tmp 还是上一轮 drop(tmp) 后的值：drop 后已置空，不会被 break 路径再次释放
+    let mut jit = JIT::default();
+    let code = r#"
+    fn search(rounds: i64, skip: i64) -> (r: i64) {
+        r = 0
+        outer = 0
+        while outer < rounds {
+            i = 0
+            while i < 10 {
+                i = i + 1
+                if outer == skip {
+                    break
+                } else {
+                    r = r
+                }
+                tmp = array [i, i * 2]
+                if tmp[1] == 4 {
+                    continue
+                } else {
+                    r = r + tmp[0]
+                }
+                if tmp[0] == 7 {
+                    drop(tmp)
+                    break
+                } else {
+                    r = r
+                }
+            }
+            outer = outer + 1
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let search = jit.get_func::<(i64, i64), i64>("search").unwrap();
+    // 每轮累加 1 + 3 + 4 + 5 + 6 + 7，在 7 处 drop 后 break
+    assert_eq!(search.call((3, 100)), 3 * 26);
+    assert_eq!(search.call((3, 1)), 2 * 26);
+
+    // 每条离开内层循环体的路径都释放 tmp：skip 时的 break、continue、
+    // drop(tmp) 与其后的 break（对已置空的 tmp 是 no-op）、迭代结束
+    let clif = clif_of(code, "search");
+    let drops: usize = drop_calls_by_block(&clif).iter().map(|(_, n)| n).sum();
+    assert_eq!(drops, 5, "{}", clif);
+}
+
+#[test]
+fn test_break_after_branch_drop() {
+    // then 分支 drop(t) 不影响 else 分支：break 的路径上 t 仍未释放，跳出前要释放它
+    let code = r#"
+    fn f(n: i64) -> (r: i64) {
+        r = 0
+        i = 0
+        while i < n {
+            t = array [i, i * 2]
+            i = i + 1
+            if t[0] < 3 {
+                r = r + t[1]
+                drop(t)
+            } else {
+                break
+            }
+        }
+    }
+    "#;
+    // drop(t)、break 之前、迭代结束各一次
+    let clif = clif_of(code, "f");
+    let drops: usize = drop_calls_by_block(&clif).iter().map(|(_, n)| n).sum();
+    assert_eq!(drops, 3, "{}", clif);
+    let mut jit = JIT::default();
+    jit.compile_program(code).unwrap();
+    let f = jit.get_func::<(i64,), i64>("f").unwrap();
+    assert_eq!(f.call((10,)), 6);
+    assert_eq!(f.call((2,)), 2);
+}
+
+#[test]
+fn test_for_loops() {
+    // 区间（含 / 不含终点、正负步长）、定长数组、动态数组；循环体修改循环变量不影响迭代
+    let mut jit = JIT::default();
+    let code = r#"
+    fn ranges(n: i64) -> (r: i64) {
+        r = 0
+        for i in 0..n {
+            r = r + i
+        }
+        for i in 1..=n step 2 {
+            r = r + 100
+        }
+        for i in n..=1 step 0 - 1 {
+            r = r + 10000
+            i = 0
+        }
+    }
+
+    fn each(stop: i64) -> (r: i64) {
+        r = 0
+        for x in [1, 2, 3] {
+            r = r + x
+        }
+        a = array [10, 20, 30]
+        for x in a {
+            if x == 20 {
+                continue
+            } else {
+                r = r + x
+            }
+            tmp = array [x]
+            if x == stop {
+                break
+            } else {
+                r = r + tmp[0]
+            }
+        }
+        drop(a)
+        for y in array [1.5, 2.5] {
+            r = r + (y * 2.0) as i64
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let ranges = jit.get_func::<(i64,), i64>("ranges").unwrap();
+    assert_eq!(ranges.call((5,)), 10 + 300 + 50000);
+    assert_eq!(ranges.call((0,)), 0);
+    let each = jit.get_func::<(i64,), i64>("each").unwrap();
+    assert_eq!(each.call((30,)), 6 + 20 + 30 + 8);
+    assert_eq!(each.call((10,)), 6 + 10 + 8);
+
+    // each 中的 tmp 在 continue、break 与迭代结束时释放，另有 drop(a) 与
+    // 临时数组 array [1.5, 2.5]；之后的循环与 tmp 的循环同一深度，不影响 tmp 的释放
+    let clif = clif_of(code, "each");
+    let drops: usize = drop_calls_by_block(&clif).iter().map(|(_, n)| n).sum();
+    assert_eq!(drops, 5, "{}", clif);
+}
+
+#[test]
+fn test_for_range_stops_at_type_bounds() {
+    // 计数器越过类型的取值范围时结束，不会回绕后继续循环
+    let mut jit = JIT::default();
+    let code = r#"
+    fn bounds() -> (r: i64) {
+        r = 0
+        for i in 120i8..=127i8 {
+            r = r + i as i64
+        }
+        for i in 120i8..=127i8 step 2 {
+            r = r + 1000
+        }
+        for i in -126i8..=-128i8 step -1 {
+            r = r + 100000
+        }
+        for i in 0i8..=-128i8 step -64 {
+            r = r + 10000000
+        }
+    }
+
+    fn stepped(n: i64, s: i64) -> (r: i64) {
+        r = 0
+        for i in 0..n step s {
+            r = r + 1
+        }
+    }
+
+    fn wide(n: i128) -> (r: i64) {
+        r = 0
+        for i in 0i128..5i128 {
+            r = r + i as i64
+        }
+        for i in 10i128..=0i128 step -3i128 {
+            r = r + 100
+        }
+        for i in 0i128..n step 2i128 {
+            r = r + 1000
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let bounds = jit.get_func::<(), i64>("bounds").unwrap();
+    // 120..=127 之和、4 次、3 次、3 次
+    assert_eq!(bounds.call(()), 988 + 4000 + 300000 + 30000000);
+    let stepped = jit.get_func::<(i64, i64), i64>("stepped").unwrap();
+    assert_eq!(stepped.call((10, 3)), 4);
+    assert_eq!(stepped.call((i64::MAX, i64::MAX / 2)), 3);
+    // i128 计数器同样检查步长与溢出
+    let wide = jit.get_func::<(i128,), i64>("wide").unwrap();
+    assert_eq!(wide.call((7,)), 10 + 400 + 4000);
+
+    // 步长不是字面量时在运行时检查是否为 0
+    assert!(clif_of(code, "stepped").contains("trapnz"));
+
+    // 字面量 0 步长在编译期拒绝
+    let zero =
+        "fn f() -> (r: i64) {\n    r = 0\n    for i in 0..3 step 0 {\n        r = 1\n    }\n}\n";
+    let errors = pipeline::check_program(zero);
+    assert!(
+        matches!(
+            errors.as_slice(),
+            [CompileError::Type(errors)]
+                if matches!(errors.as_slice(), [TypeError::ZeroStep { .. }])
+        ),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_for_over_fixed_array_skips_bounds_check() {
+    // 下标由循环控制，不会越界：不生成 translate_index 的 trapnz；显式下标访问仍然检查
+    let options = CompileOptions {
+        emit: vec![Emit::Clif],
+        ..CompileOptions::default()
+    };
+    let mut jit = JIT::new(options);
+    let code = r#"
+    fn sum(a: [i64; 4]) -> (r: i64) {
+        r = 0
+        for x in a {
+            r = r + x
+        }
+    }
+
+    fn first(a: [i64; 4]) -> (r: i64) {
+        r = a[0]
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let clif = |name: &str| {
+        jit.artifacts()
+            .iter()
+            .find(|a| a.function == name)
+            .map(|a| a.text.clone())
+            .unwrap()
+    };
+    assert!(!clif("sum").contains("trapnz"), "{}", clif("sum"));
+    assert!(clif("first").contains("trapnz"));
+    let sum = jit.get_func::<(&mut [i64; 4],), i64>("sum").unwrap();
+    assert_eq!(sum.call((&mut [1, 2, 3, 4],)), 10);
+}
+
+#[test]
+fn test_index_and_compound_assignment() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn fixed(n: i32) -> (r: i64) {
+        a = [1, 2, 3, 4]
+        a[0] = n
+        a[1] += n
+        a[2] *= a[1]
+        a[3] -= 1
+        a[3] /= 2
+        r = 0
+        for x in a {
+            r += x
+        }
+        r *= 10
+    }
+
+    fn dynamic(x: f64) -> (r: f64) {
+        d = array [0.5, 1.5]
+        d[1] = x
+        d[0] += d[1] * 2.0
+        r = d[0] - d[1]
+        drop(d)
+    }
+
+    fn grow(n: i64) -> (r: i64) {
+        d = array_new_i64()
+        array_push(d, 1)
+        for i in 0..n {
+            d[0] += array_push(d, i) + 1
+        }
+        r = d[0] * 1000 + array_len(d)
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // a = [7, 9, 27, 1]
+    let fixed = jit.get_func::<(i32,), i64>("fixed").unwrap();
+    assert_eq!(fixed.call((7,)), 440);
+    let dynamic = jit.get_func::<(f64,), f64>("dynamic").unwrap();
+    assert_eq!(dynamic.call((4.0,)), 4.5);
+    // 右侧先求值：push 触发扩容后再计算 d[0] 的地址
+    let grow = jit.get_func::<(i64,), i64>("grow").unwrap();
+    assert_eq!(grow.call((100,)), 101_101);
+}
+
+#[test]
+fn test_bool_and_short_circuit() {
+    // b == 0 时右侧的除法不会执行，否则会触发除零 trap
+    let mut jit = JIT::default();
+    let code = r#"
+    fn ratio_above_one(a: i64, b: i64) -> (r: bool) {
+        r = b != 0 && a / b > 1
+    }
+
+    fn outside(x: i64, lo: i64, hi: i64) -> (r: bool) {
+        r = x < lo || x > hi
+    }
+
+    fn pick(flag: bool, a: i64, b: i64) -> (r: i64) {
+        if !flag || a < b {
+            r = b
+        } else {
+            r = a
+        }
+    }
+
+    fn count_nonzero(a: i64, b: i64, c: i64) -> (r: i64) {
+        r = (a as bool) as i64 + (b as bool) as i64 + (c as bool) as i64
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let ratio_above_one = jit.get_func::<(i64, i64), bool>("ratio_above_one").unwrap();
+    assert!(ratio_above_one.call((12, 4)));
+    assert!(!ratio_above_one.call((3, 4)));
+    assert!(!ratio_above_one.call((12, 0)));
+    let outside = jit.get_func::<(i64, i64, i64), bool>("outside").unwrap();
+    assert!(outside.call((0, 1, 9)));
+    assert!(!outside.call((5, 1, 9)));
+    assert!(outside.call((10, 1, 9)));
+    let pick = jit.get_func::<(bool, i64, i64), i64>("pick").unwrap();
+    assert_eq!(pick.call((true, 5, 3)), 5);
+    assert_eq!(pick.call((false, 5, 3)), 3);
+    let count_nonzero = jit
+        .get_func::<(i64, i64, i64), i64>("count_nonzero")
+        .unwrap();
+    assert_eq!(count_nonzero.call((0, -3, 256)), 2);
+    assert_eq!(
+        jit.call("outside", &[Value::I64(3), Value::I64(1), Value::I64(9)]),
+        Ok(Value::Bool(false))
+    );
+}
+
+#[test]
+fn test_comments_and_statement_separators() {
+    // CRLF 换行、注释、; 分隔的语句、单行 if，文件末尾没有换行
+    let mut jit = JIT::default();
+    let code = "// 求和\r\nfn sum(n: i64) -> (r: i64) { /* 从 0 开始 */\r\n    r = 0; i = 0\r\n\r\n    while i < n { i += 1; r += i } // 累加\r\n}\r\nfn sign(x: i64) -> (r: i64) {\r\n    if x < 0 { r = -1 } else { r = 1 }\r\n}";
+    jit.compile_program(code).unwrap();
+    let sum = jit.get_func::<(i64,), i64>("sum").unwrap();
+    assert_eq!(sum.call((10,)), 55);
+    let sign = jit.get_func::<(i64,), i64>("sign").unwrap();
+    assert_eq!(sign.call((-7,)), -1);
+    assert_eq!(sign.call((7,)), 1);
+}
+
+#[test]
+fn test_numeric_literal_forms() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn ints() -> (r: i64) {
+        r = 0xFF + 0o17 + 0b1010 + 1_000_000
+    }
+
+    fn narrow(x: i32) -> (r: i32) {
+        r = x * 10i32 - 0x10i32
+    }
+
+    fn float32(x: f32) -> (r: f32) {
+        r = x * 2.5f32 + 1e1f32
+    }
+
+    fn wide() -> (r: i128) {
+        r = 1i128 << 100
+    }
+
+    fn smallest() -> (r: i64) {
+        r = -9223372036854775808 - (-128i8) as i64
+    }
+
+    fn tiny() -> (r: f64) {
+        r = 1.5e3 + 2e-3 + 1f64
+    }
+
+    fn conj() -> (r: complex128) {
+        r = 1.0 - 2.5e-1i
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    assert_eq!(jit.call("ints", &[]), Ok(Value::I64(1_000_280)));
+    assert_eq!(jit.call("narrow", &[Value::I32(3)]), Ok(Value::I32(14)));
+    assert_eq!(
+        jit.call("float32", &[Value::F32(2.0)]),
+        Ok(Value::F32(15.0))
+    );
+    assert_eq!(jit.call("wide", &[]), Ok(Value::I128(1 << 100)));
+    assert_eq!(jit.call("smallest", &[]), Ok(Value::I64(i64::MIN + 128)));
+    assert_eq!(jit.call("tiny", &[]), Ok(Value::F64(1501.002)));
+    assert_eq!(jit.call("conj", &[]), Ok(Value::Complex128(1.0, -0.25)));
+}
+
+#[test]
+fn test_unicode_strings_and_escapes() {
+    let mut jit = JIT::default();
+    let code = r##"
+    fn greeting() -> (r: string) {
+        r = "héllo, 世界 \u{1F600}"
+    }
+
+    fn escapes() -> (r: string) {
+        r = "tab\tquote\"\x41\0end"
+    }
+
+    fn raw() -> (r: string) {
+        r = r#"C:\path "quoted" // not a comment"#
+    }
+
+    fn lengths() -> (r: i64) {
+        r = str_len("héllo") * 100 + str_len("a\0b") * 10 + str_len(r"\n")
+    }
+
+    fn echo_len(s: string) -> (r: i64) {
+        r = str_len(s)
+    }
+
+    fn echo(s: string) -> (r: string) {
+        r = s
+    }
+    "##;
+    jit.compile_program(code).unwrap();
+    let string = |s: &str| Ok(Value::String(s.to_string()));
+    assert_eq!(jit.call("greeting", &[]), string("héllo, 世界 😀"));
+    // 读回时按长度头取内容，\0 之后的部分也在
+    assert_eq!(jit.call("escapes", &[]), string("tab\tquote\"A\0end"));
+    assert_eq!(
+        jit.call("raw", &[]),
+        string(r#"C:\path "quoted" // not a comment"#)
+    );
+    assert_eq!(jit.call("lengths", &[]), Ok(Value::I64(632)));
+    assert_eq!(
+        jit.call("echo_len", &[Value::String("日本".to_string())]),
+        Ok(Value::I64(6))
+    );
+    assert_eq!(
+        jit.call("echo", &[Value::String("a\0b".to_string())]),
+        string("a\0b")
+    );
+    let echo_len = jit.get_func::<(&ToyString,), i64>("echo_len").unwrap();
+    assert_eq!(echo_len.call((&ToyString::new("ünï"),)), 5);
+
+    // 非法转义、超出 ASCII 的 \x、代理码点都是语法错误
+    for bad in [r#""\q""#, r#""\x80""#, r#""\u{D800}""#, r#""\u{}""#] {
+        let code = format!("fn f() -> (r: string) {{\n    r = {}\n}}\n", bad);
+        assert!(
+            matches!(jit.compile(&code), Err(CompileError::Parse(_))),
+            "{} should not parse",
+            bad
+        );
+    }
+}
+
+// ══════════════════════════════════════════════════════
+// 多函数程序
+// ══════════════════════════════════════════════════════
+
+#[test]
+fn test_program_forward_reference() {
+    // main 在 helper 定义之前调用它，且 helper 返回 f64
+    let mut jit = JIT::default();
+    let code = r#"
+    fn main(x: i64) -> (r: i64) {
+        r = double(x as f64) as i64 + 1
+    }
+
+    fn double(v: f64) -> (d: f64) {
+        d = v * 2.0
+    }
+    "#;
+    let funcs = jit.compile_program(code).unwrap();
+    assert_eq!(funcs.len(), 2);
+    let func = jit.get_func::<(i64,), i64>("main").unwrap();
+    assert_eq!(func.call((20,)), 41);
+}
+
+#[test]
+fn test_program_mutual_recursion() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn is_even(n: i64) -> (r: i64) {
+        r = if n == 0 {
+            1
+        } else {
+            is_odd(n - 1)
+        }
+    }
+
+    fn is_odd(n: i64) -> (r: i64) {
+        r = if n == 0 {
+            0
+        } else {
+            is_even(n - 1)
+        }
+    }
+    "#;
+    let funcs = jit.compile_program(code).unwrap();
+    assert_eq!(funcs.len(), 2);
+    let is_even = jit.get_func::<(i64,), i64>("is_even").unwrap();
+    let is_odd = jit.get_func::<(i64,), i64>("is_odd").unwrap();
+    assert_eq!(is_even.call((10,)), 1);
+    assert_eq!(is_odd.call((7,)), 1);
+    assert_eq!(is_even.call((7,)), 0);
+}
+
+#[test]
+fn test_program_duplicate_function() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn f() -> (r: i64) {
+        r = 1
+    }
+
+    fn f() -> (r: i64) {
+        r = 2
+    }
+    "#;
+    let err = jit.compile_program(code).unwrap_err();
+    assert!(
+        err.to_string().contains("duplicate"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_program_typed_helper_return() {
+    // helper 返回 f64：调用结果赋给新变量时按声明的返回类型推断
+    let mut jit = JIT::default();
+    let code = r#"
+    fn main(x: i64) -> (r: f64) {
+        h = half(x as f64)
+        r = h + 0.25
+    }
+
+    fn half(v: f64) -> (d: f64) {
+        d = v / 2.0
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let func = jit.get_func::<(i64,), f64>("main").unwrap();
+    assert_eq!(func.call((5,)), 2.75);
+}
+
+#[test]
+fn test_program_call_arity_mismatch() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn main() -> (r: i64) {
+        r = add(1)
+    }
+
+    fn add(a: i64, b: i64) -> (c: i64) {
+        c = a + b
+    }
+    "#;
+    let err = jit.compile_program(code).unwrap_err();
+    assert!(
+        err.to_string().contains("expects 2 argument"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_program_call_type_mismatch() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn main() -> (r: i64) {
+        s = "text"
+        r = twice(s)
+    }
+
+    fn twice(a: i64) -> (c: i64) {
+        c = a * 2
+    }
+    "#;
+    let err = jit.compile_program(code).unwrap_err();
+    assert!(
+        err.to_string().contains("argument 1 of 'twice'"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_type_errors_reject_compilation() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn f(n: i64) -> (r: i64) {
+        r = m + 1
+    }
+    "#;
+    let err = jit.compile(code).unwrap_err();
+    assert!(
+        err.to_string().contains("undefined variable 'm'"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_compile_error_reports_source_location() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn f() -> (r: i64) {
+        a = array [1, 2]
+        drop(a)
+        r = a[1]
+    }
+    "#;
+    let err = jit.compile(code).unwrap_err();
+    let rendered = err.render("demo.toy", code);
+    assert!(
+        rendered.contains("--> demo.toy:5:13"),
+        "unexpected error: {}",
+        rendered
+    );
+    assert!(
+        rendered.contains("array 'a' dropped here"),
+        "unexpected error: {}",
+        rendered
+    );
+}
+
+#[test]
+fn test_compile_error_variants() {
+    let mut jit = JIT::default();
+    let err = jit
+        .compile("fn f() -> (r: i64) {\n    r = 1 +\n}\n")
+        .unwrap_err();
+    assert!(matches!(err, CompileError::Parse(_)), "got {:?}", err);
+
+    let err = jit
+        .compile("fn f() -> (r: i64) {\n    r = m\n}\n")
+        .unwrap_err();
+    assert!(
+        matches!(err, CompileError::Type(ref e) if e.len() == 1),
+        "got {:?}",
+        err
+    );
+
+    let code = r#"
+    fn f() -> (r: i64) {
+        a = array [1, 2]
+        r = 0
+    }
+    "#;
+    match jit.compile(code).unwrap_err() {
+        CompileError::Ownership(errors) => assert!(matches!(
+            errors.as_slice(),
+            [OwnershipError::LeakedArray { name, .. }] if name == "a"
+        )),
+        other => panic!("expected ownership error, got {:?}", other),
+    }
+
+    let code = r#"
+    fn g() -> (r: i64) {
+        r = 1
+    }
+
+    fn g() -> (r: i64) {
+        r = 2
+    }
+    "#;
+    let err = jit.compile_program(code).unwrap_err();
+    assert!(matches!(err, CompileError::Linking(_)), "got {:?}", err);
+}
+
+#[test]
+fn test_check_program_reports_every_function() {
+    // 与编译不同，check 在一个函数出错后继续检查其余函数
+    let code = r#"
+fn leak() -> (r: i64) {
+    a = array [1, 2]
+    r = 0
+}
+
+fn ok() -> (r: i64) {
+    r = 1
+}
+
+fn bad(x: i64) -> (r: i64) {
+    r = x + "s"
+}
+"#;
+    let errors = pipeline::check_program(code);
+    let lines: Vec<String> = errors
+        .iter()
+        .flat_map(|e| e.to_json("demo.toy", code))
+        .collect();
+    assert_eq!(lines.len(), 2, "got {:#?}", lines);
+    assert!(
+        lines[0].starts_with(
+            r#"{"file":"demo.toy","severity":"error","code":"ownership::leaked_array","#
+        ),
+        "got {}",
+        lines[0]
+    );
+    assert!(
+        lines[0].contains(r#""span":{"start":33,"end":45,"line":3,"column":9,"#),
+        "got {}",
+        lines[0]
+    );
+    assert!(
+        lines[1].contains(r#""code":"type::invalid_operands""#),
+        "got {}",
+        lines[1]
+    );
+
+    assert!(pipeline::check_program("fn f() -> (r: i64) {\n    r = 1\n}\n").is_empty());
+    let code = "fn f() -> (r: i64) {\n    r = 1 +\n}\n";
+    let errors = pipeline::check_program(code);
+    assert!(
+        errors[0].to_json("f.toy", code)[0].contains(r#""code":"parse::syntax_error""#),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_codegen_error_is_returned_not_panicked() {
+    // i32 元素的动态数组能通过类型检查，但翻译阶段不支持对其下标访问：
+    // 编译应当返回 Codegen 错误并指向下标表达式，而不是让进程 panic
+    let mut jit = JIT::default();
+    let code = r#"
+    fn f(x: i32) -> (r: i64) {
+        a = array [x]
+        r = a[0]
+        drop(a)
+    }
+    "#;
+    let err = jit.compile(code).unwrap_err();
+    match &err {
+        CompileError::Codegen { function, span, .. } => {
+            assert_eq!(function, "f");
+            assert!(span.is_some());
+        }
+        other => panic!("expected codegen error, got {:?}", other),
+    }
+    let rendered = err.render("f.toy", code);
+    assert!(rendered.contains("--> f.toy:4:13"), "got:\n{}", rendered);
+
+    // 出错后同一个 JIT 仍可继续使用
+    let code = r#"
+    fn g(x: i64) -> (r: i64) {
+        r = x + 1
+    }
+    "#;
+    jit.compile(code).unwrap();
+    let func = jit.get_func::<(i64,), i64>("g").unwrap();
+    assert_eq!(func.call((41,)), 42);
+}
+
+// ══════════════════════════════════════════════════════
+// 类型化函数句柄
+// ══════════════════════════════════════════════════════
+
+#[test]
+fn test_get_func_checks_signature() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn scale(v: f64, k: i64) -> (r: f64) {
+        r = v * k as f64
+    }
+    "#;
+    jit.compile(code).unwrap();
+
+    let scale = jit.get_func::<(f64, i64), f64>("scale").unwrap();
+    assert_eq!(scale.call((1.5, 4)), 6.0);
+
+    let err = jit.get_func::<(f64, f64), f64>("scale").err().unwrap();
+    assert_eq!(
+        err,
+        CallError::SignatureMismatch {
+            function: "scale".to_string(),
+            expected: "(f64, i64) -> f64".to_string(),
+            found: "(f64, f64) -> f64".to_string(),
+        }
+    );
+    assert!(jit.get_func::<(f64, i64), i64>("scale").is_err());
+    assert!(matches!(
+        jit.get_func::<(), i64>("missing"),
+        Err(CallError::UnknownFunction(_))
+    ));
+}
+
+#[test]
+fn test_get_func_array_and_string_args() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn sum3(a: [i64; 3]) -> (r: i64) {
+        r = a[0] + a[1] + a[2]
+    }
+
+    fn scale(a: [f64; 3]) -> (r: i64) {
+        a[0] = 99.0
+        r = 0
+    }
+
+    fn first_byte(s: string) -> (r: i64) {
+        r = s[0] as i64
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+
+    let sum3 = jit.get_func::<(&mut [i64; 3],), i64>("sum3").unwrap();
+    assert_eq!(sum3.call((&mut [1, 2, 3],)), 6);
+    // 数组长度也是签名的一部分
+    assert!(jit.get_func::<(&mut [i64; 4],), i64>("sum3").is_err());
+    // 数组按指针传递，toy 函数写入的元素调用方可见，所以数组只能以 &mut 传入
+    let mut arr = [1.0, 2.0, 3.0];
+    let scale = jit.get_func::<(&mut [f64; 3],), i64>("scale").unwrap();
+    scale.call((&mut arr,));
+    assert_eq!(arr, [99.0, 2.0, 3.0]);
+
+    let first_byte = jit.get_func::<(&ToyString,), i64>("first_byte").unwrap();
+    assert_eq!(first_byte.call((&ToyString::new("A"),)), 65);
+}
+
+// ══════════════════════════════════════════════════════
+// 动态调用 JIT::call
+// ══════════════════════════════════════════════════════
+
+#[test]
+fn test_call_with_runtime_values() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn add8(a: i8, b: i8) -> (r: i8) {
+        r = a + b
+    }
+
+    fn scale(v: f64, k: i64) -> (r: f64) {
+        r = v * k as f64
+    }
+
+    fn wide(a: i128) -> (r: i128) {
+        r = a * 2
+    }
+
+    fn cadd(a: complex128, b: complex128) -> (r: complex128) {
+        r = a + b
+    }
+
+    fn cmul(a: complex64, b: complex64) -> (r: complex64) {
+        r = a * b
+    }
+
+    fn first_byte(s: string) -> (r: i64) {
+        r = s[0] as i64
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+
+    assert_eq!(
+        jit.call("add8", &[Value::I8(40), Value::I8(2)]),
+        Ok(Value::I8(42))
+    );
+    assert_eq!(
+        jit.call("scale", &[Value::F64(1.5), Value::I64(4)]),
+        Ok(Value::F64(6.0))
+    );
+    assert_eq!(
+        jit.call("wide", &[Value::I128(1 << 100)]),
+        Ok(Value::I128(1 << 101))
+    );
+    assert_eq!(
+        jit.call(
+            "cadd",
+            &[Value::Complex128(1.0, 2.0), Value::Complex128(0.5, -4.0)]
+        ),
+        Ok(Value::Complex128(1.5, -2.0))
+    );
+    assert_eq!(
+        jit.call(
+            "cmul",
+            &[Value::Complex64(0.0, 1.0), Value::Complex64(0.0, 1.0)]
+        ),
+        Ok(Value::Complex64(-1.0, 0.0))
+    );
+    assert_eq!(
+        jit.call("first_byte", &[Value::String("A".to_string())]),
+        Ok(Value::I64(65))
+    );
+    // 内置外部函数也可以直接调用
+    assert_eq!(jit.call("sqrt", &[Value::F64(9.0)]), Ok(Value::F64(3.0)));
+
+    assert_eq!(
+        jit.call("scale", &[Value::F64(1.5), Value::F64(4.0)]),
+        Err(CallError::SignatureMismatch {
+            function: "scale".to_string(),
+            expected: "(f64, i64) -> f64".to_string(),
+            found: "(f64, f64)".to_string(),
+        })
+    );
+    assert!(jit.call("scale", &[Value::F64(1.5)]).is_err());
+    assert!(matches!(
+        jit.call("missing", &[]),
+        Err(CallError::UnknownFunction(_))
+    ));
+}
+
+#[test]
+fn test_call_marshals_arrays() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn sum3(a: [i64; 3]) -> (r: i64) {
+        r = a[0] + a[1] + a[2]
+    }
+
+    fn count(a: array<f64>) -> (r: i64) {
+        r = array_len_f64(a)
+    }
+
+    fn make(n: i64) -> (r: array<i64>) {
+        r = array [1, 2]
+        array_push(r, n)
+    }
+
+    fn same(a: array<i64>) -> (r: array<i64>) {
+        r = a
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+
+    let ints = |v: &[i64]| Value::Array(v.iter().copied().map(Value::I64).collect());
+
+    assert_eq!(jit.call("sum3", &[ints(&[1, 2, 3])]), Ok(Value::I64(6)));
+    // 定长数组的长度是签名的一部分
+    assert!(jit.call("sum3", &[ints(&[1, 2, 3, 4])]).is_err());
+
+    // 外部函数的数组参数展开为 (ptr, len)，[f64; 0] 接受任意长度
+    let floats = Value::Array(vec![Value::F64(1.0), Value::F64(2.5), Value::F64(3.5)]);
+    assert_eq!(
+        jit.call("toy_sum_array", std::slice::from_ref(&floats)),
+        Ok(Value::F64(7.0))
+    );
+
+    assert_eq!(jit.call("count", &[floats]), Ok(Value::I64(3)));
+    assert_eq!(jit.call("make", &[Value::I64(9)]), Ok(ints(&[1, 2, 9])));
+    // 返回传入的数组时所有权随返回值交回，不会重复释放
+    assert_eq!(jit.call("same", &[ints(&[4, 5])]), Ok(ints(&[4, 5])));
+}
+
+#[test]
+fn test_value_parse_by_declared_type() {
+    use cranelift_jit_demo::frontend::Type;
+
+    assert_eq!(Value::parse(&Type::I32, " -7 "), Ok(Value::I32(-7)));
+    assert_eq!(Value::parse(&Type::F64, "2.5"), Ok(Value::F64(2.5)));
+    assert_eq!(
+        Value::parse(&Type::Complex128, "1.5-2i"),
+        Ok(Value::Complex128(1.5, -2.0))
+    );
+    assert_eq!(
+        Value::parse(&Type::Complex64, "-i"),
+        Ok(Value::Complex64(0.0, -1.0))
+    );
+    assert_eq!(
+        Value::parse(&Type::Complex128, "1e-3+1e2i"),
+        Ok(Value::Complex128(0.001, 100.0))
+    );
+    assert_eq!(
+        Value::parse(&Type::DynamicArray(Box::new(Type::I64)), "[1, 2, 3]"),
+        Ok(Value::Array(vec![
+            Value::I64(1),
+            Value::I64(2),
+            Value::I64(3)
+        ]))
+    );
+    assert!(Value::parse(&Type::I8, "300").is_err());
+    assert_eq!(Value::parse(&Type::Bool, "true"), Ok(Value::Bool(true)));
+    assert!(Value::parse(&Type::Bool, "1").is_err());
+    // Display 与 parse 互为逆操作
+    assert_eq!(Value::Complex128(1.5, -2.0).to_string(), "1.5-2i");
+}
+
+#[test]
+fn test_structs() {
+    let mut jit = JIT::default();
+    let code = r#"
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    struct Segment { a: Point, b: Point, id: i32 }
+
+    fn point(x: f64, y: f64) -> (p: Point) {
+        p = Point { x: x, y: y }
+    }
+
+    fn norm2(p: Point) -> (r: f64) {
+        r = p.x * p.x + p.y * p.y
+        // 参数按值传递，修改不影响调用方
+        p.x = 0.0
+    }
+
+    fn length2(s: Segment) -> (r: f64) {
+        r = norm2(Point { x: s.b.x - s.a.x, y: s.b.y - s.a.y })
+    }
+
+    fn test(n: i64) -> (r: f64) {
+        s = Segment { a: point(1.0, 1.0), b: point(4.0, 5.0), id: 7i32 }
+        t = s
+        t.b.x += 10.0
+        r = length2(s) + norm2(s.a) + s.a.x + s.id as f64 + n as f64
+    }
+
+    fn sum(n: i64) -> (r: f64) {
+        pts = [point(1.0, 2.0), point(3.0, 4.0), point(5.0, 6.0)]
+        pts[1].y = n as f64
+        r = 0.0
+        for p in pts {
+            r = r + p.x * p.y
+            p.x = 100.0
+        }
+        r = r + pts[0].x
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // 25 + 2 + 1 + 7 + 1：t 是 s 的副本
+    assert_eq!(jit.call("test", &[Value::I64(1)]), Ok(Value::F64(36.0)));
+    // 2 + 3 * 10 + 30 + 1：遍历变量是元素的副本
+    assert_eq!(jit.call("sum", &[Value::I64(10)]), Ok(Value::F64(63.0)));
+    assert!(jit.signature("point").is_some());
+    assert!(
+        jit.call("point", &[Value::F64(1.0), Value::F64(2.0)])
+            .is_err()
+    );
+}
+
+#[test]
+fn test_struct_layout_is_c_compatible() {
+    #[repr(C)]
+    struct Inner {
+        flag: bool,
+        value: f32,
+    }
+    #[repr(C)]
+    struct Outer {
+        tag: i8,
+        inner: Inner,
+        wide: i128,
+        pair: [i16; 3],
+        c: (f64, f64),
+        items: *mut u8,
+    }
+
+    let mut jit = JIT::default();
+    let code = r#"
+    struct Inner { flag: bool, value: f32 }
+    struct Outer {
+        tag: i8,
+        inner: Inner,
+        wide: i128,
+        pair: [i16; 3],
+        c: complex128,
+        items: array<i64>,
+    }
+
+    fn f() -> (r: i64) {
+        r = 0
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let layout = jit.type_checker().struct_layout("Outer").unwrap();
+    assert_eq!(layout.size as usize, std::mem::size_of::<Outer>());
+    assert_eq!(layout.align as usize, std::mem::align_of::<Outer>());
+    let offset = |name: &str| layout.field(name).unwrap().0 as usize;
+    assert_eq!(offset("tag"), std::mem::offset_of!(Outer, tag));
+    assert_eq!(offset("inner"), std::mem::offset_of!(Outer, inner));
+    assert_eq!(offset("wide"), std::mem::offset_of!(Outer, wide));
+    assert_eq!(offset("pair"), std::mem::offset_of!(Outer, pair));
+    assert_eq!(offset("c"), std::mem::offset_of!(Outer, c));
+    assert_eq!(offset("items"), std::mem::offset_of!(Outer, items));
+
+    let inner = jit.type_checker().struct_layout("Inner").unwrap();
+    assert_eq!(inner.size as usize, std::mem::size_of::<Inner>());
+    assert_eq!(
+        inner.field("value").unwrap().0 as usize,
+        std::mem::offset_of!(Inner, value)
+    );
+}
+
+#[test]
+fn test_struct_array_fields_are_dropped() {
+    let mut jit = JIT::default();
+    let code = r#"
+    struct Bag {
+        items: array<i64>,
+        count: i64,
+    }
+
+    fn bag(n: i64) -> (b: Bag) {
+        b = Bag { items: array [n, n + 1], count: 2 }
+    }
+
+    fn test(n: i64) -> (r: i64) {
+        i = 0
+        r = 0
+        while i < n {
+            // 循环体中的 b.items 在每次迭代结束时释放
+            b = bag(i)
+            array_push(b.items, 1)
+            r = r + b.items[1] + array_len(b.items)
+            i = i + 1
+        }
+        keep = bag(100)
+        keep.items[0] += 1
+        r = r + keep.items[0]
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // Σ(i + 1 + 3) for i in 0..10 = 85，加上 101
+    assert_eq!(jit.call("test", &[Value::I64(10)]), Ok(Value::I64(186)));
+}
+
+#[test]
+fn test_for_each_borrows_field_and_index_places() {
+    let mut jit = JIT::default();
+    let code = r#"
+    struct Bag {
+        items: array<i64>,
+        count: i64,
+    }
+
+    fn test(n: i64) -> (r: i64) {
+        b = Bag { items: array [n, n + 1, n + 2], count: 3 }
+        rows = [array [1, 2], array [3, 4]]
+        r = 0
+        // 遍历字段和下标只是借用，循环结束后仍可读取
+        for x in b.items {
+            r = r + x
+        }
+        for x in rows[1] {
+            r = r + x
+        }
+        r = r * 100 + b.items[2] + array_len(b.items) + rows[1][0]
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // (3n + 3 + 7) * 100 + (n + 2) + 3 + 3
+    assert_eq!(jit.call("test", &[Value::I64(10)]), Ok(Value::I64(4018)));
+}
+
+#[test]
+fn test_struct_definition_errors() {
+    let code = r#"
+    struct A { x: i64, x: f64 }
+    struct B { next: B }
+    struct C { p: Missing }
+    struct A { y: i64 }
+
+    fn f(p: Nope) -> (r: i64) {
+        r = 0
+    }
+    "#;
+    let errors = pipeline::check_program(code);
+    // 重复的声明报在第二个声明上
+    let duplicate = errors.iter().find_map(|e| match e {
+        CompileError::Type(errors) => errors.iter().find_map(|e| match e {
+            TypeError::DuplicateType { name, span } => Some((name, span)),
+            _ => None,
+        }),
+        _ => None,
+    });
+    let (name, span) = duplicate.expect("duplicate struct is reported");
+    assert_eq!(name, "A");
+    assert_eq!(&code[span.start..span.end], "struct A { y: i64 }");
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    let text = messages.join("\n");
+    for expected in ["x", "B", "Missing", "Nope"] {
+        assert!(text.contains(expected), "{}", text);
+    }
+
+    let mut jit = JIT::default();
+    let err = jit
+        .compile_program("struct B { next: B }\nfn f() -> (r: i64) {\n    r = 0\n}\n")
+        .unwrap_err();
+    assert!(matches!(err, CompileError::Type(_)), "{:?}", err);
+}
+
+/// `pipeline::check_program` 报告的类型错误：(错误代码, span 所指的源码)
+fn type_errors(code: &str) -> Vec<(&'static str, &str)> {
+    pipeline::check_program(code)
+        .iter()
+        .flat_map(|e| match e {
+            CompileError::Type(errors) => errors.clone(),
+            other => panic!("unexpected error {:?}", other),
+        })
+        .map(|e| (e.code(), &code[e.span().start..e.span().end]))
+        .collect()
+}
+
+#[test]
+fn test_struct_valued_if_needs_matching_else() {
+    // 结构体按指针传递：没有 else 的 if 与 else 分支的整数都不能当作结构体使用
+    let code = r#"
+    struct P { x: f64, y: f64 }
+    struct Q { x: f64 }
+
+    fn f(c: bool) -> (r: f64) {
+        p = if c { P { x: 1.0, y: 2.0 } }
+        q = if c { P { x: 1.0, y: 2.0 } } else { 7 }
+        s = if c { P { x: 1.0, y: 2.0 } } else { Q { x: 1.0 } }
+        if c {
+            t = P { x: 1.0, y: 2.0 }
+        }
+        r = p.x
+    }
+    "#;
+    assert_eq!(
+        type_errors(code),
+        vec![
+            ("type::missing_else", "P { x: 1.0, y: 2.0 }"),
+            ("type::branch_type_mismatch", "7"),
+            ("type::branch_type_mismatch", "Q { x: 1.0 }"),
+        ]
+    );
+
+    let mut jit = JIT::default();
+    let code = r#"
+    struct P { x: f64, y: f64 }
+
+    fn pick(c: bool) -> (r: f64) {
+        p = if c { P { x: 1.0, y: 2.0 } } else { P { x: 3.0, y: 4.0 } }
+        r = p.x + p.y
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let pick = jit.get_func::<(bool,), f64>("pick").unwrap();
+    assert_eq!(pick.call((true,)), 3.0);
+    assert_eq!(pick.call((false,)), 7.0);
+}
+
+#[test]
+fn test_enums_and_match() {
+    let mut jit = JIT::default();
+    let code = r#"
+    struct Point { x: f64, y: f64 }
+
+    enum Shape {
+        Circle(Point, f64),
+        Rect(f64, f64),
+        Empty,
+    }
+
+    fn area(s: Shape) -> (r: f64) {
+        r = match s {
+            Shape::Circle(_, radius) => 3.0 * radius * radius,
+            Shape::Rect(w, h) => w * h,
+            Shape::Empty => 0.0,
+        }
+    }
+
+    fn center_x(s: Shape) -> (r: f64) {
+        r = match s {
+            Shape::Circle(c, _) => {
+                // 绑定的结构体是副本
+                c.x += 100.0
+                c.x
+            }
+            _ => -1.0,
+        }
+    }
+
+    fn shape(k: i64) -> (s: Shape) {
+        s = match k {
+            0 => Shape::Circle(Point { x: 1.0, y: 2.0 }, 2.0),
+            1 => Shape::Rect(2.0, 3.0),
+            _ => Shape::Empty,
+        }
+    }
+
+    fn test(k: i64) -> (r: f64) {
+        s = shape(k)
+        r = area(s) + center_x(s)
+        r = match s {
+            Shape::Circle(..) | Shape::Rect(..) => r,
+            other => area(other) - 1000.0,
+        }
+    }
+    "#;
+    // `(..)` 不是合法的载荷模式
+    assert!(jit.compile_program(code).is_err());
+
+    let code = code.replace(
+        "Shape::Circle(..) | Shape::Rect(..)",
+        "Shape::Circle(_, _) | Shape::Rect(_, _)",
+    );
+    let mut jit = JIT::default();
+    jit.compile_program(&code).unwrap();
+    assert_eq!(jit.call("test", &[Value::I64(0)]), Ok(Value::F64(113.0)));
+    assert_eq!(jit.call("test", &[Value::I64(1)]), Ok(Value::F64(5.0)));
+    assert_eq!(jit.call("test", &[Value::I64(2)]), Ok(Value::F64(-1000.0)));
+}
+
+#[test]
+fn test_integer_match() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn bucket(n: i64) -> (r: i64) {
+        r = match n {
+            0 => 0,
+            1 | 2 | 3 => 1,
+            4..10 => 2,
+            10..=99 => 3,
+            -9..=-1 => -1,
+            other => other * 10,
+        }
+    }
+
+    fn small(n: i8) -> (r: i64) {
+        r = match n {
+            -128..=-1 => -1,
+            0 => 0,
+            1..=127 => {
+                if n > 100 {
+                    return 2
+                }
+                1
+            }
+        }
+    }
+
+    fn steps(n: i64) -> (r: i64) {
+        r = 0
+        while true {
+            match n % 3 {
+                0 => {
+                    break
+                }
+                _ => {
+                    n += 1
+                    r += 1
+                }
+            }
+        }
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    let bucket = |jit: &mut JIT, n: i64| jit.call("bucket", &[Value::I64(n)]);
+    assert_eq!(bucket(&mut jit, 0), Ok(Value::I64(0)));
+    assert_eq!(bucket(&mut jit, 3), Ok(Value::I64(1)));
+    assert_eq!(bucket(&mut jit, 9), Ok(Value::I64(2)));
+    assert_eq!(bucket(&mut jit, 10), Ok(Value::I64(3)));
+    assert_eq!(bucket(&mut jit, -9), Ok(Value::I64(-1)));
+    assert_eq!(bucket(&mut jit, -10), Ok(Value::I64(-100)));
+    assert_eq!(bucket(&mut jit, 100), Ok(Value::I64(1000)));
+    for (n, expected) in [(-128, -1), (-1, -1), (0, 0), (50, 1), (127, 2)] {
+        assert_eq!(jit.call("small", &[Value::I8(n)]), Ok(Value::I64(expected)));
+    }
+    assert_eq!(jit.call("steps", &[Value::I64(4)]), Ok(Value::I64(2)));
+}
+
+#[test]
+fn test_enum_layout_is_c_compatible() {
+    #[repr(C)]
+    #[allow(dead_code)]
+    enum Shape {
+        Circle(f64),
+        Rect(f32, f64),
+        Flag(bool, i16),
+        Empty,
+    }
+
+    let mut jit = JIT::default();
+    let code = r#"
+    enum Shape {
+        Circle(f64),
+        Rect(f32, f64),
+        Flag(bool, i16),
+        Empty,
+    }
+
+    fn measure(s: Shape) -> (r: f64) {
+        r = match s {
+            Shape::Circle(radius) => radius,
+            Shape::Rect(w, h) => w as f64 * h,
+            Shape::Flag(on, n) => if on { n as f64 } else { -1.0 },
+            Shape::Empty => 0.0,
+        }
+    }
+    "#;
+    let funcs = jit.compile_program(code).unwrap();
+    let layout = jit.type_checker().enum_layout("Shape").unwrap();
+    assert_eq!(layout.size as usize, std::mem::size_of::<Shape>());
+    assert_eq!(layout.align as usize, std::mem::align_of::<Shape>());
+
+    // 枚举参数按指针传递，直接把 Rust 的枚举值交给编译出的函数
+    let measure: extern "C" fn(*const Shape) -> f64 =
+        unsafe { std::mem::transmute(funcs["measure"]) };
+    assert_eq!(measure(&Shape::Circle(1.5)), 1.5);
+    assert_eq!(measure(&Shape::Rect(2.0, 2.5)), 5.0);
+    assert_eq!(measure(&Shape::Flag(true, -7)), -7.0);
+    assert_eq!(measure(&Shape::Flag(false, 3)), -1.0);
+    assert_eq!(measure(&Shape::Empty), 0.0);
+}
+
+#[test]
+fn test_enum_and_match_errors() {
+    let errors = pipeline::check_program(
+        r#"
+    enum E { A, B(i64), A }
+    enum Shape { Circle(f64) }
+    struct Shape { x: i64 }
+    enum Bag { Items(array<i64>) }
+
+    fn f(e: E) -> (r: i64) {
+        r = match e {
+            E::A => 1,
+        }
+    }
+    "#,
+    );
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert!(
+        messages
+            .iter()
+            .any(|m| m.contains("the type 'Shape' is defined more than once")),
+        "{:?}",
+        messages
+    );
+    let text = messages.join("\n");
+    for expected in [
+        "variant 'A' is declared more than once",
+        "variant 'Bag::Items' cannot hold a dynamic array",
+        "E::B not covered",
+    ] {
+        assert!(text.contains(expected), "{}", text);
+    }
+}
+
+#[test]
+fn test_enum_valued_branches_must_agree() {
+    // 枚举按指针传递：各分支的值都必须是同一个枚举，if 必须有 else
+    let code = r#"
+    enum S { A(f64), B }
+
+    fn f(x: i64, c: bool) -> (r: i64) {
+        s = match x {
+            1 => S::A(1.0),
+            _ => 5,
+        }
+        t = if c { S::A(1.0) }
+        u = match x {
+            1 => S::B,
+            2 => return 0,
+            _ => S::A(2.0),
+        }
+        match x {
+            1 => S::B,
+            _ => 5,
+        }
+        r = 0
+    }
+    "#;
+    assert_eq!(
+        type_errors(code),
+        vec![
+            ("type::branch_type_mismatch", "5"),
+            ("type::missing_else", "S::A(1.0)"),
+        ]
+    );
+}
+
+#[test]
+fn test_let_declarations_and_block_scope() {
+    let mut jit = JIT::default();
+    let code = r#"
+    fn shadow(n: i64) -> (r: f64) {
+        let x: f32 = 1.5
+        let mut total = 0.0
+        {
+            // 内层的 n 遮蔽参数，块结束后参数重新可见
+            let n = 2.5
+            total = total + n
+        }
+        let mut i = 0
+        while i < n {
+            let sq = i * i
+            total += sq as f64
+            i += 1
+        }
+        let x = x as f64 * 2.0
+        r = total + x
+    }
+
+    fn narrow() -> (r: i64) {
+        let small: i8 = 100
+        let mut wide: i64 = small
+        wide = wide * 3
+        r = wide
+    }
+
+    fn arrays() -> (r: i64) {
+        let a = array [1, 2, 3]
+        r = 0
+        {
+            // 块中的数组在块结束时自动释放，外层的 a 不受影响
+            let a = array [10, 20]
+            r = a[1]
+        }
+        r = r + a[2]
+        drop(a)
+    }
+
+    fn implicit(k: i64) -> (r: i64) {
+        for i in 0..k {
+            let i = i * 10
+            r = r + i
+        }
+        // 隐式声明的变量仍然属于整个函数
+        if k > 0 {
+            y = 7
+        }
+        r = r + y
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // 2.5 + (0 + 1 + 4 + 9) + 3.0
+    assert_eq!(jit.call("shadow", &[Value::I64(4)]), Ok(Value::F64(19.5)));
+    assert_eq!(jit.call("narrow", &[]), Ok(Value::I64(300)));
+    assert_eq!(jit.call("arrays", &[]), Ok(Value::I64(23)));
+    assert_eq!(jit.call("implicit", &[Value::I64(3)]), Ok(Value::I64(37)));
+}
+
+#[test]
+fn test_let_errors_and_strict_mode() {
+    let code = r#"
+    fn f() -> (r: i64) {
+        let x = 1
+        x = 2
+        {
+            let y = 5
+        }
+        r = y
+        let z: f64 = 1
+        let w: i8 = 300
+        let mut q = 1
+        q = 2.5
+        typo = 3
+    }
+    "#;
+    let errors = pipeline::check_program(code);
+    let text = errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    for expected in [
+        "cannot assign twice to immutable variable 'x'",
+        "variable 'y' is not in scope",
+        "variable 'z' has type f64 but is assigned a value of type i64",
+        "literal out of range for i8",
+        "variable 'q' has type i64 but is assigned a value of type f64",
+    ] {
+        assert!(text.contains(expected), "{}", text);
+    }
+    // 隐式声明只在严格模式下报错
+    assert!(!text.contains("typo"), "{}", text);
+    let strict = CompileOptions {
+        strict: true,
+        ..CompileOptions::default()
+    };
+    let errors = pipeline::check_program_with(code, &strict);
+    let rendered = errors
+        .iter()
+        .map(|e| e.render("strict.toy", code))
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(
+        rendered.contains("error: variable 'typo' is assigned without being declared"),
+        "{}",
+        rendered
+    );
+    assert!(
+        rendered.contains("declared here without 'mut'"),
+        "{}",
+        rendered
+    );
+
+    // 严格模式下用 let 声明的程序照常编译
+    let mut jit = JIT::new(strict.clone());
+    let ok = r#"
+    fn g(n: i64) -> (r: i64) {
+        let mut sum = 0
+        for i in 0..n {
+            sum += i
+        }
+        r = sum
+    }
+    "#;
+    jit.compile_program(ok).unwrap();
+    assert_eq!(jit.call("g", &[Value::I64(5)]), Ok(Value::I64(10)));
+    assert!(JIT::new(strict).compile_program(code).is_err());
+}
+
+#[test]
+fn test_const_and_static_globals() {
+    let mut jit = JIT::default();
+    let code = r#"
+    const N: i64 = 4
+    const AREA: i64 = N * N
+    const SCALE: f64 = 1.5
+    static BASE: i32 = -10
+    static mut calls: i64 = 0
+    static mut total: f64 = 0.0
+
+    fn record(x: f64) -> (r: i64) {
+        calls += 1
+        total += x * SCALE
+        r = calls
+    }
+
+    fn sum_grid() -> (r: i64) {
+        let mut grid: [i64; N] = [1, 2, 3, 4]
+        let rows: [[i64; N]; 2] = [grid, grid]
+        r = 0
+        for row in rows {
+            for x in row {
+                r += x
+            }
+        }
+    }
+
+    fn run() -> (r: f64) {
+        record(2.0)
+        record(4.0)
+        r = total + calls as f64 + AREA as f64 + BASE as f64 + sum_grid() as f64
+    }
+
+    fn shadow(N: i64) -> (r: i64) {
+        r = N + AREA
+    }
+    "#;
+    jit.compile_program(code).unwrap();
+    // total = 9.0, calls = 2, AREA = 16, BASE = -10, sum_grid = 20
+    assert_eq!(jit.call("run", &[]), Ok(Value::F64(37.0)));
+    // 静态变量在多次调用之间保留
+    assert_eq!(jit.call("record", &[Value::F64(0.0)]), Ok(Value::I64(3)));
+    // 同名参数遮蔽常量
+    assert_eq!(jit.call("shadow", &[Value::I64(1)]), Ok(Value::I64(17)));
+
+    // 常量在使用处被折叠为字面量，IR 中没有对它的读取
+    let options = CompileOptions {
+        emit: vec![Emit::Clif],
+        ..CompileOptions::default()
+    };
+    let mut jit = JIT::new(options);
+    jit.compile_program("const K: i64 = 6 * 7\nfn k() -> (r: i64) {\n    r = K + 0\n}\n")
+        .unwrap();
+    let ir = &jit.artifacts()[0].text;
+    assert!(ir.contains("iconst.i64 42"), "{}", ir);
+    assert!(!ir.contains("symbol_value"), "{}", ir);
+}
+
+#[test]
+fn test_global_errors() {
+    let code = r#"
+    static mut s: i64 = 1
+    const A: i64 = s + 1
+    const B: i8 = 300
+    static C: string = "c"
+    const N: i64 = 3
+    const N: i64 = 4
+    static K: i64 = 3
+
+    fn f() -> (r: i64) {
+        N = 4
+        K = 2
+        let a: [i64; K] = [1, 2, 3]
+        r = N
+    }
+    "#;
+    let errors = pipeline::check_program(code);
+    let text = errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    for expected in [
+        "initializer of 'A' is not a constant expression",
+        "literal out of range for i8",
+        "'C' cannot have type string",
+        "the global 'N' is defined more than once",
+        "cannot assign twice to immutable variable 'N'",
+        "cannot assign twice to immutable variable 'K'",
+        "array length 'K' is not an integer constant",
+    ] {
+        assert!(text.contains(expected), "{}", text);
+    }
+    assert!(JIT::default().compile_program(code).is_err());
+    // 重复的声明报在第二个声明上
+    let duplicate = errors.iter().find_map(|e| match e {
+        CompileError::Type(errors) => errors.iter().find_map(|e| match e {
+            TypeError::DuplicateGlobal { name, span } => Some((name, span)),
+            _ => None,
+        }),
+        _ => None,
+    });
+    let (name, span) = duplicate.expect("duplicate global is reported");
+    assert_eq!(name, "N");
+    assert_eq!(&code[span.start..span.end], "const N: i64 = 4");
+
+    // 签名中的数组长度与局部变量的一样按类型错误报告
+    let signature = "const N: f64 = 2.0\nfn f(a: [i64; N]) -> (r: i64) {\n    r = 0\n}\n";
+    let errors = pipeline::check_program(signature);
+    assert!(
+        matches!(
+            errors.as_slice(),
+            [CompileError::Type(errors)]
+                if matches!(errors.as_slice(), [TypeError::ArrayLengthNotConst { name, .. }] if name == "N")
+        ),
+        "got {:?}",
+        errors
+    );
 }
@@ -0,0 +1,207 @@
+//! 原 `toy --test` 内置自检：每段 toy 代码单独编译、调用并检查结果
+
+use cranelift_jit_demo::func::{Func, ToyArgs, ToyRet};
+use cranelift_jit_demo::jit::JIT;
+
+/// 编译单个 toy 函数，并按给定的 Rust 签名取出类型化句柄
+fn compile_func<'a, Args: ToyArgs, Ret: ToyRet>(
+    jit: &'a mut JIT,
+    code: &str,
+    name: &str,
+) -> Func<'a, Args, Ret> {
+    jit.compile(code).unwrap();
+    jit.get_func(name).unwrap()
+}
+
+#[test]
+fn test_foo() {
+    let mut jit = JIT::default();
+    let foo = compile_func::<(i64, i64), i64>(&mut jit, FOO_CODE, "foo");
+    assert_eq!(foo.call((1, 0)), 42);
+}
+
+#[test]
+fn test_fib() {
+    let mut jit = JIT::default();
+    let fib = compile_func::<(i64,), i64>(&mut jit, RECURSIVE_FIB_CODE, "recursive_fib");
+    assert_eq!(fib.call((10,)), 55);
+    let fib = compile_func::<(i64,), i64>(&mut jit, ITERATIVE_FIB_CODE, "iterative_fib");
+    assert_eq!(fib.call((10,)), 55);
+}
+
+#[test]
+fn test_float_arithmetic() {
+    let mut jit = JIT::default();
+    let float_add = compile_func::<(f64, f64), f64>(&mut jit, FLOAT_ADD_CODE, "float_add");
+    assert_eq!(float_add.call((1.5, 2.5)), 4.0);
+    let mixed_add = compile_func::<(i32, f64, f64), f64>(&mut jit, MIXED_ADD_CODE, "mixed_add");
+    assert_eq!(mixed_add.call((10, 2.5, 2.5)), 15.0);
+    let mul_div = compile_func::<(f64, f64), f64>(&mut jit, MUL_DIV_CODE, "mul_div");
+    assert_eq!(mul_div.call((10.0, 5.0)), 25.0);
+}
+
+#[test]
+fn test_global_data_strings() {
+    let mut jit = JIT::default();
+    jit.create_data("hello_string", b"hello world!\0".to_vec())
+        .unwrap();
+    let hello = compile_func::<(), i64>(&mut jit, HELLO_CODE, "hello");
+    hello.call(());
+
+    jit.create_data("custom_msg", b"Customize String Test Success!\0".to_vec())
+        .unwrap();
+    let custom = compile_func::<(), i64>(&mut jit, CUSTOM_STRING_CODE, "custom_string_func");
+    custom.call(());
+}
+
+#[test]
+fn test_string_literals() {
+    let mut jit = JIT::default();
+    let string_test = compile_func::<(), i64>(&mut jit, STRING_TEST_CODE, "string_test");
+    assert_eq!(string_test.call(()), 0);
+}
+
+#[test]
+fn test_i128_cast() {
+    let mut jit = JIT::default();
+    let i128_test = compile_func::<(), i64>(&mut jit, I128_TEST_CODE, "i128_test");
+    assert_eq!(i128_test.call(()), 100);
+}
+
+#[test]
+fn test_complex_literals() {
+    let mut jit = JIT::default();
+    let complex_test = compile_func::<(), i64>(&mut jit, COMPLEX_TEST_CODE, "complex_test");
+    assert_eq!(complex_test.call(()), 1);
+}
+
+#[test]
+fn test_arrays() {
+    let mut jit = JIT::default();
+    let array_test = compile_func::<(), i64>(&mut jit, ARRAY_TEST_CODE, "array_test");
+    assert_eq!(array_test.call(()), 30);
+    let dynamic_array_test =
+        compile_func::<(), i64>(&mut jit, DYNAMIC_ARRAY_TEST_CODE, "dynamic_array_test");
+    assert_eq!(dynamic_array_test.call(()), 40);
+}
+
+const FOO_CODE: &str = r#"
+    fn foo(a: i64, b: i64) -> (c: i64) {
+        c = if a != 0 {
+            if b != 0 {
+                30
+            } else {
+                40
+            }
+        } else {
+            50
+        }
+        c = c + 2
+    }
+"#;
+
+const RECURSIVE_FIB_CODE: &str = r#"
+    fn recursive_fib(n: i64) -> (r: i64) {
+        r = if n == 0 {
+                    0
+            } else {
+                if n == 1 {
+                    1
+                } else {
+                    recursive_fib(n - 1) + recursive_fib(n - 2)
+                }
+            }
+    }
+"#;
+
+const ITERATIVE_FIB_CODE: &str = r#"
+    fn iterative_fib(n: i64) -> (r: i64) {
+        if n == 0 {
+            r = 0
+        } else {
+            n = n - 1
+            a = 0
+            r = 1
+            while n != 0 {
+                t = r
+                r = r + a
+                a = t
+                n = n - 1
+            }
+        }
+    }
+"#;
+
+const FLOAT_ADD_CODE: &str = r#"
+    fn float_add(a: f64, b: f64) -> (c: f64) {
+        c = a + b
+    }
+"#;
+
+const MIXED_ADD_CODE: &str = r#"
+    fn mixed_add(a: i32, b: f64, c: f64) -> (r: f64) {
+        r = (a as f64) + b + c
+    }
+"#;
+
+const HELLO_CODE: &str = r#"
+fn hello() -> (r: i64) {
+    puts(&hello_string)
+}
+"#;
+
+const MUL_DIV_CODE: &str = r#"
+fn mul_div(a: f64, b: f64) -> (c: f64) {
+    c = a * b / 2.0
+}
+"#;
+
+const CUSTOM_STRING_CODE: &str = r#"
+fn custom_string_func() -> (r: i64) {
+    puts(&custom_msg)
+}
+"#;
+
+const STRING_TEST_CODE: &str = r#"
+fn string_test() -> (r: i64) {
+    s = "Hello from JIT String Literal!\nWith Newline\tAnd Tab"
+    puts(s)
+    fmt = "Printf Test: %s %d\n"
+    world = "World"
+    num = 123
+    printf(fmt, world, num)
+    r = 0
+}
+"#;
+
+const I128_TEST_CODE: &str = r#"
+    fn i128_test() -> (r: i64) {
+        x = 100 as i128
+        r = x as i64
+    }
+"#;
+
+const COMPLEX_TEST_CODE: &str = r#"
+    fn complex_test() -> (status: i64) {
+        c1 = 1.5 + 2.5i
+        c2 = 0.5 + 0.5i
+        c = c1 + c2
+        status = 1
+    }
+"#;
+
+const ARRAY_TEST_CODE: &str = r#"
+fn array_test() -> (r: i64) {
+    arr = [10, 20, 30] 
+    x = arr[2]
+    r = x
+}
+"#;
+
+const DYNAMIC_ARRAY_TEST_CODE: &str = r#"
+fn dynamic_array_test() -> (r: i64) {
+    arr = array [10, 20, 30]
+    array_push(arr, 40)
+    r = arr[3]
+}
+"#;
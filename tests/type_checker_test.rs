@@ -1,5 +1,6 @@
-use cranelift_jit_demo::frontend::{Expr, Type};
-use cranelift_jit_demo::type_checker::{TypeChecker, infer_type};
+use cranelift_jit_demo::frontend::{Expr, ExprKind, GlobalDef, Type, parser};
+use cranelift_jit_demo::optimizer;
+use cranelift_jit_demo::type_checker::{TypeChecker, TypeError, infer_type};
 
 #[test]
 fn test_resolve_func() {
@@ -12,20 +13,21 @@ fn test_resolve_func() {
 
 #[test]
 fn test_infer_type_simple() {
-    let expr = Expr::Literal("123".to_string(), Type::I64);
+    let expr: Expr = ExprKind::Literal("123".to_string(), Type::I64).into();
     let ty = infer_type(&expr, &|_| None);
     assert_eq!(ty, Type::I64);
 }
 
 #[test]
 fn test_infer_type_array() {
-    let expr = Expr::ArrayLiteral(
+    let expr: Expr = ExprKind::ArrayLiteral(
         vec![
-            Expr::Literal("1.0".to_string(), Type::F64),
-            Expr::Literal("2.0".to_string(), Type::F64),
+            ExprKind::Literal("1.0".to_string(), Type::F64).into(),
+            ExprKind::Literal("2.0".to_string(), Type::F64).into(),
         ],
         Type::I64,
-    ); // Placeholder type
+    )
+    .into(); // Placeholder type
 
     let ty = infer_type(&expr, &|_| None);
     if let Type::Array(inner, len) = ty {
@@ -35,3 +37,705 @@ fn test_infer_type_array() {
         panic!("Expected Array type");
     }
 }
+
+#[test]
+fn test_registered_function_return_type() {
+    let mut tc = TypeChecker::new();
+    tc.register_function("half", vec![Type::F64], Type::F64);
+    let sig = tc.resolve_func("half").unwrap();
+    assert!(!sig.is_external);
+
+    let call: Expr = ExprKind::Call(
+        "half".to_string(),
+        vec![ExprKind::Literal("1.0".to_string(), Type::F64).into()],
+    )
+    .into();
+    assert_eq!(tc.infer_type(&call, &|_| None), Type::F64);
+    // 自由函数 infer_type 不知道用户函数，仍按未知函数处理
+    assert_eq!(infer_type(&call, &|_| None), Type::I64);
+}
+
+fn check(code: &str) -> Vec<TypeError> {
+    let (_name, params, the_return, stmts) = parser::function(code).unwrap();
+    TypeChecker::new().check_function(&params, &the_return, &stmts)
+}
+
+#[test]
+fn test_check_valid_function() {
+    let code = r#"
+    fn f(a: i64, b: f64) -> (r: f64) {
+        arr = [1.0, 2.0]
+        x = a + 1
+        r = b * arr[x - 1] + sqrt(b)
+    }
+    "#;
+    let errors = check(code);
+    assert!(errors.is_empty(), "expected no errors, got {:?}", errors);
+}
+
+#[test]
+fn test_check_undefined_variable() {
+    let code = r#"
+    fn f(count: i64) -> (r: i64) {
+        r = cuont + 1
+    }
+    "#;
+    let errors = check(code);
+    assert!(
+        matches!(
+            errors.as_slice(),
+            [TypeError::UndefinedVariable { name, .. }] if name == "cuont"
+        ),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_check_arithmetic_on_string_and_array() {
+    let code = r#"
+    fn f() -> (r: i64) {
+        s = "abc"
+        a = [1, 2]
+        x = s + 1
+        y = a * 2
+        r = 0
+    }
+    "#;
+    let errors = check(code);
+    assert_eq!(errors.len(), 2, "got {:?}", errors);
+    assert!(
+        errors
+            .iter()
+            .all(|e| matches!(e, TypeError::InvalidOperands { .. }))
+    );
+}
+
+#[test]
+fn test_check_index_errors() {
+    let code = r#"
+    fn f(n: i64) -> (r: i64) {
+        a = [1, 2, 3]
+        r = n[0] + a[1.5]
+    }
+    "#;
+    let errors = check(code);
+    assert!(
+        errors
+            .iter()
+            .any(|e| matches!(e, TypeError::IndexNonArray { ty: Type::I64, .. }))
+    );
+    assert!(
+        errors
+            .iter()
+            .any(|e| matches!(e, TypeError::NonIntegerIndex { ty: Type::F64, .. }))
+    );
+}
+
+#[test]
+fn test_check_builtin_argument_type() {
+    let code = r#"
+    fn f() -> (r: f64) {
+        s = "x"
+        r = sin(s)
+    }
+    "#;
+    assert!(matches!(
+        check(code).as_slice(),
+        [TypeError::ArgumentType { func, index: 1, .. }] if func == "sin"
+    ));
+}
+
+#[test]
+fn test_check_return_type_mismatch() {
+    let code = r#"
+    fn f() -> (r: i64) {
+        r = 2.5
+    }
+    "#;
+    let errors = check(code);
+    assert!(
+        matches!(
+            errors.as_slice(),
+            [TypeError::ReturnTypeMismatch {
+                name,
+                expected: Type::I64,
+                found: Type::F64,
+                ..
+            }] if name == "r"
+        ),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_check_bool_operators_and_conditions() {
+    let code = r#"
+    fn f(n: i64, x: f64) -> (r: i64) {
+        ok = n > 0 && !(n == 3) || false
+        same = ok == true
+        if n {
+            r = 1
+        }
+        a = n && ok
+        b = !n
+        c = x as bool
+        r = ok as i64 + (n as bool) as i64
+    }
+    "#;
+    let errors = check(code);
+    assert_eq!(errors.len(), 4, "got {:?}", errors);
+    assert!(matches!(
+        &errors[0],
+        TypeError::InvalidCondition { ty: Type::I64, .. }
+    ));
+    assert!(matches!(
+        &errors[1],
+        TypeError::InvalidOperands { op, lhs: Type::I64, rhs: Type::Bool, .. } if op == "&&"
+    ));
+    assert!(matches!(
+        &errors[2],
+        TypeError::InvalidOperand { op, ty: Type::I64, .. } if op == "!"
+    ));
+    assert!(matches!(
+        &errors[3],
+        TypeError::InvalidCast {
+            from: Type::F64,
+            to: Type::Bool,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_check_if_branch_types() {
+    // 作为值使用的 if 各分支类型必须一致；作为语句时分支的值不被使用，不做比较
+    let code = r#"
+    fn f(c: bool) -> (r: f64) {
+        r = if c { 1.5 } else { "hi" }
+        if c {
+            x = 1.5
+        } else {
+            s = "hi"
+        }
+        y = if c { 1 } else { 2i8 }
+        z = if c { 1.5f32 } else { 2.5 }
+        w = 1 + (if c { 2 } else { 3.5 })
+    }
+    "#;
+    let errors = check(code);
+    let found: Vec<(&str, &Type, &Type)> = errors
+        .iter()
+        .map(|e| match e {
+            TypeError::BranchTypeMismatch {
+                expected,
+                found,
+                span,
+            } => (&code[span.start..span.end], expected, found),
+            other => panic!("unexpected error {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("\"hi\"", &Type::F64, &Type::String),
+            ("3.5", &Type::I64, &Type::F64),
+        ]
+    );
+    assert_eq!(errors[0].code(), "type::branch_type_mismatch");
+}
+
+#[test]
+fn test_check_unary_bitwise_and_shift_operands() {
+    let code = r#"
+    fn f(a: i64, n: i8, x: f64, s: string) -> (r: i64) {
+        ok = -a % 3 + (a & 6 | a ^ 1) << n >> 1
+        flags = (a > 0) & (a < 9) | true ^ false
+        fine = -x % 2.5
+        y = x << 1
+        z = x & 1.0
+        w = -s
+        r = -1
+    }
+    "#;
+    let errors = check(code);
+    assert_eq!(errors.len(), 3, "got {:?}", errors);
+    assert!(matches!(
+        &errors[0],
+        TypeError::InvalidOperands { op, lhs: Type::F64, rhs: Type::I64, .. } if op == "<<"
+    ));
+    assert!(matches!(
+        &errors[1],
+        TypeError::InvalidOperands { op, lhs: Type::F64, rhs: Type::F64, .. } if op == "&"
+    ));
+    assert!(matches!(
+        &errors[2],
+        TypeError::InvalidOperand { op, ty: Type::String, .. } if op == "-"
+    ));
+}
+
+#[test]
+fn test_check_index_and_compound_assignment() {
+    let code = r#"
+    fn f(n: i32, x: f64, s: string) -> (r: i64) {
+        a = [1, 2, 3]
+        d = array [1.5, 2.5]
+        a[0] = n
+        a[n] += 2
+        d[1] *= x
+        r = 0
+        r -= a[2]
+        a[1] = 1.5
+        d[x] = 1.0
+        s[0] = 1
+        r /= x
+    }
+    "#;
+    let errors = check(code);
+    assert_eq!(errors.len(), 4, "got {:?}", errors);
+    assert!(matches!(
+        &errors[0],
+        TypeError::ElementTypeMismatch {
+            expected: Type::I64,
+            found: Type::F64,
+            ..
+        }
+    ));
+    assert!(matches!(
+        &errors[1],
+        TypeError::NonIntegerIndex { ty: Type::F64, .. }
+    ));
+    assert!(matches!(
+        &errors[2],
+        TypeError::InvalidOperand { op, ty: Type::String, .. } if op == "[]="
+    ));
+    assert!(matches!(
+        &errors[3],
+        TypeError::InvalidOperands { op, lhs: Type::I64, rhs: Type::F64, .. } if op == "/="
+    ));
+}
+
+#[test]
+fn test_check_struct_fields() {
+    let mut tc = TypeChecker::new();
+    let point = parser::struct_def("struct Point { x: f64, y: f64 }").unwrap();
+    let errors = tc.check_structs(std::slice::from_ref(&point));
+    assert!(errors.is_empty(), "got {:?}", errors);
+    tc.register_struct(point);
+
+    let code = r#"
+    fn f(p: Point) -> (r: f64) {
+        a = Point { x: 1.0, y: 2.0 }
+        b = Point { x: 1.0 }
+        c = Point { x: 1.0, y: "s", z: 0.0 }
+        e = p.z
+        a.x = "s"
+        a.x += 1.0
+        p.y = a.x
+        d = Box { v: 1 }
+        a = 1.0
+        r = a.y
+    }
+    "#;
+    let (_name, params, the_return, stmts) = parser::function(code).unwrap();
+    let errors = tc.check_function(&params, &the_return, &stmts);
+    assert_eq!(errors.len(), 7, "got {:?}", errors);
+    assert!(matches!(
+        &errors[0],
+        TypeError::MissingFields { name, fields, .. } if name == "Point" && fields == &["y"]
+    ));
+    assert!(matches!(
+        &errors[1],
+        TypeError::FieldTypeMismatch { field, expected: Type::F64, found: Type::String, .. }
+            if field == "y"
+    ));
+    assert!(matches!(
+        &errors[2],
+        TypeError::UnknownField { field, .. } if field == "z"
+    ));
+    assert!(matches!(
+        &errors[3],
+        TypeError::UnknownField { ty: Type::Struct(name), field, .. } if name == "Point" && field == "z"
+    ));
+    assert!(matches!(
+        &errors[4],
+        TypeError::FieldTypeMismatch { field, .. } if field == "x"
+    ));
+    assert!(matches!(
+        &errors[5],
+        TypeError::UndefinedType { name, .. } if name == "Box"
+    ));
+    assert!(matches!(
+        &errors[6],
+        TypeError::AssignTypeMismatch { name, found: Type::F64, .. } if name == "a"
+    ));
+
+    // 结构体声明本身的错误
+    let defs = [
+        parser::struct_def("struct A { x: i64, x: f64 }").unwrap(),
+        parser::struct_def("struct B { c: C }").unwrap(),
+        parser::struct_def("struct C { b: [B; 2] }").unwrap(),
+    ];
+    for def in &defs {
+        tc.register_struct(def.clone());
+    }
+    let errors = tc.check_structs(&defs);
+    assert!(matches!(
+        &errors[0],
+        TypeError::DuplicateField { field, .. } if field == "x"
+    ));
+    assert!(
+        errors
+            .iter()
+            .any(|e| matches!(e, TypeError::RecursiveStruct { name, .. } if name == "B")),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_check_enums_and_match() {
+    let mut tc = TypeChecker::new();
+    let shape = parser::enum_def("enum Shape { Circle(f64), Rect(f64, f64), Empty }").unwrap();
+    let errors = tc.check_enums(std::slice::from_ref(&shape));
+    assert!(errors.is_empty(), "got {:?}", errors);
+    tc.register_enum(shape);
+
+    let code = r#"
+    fn f(s: Shape, n: i8) -> (r: f64) {
+        a = Shape::Rect(1.0)
+        b = Shape::Square
+        c = Shape::Circle("s")
+        r = match s {
+            Shape::Circle(x) => x,
+            Shape::Rect(w, _, _) => w,
+            Shape::Empty => 0.0,
+        }
+        r = match s {
+            Shape::Rect(w, h) => w * h,
+        }
+        k = match n {
+            0..=127 => 1,
+            -3 | -1 => 2,
+        }
+        k = match n {
+            200 => 1,
+            _ => 0,
+        }
+        k = match s {
+            1 => 1,
+            _ => 0,
+        }
+        k = match r {
+            _ => 0,
+        }
+    }
+    "#;
+    let (_name, params, the_return, stmts) = parser::function(code).unwrap();
+    let errors = tc.check_function(&params, &the_return, &stmts);
+    assert_eq!(errors.len(), 9, "got {:?}", errors);
+    assert!(matches!(
+        &errors[0],
+        TypeError::VariantArity { variant, expected: 2, found: 1, .. } if variant == "Shape::Rect"
+    ));
+    assert!(matches!(
+        &errors[1],
+        TypeError::UnknownVariant { name, variant, .. } if name == "Shape" && variant == "Square"
+    ));
+    assert!(matches!(
+        &errors[2],
+        TypeError::ArgumentType { func, found: Type::String, .. } if func == "Shape::Circle"
+    ));
+    assert!(matches!(
+        &errors[3],
+        TypeError::VariantArity {
+            expected: 2,
+            found: 3,
+            ..
+        }
+    ));
+    assert!(matches!(
+        &errors[4],
+        TypeError::NonExhaustiveMatch { missing, .. } if missing == &["Shape::Circle", "Shape::Empty"]
+    ));
+    assert!(matches!(
+        &errors[5],
+        TypeError::NonExhaustiveMatch { missing, .. }
+            if missing == &["i8::MIN..=-4", "-2"]
+    ));
+    assert!(matches!(
+        &errors[6],
+        TypeError::LiteralOutOfRange { ty: Type::I8, .. }
+    ));
+    assert!(matches!(
+        &errors[7],
+        TypeError::PatternTypeMismatch { ty: Type::Struct(name), .. } if name == "Shape"
+    ));
+    assert!(matches!(
+        &errors[8],
+        TypeError::InvalidOperand { op, ty: Type::F64, .. } if op == "match"
+    ));
+
+    // 枚举声明本身的错误
+    let defs = [
+        parser::enum_def("enum A { X, Y(i64), X }").unwrap(),
+        parser::enum_def("enum B { Items(array<i64>) }").unwrap(),
+        parser::enum_def("enum C { Leaf, Node(i64, [C; 2]) }").unwrap(),
+    ];
+    for def in &defs {
+        tc.register_enum(def.clone());
+    }
+    let errors = tc.check_enums(&defs);
+    assert!(matches!(
+        &errors[0],
+        TypeError::DuplicateVariant { variant, .. } if variant == "X"
+    ));
+    assert!(matches!(
+        &errors[1],
+        TypeError::OwnedPayload { variant, .. } if variant == "B::Items"
+    ));
+    assert!(matches!(
+        &errors[2],
+        TypeError::RecursiveStruct { name, .. } if name == "C"
+    ));
+    assert_eq!(errors.len(), 3, "got {:?}", errors);
+}
+
+#[test]
+fn test_check_let_declarations() {
+    let code = r#"
+    fn f(n: i64) -> (r: f64) {
+        let a: f32 = 1.5
+        let b: f64 = a
+        let c: i8 = -128
+        let mut d: i64 = 1i32
+        d = 2.5
+        let e: i32 = n
+        let p: Point = 1
+        x = 1
+        x = 2.5
+        r = b
+    }
+    "#;
+    let errors = check(code);
+    let found: Vec<(&str, String)> = errors
+        .iter()
+        .map(|e| {
+            let span = e.span();
+            let kind = match e {
+                TypeError::AssignTypeMismatch { name, .. } => format!("mismatch {}", name),
+                TypeError::UndefinedType { name, .. } => format!("undefined {}", name),
+                other => panic!("unexpected error {:?}", other),
+            };
+            (&code[span.start..span.end], kind)
+        })
+        .collect();
+    // 隐式变量 x 仍按第一次赋值的类型转换，let 声明的变量只接受隐式拓宽
+    assert_eq!(
+        found,
+        vec![
+            ("2.5", "mismatch d".to_string()),
+            ("n", "mismatch e".to_string()),
+            ("1", "undefined Point".to_string()),
+        ]
+    );
+
+    let errors = check("fn f() -> (r: i64) {\n    let big: i8 = 128\n    r = big\n}\n");
+    assert!(
+        matches!(
+            errors.as_slice(),
+            [TypeError::LiteralOutOfRange { ty: Type::I8, .. }]
+        ),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_check_globals() {
+    let mut tc = TypeChecker::new();
+    let global = |code: &str| {
+        let def = parser::global_def(code).unwrap();
+        let folded = optimizer::fold_constants(def.value.clone());
+        (def, folded)
+    };
+
+    // 初值折叠后按声明类型重写为字面量，负数带符号
+    let (def, folded) = global("const N: i8 = -(2 * 64)\n");
+    let value = tc.check_global(&def, &folded).unwrap();
+    assert_eq!(value.kind, ExprKind::Literal("-128".to_string(), Type::I8));
+    tc.register_global(GlobalDef { value, ..def });
+    let (def, folded) = global("static mut ready: bool = 1 < 2\n");
+    tc.register_global(GlobalDef {
+        value: tc.check_global(&def, &folded).unwrap(),
+        ..def
+    });
+    assert_eq!(tc.constants().keys().collect::<Vec<_>>(), vec!["N"]);
+
+    let error = |code: &str| {
+        let (def, folded) = global(code);
+        tc.check_global(&def, &folded).unwrap_err()
+    };
+    assert!(matches!(
+        error("const A: i64 = n + 1\n"),
+        TypeError::NonConstantInitializer { name, .. } if name == "A"
+    ));
+    assert!(matches!(
+        error("static S: string = \"s\"\n"),
+        TypeError::UnsupportedGlobalType {
+            ty: Type::String,
+            ..
+        }
+    ));
+    assert!(matches!(
+        error("const F: f64 = 2\n"),
+        TypeError::AssignTypeMismatch {
+            found: Type::I64,
+            ..
+        }
+    ));
+    assert!(matches!(
+        error("const B: i8 = 200\n"),
+        TypeError::LiteralOutOfRange { ty: Type::I8, .. }
+    ));
+
+    // 函数中常量与静态变量可见，不能赋予不兼容的值
+    let code = "fn f() -> (r: i64) {\n    ready = 2.5\n    r = N\n}\n";
+    let (_name, params, the_return, stmts) = parser::function(code).unwrap();
+    let errors = tc.check_function(&params, &the_return, &stmts);
+    assert!(
+        matches!(
+            errors.as_slice(),
+            [TypeError::AssignTypeMismatch { name, .. }] if name == "ready"
+        ),
+        "got {:?}",
+        errors
+    );
+}
+
+#[test]
+fn test_literal_types_and_ranges() {
+    let parse = |src: &str| {
+        let code = format!("fn f() -> (r: i64) {{\n    r = {}\n}}\n", src);
+        let stmts = parser::program(&code).unwrap().split().2.remove(0).3;
+        match &stmts[0].kind {
+            ExprKind::Assign(_, value) => value.kind.clone(),
+            other => panic!("unexpected statement {:?}", other),
+        }
+    };
+    let lit = |text: &str, ty: Type| ExprKind::Literal(text.to_string(), ty);
+    assert_eq!(parse("0xFF"), lit("255", Type::I64));
+    assert_eq!(parse("0b1010_1010i16"), lit("170", Type::I16));
+    assert_eq!(parse("0o17"), lit("15", Type::I64));
+    assert_eq!(parse("1_000_000"), lit("1000000", Type::I64));
+    assert_eq!(parse("10i32"), lit("10", Type::I32));
+    assert_eq!(parse("1i128"), lit("1", Type::I128));
+    assert_eq!(parse("2.5f32"), lit("2.5", Type::F32));
+    assert_eq!(parse("1f64"), lit("1", Type::F64));
+    assert_eq!(parse("1e-9"), lit("1e-9", Type::F64));
+    assert_eq!(parse("6.02E+23"), lit("6.02E+23", Type::F64));
+    assert_eq!(
+        parse("1.0 - 2.5e1i"),
+        ExprKind::ComplexLiteral(1.0, -25.0, Type::Complex128)
+    );
+    assert!(parser::program("fn f() -> (r: i64) {\n    r = 10u8\n}\n").is_err());
+
+    let code = r#"
+    fn f() -> (r: i64) {
+        a = 127i8
+        b = -128i8
+        c = 128i8
+        d = 0xFFFF_FFFFi32
+        e = -9223372036854775808
+        g = 9223372036854775808
+        h = 1e39f32
+        k = 170141183460469231731687303715884105727i128
+        r = 0
+    }
+    "#;
+    let errors = check(code);
+    assert_eq!(errors.len(), 4, "got {:?}", errors);
+    let out_of_range: Vec<(&str, &Type)> = errors
+        .iter()
+        .map(|e| match e {
+            TypeError::LiteralOutOfRange { ty, span } => (&code[span.start..span.end], ty),
+            other => panic!("unexpected error {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        out_of_range,
+        vec![
+            ("128i8", &Type::I8),
+            ("0xFFFF_FFFFi32", &Type::I32),
+            ("9223372036854775808", &Type::I64),
+            ("1e39f32", &Type::F32),
+        ]
+    );
+}
+
+#[test]
+fn test_literal_range_at_coercion_sites() {
+    // 字面量隐式转换为目标类型的每一处都检查取值范围
+    let mut tc = TypeChecker::new();
+    tc.register_struct(parser::struct_def("struct S { a: i8, b: f32 }").unwrap());
+    tc.register_enum(parser::enum_def("enum E { V(i16) }").unwrap());
+    tc.register_function("g", vec![Type::I8], Type::I8);
+
+    let code = r#"
+    fn f(n: i64) -> (r: i8) {
+        let mut t: i8 = 1
+        t = 128
+        c = g(-200)
+        s = S { a: 1000, b: 1e39 }
+        s.a = 256
+        a = [1i8, 2i8]
+        a[0] = 300
+        e = E::V(40000)
+        if n > 0 {
+            return -129
+        }
+        t = -128
+        s.a = 127
+        a[1] = -128
+        c = g(127)
+        r = 300
+    }
+    "#;
+    let (_name, params, the_return, stmts) = parser::function(code).unwrap();
+    let errors = tc.check_function(&params, &the_return, &stmts);
+    let out_of_range: Vec<(&str, &Type)> = errors
+        .iter()
+        .map(|e| match e {
+            TypeError::LiteralOutOfRange { ty, span } => (&code[span.start..span.end], ty),
+            other => panic!("unexpected error {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        out_of_range,
+        vec![
+            ("128", &Type::I8),
+            ("-200", &Type::I8),
+            ("1000", &Type::I8),
+            ("1e39", &Type::F32),
+            ("256", &Type::I8),
+            ("300", &Type::I8),
+            ("40000", &Type::I16),
+            ("-129", &Type::I8),
+            ("300", &Type::I8),
+        ]
+    );
+}
+
+#[test]
+fn test_type_error_span_points_at_expression() {
+    let code = "fn f(count: i64) -> (r: i64) {\n    r = count + cuont\n}\n";
+    let errors = check(code);
+    assert_eq!(errors.len(), 1, "got {:?}", errors);
+    let span = errors[0].span();
+    assert_eq!(&code[span.start..span.end], "cuont");
+
+    let rendered = errors[0].to_diagnostic().render("f.toy", code);
+    assert!(rendered.contains("--> f.toy:2:17"), "got:\n{}", rendered);
+    assert!(rendered.contains("r = count + cuont"), "got:\n{}", rendered);
+}